@@ -0,0 +1,106 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compare [buffered_chars](anysexpr::buffered_chars::buffered_chars)
+//! with the earlier way of decoding via `utf8::BufReadDecoder` (which
+//! copies each decoded chunk), for reading characters only and for
+//! parsing. Run with `cargo bench`.
+
+use anysexpr::buffered_chars::buffered_chars;
+use anysexpr::parse::parse;
+use anysexpr::pos::Pos;
+use anysexpr::settings::{Modes, Settings, GAMBIT_FORMAT};
+use anyhow::{Result, anyhow};
+use std::hint::black_box;
+use std::io::{BufRead, BufReader};
+use std::time::{Duration, Instant};
+use utf8::BufReadDecoder;
+
+/// The baseline: decode chunks via BufReadDecoder, copy them, and
+/// iterate over their characters.
+fn utf8_decode_chars<R: BufRead>(fh: R) -> impl Iterator<Item=Result<(char, Pos)>> {
+    let mut inp = BufReadDecoder::new(fh);
+    let mut chunk = String::new();
+    let mut i = 0;
+    let mut pos = Pos::default();
+    let mut done = false;
+    std::iter::from_fn(move || {
+        while i == chunk.len() {
+            if done {
+                return None
+            }
+            match inp.next_strict() {
+                Some(Ok(s)) => {
+                    chunk.clear();
+                    chunk.push_str(s);
+                    i = 0;
+                }
+                Some(Err(e)) => {
+                    done = true;
+                    return Some(Err(anyhow!("buffered_chars: {}", e)))
+                }
+                None => {
+                    done = true;
+                    return None
+                }
+            }
+        }
+        let c = chunk[i..].chars().next().unwrap();
+        i += c.len_utf8();
+        let p = pos;
+        pos = p.after(c);
+        Some(Ok((c, p)))
+    })
+}
+
+fn input() -> Vec<u8> {
+    let mut s = String::new();
+    for i in 0..100_000 {
+        s.push_str(&format!(
+            "(define (f{i} x) ; Grüße, λ\n  (string-append \"naïve café {i}\" #\\x (g x 3.25)))\n"));
+    }
+    s.into_bytes()
+}
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+const RUNS: u32 = 5;
+
+/// The best time of `RUNS` runs of `f`.
+fn time(f: impl Fn() -> usize) -> Duration {
+    (0..RUNS).map(|_| {
+        let t = Instant::now();
+        black_box(f());
+        t.elapsed()
+    }).min().unwrap()
+}
+
+fn report(what: &str, bytes: usize, new: Duration, old: Duration) {
+    let mb_s = |d: Duration| bytes as f64 / d.as_secs_f64() / 1e6;
+    println!("{what:>6}: buffered_chars {:8.1} MB/s, utf8-decode {:8.1} MB/s ({:.2}x)",
+             mb_s(new), mb_s(old), old.as_secs_f64() / new.as_secs_f64());
+}
+
+fn main() {
+    let inp = input();
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &MODES };
+
+    let new = time(|| buffered_chars(BufReader::new(&inp[..])).count());
+    let old = time(|| utf8_decode_chars(BufReader::new(&inp[..])).count());
+    report("chars", inp.len(), new, old);
+
+    let new = time(|| parse(buffered_chars(BufReader::new(&inp[..])), &settings).count());
+    let old = time(|| parse(utf8_decode_chars(BufReader::new(&inp[..])), &settings).count());
+    report("parse", inp.len(), new, old);
+}
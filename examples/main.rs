@@ -10,7 +10,7 @@
 //! An example that also serves to inspect how inputs are being parsed
 //! and to generate test output.
 
-use anysexpr::pos::Pos;
+use anysexpr::pos::Span;
 use anysexpr::value::{Parenkind, VValueWithPos};
 use anysexpr::parse::{Token, parse, TokenWithPos};
 use anysexpr::settings::{Settings, Modes, GAMBIT_FORMAT};
@@ -65,6 +65,7 @@ struct Args {
 
 const MODES: Modes = Modes {
     allow_improper_lists: true,
+    recover_from_errors: false,
     retain_whitespace: false,
     retain_comments: false,
 };
@@ -84,7 +85,7 @@ fn main() -> Result<()> {
         if args.dump {
             for val in vals {
                 // Print line information as s-expression
-                write!(&mut out, "(line {})\n", val.1.line + 1)?;
+                writeln!(&mut out, "(line {})", val.1.start.line + 1)?;
                 GAMBIT_FORMAT.writeln(&mut out, &val.dump())?;
             }
         }
@@ -101,13 +102,14 @@ fn main() -> Result<()> {
             format: &GAMBIT_FORMAT,
             modes: &Modes {
                 allow_improper_lists: args.allow_improper_lists,
+                recover_from_errors: false,
                 retain_whitespace: args.whitespace,
                 retain_comments: args.comments,
             }};
         let ts = parse(&mut cs, &settings);
         let mut count_toplevel = 0;
         let mut count_enter = 0;
-        let mut parenstack: Vec<(Parenkind, Pos)> = Vec::new();
+        let mut parenstack: Vec<(Parenkind, Span)> = Vec::new();
         for te in ts {
             let TokenWithPos(token, pos) = te?;
             let indentlevel;
@@ -148,9 +150,9 @@ fn main() -> Result<()> {
                         write!(out, "{pos} ")?;
                     }
                     if args.dump {
-                        write!(out, "{token:?}\n")?;
+                        writeln!(out, "{token:?}")?;
                     } else {
-                        write!(out, "{token}\n")?;
+                        writeln!(out, "{token}")?;
                     }
                 } else {
                     bail!("lists nested too deeply at {:?}{}", args.input_path, pos)
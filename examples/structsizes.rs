@@ -10,7 +10,7 @@
 //! Not an example, but a program to show the struct sizes for
 //! possible optimization.
 
-use anysexpr::{settings::{AnysexprFormat, Modes, Settings, GAMBIT_FORMAT}, context::{FileContext, SpecialContext}, parse::{ParseErrorWithPos, TokenWithPos, Token, ParseError, parse}, pos::Pos, read::{ReadErrorWithPos, ReadErrorWithContext, ReadErrorWithLocation, ReadError}, value::{VValue, VValueWithPos, SpecialKind, Atom, Parenkind}, number::{R5RSNumber, Integer, Rational}, buffered_chars::buffered_chars};
+use anysexpr::{settings::{AnysexprFormat, Modes, Settings, GAMBIT_FORMAT}, context::{FileContext, SpecialContext}, parse::{ParseErrorWithPos, TokenWithPos, Token, ParseError, parse}, pos::{Pos, Span}, read::{ReadErrorWithPos, ReadErrorWithContext, ReadErrorWithLocation, ReadError}, value::{VValue, VValueWithPos, SpecialKind, Atom, Parenkind}, number::{R5RSNumber, Integer, Rational, NumericVector}, symbol::{IStr, Symbol, SymbolId}, graph::{Node, NodeId}, buffered_chars::buffered_chars};
 use kstring::KString;
 use num::BigInt;
 
@@ -50,6 +50,7 @@ fn main() {
         p!{FileContext};
         p!{SpecialContext};
         p!{Pos};
+        p!{Span};
     }
 
     {
@@ -65,6 +66,7 @@ fn main() {
         p!{Integer};
         p!{Rational};
         p!{R5RSNumber};
+        p!{NumericVector};
         p!{KString};
         p!{SpecialKind};
         p!{Atom};
@@ -72,6 +74,21 @@ fn main() {
         p!{VValueWithPos};
     }
 
+    {
+        ctx!("graph");
+        p!{NodeId};
+        p!{Node};
+    }
+
+    {
+        ctx!("symbol");
+        p!{SymbolId};
+        p!{Symbol};
+        p!{IStr};
+        p!{Atom<IStr>};
+        p!{VValueWithPos<IStr>};
+    }
+
     {
         ctx!("parse");
         p!{Token};
@@ -93,6 +110,7 @@ fn main() {
                     retain_comments: true,
                     retain_whitespace: true,
                     allow_improper_lists: true,
+                    recover_from_errors: false,
                 }
             };
             pr("parse", "return type of `parse`", sz(|| {
@@ -115,7 +133,7 @@ fn main() {
         p!{ReadErrorWithLocation};
         
         p!{Result<Option<VValueWithPos>, ReadErrorWithPos>};
-        p!{Result<(Vec<VValueWithPos>, Option<Pos>), ReadErrorWithPos>};
+        p!{Result<(Vec<VValueWithPos>, Option<Span>), ReadErrorWithPos>};
         p!{Result<Vec<VValueWithPos>, ReadErrorWithPos>};
         p!{Result<Vec<VValueWithPos>, ReadErrorWithLocation>};
     }
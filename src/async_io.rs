@@ -0,0 +1,220 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Async counterparts to [parse](crate::parse::parse),
+//! [read_all](crate::settings::AnysexprFormat::read_all) and the
+//! `write` methods of [AnysexprFormat](crate::settings::AnysexprFormat),
+//! for tokio's `AsyncBufRead` and `AsyncWrite` (requires the `async`
+//! feature). Input is processed via [push](crate::push) as it
+//! arrives.
+
+use crate::push::{PushParser, PushReader};
+use crate::parse::{TokenWithPos, ParseErrorWithPos};
+use crate::read::ReadErrorWithPos;
+use crate::builder::{TreeBuilder, VValueBuilder};
+use crate::settings::{AnysexprFormat, Settings};
+use crate::write::{Pieces, Writable, WriteError, CHUNK_SIZE};
+use futures_core::Stream;
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The stream returned by [parse_async](parse_async).
+pub struct TokenStream<'s, 't, R> {
+    input: R,
+    parser: PushParser<'s, 't>,
+    pending: VecDeque<Result<TokenWithPos, ParseErrorWithPos>>,
+    done: bool,
+}
+
+/// Parse the input from `input` into a stream of tokens, like
+/// [parse](crate::parse::parse) does from a blocking source.
+pub fn parse_async<'s, 't, R: AsyncBufRead + Unpin>(
+    input: R,
+    settings: &'s Settings<'t>,
+) -> TokenStream<'s, 't, R>
+{
+    TokenStream {
+        input,
+        parser: PushParser::new(settings),
+        pending: VecDeque::new(),
+        done: false,
+    }
+}
+
+impl<'s, 't, R: AsyncBufRead + Unpin> Stream for TokenStream<'s, 't, R> {
+    type Item = Result<TokenWithPos, ParseErrorWithPos>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>)
+                 -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(r) = this.pending.pop_front() {
+                return Poll::Ready(Some(r))
+            }
+            if this.done {
+                return Poll::Ready(None)
+            }
+            match Pin::new(&mut this.input).poll_fill_buf(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    this.pending.extend(this.parser.fail(e.into()));
+                    this.done = true;
+                }
+                Poll::Ready(Ok(buf)) => {
+                    if buf.is_empty() {
+                        this.pending.extend(this.parser.finish());
+                        this.done = true;
+                    } else {
+                        let len = buf.len();
+                        this.pending.extend(this.parser.feed(buf));
+                        Pin::new(&mut this.input).consume(len);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The stream returned by [read_async](read_async) and
+/// [read_async_with](read_async_with).
+pub struct ValueStream<'s, 't, R, B: TreeBuilder = VValueBuilder> {
+    input: R,
+    reader: PushReader<'s, 't, B>,
+    pending: VecDeque<Result<B::Value, ReadErrorWithPos>>,
+    done: bool,
+}
+
+/// Read the input from `input` into a stream of top-level values,
+/// like [read_all](crate::settings::AnysexprFormat::read_all) does
+/// from a blocking source. Stops after the first error.
+pub fn read_async<'s, 't, R: AsyncBufRead + Unpin>(
+    input: R,
+    settings: &'s Settings<'t>,
+) -> ValueStream<'s, 't, R>
+{
+    read_async_with(VValueBuilder, input, settings)
+}
+
+/// Same as [read_async](read_async) but building the values via
+/// `builder`.
+pub fn read_async_with<'s, 't, R: AsyncBufRead + Unpin, B: TreeBuilder>(
+    builder: B,
+    input: R,
+    settings: &'s Settings<'t>,
+) -> ValueStream<'s, 't, R, B>
+{
+    ValueStream {
+        input,
+        reader: PushReader::with_builder(builder, settings),
+        pending: VecDeque::new(),
+        done: false,
+    }
+}
+
+impl<'s, 't, R, B> Stream for ValueStream<'s, 't, R, B>
+    where R: AsyncBufRead + Unpin,
+          B: TreeBuilder + Unpin,
+          B::Value: Unpin
+{
+    type Item = Result<B::Value, ReadErrorWithPos>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>)
+                 -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(r) = this.pending.pop_front() {
+                return Poll::Ready(Some(r))
+            }
+            if this.done {
+                return Poll::Ready(None)
+            }
+            match Pin::new(&mut this.input).poll_fill_buf(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    this.pending.extend(this.reader.fail(e.into()));
+                    this.done = true;
+                }
+                Poll::Ready(Ok(buf)) => {
+                    if buf.is_empty() {
+                        this.pending.extend(this.reader.finish());
+                        this.done = true;
+                    } else {
+                        let len = buf.len();
+                        this.pending.extend(this.reader.feed(buf));
+                        Pin::new(&mut this.input).consume(len);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Write `pieces` followed by `end` to `out`, passing the text on in
+// chunks as it's generated. On errors, the chunks before the error
+// have been written.
+async fn write_pieces(
+    out: &mut (impl AsyncWrite + Unpin),
+    mut pieces: impl Pieces,
+    end: &str
+) -> Result<(), WriteError> {
+    let mut buf = String::new();
+    while pieces.write_next(&mut buf)? {
+        if buf.len() >= CHUNK_SIZE {
+            out.write_all(buf.as_bytes()).await?;
+            buf.clear();
+        }
+    }
+    buf.push_str(end);
+    out.write_all(buf.as_bytes()).await?;
+    Ok(())
+}
+
+impl<'f> AnysexprFormat<'f> {
+
+    /// Same as [write](AnysexprFormat::write) but to an async output
+    /// stream: the text is written in chunks as it's generated.
+    pub async fn write_async<'t, T: Writable + ?Sized + 't>(
+        &self,
+        out: &mut (impl AsyncWrite + Unpin),
+        val: &'t T
+    ) -> Result<(), WriteError> {
+        write_pieces(out, val.pieces(self), "").await
+    }
+
+    /// Same as [writeln](AnysexprFormat::writeln) but to an async
+    /// output stream.
+    pub async fn writeln_async<'t, T: Writable + ?Sized + 't>(
+        &self,
+        out: &mut (impl AsyncWrite + Unpin),
+        val: &'t T
+    ) -> Result<(), WriteError> {
+        write_pieces(out, val.pieces(self), "\n").await
+    }
+
+    /// Same as [write_all](AnysexprFormat::write_all) but to an async
+    /// output stream.
+    pub async fn write_all_async<'t, T: Writable + ?Sized + 't>(
+        &self,
+        out: &mut (impl AsyncWrite + Unpin),
+        vals: impl IntoIterator<Item = &'t T>
+    ) -> Result<(), WriteError> {
+        let mut seen_item = false;
+        for v in vals.into_iter() {
+            if seen_item {
+                out.write_all(b"\n").await?;
+            }
+            self.writeln_async(out, v).await?;
+            seen_item = true;
+        }
+        Ok(())
+    }
+
+}
@@ -13,47 +13,165 @@
 //! This exists because it's not clear if any dependency (some of them
 //! large) would be better.
 
-/// TODO: This uses genawaiter, find out if that is a performance
-/// bottleneck.
-
 use crate::pos::Pos;
-use std::io::BufRead;
+use std::io::{BufRead, ErrorKind};
+use std::iter::FusedIterator;
 use anyhow::{Result, anyhow};
-use utf8::BufReadDecoder;
-use genawaiter::rc::Gen;
 
 
-pub fn buffered_chars<R>(
-    fh: R
-) -> impl Iterator<Item=Result<(char, Pos)>>
-    where R: BufRead
-{
-    Gen::new(|co| async move {
-        let mut inp = BufReadDecoder::new(fh);
-        let mut pos = Pos { line: 0, col: 0 };
+// Decodes directly from the buffer of the BufRead, only characters
+// that are split across refills are copied (into `partial`).
+struct BufferedChars<R: BufRead> {
+    inp: R,
+    pos: Pos,
+    partial: [u8; 4],
+    npartial: u8,
+    done: bool,
+}
+
+// The length of the UTF-8 sequence starting with `b`, 0 if `b` can't
+// start one.
+fn sequence_len(b: u8) -> usize {
+    match b {
+        0x00..=0x7f => 1,
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => 0,
+    }
+}
+
+// Decode the single character in `bytes`, or give the length of the
+// invalid sequence at its start.
+fn decode(bytes: &[u8]) -> Result<char, usize> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Ok(s.chars().next().expect("non-empty")),
+        Err(e) => Err(e.error_len().unwrap_or(bytes.len()).max(1)),
+    }
+}
+
+impl<R: BufRead> BufferedChars<R> {
+    fn invalid(&mut self, bytes: &[u8]) -> Option<Result<(char, Pos)>> {
+        self.done = true;
+        Some(Err(anyhow!("buffered_chars: invalid byte sequence: {:02x?}", bytes)))
+    }
+
+    #[inline]
+    fn deliver(&mut self, c: char) -> Option<Result<(char, Pos)>> {
+        let pos = self.pos;
+        self.pos = pos.after(c);
+        Some(Ok((c, pos)))
+    }
+
+    // Everything but ASCII characters in the buffer.
+    #[inline(never)]
+    fn next_slow(&mut self) -> Option<Result<(char, Pos)>> {
+        if self.done {
+            return None
+        }
         loop {
-            if let Some(r) = inp.next_strict() {
-                match r {
-                    Ok(x) => {
-                        for c in x.chars() {
-                            co.yield_(Ok((c, pos))).await;
-                            pos =
-                                if c == '\n' {
-                                    Pos { line: pos.line + 1, col: 0 }
-                                } else {
-                                    Pos { line: pos.line, col: pos.col + 1 }
-                                };
+            let buf = match self.inp.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(anyhow!(
+                        "buffered_chars: underlying bytestream error: {}", e)))
+                }
+            };
+            if self.npartial == 0 {
+                let Some(&b) = buf.first() else {
+                    self.done = true;
+                    return None
+                };
+                if b < 0x80 {
+                    self.inp.consume(1);
+                    return self.deliver(b as char)
+                }
+                let len = sequence_len(b);
+                if len == 0 {
+                    return self.invalid(&[b])
+                }
+                if buf.len() >= len {
+                    return match decode(&buf[..len]) {
+                        Ok(c) => {
+                            self.inp.consume(len);
+                            self.deliver(c)
+                        }
+                        Err(n) => {
+                            let bytes = buf[..n].to_vec();
+                            self.invalid(&bytes)
                         }
-                    },
-                    Err(e) => {
-                        co.yield_(Err(anyhow!("buffered_chars: {}", e))).await;
-                        return;
                     }
                 }
+                let n = buf.len();
+                self.partial[..n].copy_from_slice(buf);
+                self.npartial = n as u8;
+                self.inp.consume(n);
             } else {
-                return;
+                let have = self.npartial as usize;
+                let len = sequence_len(self.partial[0]);
+                if buf.is_empty() {
+                    let bytes = self.partial;
+                    return self.invalid(&bytes[..have])
+                }
+                let n = (len - have).min(buf.len());
+                self.partial[have..have + n].copy_from_slice(&buf[..n]);
+                self.inp.consume(n);
+                self.npartial += n as u8;
+                if have + n == len {
+                    self.npartial = 0;
+                    let bytes = self.partial;
+                    return match decode(&bytes[..len]) {
+                        Ok(c) => self.deliver(c),
+                        Err(n) => self.invalid(&bytes[..n])
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for BufferedChars<R> {
+    type Item = Result<(char, Pos)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.npartial == 0 && !self.done {
+            if let Ok(&[b, ..]) = self.inp.fill_buf().as_deref() {
+                if b < 0x80 {
+                    self.inp.consume(1);
+                    return self.deliver(b as char)
+                }
             }
         }
-    }).into_iter()
+        self.next_slow()
+    }
 }
 
+impl<R: BufRead> FusedIterator for BufferedChars<R> {}
+
+pub fn buffered_chars<R>(
+    fh: R
+) -> impl Iterator<Item=Result<(char, Pos)>>
+    where R: BufRead
+{
+    BufferedChars {
+        inp: fh,
+        pos: Pos::default(),
+        partial: [0; 4],
+        npartial: 0,
+        done: false,
+    }
+}
+
+/// Get the characters of a string and their positions.
+pub fn str_chars(
+    s: &str
+) -> impl Iterator<Item=Result<(char, Pos)>> + '_
+{
+    s.chars().scan(Pos::default(), |pos, c| {
+        let p = *pos;
+        *pos = p.after(c);
+        Some(Ok((c, p)))
+    })
+}
@@ -0,0 +1,228 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The data constructors used by [read](crate::read), so that trees
+//! can be built from user-defined data types directly.
+
+//! [VValueBuilder](VValueBuilder) is the implementation used by the
+//! non-`_with` variants of the read functions.
+
+use crate::number::R5RSNumber;
+use crate::pos::Span;
+use crate::value::{Atom, Parenkind, ReaderMacro, VValue, VValueWithPos};
+use kstring::KString;
+
+/// Constructors for the values that the reader builds. Every
+/// constructor receives the span of the syntax that it represents;
+/// it's up to the implementation whether to keep it. `S` is the
+/// string type in the atoms, see [value](crate::value).
+pub trait TreeBuilder<S = KString> {
+    type Value;
+
+    /// Build an atom found at `span`.
+    fn atom(&mut self, atom: Atom<S>, span: Span) -> Self::Value;
+
+    /// Build a proper list of kind `pk` spanning `span` (from the
+    /// opening to the closing paren).
+    fn list(
+        &mut self,
+        pk: Parenkind,
+        items: Vec<Self::Value>,
+        span: Span
+    ) -> Self::Value;
+
+    /// Build a list with a `.` at `dotspan` followed by `tail`. `items`
+    /// is never empty. The reader only calls this for
+    /// `Parenkind::Round` lists. If `tail` was itself written as a
+    /// `(..)` list, the result is expected to be the same as if it
+    /// had been spliced in (`(a . (b c))` is the same as `(a b c)`).
+    fn improper_list(
+        &mut self,
+        pk: Parenkind,
+        items: Vec<Self::Value>,
+        dotspan: Span,
+        tail: Self::Value,
+        span: Span
+    ) -> Self::Value;
+
+    /// Build the representation of a prefix syntax like `'` (with
+    /// `symname` being "quote") applied to `value`, `span` covering
+    /// both. The default builds a two-element list `(symname value)`.
+    fn prefixed(
+        &mut self,
+        symname: &'static str,
+        value: Self::Value,
+        span: Span
+    ) -> Self::Value
+        where S: From<KString>
+    {
+        let sym = self.atom(Atom::Symbol(KString::from_static(symname).into()), span);
+        self.list(Parenkind::Round, vec![sym, value], span)
+    }
+
+    /// Build the representation of reader macro `rm` applied to
+    /// `value`, `span` covering both. For the reader macros followed
+    /// by a list (like `#{..}`), `value` is that list. The default
+    /// builds a two-element list `(name value)` via
+    /// [prefixed](TreeBuilder::prefixed), `name` being
+    /// `rm.name()`.
+    fn reader_macro(
+        &mut self,
+        rm: ReaderMacro,
+        value: Self::Value,
+        span: Span
+    ) -> Self::Value
+        where S: From<KString>
+    {
+        self.prefixed(rm.name(), value, span)
+    }
+
+    /// Build the representation of the tagged element `#tag value`
+    /// (`tag` without the `#`), `span` covering both. The default
+    /// builds a two-element list `(tag value)`.
+    fn tagged(
+        &mut self,
+        tag: S,
+        value: Self::Value,
+        span: Span
+    ) -> Self::Value {
+        let sym = self.atom(Atom::Symbol(tag), span);
+        self.list(Parenkind::Round, vec![sym, value], span)
+    }
+
+    /// Build the representation of Common Lisp's `#+feature form`
+    /// (`positive`) or `#-feature form`, `span` covering all of
+    /// it. The default builds a three-element list `(if-feature
+    /// feature form)` or `(unless-feature feature form)`.
+    fn feature_conditional(
+        &mut self,
+        positive: bool,
+        feature: Self::Value,
+        form: Self::Value,
+        span: Span
+    ) -> Self::Value
+        where S: From<KString>
+    {
+        let symname = if positive { "if-feature" } else { "unless-feature" };
+        let sym = self.atom(Atom::Symbol(KString::from_static(symname).into()), span);
+        self.list(Parenkind::Round, vec![sym, feature, form], span)
+    }
+
+    /// Build the representation of the datum label `#n=value`,
+    /// `span` covering all of it. The default builds a three-element
+    /// list `(datum-label n value)`.
+    fn labeled(&mut self, n: u32, value: Self::Value, span: Span) -> Self::Value
+        where S: From<KString>
+    {
+        let sym = self.atom(Atom::Symbol(KString::from_static("datum-label").into()), span);
+        let n = self.atom(Atom::Number(R5RSNumber::Integer(n.into())), span);
+        self.list(Parenkind::Round, vec![sym, n, value], span)
+    }
+
+    /// Build the representation of the reference `#n#` to a datum
+    /// label. The default builds a two-element list `(datum-ref n)`.
+    fn label_ref(&mut self, n: u32, span: Span) -> Self::Value
+        where S: From<KString>
+    {
+        let sym = self.atom(Atom::Symbol(KString::from_static("datum-ref").into()), span);
+        let n = self.atom(Atom::Number(R5RSNumber::Integer(n.into())), span);
+        self.list(Parenkind::Round, vec![sym, n], span)
+    }
+}
+
+/// Append `tail` to `items`, splicing it in if it is a `(..)` list,
+/// and return the span of the dot in the result, if it's still
+/// improper.
+pub fn splice_tail<S>(
+    items: &mut Vec<VValueWithPos<S>>,
+    dotspan: Span,
+    tail: VValueWithPos<S>
+) -> Option<Span> {
+    match tail {
+        VValueWithPos(VValue::List(Parenkind::Round, improper1, mut vs1), _) => {
+            items.append(&mut vs1);
+            // Whether the list is proper now depends on whether the
+            // tail was.
+            improper1.map(|dot| *dot)
+        }
+        _ => {
+            items.push(tail);
+            Some(dotspan)
+        }
+    }
+}
+
+/// Builds [VValueWithPos](VValueWithPos) trees.
+#[derive(Debug, Default)]
+pub struct VValueBuilder;
+
+impl<S> TreeBuilder<S> for VValueBuilder {
+    type Value = VValueWithPos<S>;
+
+    fn atom(&mut self, atom: Atom<S>, span: Span) -> VValueWithPos<S> {
+        VValue::Atom(atom).at(span)
+    }
+
+    fn list(
+        &mut self,
+        pk: Parenkind,
+        items: Vec<VValueWithPos<S>>,
+        span: Span
+    ) -> VValueWithPos<S> {
+        VValue::List(pk, None, items).at(span)
+    }
+
+    fn improper_list(
+        &mut self,
+        pk: Parenkind,
+        mut items: Vec<VValueWithPos<S>>,
+        dotspan: Span,
+        tail: VValueWithPos<S>,
+        span: Span
+    ) -> VValueWithPos<S> {
+        let improper = splice_tail(&mut items, dotspan, tail);
+        VValue::List(pk, improper.map(Box::new), items).at(span)
+    }
+
+    fn reader_macro(
+        &mut self,
+        rm: ReaderMacro,
+        value: VValueWithPos<S>,
+        span: Span
+    ) -> VValueWithPos<S> {
+        VValue::ReaderMacro(rm, Box::new(value)).at(span)
+    }
+
+    fn tagged(
+        &mut self,
+        tag: S,
+        value: VValueWithPos<S>,
+        span: Span
+    ) -> VValueWithPos<S> {
+        VValue::Tagged(tag, Box::new(value)).at(span)
+    }
+
+    fn feature_conditional(
+        &mut self,
+        positive: bool,
+        feature: VValueWithPos<S>,
+        form: VValueWithPos<S>,
+        span: Span
+    ) -> VValueWithPos<S> {
+        VValue::FeatureConditional(positive, Box::new((feature, form))).at(span)
+    }
+
+    fn labeled(&mut self, n: u32, value: VValueWithPos<S>, span: Span) -> VValueWithPos<S> {
+        VValue::Labeled(n, Box::new(value)).at(span)
+    }
+
+    fn label_ref(&mut self, n: u32, span: Span) -> VValueWithPos<S> {
+        VValue::LabelRef(n).at(span)
+    }
+}
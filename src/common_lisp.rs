@@ -0,0 +1,99 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The Common Lisp syntax of names, characters, strings and numbers,
+//! for writing with
+//! [COMMON_LISP_FORMAT](crate::settings::COMMON_LISP_FORMAT).
+
+//! The parts of package-qualified symbols are written with `|..|`
+//! unless they read back the same when unescaped (i.e. they have no
+//! lowercase characters, don't look like numbers etc.), inexact reals
+//! with the `d` exponent marker so that they are read back as double
+//! floats.
+
+use crate::parse::read_common_lisp_number;
+use crate::number::R5RSNumber;
+use std::fmt::Write as _;
+
+// Whether `s` reads back as the same symbol name without `|..|`.
+fn is_plain_symbol_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.chars().all(|c| c == '.')
+        && !s.starts_with('#')
+        && s.chars().all(|c| {
+            !(c.is_whitespace() || c.is_lowercase() || "()'\"`,;|\\:".contains(c))
+        })
+        && matches!(read_common_lisp_number(s), Ok(None))
+}
+
+pub(crate) fn write_symbol_name(out: &mut String, s: &str) {
+    if is_plain_symbol_name(s) {
+        out.push_str(s);
+    } else {
+        out.push('|');
+        for c in s.chars() {
+            if c == '|' || c == '\\' {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('|');
+    }
+}
+
+pub(crate) fn write_char(out: &mut String, c: char) {
+    out.push_str("#\\");
+    match c {
+        ' ' => out.push_str("Space"),
+        '\n' => out.push_str("Newline"),
+        '\t' => out.push_str("Tab"),
+        '\r' => out.push_str("Return"),
+        '\x08' => out.push_str("Backspace"),
+        '\x0C' => out.push_str("Page"),
+        '\x7F' => out.push_str("Rubout"),
+        '\0' => out.push_str("Nul"),
+        c => out.push(c)
+    }
+}
+
+pub(crate) fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+// Reals and integers (the parts of complex numbers, too); false for
+// other numbers and infinite or NaN reals, which have no syntax.
+pub(crate) fn write_real(out: &mut String, n: &R5RSNumber) -> bool {
+    match n {
+        R5RSNumber::Integer(i) => {
+            let _ = write!(out, "{}", i);
+        }
+        R5RSNumber::Rational(_) => {
+            let _ = write!(out, "{}", n);
+        }
+        R5RSNumber::Real(x) if x.is_finite() => {
+            // Debug always gives a fractional part or exponent
+            let s = format!("{:?}", x);
+            if s.contains('e') {
+                out.push_str(&s.replace('e', "d"));
+            } else {
+                out.push_str(&s);
+                out.push_str("d0");
+            }
+        }
+        _ => return false
+    }
+    true
+}
@@ -9,13 +9,13 @@
 
 //! Utilities for debugging the anysexpr library
 
-use crate::{value::{VValue, Atom, Parenkind, symbol, VValueWithPos}, number::R5RSNumber, pos::Pos};
+use crate::{value::{VValue, Atom, Parenkind, symbol, VValueWithPos}, number::R5RSNumber, pos::Span};
 
 fn listlike(
     pk: Parenkind,
     improper: bool,
     vals: Vec<VValueWithPos>,
-    pos: Pos
+    pos: Span
 ) -> VValueWithPos {
     let mut vals2 : Vec<VValueWithPos> = Vec::new();
     vals2.push(symbol(if improper {"improper-list"} else {"list"}).at(pos));
@@ -28,18 +28,19 @@ fn listlike(
 fn list2(
     symname: &str,
     a: Atom,
-    pos: Pos,
+    pos: Span,
 ) -> VValueWithPos {
-    let mut vals : Vec<VValueWithPos> = Vec::new();
-    vals.push(symbol(symname).at(pos));
-    vals.push(VValue::Atom(a).at(pos));
+    let vals : Vec<VValueWithPos> = vec![
+        symbol(symname).at(pos),
+        VValue::Atom(a).at(pos),
+    ];
     VValue::List(Parenkind::Round, None, vals).at(pos)
 }
 
 fn listn(
     symname: &str,
     atoms: impl Iterator<Item=Atom>,
-    pos: Pos
+    pos: Span
 ) -> VValueWithPos {
     let mut vals : Vec<VValueWithPos> = Vec::new();
     vals.push(symbol(symname).at(pos));
@@ -62,6 +63,7 @@ impl VValueWithPos {
         let VValueWithPos(val, pos) = self;
         match val {
             VValue::Atom(a) => match a {
+                Atom::Nil => symbol("nil").at(*pos),
                 Atom::Bool(b) =>
                     symbol(if *b { "true" } else { "false" }).at(*pos),
                 Atom::Char(c) => list2("integer->char", integer(*c as u32), *pos),
@@ -75,6 +77,13 @@ impl VValueWithPos {
                     listn("special", chars2atoms(<&str>::from(*kind).chars()),
                           *pos),
                 Atom::Number(_) => list2("number", a.clone(), *pos), //X ?
+                Atom::PackageSymbol(s, _) =>
+                    listn("package-symbol", chars2atoms(s.chars()), *pos),
+                Atom::BitVector(bits) =>
+                    listn("bit-vector", bits.iter().map(|b| integer(*b as u32)), *pos),
+                Atom::Decimal(s) => listn("decimal", chars2atoms(s.chars()), *pos),
+                Atom::Hexadecimal(s) => listn("hexadecimal", chars2atoms(s.chars()), *pos),
+                Atom::NumericVector(_) => list2("numeric-vector", a.clone(), *pos),
             }
             VValue::List(pk, improper, vals) => {
                 listlike(*pk,
@@ -82,6 +91,36 @@ impl VValueWithPos {
                          vals.iter().map(|v| v.dump()).collect(),
                          *pos)
             }
+            VValue::ReaderMacro(rm, v) => {
+                VValue::List(Parenkind::Round, None, vec![
+                    symbol("reader-macro").at(*pos),
+                    symbol(rm.name()).at(*pos),
+                    v.dump(),
+                ]).at(*pos)
+            }
+            VValue::Tagged(tag, v) => {
+                VValue::List(Parenkind::Round, None, vec![
+                    symbol("tagged").at(*pos),
+                    listn("symbol", chars2atoms(tag.chars()), *pos),
+                    v.dump(),
+                ]).at(*pos)
+            }
+            VValue::FeatureConditional(positive, b) => {
+                VValue::List(Parenkind::Round, None, vec![
+                    symbol(if *positive { "if-feature" } else { "unless-feature" })
+                        .at(*pos),
+                    b.0.dump(),
+                    b.1.dump(),
+                ]).at(*pos)
+            }
+            VValue::Labeled(n, v) => {
+                VValue::List(Parenkind::Round, None, vec![
+                    symbol("datum-label").at(*pos),
+                    VValue::Atom(integer(*n)).at(*pos),
+                    v.dump(),
+                ]).at(*pos)
+            }
+            VValue::LabelRef(n) => listn("datum-ref", std::iter::once(integer(*n)), *pos),
         }
     }
 }
@@ -0,0 +1,251 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading and writing [EDN](https://github.com/edn-format/edn).
+
+//! [EdnReader](EdnReader) reads via [parse](crate::parse) with
+//! [EDN_FORMAT](crate::settings::EDN_FORMAT) (which reads EDN's
+//! numbers, see [NumberSyntax::Edn](crate::settings::NumberSyntax::Edn)),
+//! rejecting the syntax that the format settings let through but EDN
+//! doesn't have (quote syntax, maps with an odd number of forms), and
+//! passes tagged elements like `#inst "..."` to the handler
+//! registered for the tag. Elements with tags that have no handler are
+//! kept as [VValue::Tagged](VValue::Tagged).
+
+//! [EDN_FORMAT](crate::settings::EDN_FORMAT)'s
+//! [write](crate::settings::AnysexprFormat::write) gives an error
+//! instead of output for values that EdnReader wouldn't read back.
+
+use crate::parse::{parse, Token, TokenWithPos, ParseError, ParseErrorWithPos};
+use crate::pos::Pos;
+use crate::read::{ReadError, ReadErrorWithPos, TokensRead};
+use crate::settings::{EDN_FORMAT, Modes, Settings};
+use crate::value::{Atom, Parenkind, ReaderMacro, VValue, VValueWithPos};
+use kstring::KString;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+const MODES: Modes = Modes {
+    allow_improper_lists: false,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+/// A handler for a tag: receives the element following the tag and
+/// returns the value to use in place of the tagged element.
+pub type TagHandler =
+    Box<dyn Fn(VValueWithPos) -> anyhow::Result<VValue> + Send + Sync>;
+
+/// Reads EDN, applying the registered [TagHandler](TagHandler)s.
+pub struct EdnReader {
+    handlers: HashMap<KString, TagHandler>,
+}
+
+impl Default for EdnReader {
+    fn default() -> Self {
+        EdnReader::new()
+    }
+}
+
+impl EdnReader {
+    /// A reader with handlers for the built-in tags `#inst` and
+    /// `#uuid`, which check the syntax of their strings and keep the
+    /// tagged element as it is.
+    pub fn new() -> EdnReader {
+        let mut reader = EdnReader { handlers: HashMap::new() };
+        reader.register_tag("inst", |v| {
+            check_string(&v, is_rfc3339_timestamp, "RFC 3339 timestamp")?;
+            Ok(VValue::Tagged(KString::from_static("inst"), Box::new(v)))
+        });
+        reader.register_tag("uuid", |v| {
+            check_string(&v, is_uuid, "UUID")?;
+            Ok(VValue::Tagged(KString::from_static("uuid"), Box::new(v)))
+        });
+        reader
+    }
+
+    /// Use `handler` for elements tagged with `#tag` (replacing an
+    /// earlier handler for the same tag, including the built-in
+    /// ones). Errors from the handler are reported as
+    /// [ReadError::InvalidTaggedElement](ReadError::InvalidTaggedElement).
+    pub fn register_tag(
+        &mut self,
+        tag: &str,
+        handler: impl Fn(VValueWithPos) -> anyhow::Result<VValue> + Send + Sync + 'static
+    ) {
+        self.handlers.insert(KString::from_ref(tag), Box::new(handler));
+    }
+
+    /// Read all of an input stream.
+    pub fn read_all(
+        &self,
+        charswithpos: impl IntoIterator<Item = anyhow::Result<(char, Pos)>>,
+    ) -> Result<Vec<VValueWithPos>, ReadErrorWithPos>
+    {
+        let settings = Settings {
+            format: &EDN_FORMAT,
+            modes: &MODES,
+        };
+        let depth_fuel = 500;
+        let mut ts = parse(charswithpos.into_iter(), &settings)
+            .map(|r| r.and_then(check_token));
+        // EDN_FORMAT has no dotted pairs
+        let (vals, _) = ts.read_all(None, depth_fuel, &MODES)?;
+        self.resolve_all(vals)
+    }
+
+    fn resolve_all(
+        &self,
+        vals: Vec<VValueWithPos>
+    ) -> Result<Vec<VValueWithPos>, ReadErrorWithPos> {
+        vals.into_iter().map(|v| self.resolve(v)).collect()
+    }
+
+    // Check maps and apply the tag handlers, inside out.
+    fn resolve(&self, v: VValueWithPos) -> Result<VValueWithPos, ReadErrorWithPos> {
+        let VValueWithPos(val, span) = v;
+        let val = match val {
+            VValue::List(pk, impr, vs) => {
+                if pk == Parenkind::Curly && vs.len() % 2 != 0 {
+                    return Err(ReadError::OddNumberOfMapForms.at(span))
+                }
+                VValue::List(pk, impr, self.resolve_all(vs)?)
+            }
+            VValue::ReaderMacro(rm, inner) => {
+                // The items of sets don't come in pairs
+                let VValueWithPos(inner, ispan) = *inner;
+                let inner = match inner {
+                    VValue::List(pk, impr, vs) =>
+                        VValue::List(pk, impr, self.resolve_all(vs)?),
+                    other => other
+                };
+                VValue::ReaderMacro(rm, Box::new(inner.at(ispan)))
+            }
+            VValue::Tagged(tag, inner) => {
+                let inner = self.resolve(*inner)?;
+                if let Some(handler) = self.handlers.get(&tag) {
+                    match handler(inner) {
+                        Ok(v) => v,
+                        Err(e) => return Err(
+                            ReadError::InvalidTaggedElement(Box::new((tag, e)))
+                                .at(span))
+                    }
+                } else {
+                    VValue::Tagged(tag, Box::new(inner))
+                }
+            }
+            // (EDN_FORMAT doesn't read feature conditionals or datum
+            // labels)
+            v @ (VValue::Atom(_) | VValue::FeatureConditional(..) | VValue::Labeled(..)
+                 | VValue::LabelRef(_)) => v
+        };
+        Ok(val.at(span))
+    }
+}
+
+// Reject the syntax that EDN_FORMAT lets through but EDN doesn't
+// have.
+fn check_token(t: TokenWithPos) -> Result<TokenWithPos, ParseErrorWithPos> {
+    let what = match &t.0 {
+        Token::Quote => "quote",
+        Token::Quasiquote => "quasiquote",
+        Token::Unquote | Token::UnquoteSplicing => "unquote",
+        Token::ReaderMacro(rm) if *rm != ReaderMacro::Set => rm.name(),
+        _ => return Ok(t)
+    };
+    Err(ParseErrorWithPos {
+        err: ParseError::NotSupportedByFormat(Box::new(what)),
+        span: t.1
+    })
+}
+
+fn check_string(
+    v: &VValueWithPos,
+    valid: fn(&str) -> bool,
+    what: &str
+) -> anyhow::Result<()> {
+    match &v.0 {
+        VValue::Atom(Atom::String(s)) if valid(s) => Ok(()),
+        VValue::Atom(Atom::String(s)) => anyhow::bail!("not a {}: {:?}", what, s.as_str()),
+        _ => anyhow::bail!("expecting a string, got: {}", v)
+    }
+}
+
+// The first `n` characters of `s` and the rest, if those are all
+// digits.
+fn digit_groups(s: &str, n: usize) -> Option<(&str, &str)> {
+    if s.len() >= n && s.as_bytes()[..n].iter().all(u8::is_ascii_digit) {
+        Some(s.split_at(n))
+    } else {
+        None
+    }
+}
+
+// The timestamps that Clojure accepts for `#inst`: `yyyy-mm-ddThh:mm:ss.fff`
+// with all parts after the year optional, and an optional `Z` or
+// `+hh:mm` offset.
+fn is_rfc3339_timestamp(s: &str) -> bool {
+    let in_range = |digits: &str, min: u32, max: u32| {
+        digits.parse::<u32>().is_ok_and(|n| min <= n && n <= max)
+    };
+    let Some((_year, mut rest)) = digit_groups(s, 4) else { return false };
+    // (separator, digits, min, max) of the optional parts
+    let parts = [('-', 1, 12), ('-', 1, 31), ('T', 0, 23), (':', 0, 59), (':', 0, 60)];
+    for (sep, min, max) in parts {
+        let Some(r) = rest.strip_prefix(sep) else { break };
+        let Some((digits, r)) = digit_groups(r, 2) else { return false };
+        if !in_range(digits, min, max) {
+            return false
+        }
+        rest = r;
+        if sep == ':' && max == 60 {
+            if let Some(r) = rest.strip_prefix('.') {
+                let n = r.bytes().take_while(u8::is_ascii_digit).count();
+                if n == 0 {
+                    return false
+                }
+                rest = &r[n..];
+            }
+        }
+    }
+    if rest.is_empty() || rest == "Z" {
+        return true
+    }
+    let Some(offset) = rest.strip_prefix(['+', '-']) else { return false };
+    match (digit_groups(offset, 2), offset.get(2..3), offset.get(3..)) {
+        (Some((hh, _)), Some(":"), Some(mm)) =>
+            mm.len() == 2 && in_range(hh, 0, 23) && in_range(mm, 0, 59),
+        _ => false
+    }
+}
+
+fn is_uuid(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12])
+        .all(|(g, n)| g.len() == n && g.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+
+pub(crate) fn write_char(out: &mut String, c: char) {
+    match c {
+        '\n' => out.push_str("\\newline"),
+        '\r' => out.push_str("\\return"),
+        ' ' => out.push_str("\\space"),
+        '\t' => out.push_str("\\tab"),
+        c if c.is_control() || c.is_whitespace() => {
+            let _ = write!(out, "\\u{:04x}", c as u32);
+        }
+        c => {
+            out.push('\\');
+            out.push(c)
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The Emacs Lisp syntax of symbols, characters and numbers, for
+//! writing with [ELISP_FORMAT](crate::settings::ELISP_FORMAT) (as
+//! read by Emacs' `read`, too).
+
+//! Symbols get `\` escapes where needed, characters are written with
+//! `?` syntax.
+
+use crate::parse::read_elisp_number;
+use crate::number::R5RSNumber;
+use std::fmt::Write as _;
+
+// Whether `c` needs a `\` in a symbol, anywhere or (`at_start`) as
+// the first character.
+fn needs_escape(c: char, at_start: bool) -> bool {
+    c.is_whitespace() || c.is_control() || "()[]\"';`,\\|".contains(c)
+        || (at_start && "#?:".contains(c))
+}
+
+// `s` must not be empty (the empty symbol has no syntax).
+pub(crate) fn write_symbol_name(out: &mut String, s: &str) {
+    // Would be read as a number or a dot otherwise
+    let escape_first = read_elisp_number(s).is_some() || s == ".";
+    for (i, c) in s.chars().enumerate() {
+        if needs_escape(c, i == 0) || (i == 0 && escape_first) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+pub(crate) fn write_char(out: &mut String, c: char) {
+    out.push('?');
+    match c {
+        '\x07' => out.push_str("\\a"),
+        '\x08' => out.push_str("\\b"),
+        '\t' => out.push_str("\\t"),
+        '\n' => out.push_str("\\n"),
+        '\x0B' => out.push_str("\\v"),
+        '\x0C' => out.push_str("\\f"),
+        '\r' => out.push_str("\\r"),
+        '\x1B' => out.push_str("\\e"),
+        ' ' => out.push_str("\\s"),
+        '\x7F' => out.push_str("\\d"),
+        c if c.is_control() => {
+            let _ = write!(out, "\\x{:x}", c as u32);
+        }
+        c => {
+            if "()[]\\;\"'`#?,.|".contains(c) {
+                out.push('\\');
+            }
+            out.push(c)
+        }
+    }
+}
+
+// Integers and reals; false for other numbers, which have no syntax.
+pub(crate) fn write_number(out: &mut String, n: &R5RSNumber) -> bool {
+    match n {
+        R5RSNumber::Integer(i) => {
+            let _ = write!(out, "{}", i);
+        }
+        R5RSNumber::Real(x) => {
+            if x.is_nan() {
+                out.push_str("0.0e+NaN")
+            } else if x.is_infinite() {
+                out.push_str(if *x > 0.0 { "1.0e+INF" } else { "-1.0e+INF" })
+            } else {
+                // Debug always gives a fractional part or exponent
+                let _ = write!(out, "{:?}", x);
+            }
+        }
+        _ => return false
+    }
+    true
+}
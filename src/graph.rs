@@ -0,0 +1,257 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Values with shared and cyclic structure.
+
+//! [VValue](VValue) trees keep datum labels (`#0=` and `#0#`) as
+//! written. [Graph::add_value](Graph::add_value) resolves them into
+//! a [Graph](Graph), where values refer to the values they contain
+//! via [NodeId](NodeId)s, thus a value can be contained in several
+//! places, or in itself. [Graph::to_value](Graph::to_value) turns
+//! a node back into a tree, with labels for the nodes that are
+//! reached more than once, for writing with any of the writers that
+//! support datum labels.
+
+//! A node is a whole list, not a pair as in Scheme, thus the tail of
+//! a list can only be shared if it was written as a value of its own,
+//! as in `(a . #0=(b))` (which stays an improper list).
+
+use crate::pos::Span;
+use crate::read::{ReadError, ReadErrorWithPos};
+use crate::value::{Atom, Parenkind, ReaderMacro, VValue, VValueWithPos};
+use kstring::KString;
+use std::collections::HashMap;
+
+/// The index of a node in its [Graph](Graph).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u32);
+
+/// A value in a [Graph](Graph): the same as [VValue](VValue), except
+/// that contained values are referred to by id, and there are no
+/// labels (they have been resolved).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node<S = KString> {
+    Atom(Atom<S>),
+    /// .1 is the span of the Dot, if any; the last item is the tail
+    /// then
+    List(Parenkind, Option<Box<Span>>, Vec<NodeId>),
+    ReaderMacro(ReaderMacro, NodeId),
+    Tagged(S, NodeId),
+    /// The feature expression and the form
+    FeatureConditional(bool, NodeId, NodeId),
+}
+
+/// An arena of [Node](Node)s with their spans.
+#[derive(Debug, Clone)]
+pub struct Graph<S = KString> {
+    nodes: Vec<(Node<S>, Span)>,
+}
+
+impl<S> Default for Graph<S> {
+    fn default() -> Self {
+        Graph { nodes: Vec::new() }
+    }
+}
+
+impl<S> Graph<S> {
+    pub fn new() -> Graph<S> {
+        Graph::default()
+    }
+
+    /// Build a graph from the values read from a file or string,
+    /// returning the ids of the values in the same order.
+    pub fn from_values(
+        vals: impl IntoIterator<Item = VValueWithPos<S>>
+    ) -> Result<(Graph<S>, Vec<NodeId>), ReadErrorWithPos> {
+        let mut graph = Graph::new();
+        let ids = vals.into_iter().map(|v| graph.add_value(v))
+            .collect::<Result<_, _>>()?;
+        Ok((graph, ids))
+    }
+
+    /// Add `val` to the graph, resolving its datum labels. Labels are
+    /// scoped to `val`, as when reading (`#0#` in one top-level datum
+    /// does not refer to `#0=` in another). A reference to a label
+    /// that has not been defined yet (to the left of it, or
+    /// enclosing it), or defining a label twice, is an error; the
+    /// graph is unchanged then.
+    pub fn add_value(&mut self, val: VValueWithPos<S>) -> Result<NodeId, ReadErrorWithPos> {
+        let len = self.nodes.len();
+        let r = Resolver { graph: self, labels: HashMap::new() }.add(val);
+        if r.is_err() {
+            self.nodes.truncate(len);
+        }
+        r
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node<S> {
+        &self.nodes[id.0 as usize].0
+    }
+
+    /// Where the value was read from (the value itself, not its
+    /// labels).
+    pub fn span(&self, id: NodeId) -> Span {
+        self.nodes[id.0 as usize].1
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn push(&mut self, node: Node<S>, span: Span) -> NodeId {
+        let id = NodeId(u32::try_from(self.nodes.len()).expect("less than 2^32 nodes"));
+        self.nodes.push((node, span));
+        id
+    }
+
+    // Which of the nodes reachable from `root` are reached more than
+    // once, i.e. are shared or part of a cycle.
+    fn reached_repeatedly(&self, root: NodeId) -> Vec<bool> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut repeated = vec![false; self.nodes.len()];
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            let i = id.0 as usize;
+            if seen[i] {
+                repeated[i] = true;
+                continue
+            }
+            seen[i] = true;
+            match &self.nodes[i].0 {
+                Node::Atom(_) => (),
+                Node::List(_, _, ids) => stack.extend(ids.iter().rev()),
+                Node::ReaderMacro(_, id) | Node::Tagged(_, id) => stack.push(*id),
+                Node::FeatureConditional(_, feature, form) => {
+                    stack.push(*form);
+                    stack.push(*feature);
+                }
+            }
+        }
+        repeated
+    }
+}
+
+impl<S: Clone> Graph<S> {
+    /// The tree for the value at `root`. Nodes reached more than once
+    /// are written out the first time with a `#n=` label, and as
+    /// `#n#` afterwards, numbered from 0 in the order they appear.
+    /// Labels are chosen per call, sharing between the values
+    /// returned from different calls is not preserved.
+    pub fn to_value(&self, root: NodeId) -> VValueWithPos<S> {
+        let mut unfolder = Unfolder {
+            graph: self,
+            repeated: self.reached_repeatedly(root),
+            labels: vec![None; self.nodes.len()],
+            next_label: 0,
+        };
+        unfolder.unfold(root)
+    }
+}
+
+struct Resolver<'g, S> {
+    graph: &'g mut Graph<S>,
+    labels: HashMap<u32, NodeId>,
+}
+
+impl<'g, S> Resolver<'g, S> {
+    fn add(&mut self, val: VValueWithPos<S>) -> Result<NodeId, ReadErrorWithPos> {
+        let VValueWithPos(mut v, mut span) = val;
+        // `#0=#1=x` gives x both labels
+        let mut names = Vec::new();
+        while let VValue::Labeled(n, inner) = v {
+            if self.labels.contains_key(&n) || names.contains(&n) {
+                return Err(ReadError::DuplicateDatumLabel(n).at(span))
+            }
+            names.push(n);
+            VValueWithPos(v, span) = *inner;
+        }
+        let id = match v {
+            VValue::LabelRef(n) => *self.labels.get(&n).ok_or_else(
+                || ReadError::UndefinedDatumLabel(n).at(span))?,
+            v => {
+                // The labels must be known while reading the
+                // contained values, for cycles; the node is filled
+                // in afterwards
+                let id = self.graph.push(Node::Atom(Atom::Nil), span);
+                for n in names.drain(..) {
+                    self.labels.insert(n, id);
+                }
+                let node = self.node(v)?;
+                self.graph.nodes[id.0 as usize].0 = node;
+                id
+            }
+        };
+        for n in names {
+            self.labels.insert(n, id);
+        }
+        Ok(id)
+    }
+
+    fn node(&mut self, v: VValue<S>) -> Result<Node<S>, ReadErrorWithPos> {
+        Ok(match v {
+            VValue::Atom(a) => Node::Atom(a),
+            VValue::List(pk, dot, vs) => Node::List(
+                pk, dot, vs.into_iter().map(|v| self.add(v)).collect::<Result<_, _>>()?),
+            VValue::ReaderMacro(rm, v) => Node::ReaderMacro(rm, self.add(*v)?),
+            VValue::Tagged(tag, v) => Node::Tagged(tag, self.add(*v)?),
+            VValue::FeatureConditional(positive, b) => {
+                let (feature, form) = *b;
+                Node::FeatureConditional(positive, self.add(feature)?, self.add(form)?)
+            }
+            VValue::Labeled(..) | VValue::LabelRef(_) =>
+                panic!("handled by add")
+        })
+    }
+}
+
+struct Unfolder<'g, S> {
+    graph: &'g Graph<S>,
+    repeated: Vec<bool>,
+    labels: Vec<Option<u32>>,
+    next_label: u32,
+}
+
+impl<'g, S: Clone> Unfolder<'g, S> {
+    fn unfold(&mut self, id: NodeId) -> VValueWithPos<S> {
+        let i = id.0 as usize;
+        let span = self.graph.nodes[i].1;
+        if !self.repeated[i] {
+            return VValueWithPos(self.unfold_node(i), span)
+        }
+        if let Some(n) = self.labels[i] {
+            return VValueWithPos(VValue::LabelRef(n), span)
+        }
+        let n = self.next_label;
+        self.next_label += 1;
+        self.labels[i] = Some(n);
+        let v = VValueWithPos(self.unfold_node(i), span);
+        VValueWithPos(VValue::Labeled(n, Box::new(v)), span)
+    }
+
+    fn unfold_node(&mut self, i: usize) -> VValue<S> {
+        match &self.graph.nodes[i].0 {
+            Node::Atom(a) => VValue::Atom(a.clone()),
+            Node::List(pk, dot, ids) =>
+                VValue::List(*pk, dot.clone(), ids.iter().map(|id| self.unfold(*id)).collect()),
+            Node::ReaderMacro(rm, id) =>
+                VValue::ReaderMacro(*rm, Box::new(self.unfold(*id))),
+            Node::Tagged(tag, id) =>
+                VValue::Tagged(tag.clone(), Box::new(self.unfold(*id))),
+            Node::FeatureConditional(positive, feature, form) => {
+                let feature = self.unfold(*feature);
+                let form = self.unfold(*form);
+                VValue::FeatureConditional(*positive, Box::new((feature, form)))
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Writing trees that are generated on demand while printing.
+
+//! A [LazyValue](LazyValue) gives its [Node](Node) only when the
+//! writer gets to it, and the items of lists are iterators, thus
+//! huge results don't need to be built as a [VValue](VValue)
+//! first. [write_lazy](write_lazy) (or
+//! [AnysexprFormat::write_lazy](crate::settings::AnysexprFormat::write_lazy))
+//! writes the tokens to the output stream as they are generated;
+//! the memory used is bounded by the nesting depth (one items
+//! iterator per open list), not the size of the tree. Pass a
+//! buffered output stream, as the tokens are written one by one.
+
+use crate::parse::Token;
+use crate::tokens::{tokens, TokenWriter};
+use crate::value::{Atom, Parenkind, VValue};
+use crate::write::WriteError;
+use std::io::Write;
+use std::ops::Deref;
+
+/// A value whose contents are generated when it's written. `S` is
+/// the string type in the atoms, see [value](crate::value).
+pub trait LazyValue<S>: Sized {
+    /// The iterator over the items of lists.
+    type Items: Iterator<Item = Self>;
+
+    /// Generate the node at the top of this value.
+    fn node(self) -> Node<S, Self>;
+}
+
+/// What a [LazyValue](LazyValue) consists of.
+pub enum Node<S, T: LazyValue<S>> {
+    Atom(Atom<S>),
+    /// A proper list of the given kind.
+    List(Parenkind, T::Items),
+    /// A `(..)` list of the items, followed by a `.` and the given
+    /// tail (which is only generated after the items). There must be
+    /// at least one item.
+    ImproperList(T::Items, T),
+    /// A tree that's already in memory, written as with
+    /// [AnysexprFormat::write](crate::settings::AnysexprFormat::write).
+    Value(VValue<S>),
+}
+
+struct Frame<I, T> {
+    pk: Parenkind,
+    items: I,
+    tail: Option<T>,
+    empty: bool,
+}
+
+/// Write `v` via `writer`, generating its nodes while writing. On
+/// errors, the tokens up to the error have been written.
+pub fn write_lazy<S, T, W>(writer: &mut TokenWriter<W>, v: T) -> Result<(), WriteError>
+where S: Deref<Target = str> + Clone,
+      T: LazyValue<S>,
+      W: Write
+{
+    let mut stack: Vec<Frame<T::Items, T>> = Vec::new();
+    let mut next = Some(v);
+    loop {
+        if let Some(v) = next.take() {
+            match v.node() {
+                Node::Atom(a) => writer.write(&Token::Atom(a))?,
+                Node::List(pk, items) => {
+                    writer.write(&Token::<S>::Open(pk))?;
+                    stack.push(Frame { pk, items, tail: None, empty: true });
+                }
+                Node::ImproperList(items, tail) => {
+                    let pk = Parenkind::Round;
+                    writer.write(&Token::<S>::Open(pk))?;
+                    stack.push(Frame { pk, items, tail: Some(tail), empty: true });
+                }
+                Node::Value(v) => for token in tokens(&v, false) {
+                    writer.write(&token)?;
+                }
+            }
+        }
+        let Some(frame) = stack.last_mut() else {
+            return Ok(())
+        };
+        if let Some(item) = frame.items.next() {
+            frame.empty = false;
+            next = Some(item);
+        } else if let Some(tail) = frame.tail.take() {
+            if frame.empty {
+                return Err(writer.not_representable("improper list without items"))
+            }
+            writer.write(&Token::<S>::Dot)?;
+            next = Some(tail);
+        } else {
+            let pk = frame.pk;
+            stack.pop();
+            writer.write(&Token::<S>::Close(pk))?;
+        }
+    }
+}
@@ -15,19 +15,24 @@
 //! * Good error reporting (precise location information and
 //!   messages).
 //! 
-//! * (Future) Make the data constructors for [anysexpr::read](crate::read)
-//!   parametrizable (generic), e.g. like in the `sexpr_parser` crate.
+//! * Make the data constructors for [anysexpr::read](crate::read)
+//!   parametrizable (generic), via [TreeBuilder](crate::builder::TreeBuilder).
 //! 
 //! * Streaming: allow to read from and print to file handles lazily,
 //!   for use e.g. in communications. This currently works by using
-//!   [anysexpr::parse](crate::parse) directly for input, or creating
-//!   tokens to print via a custom loop for output. Future: more
-//!   possibilities, e.g. turn a tree into a token stream, or
-//!   parameterize with a tree that's generated on demand while
-//!   printing.
+//!   [anysexpr::parse](crate::parse) directly for input (or
+//!   [anysexpr::push](crate::push) for input that arrives in chunks,
+//!   without blocking, or `anysexpr::async_io` with the `async`
+//!   feature), or writing tokens via
+//!   [TokenWriter](crate::tokens::TokenWriter) for output;
+//!   [tokens](crate::tokens::tokens) turns a tree into a token
+//!   stream, and [lazy](crate::lazy) writes trees that are generated
+//!   on demand while printing.
 //! 
-//! * (Future) Support various s-expression variants (R*RS, Guile, Clojure,
-//!   Common Lisp, ..) via runtime (and compile-time?) settings.
+//! * Support various s-expression variants (currently Gambit, R7RS,
+//!   Guile, Clojure, EDN, Common Lisp, Emacs Lisp, OCaml's sexplib,
+//!   SMT-LIB and WAT; future: ..)
+//!   via runtime (and compile-time?) settings, see [settings](settings).
 //! 
 //! * (Perhaps) be usable on microcontrollers (small code, no-std?).
 //! 
@@ -35,12 +40,28 @@
 //! not currently being followed, help in that area is as welcome as in
 //! other areas.
 
+#[cfg(feature = "async")]
+pub mod async_io;
 pub mod buffered_chars; // although this is a hack
+pub mod builder;
+mod common_lisp;
 pub mod context;
+pub mod edn;
+mod elisp;
+pub mod graph;
+pub mod lazy;
 pub mod number;
 pub mod parse;
 pub mod pos;
+pub mod pretty;
+pub mod push;
 pub mod read;
 pub mod settings;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod sexplib;
+pub mod symbol;
+pub mod tokens;
 pub mod value;
+pub mod write;
 pub mod debug;
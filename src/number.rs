@@ -11,8 +11,47 @@
 //! (numeric tower).
 
 use std::ops::{Mul, Add, Neg, Rem, Div};
+use std::fmt::Write;
 
-use num::BigInt;
+use num::{BigInt, BigRational, ToPrimitive};
+
+/// The radixes that number syntax supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    pub fn from_u32(radix: u32) -> Option<Radix> {
+        match radix {
+            2 => Some(Radix::Binary),
+            8 => Some(Radix::Octal),
+            10 => Some(Radix::Decimal),
+            16 => Some(Radix::Hexadecimal),
+            _ => None
+        }
+    }
+    pub fn to_u32(self) -> u32 {
+        match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hexadecimal => 16,
+        }
+    }
+    /// The prefix that specifies the radix in number syntax, "#x" etc.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Radix::Binary => "#b",
+            Radix::Octal => "#o",
+            Radix::Decimal => "#d",
+            Radix::Hexadecimal => "#x",
+        }
+    }
+}
 
 // XXX how does PartialOrd work here? OK?
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -31,6 +70,60 @@ impl std::fmt::Display for Integer {
     }
 }
 
+impl Integer {
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Integer::Small(i) => *i < 0,
+            Integer::Big(b) => b.sign() == num::bigint::Sign::Minus,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Integer::Small(i) => *i as f64,
+            // Can't fail for BigInt (gives infinity when too large)
+            Integer::Big(b) => b.to_f64().unwrap(),
+        }
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        match self {
+            Integer::Small(i) => (*i).into(),
+            Integer::Big(b) => (**b).clone(),
+        }
+    }
+
+    /// Write self in the given radix (without prefix).
+    pub fn fmt_radix(&self, radix: Radix, f: &mut std::fmt::Formatter<'_>)
+                     -> Result<(), std::fmt::Error> {
+        match self {
+            Integer::Small(i) => {
+                if *i < 0 {
+                    f.write_char('-')?;
+                }
+                let u = i.unsigned_abs();
+                match radix {
+                    Radix::Binary => f.write_fmt(format_args!("{:b}", u)),
+                    Radix::Octal => f.write_fmt(format_args!("{:o}", u)),
+                    Radix::Decimal => f.write_fmt(format_args!("{}", u)),
+                    Radix::Hexadecimal => f.write_fmt(format_args!("{:x}", u)),
+                }
+            }
+            Integer::Big(b) => f.write_str(&b.to_str_radix(radix.to_u32())),
+        }
+    }
+}
+
+impl From<BigInt> for Integer {
+    fn from(n: BigInt) -> Self {
+        if let Ok(i) = (&n).try_into() {
+            Integer::Small(i)
+        } else {
+            Integer::Big(Box::new(n))
+        }
+    }
+}
+
 impl From<i64> for Integer {
     fn from(n: i64) -> Self { Integer::Small(n) }
 }
@@ -229,27 +322,196 @@ fn gcd(a: &Integer, b: &Integer) -> Integer {
 impl Rational {
     pub fn new(n: Integer, d: Integer) -> Rational {
         let f = gcd(&n, &d);
-        if &f == &1.into() {
+        if f == 1.into() {
             Rational(n, d)
         } else {
             Rational(&n / &f, &d / &f)
         }
     }
+
+    pub fn to_f64(&self) -> f64 {
+        match (&self.0, &self.1) {
+            (Integer::Small(n), Integer::Small(d))
+                if n.unsigned_abs() < 1 << 53 && d.unsigned_abs() < 1 << 53 =>
+                // Both exactly representable, thus correctly rounded
+                *n as f64 / *d as f64,
+            _ => BigRational::new(self.0.to_bigint(), self.1.to_bigint())
+                .to_f64().unwrap_or(f64::NAN),
+        }
+    }
 }
 
-/// TODO: complex numbers, inexact reals
 #[derive(Debug, Clone, PartialEq)]
 pub enum R5RSNumber {
-    // Complex(Box<R5RSNumber>, Box<R5RSNumber>),
-    // Real(f64),
+    /// Rectangular complex: real and imaginary parts (both of which
+    /// are never complex themselves)
+    Complex(Box<R5RSNumber>, Box<R5RSNumber>),
+    /// Polar complex as written with `@`: magnitude and angle. Kept
+    /// as such so that it prints back the same way.
+    Polar(Box<R5RSNumber>, Box<R5RSNumber>),
+    /// Inexact real
+    Real(f64),
     Rational(Box<Rational>),
     Integer(Integer)
 }
 
+impl R5RSNumber {
+    /// Whether self is the exact integer `i`.
+    pub fn is_exact_integer(&self, i: i64) -> bool {
+        *self == R5RSNumber::Integer(Integer::Small(i))
+    }
+
+    /// Make a number from the exact fraction `n/d`, giving an Integer
+    /// if the (reduced) denominator is 1.
+    pub fn from_fraction(n: Integer, d: Integer) -> R5RSNumber {
+        let r = Rational::new(n, d);
+        if r.1 == 1.into() {
+            R5RSNumber::Integer(r.0)
+        } else {
+            R5RSNumber::Rational(Box::new(r))
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        match self {
+            R5RSNumber::Complex(re, im) => re.is_exact() && im.is_exact(),
+            R5RSNumber::Polar(mag, angle) => mag.is_exact() && angle.is_exact(),
+            R5RSNumber::Real(_) => false,
+            R5RSNumber::Rational(_) => true,
+            R5RSNumber::Integer(_) => true,
+        }
+    }
+
+    /// Convert to an inexact number (exact parts of complex numbers
+    /// are converted, too).
+    pub fn to_inexact(self) -> R5RSNumber {
+        match self {
+            R5RSNumber::Complex(re, im) =>
+                R5RSNumber::Complex(Box::new(re.to_inexact()),
+                                    Box::new(im.to_inexact())),
+            R5RSNumber::Polar(mag, angle) =>
+                R5RSNumber::Polar(Box::new(mag.to_inexact()),
+                                  Box::new(angle.to_inexact())),
+            R5RSNumber::Real(_) => self,
+            R5RSNumber::Rational(r) => R5RSNumber::Real(r.to_f64()),
+            R5RSNumber::Integer(i) => R5RSNumber::Real(i.to_f64()),
+        }
+    }
+
+    /// Write self in the given radix, with the radix prefix if it's
+    /// not decimal. Inexact numbers can only be written in decimal,
+    /// thus numbers with inexact parts are written in decimal.
+    pub fn fmt_radix(&self, radix: Radix, f: &mut std::fmt::Formatter<'_>)
+                     -> Result<(), std::fmt::Error> {
+        if radix == Radix::Decimal || !self.is_exact() {
+            return std::fmt::Display::fmt(self, f)
+        }
+        f.write_str(radix.prefix())?;
+        self.fmt_digits(radix, f)
+    }
+
+    // fmt_radix without the prefix
+    fn fmt_digits(&self, radix: Radix, f: &mut std::fmt::Formatter<'_>)
+                  -> Result<(), std::fmt::Error> {
+        match self {
+            R5RSNumber::Complex(re, im) => {
+                if !re.is_exact_integer(0) {
+                    re.fmt_digits(radix, f)?;
+                }
+                if im.is_exact_integer(1) {
+                    f.write_str("+i")
+                } else if im.is_exact_integer(-1) {
+                    f.write_str("-i")
+                } else {
+                    if !im.is_negative() {
+                        f.write_char('+')?;
+                    }
+                    im.fmt_digits(radix, f)?;
+                    f.write_char('i')
+                }
+            }
+            R5RSNumber::Polar(mag, angle) => {
+                mag.fmt_digits(radix, f)?;
+                f.write_char('@')?;
+                angle.fmt_digits(radix, f)
+            }
+            R5RSNumber::Real(x) => fmt_real(*x, f),
+            R5RSNumber::Rational(n) => {
+                n.0.fmt_radix(radix, f)?;
+                f.write_char('/')?;
+                n.1.fmt_radix(radix, f)
+            }
+            R5RSNumber::Integer(n) => n.fmt_radix(radix, f),
+        }
+    }
+
+    // Only meaningful for non-complex numbers.
+    fn is_negative(&self) -> bool {
+        match self {
+            R5RSNumber::Complex(_, _) | R5RSNumber::Polar(_, _) => false,
+            R5RSNumber::Real(x) => x.is_sign_negative(),
+            R5RSNumber::Rational(r) => r.0.is_negative(),
+            R5RSNumber::Integer(i) => i.is_negative(),
+        }
+    }
+}
+
+impl Neg for R5RSNumber {
+    type Output = R5RSNumber;
+    fn neg(self) -> <Self as Neg>::Output {
+        match self {
+            R5RSNumber::Complex(re, im) =>
+                R5RSNumber::Complex(Box::new(-*re), Box::new(-*im)),
+            R5RSNumber::Polar(mag, angle) =>
+                R5RSNumber::Polar(Box::new(-*mag), angle),
+            R5RSNumber::Real(x) => R5RSNumber::Real(-x),
+            R5RSNumber::Rational(n) => R5RSNumber::Rational(Box::new(-*n)),
+            R5RSNumber::Integer(n) => R5RSNumber::Integer(-n),
+        }
+    }
+}
+
+/// Write an inexact real in Scheme syntax: the shortest representation
+/// that reads back as the same number, always with a `.` or an
+/// exponent, and `+inf.0`, `-inf.0`, `+nan.0` for the special values.
+pub fn fmt_real(x: f64, f: &mut std::fmt::Formatter<'_>)
+                -> Result<(), std::fmt::Error> {
+    if x.is_nan() {
+        f.write_str("+nan.0")
+    } else if x.is_infinite() {
+        f.write_str(if x > 0. { "+inf.0" } else { "-inf.0" })
+    } else {
+        // Debug gives "1.0", "1e16", "1e-7"
+        f.write_fmt(format_args!("{:?}", x))
+    }
+}
+
 impl std::fmt::Display for R5RSNumber {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
            -> Result<(), std::fmt::Error> {
         match self {
+            R5RSNumber::Complex(re, im) => {
+                // Like Gambit: omit an exact zero real part, and a 1
+                // as the imaginary part
+                if !re.is_exact_integer(0) {
+                    re.fmt(f)?;
+                }
+                if im.is_exact_integer(1) {
+                    f.write_str("+i")
+                } else if im.is_exact_integer(-1) {
+                    f.write_str("-i")
+                } else {
+                    let ims = im.to_string();
+                    if !ims.starts_with(['+', '-']) {
+                        f.write_char('+')?;
+                    }
+                    f.write_str(&ims)?;
+                    f.write_char('i')
+                }
+            }
+            R5RSNumber::Polar(mag, angle) =>
+                f.write_fmt(format_args!("{}@{}", mag, angle)),
+            R5RSNumber::Real(x) => fmt_real(*x, f),
             R5RSNumber::Rational(n) =>
                 f.write_fmt(format_args!("{}/{}", n.0, n.1)),
             R5RSNumber::Integer(n) => f.write_fmt(format_args!("{}", n)),
@@ -257,3 +519,225 @@ impl std::fmt::Display for R5RSNumber {
     }
 }
 
+
+/// The element type of a homogeneous numeric vector: `#u8(..)` is
+/// R7RS's bytevector, the others (and `#u8(..)` again) are from
+/// SRFI 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericVectorKind {
+    U8, S8, U16, S16, U32, S32, U64, S64, F32, F64
+}
+
+impl NumericVectorKind {
+    /// The name as written after the `#`.
+    pub fn name(self) -> &'static str {
+        match self {
+            NumericVectorKind::U8 => "u8",
+            NumericVectorKind::S8 => "s8",
+            NumericVectorKind::U16 => "u16",
+            NumericVectorKind::S16 => "s16",
+            NumericVectorKind::U32 => "u32",
+            NumericVectorKind::S32 => "s32",
+            NumericVectorKind::U64 => "u64",
+            NumericVectorKind::S64 => "s64",
+            NumericVectorKind::F32 => "f32",
+            NumericVectorKind::F64 => "f64",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<NumericVectorKind> {
+        match name {
+            "u8" => Some(NumericVectorKind::U8),
+            "s8" => Some(NumericVectorKind::S8),
+            "u16" => Some(NumericVectorKind::U16),
+            "s16" => Some(NumericVectorKind::S16),
+            "u32" => Some(NumericVectorKind::U32),
+            "s32" => Some(NumericVectorKind::S32),
+            "u64" => Some(NumericVectorKind::U64),
+            "s64" => Some(NumericVectorKind::S64),
+            "f32" => Some(NumericVectorKind::F32),
+            "f64" => Some(NumericVectorKind::F64),
+            _ => None
+        }
+    }
+
+    /// Whether the elements are floating point numbers.
+    pub fn is_float(self) -> bool {
+        matches!(self, NumericVectorKind::F32 | NumericVectorKind::F64)
+    }
+}
+
+/// A homogeneous numeric vector, `#u8(1 2 255)`, `#f64(1.5)` etc.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumericVector {
+    U8(Vec<u8>),
+    S8(Vec<i8>),
+    U16(Vec<u16>),
+    S16(Vec<i16>),
+    U32(Vec<u32>),
+    S32(Vec<i32>),
+    U64(Vec<u64>),
+    S64(Vec<i64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+impl NumericVector {
+    pub fn new(kind: NumericVectorKind) -> NumericVector {
+        match kind {
+            NumericVectorKind::U8 => NumericVector::U8(Vec::new()),
+            NumericVectorKind::S8 => NumericVector::S8(Vec::new()),
+            NumericVectorKind::U16 => NumericVector::U16(Vec::new()),
+            NumericVectorKind::S16 => NumericVector::S16(Vec::new()),
+            NumericVectorKind::U32 => NumericVector::U32(Vec::new()),
+            NumericVectorKind::S32 => NumericVector::S32(Vec::new()),
+            NumericVectorKind::U64 => NumericVector::U64(Vec::new()),
+            NumericVectorKind::S64 => NumericVector::S64(Vec::new()),
+            NumericVectorKind::F32 => NumericVector::F32(Vec::new()),
+            NumericVectorKind::F64 => NumericVector::F64(Vec::new()),
+        }
+    }
+
+    pub fn kind(&self) -> NumericVectorKind {
+        match self {
+            NumericVector::U8(_) => NumericVectorKind::U8,
+            NumericVector::S8(_) => NumericVectorKind::S8,
+            NumericVector::U16(_) => NumericVectorKind::U16,
+            NumericVector::S16(_) => NumericVectorKind::S16,
+            NumericVector::U32(_) => NumericVectorKind::U32,
+            NumericVector::S32(_) => NumericVectorKind::S32,
+            NumericVector::U64(_) => NumericVectorKind::U64,
+            NumericVector::S64(_) => NumericVectorKind::S64,
+            NumericVector::F32(_) => NumericVectorKind::F32,
+            NumericVector::F64(_) => NumericVectorKind::F64,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            NumericVector::U8(v) => v.len(),
+            NumericVector::S8(v) => v.len(),
+            NumericVector::U16(v) => v.len(),
+            NumericVector::S16(v) => v.len(),
+            NumericVector::U32(v) => v.len(),
+            NumericVector::S32(v) => v.len(),
+            NumericVector::U64(v) => v.len(),
+            NumericVector::S64(v) => v.len(),
+            NumericVector::F32(v) => v.len(),
+            NumericVector::F64(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append `n`. Integer vectors take exact integers in their
+    /// range, float vectors take any real number (exact ones are
+    /// converted) that is in their range. Returns false if `n` is
+    /// not taken.
+    pub fn push(&mut self, n: &R5RSNumber) -> bool {
+        let int = |n: &R5RSNumber| match n {
+            R5RSNumber::Integer(Integer::Small(i)) => Some(i128::from(*i)),
+            R5RSNumber::Integer(Integer::Big(b)) => b.to_i128(),
+            _ => None
+        };
+        let float = |n: &R5RSNumber| match n {
+            R5RSNumber::Real(x) => Some(*x),
+            R5RSNumber::Rational(r) => Some(r.to_f64()),
+            R5RSNumber::Integer(i) => Some(i.to_f64()),
+            _ => None
+        };
+        // Infinities and NaN are taken as written, but not from
+        // overflow
+        let fits = |converted_is_finite: bool, x: f64|
+            converted_is_finite || (!x.is_finite() && matches!(n, R5RSNumber::Real(_)));
+        fn put<T: TryFrom<i128>>(v: &mut Vec<T>, i: Option<i128>) -> bool {
+            match i.map(T::try_from) {
+                Some(Ok(x)) => {
+                    v.push(x);
+                    true
+                }
+                _ => false
+            }
+        }
+        match self {
+            NumericVector::U8(v) => put(v, int(n)),
+            NumericVector::S8(v) => put(v, int(n)),
+            NumericVector::U16(v) => put(v, int(n)),
+            NumericVector::S16(v) => put(v, int(n)),
+            NumericVector::U32(v) => put(v, int(n)),
+            NumericVector::S32(v) => put(v, int(n)),
+            NumericVector::U64(v) => put(v, int(n)),
+            NumericVector::S64(v) => put(v, int(n)),
+            NumericVector::F32(v) => match float(n) {
+                Some(x) if fits((x as f32).is_finite(), x) => {
+                    v.push(x as f32);
+                    true
+                }
+                _ => false
+            }
+            NumericVector::F64(v) => match float(n) {
+                Some(x) if fits(x.is_finite(), x) => {
+                    v.push(x);
+                    true
+                }
+                _ => false
+            }
+        }
+    }
+}
+
+fn fmt_elements<T: std::fmt::Display>(
+    f: &mut std::fmt::Formatter<'_>,
+    v: &[T]
+) -> Result<(), std::fmt::Error> {
+    for (i, x) in v.iter().enumerate() {
+        if i > 0 {
+            f.write_char(' ')?;
+        }
+        x.fmt(f)?;
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for NumericVector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
+           -> Result<(), std::fmt::Error> {
+        f.write_char('#')?;
+        f.write_str(self.kind().name())?;
+        f.write_char('(')?;
+        match self {
+            NumericVector::U8(v) => fmt_elements(f, v)?,
+            NumericVector::S8(v) => fmt_elements(f, v)?,
+            NumericVector::U16(v) => fmt_elements(f, v)?,
+            NumericVector::S16(v) => fmt_elements(f, v)?,
+            NumericVector::U32(v) => fmt_elements(f, v)?,
+            NumericVector::S32(v) => fmt_elements(f, v)?,
+            NumericVector::U64(v) => fmt_elements(f, v)?,
+            NumericVector::S64(v) => fmt_elements(f, v)?,
+            NumericVector::F32(v) => {
+                for (i, x) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    if x.is_finite() {
+                        // The shortest representation as f32
+                        f.write_fmt(format_args!("{:?}", x))?;
+                    } else {
+                        fmt_real(f64::from(*x), f)?;
+                    }
+                }
+            }
+            NumericVector::F64(v) => {
+                for (i, x) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    fmt_real(*x, f)?;
+                }
+            }
+        }
+        f.write_char(')')
+    }
+}
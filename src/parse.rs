@@ -15,23 +15,27 @@
 //! [`Token::Close`](Token::Close). See [read](crate::read) if
 //! interested in trees rather than atoms / tokens.
 
-use crate::pos::Pos;
-use crate::value::{Atom, Parenkind, SpecialKind};
-use crate::number::{R5RSNumber, Integer, Rational};
-use crate::settings::Settings;
-use kstring::KString;
+use crate::pos::{Pos, Span};
+use crate::buffered_chars::str_chars;
+use crate::value::{Atom, Parenkind, ReaderMacro, SpecialKind};
+use crate::number::{Integer, NumericVector, NumericVectorKind, R5RSNumber};
+use crate::settings::{NumberSyntax, Settings};
+use kstring::{KString, KStringCow};
+use num::BigInt;
 use thiserror::Error;
-use genawaiter::rc::Gen;
+use std::collections::VecDeque;
+use std::iter::{Fuse, FusedIterator};
 use std::fmt::{Write, Display};
+use std::ops::Deref;
 use std::convert::TryFrom;
 
-fn take_while_and_rest<'s>(
-    s: &'s str, pred: impl Fn(char) -> bool
-) -> (&'s str, &'s str) {
+fn take_while_and_rest(
+    s: &str, pred: impl Fn(char) -> bool
+) -> (&str, &str) {
     if let Some(i) = s.find(|c| ! pred(c)) {
         (&s[0..i], &s[i..])
     } else {
-        (&s, "")
+        (s, "")
     }
 }
 
@@ -40,6 +44,8 @@ pub enum Context {
     Stringlike,
     Comment,
     KeywordOrUninternedSymbol, // after #:
+    Char, // after ? in Emacs Lisp
+    NumericVector, // #u8( etc.
 }
 
 fn context_to_str(c: &Context) -> &str {
@@ -49,6 +55,8 @@ fn context_to_str(c: &Context) -> &str {
         Context::KeywordOrUninternedSymbol =>
             // would need settings here!
             "keyword or uninterned symbol",
+        Context::Char => "character",
+        Context::NumericVector => "numeric vector",
     }
 }
 
@@ -80,28 +88,70 @@ pub enum ParseError {
     TooManyDigits,
     #[error("invalid '#' token")]
     InvalidHashToken,
-    #[error("invalid '#!' name {0:?}")]
+    #[error("invalid '#!' name {:?}", .0.as_str())]
     InvalidSpecialToken(Box<KString>),
+    #[error("invalid character name {:?}", .0.as_str())]
+    InvalidCharName(Box<KString>),
+    #[error("{0} not supported by the format")]
+    NotSupportedByFormat(Box<&'static str>),
+    #[error("'#.' (read-time evaluation) not allowed")]
+    ReadEvalNotAllowed,
+    #[error("invalid bit vector")]
+    InvalidBitVector,
+    #[error("invalid '#c' complex number")]
+    InvalidComplex,
+    #[error("#{}(..) can only contain numbers", .0.name())]
+    InvalidNumericVectorElement(NumericVectorKind),
+    #[error("number out of range for #{}(..)", .0.name())]
+    NumericVectorElementOutOfRange(NumericVectorKind),
+    #[error("zero denominator in rational number")]
+    ZeroDenominator,
 }
 
 #[derive(Error, Debug)]
-#[error("{err} {pos}")]
+#[error("{err} {span}")]
 pub struct ParseErrorWithPos {
     pub err: ParseError,
-    pub pos: Pos
+    pub span: Span
+}
+
+impl ParseErrorWithPos {
+    // Extend the span to `end`; errors are created with an empty span
+    // at the position of the problem, which is extended to cover the
+    // input consumed when it is reported.
+    fn until(mut self, end: Pos) -> ParseErrorWithPos {
+        if end.byte_offset > self.span.end.byte_offset {
+            self.span.end = end;
+        }
+        self
+    }
 }
 
 impl ParseError {
     fn at(self, p: Pos) -> ParseErrorWithPos {
         ParseErrorWithPos {
             err: self,
-            pos: p
+            span: Span::new(p, p)
+        }
+    }
+
+    // For errors about the single character `c` at `p`.
+    fn at_char(self, c: char, p: Pos) -> ParseErrorWithPos {
+        ParseErrorWithPos {
+            err: self,
+            span: Span::new(p, p.after(c))
         }
     }
+
+    /// Whether parsing can continue after this error (it can't after
+    /// IO errors or reaching EOF).
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(self, ParseError::IOError(_) | ParseError::UnexpectedEOF(_))
+    }
 }
 
 /// Possibly return opening or closing token for a given character.
-pub fn maybe_open_close(c: char) -> Option<Token> {
+pub fn maybe_open_close<S>(c: char) -> Option<Token<S>> {
     match c {
         '(' => Some(Token::Open(Parenkind::Round)),
         '[' => Some(Token::Open(Parenkind::Square)),
@@ -117,11 +167,13 @@ pub fn maybe_open_close(c: char) -> Option<Token> {
 pub enum CommentStyle {
     Singleline(u8), // ;  ;;  ;;;  etc.
     Multiline, // #| |#
+    ParenSemicolon, // (; ;)
 }
 
+/// `S` is the string type, see [value](crate::value).
 #[derive(Debug, PartialEq)]
-pub enum Token {
-    Atom(Atom),
+pub enum Token<S = KString> {
+    Atom(Atom<S>),
     Dot,
     Quasiquote,
     Quote,
@@ -129,15 +181,28 @@ pub enum Token {
     UnquoteSplicing,
     Open(Parenkind),
     Close(Parenkind),
-    Whitespace(KString),
-    CommentExpr, // #;
-    Comment(CommentStyle, KString),
+    Whitespace(S),
+    CommentExpr, // #; (or #_ in Clojure)
+    Comment(CommentStyle, S),
+    /// Followed by the `Open` token of the list, for the reader
+    /// macros that require one
+    ReaderMacro(ReaderMacro),
+    /// `#tag`, the text without the `#`
+    Tag(S),
+    /// `#+` (true) or `#-`, followed by the feature expression and
+    /// the form
+    FeatureConditional(bool),
+    /// `#n=`, followed by the labeled value
+    DatumLabel(u32),
+    /// `#n#`
+    DatumRef(u32),
 }
 
-/// NOTE: display doesn't know the settings, so can't target
-/// particular S-expression syntax variants! Use separate write
-/// functionality instead (TODO).
-impl std::fmt::Display for Token {
+/// NOTE: display doesn't know the settings, so atoms are written in
+/// a fixed syntax; use [Writable](crate::write::Writable) (e.g. via
+/// [AnysexprFormat::to_string](crate::settings::AnysexprFormat::to_string))
+/// to target particular S-expression syntax variants.
+impl<S: Deref<Target = str>> std::fmt::Display for Token<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
            -> Result<(), std::fmt::Error> {
         match self {
@@ -164,14 +229,28 @@ impl std::fmt::Display for Token {
                         f.write_str(s)?;
                         f.write_str("|#")
                     }
+                    CommentStyle::ParenSemicolon => {
+                        f.write_str("(;")?;
+                        f.write_str(s)?;
+                        f.write_str(";)")
+                    }
                 }
             }
+            Token::ReaderMacro(rm) => f.write_str(rm.prefix()),
+            Token::Tag(tag) => {
+                f.write_char('#')?;
+                f.write_str(tag)
+            }
+            Token::FeatureConditional(positive) =>
+                f.write_str(if *positive { "#+" } else { "#-" }),
+            Token::DatumLabel(n) => f.write_fmt(format_args!("#{}=", n)),
+            Token::DatumRef(n) => f.write_fmt(format_args!("#{}#", n)),
         }
     }
 }
 
 #[derive(Debug)]
-pub struct TokenWithPos(pub Token, pub Pos);
+pub struct TokenWithPos<S = KString>(pub Token<S>, pub Span);
 
 
 trait At<T> {
@@ -220,34 +299,461 @@ impl<T> TransposeIoAt<T> for Option<anyhow::Result<T>> {
 }
 
 
-fn read_number(is_neg: bool, s: &str) -> Option<R5RSNumber> {
+// Exponents beyond this are refused for exact decimals (`#e1e10`),
+// to avoid constructing huge numbers from short input.
+const MAX_EXACT_EXPONENT: i64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Exactness {
+    Unspecified,
+    Exact,
+    Inexact,
+}
+
+// Read an unsigned integer in the given radix from the start of s,
+// return it and the rest of s.
+fn read_uinteger(s: &str, radix: u32) -> Option<(Integer, &str)> {
+    let (digits, rest) = take_while_and_rest(s, |c| c.is_digit(radix));
+    if digits.is_empty() {
+        return None
+    }
     let mut n: Integer = 0.into();
-    let mut cs = s.chars();
-    while let Some(c) = cs.next() {
-        if c.is_ascii_digit() {
-            n = n * 10 + c.to_digit(10).unwrap();
-        } else if c == '/' {
-            let numer = n;
-            let mut n: Integer = 0.into();
-            while let Some(c) = cs.next() {
-                if c.is_ascii_digit() {
-                    n = n * 10 + c.to_digit(10).unwrap();
-                } else {
-                    return None;
+    for c in digits.chars() {
+        n = n * radix as i64 + c.to_digit(radix).unwrap();
+    }
+    Some((n, rest))
+}
+
+// The exact value of a decimal with the given digits before and after
+// the point and exponent.
+fn exact_decimal(intpart: &str, fracpart: &str, exponent: i64) -> Option<R5RSNumber> {
+    let mut digits = String::with_capacity(intpart.len() + fracpart.len());
+    digits.push_str(intpart);
+    digits.push_str(fracpart);
+    let mantissa = BigInt::parse_bytes(digits.as_bytes(), 10)?;
+    let scale = exponent - fracpart.len() as i64;
+    if scale.abs() > MAX_EXACT_EXPONENT {
+        return None
+    }
+    let power = num::pow(BigInt::from(10), scale.unsigned_abs() as usize);
+    if scale >= 0 {
+        Some(R5RSNumber::Integer((mantissa * power).into()))
+    } else {
+        Some(R5RSNumber::from_fraction(mantissa.into(), power.into()))
+    }
+}
+
+// Read `<ureal R>` (R7RS 7.1.1) from the start of s: an integer, a
+// rational or (only in radix 10) a decimal, return it and the rest of
+// s. A rational with a zero denominator gives 0 and sets
+// `zero_denominator`.
+fn read_ureal<'s>(
+    s: &'s str,
+    radix: u32,
+    exactness: Exactness,
+    zero_denominator: &mut bool
+) -> Option<(R5RSNumber, &'s str)> {
+    let (intpart, mut rest) = take_while_and_rest(s, |c| c.is_digit(radix));
+    if !intpart.is_empty() {
+        if let Some(r) = rest.strip_prefix('/') {
+            let (numer, _) = read_uinteger(intpart, radix)?;
+            let (denom, rest) = read_uinteger(r, radix)?;
+            if denom == 0.into() {
+                *zero_denominator = true;
+                return Some((R5RSNumber::Integer(0.into()), rest))
+            }
+            let n = R5RSNumber::from_fraction(numer, denom);
+            return Some((if exactness == Exactness::Inexact { n.to_inexact() } else { n },
+                         rest))
+        }
+    }
+    let mut fracpart = "";
+    let mut exponent = "0";
+    let mut is_decimal = false;
+    if radix == 10 {
+        if let Some(r) = rest.strip_prefix('.') {
+            let (f, r) = take_while_and_rest(r, is_digit);
+            if intpart.is_empty() && f.is_empty() {
+                return None
+            }
+            fracpart = f;
+            rest = r;
+            is_decimal = true;
+        }
+        if !intpart.is_empty() || is_decimal {
+            if let Some(r) = rest.strip_prefix(['e', 'E']) {
+                let digits = r.strip_prefix(['+', '-']).unwrap_or(r);
+                let (exp, r2) = take_while_and_rest(digits, is_digit);
+                // Otherwise the 'e' isn't part of the number
+                if !exp.is_empty() {
+                    exponent = &r[0..r.len() - r2.len()];
+                    rest = r2;
+                    is_decimal = true;
                 }
             }
-            let denom = n;
-            let n = Rational::new(numer, denom);
-            return Some(R5RSNumber::Rational(Box::new(if is_neg { -n } else { n })))
+        }
+    }
+    if intpart.is_empty() && !is_decimal {
+        return None
+    }
+    let numstr = &s[0..s.len() - rest.len()];
+    let n =
+        if is_decimal {
+            if exactness == Exactness::Exact {
+                exact_decimal(intpart, fracpart, exponent.parse().ok()?)?
+            } else {
+                // (Also accepts 'E', which Rust handles)
+                R5RSNumber::Real(numstr.parse().ok()?)
+            }
+        } else {
+            let n = R5RSNumber::Integer(read_uinteger(numstr, radix)?.0);
+            if exactness == Exactness::Inexact { n.to_inexact() } else { n }
+        };
+    Some((n, rest))
+}
+
+// Read `<real R>` (R7RS 7.1.1) from the start of s, return it and
+// the rest of s.
+fn read_real<'s>(
+    s: &'s str,
+    radix: u32,
+    exactness: Exactness,
+    zero_denominator: &mut bool
+) -> Option<(R5RSNumber, &'s str)> {
+    let (is_neg, body) =
+        if let Some(body) = s.strip_prefix('-') {
+            (true, body)
+        } else if let Some(body) = s.strip_prefix('+') {
+            (false, body)
+        } else {
+            return read_ureal(s, radix, exactness, zero_denominator)
+        };
+    let infnan = |x, rest| {
+        if exactness == Exactness::Exact {
+            None
+        } else {
+            Some((R5RSNumber::Real(x), rest))
+        }
+    };
+    let (n, rest) =
+        if let Some(rest) = body.strip_prefix("inf.0") {
+            infnan(f64::INFINITY, rest)?
+        } else if let Some(rest) = body.strip_prefix("nan.0") {
+            infnan(f64::NAN, rest)?
+        } else {
+            read_ureal(body, radix, exactness, zero_denominator)?
+        };
+    Some((if is_neg { -n } else { n }, rest))
+}
+
+// Read `<complex R>` (R7RS 7.1.1), which includes reals, from all of
+// s.
+fn read_complex(
+    s: &str,
+    radix: u32,
+    exactness: Exactness,
+    zero_denominator: &mut bool
+) -> Option<R5RSNumber> {
+    let complex = |re, im| {
+        Some(R5RSNumber::Complex(Box::new(re), Box::new(im)))
+    };
+    let int = |i: i32| {
+        let n = R5RSNumber::Integer(i.into());
+        if exactness == Exactness::Inexact { n.to_inexact() } else { n }
+    };
+    match s {
+        "+i" => return complex(int(0), int(1)),
+        "-i" => return complex(int(0), int(-1)),
+        _ => {}
+    }
+    let (a, rest) = read_real(s, radix, exactness, zero_denominator)?;
+    if rest.is_empty() {
+        return Some(a)
+    }
+    if rest == "i" {
+        // Imaginary part only, a sign is required
+        return if s.starts_with(['+', '-']) {
+            complex(int(0), a)
+        } else {
+            None
+        }
+    }
+    if let Some(r) = rest.strip_prefix('@') {
+        let (b, rest) = read_real(r, radix, exactness, zero_denominator)?;
+        return if rest.is_empty() {
+            Some(R5RSNumber::Polar(Box::new(a), Box::new(b)))
+        } else {
+            None
+        }
+    }
+    match rest {
+        "+i" => complex(a, int(1)),
+        "-i" => complex(a, int(-1)),
+        _ => {
+            if !rest.starts_with(['+', '-']) {
+                return None
+            }
+            let (b, rest) = read_real(rest, radix, exactness, zero_denominator)?;
+            if rest == "i" {
+                complex(a, b)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// The result of `read_complex`, which is an error if it's a number
+// with a zero denominator.
+fn check_denominators(
+    read_complex: impl FnOnce(&mut bool) -> Option<R5RSNumber>
+) -> Result<Option<R5RSNumber>, ParseError> {
+    let mut zero_denominator = false;
+    match read_complex(&mut zero_denominator) {
+        Some(_) if zero_denominator => Err(ParseError::ZeroDenominator),
+        n => Ok(n)
+    }
+}
+
+// Read a number without prefix. Ok(None) if it's not a number.
+fn read_number(s: &str) -> Result<Option<R5RSNumber>, ParseError> {
+    check_denominators(|zero| read_complex(s, 10, Exactness::Unspecified, zero))
+}
+
+// Read a Common Lisp number (integer, ratio or float) from all of s.
+// Ok(None) if it's not a number.
+pub(crate) fn read_common_lisp_number(s: &str) -> Result<Option<R5RSNumber>, ParseError> {
+    let (sign, body) = match s.strip_prefix(['+', '-']) {
+        Some(body) => (&s[0..1], body),
+        None => ("", s)
+    };
+    // `1.` is the integer 1
+    if let Some(digits) = body.strip_suffix('.') {
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            return read_number(&s[0..s.len() - 1])
+        }
+    }
+    if !body.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return Ok(None)
+    }
+    // Exponent markers other than 'e' denote float formats; all are
+    // read as f64
+    let normalized: String = body.chars().map(|c| match c {
+        's' | 'S' | 'f' | 'F' | 'd' | 'D' | 'l' | 'L' => 'e',
+        c => c
+    }).collect();
+    if normalized.contains(|c: char| !(c.is_ascii_digit() || "./eE+-".contains(c))) {
+        return Ok(None)
+    }
+    match read_number(&format!("{}{}", sign, normalized))? {
+        Some(R5RSNumber::Complex(..) | R5RSNumber::Polar(..)) => Ok(None),
+        n => Ok(n)
+    }
+}
+
+// What `read_edn_number` found.
+enum EdnNumber {
+    Number(R5RSNumber),
+    // `3.14M`, kept as the text without the `M`
+    Decimal,
+}
+
+// Whether `s` starts with a digit, or with `-`, `+` or `.` followed by
+// one (EDN symbols can't).
+pub(crate) fn starts_like_number(s: &str) -> bool {
+    let s = s.strip_prefix(['-', '+', '.']).unwrap_or(s);
+    s.starts_with(|c: char| c.is_ascii_digit())
+}
+
+// The length of the digits at the start of `s`.
+fn count_digits(s: &str) -> usize {
+    s.bytes().take_while(u8::is_ascii_digit).count()
+}
+
+// An optional sign followed by digits without leading zeros.
+fn is_edn_integer(s: &str) -> bool {
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let n = count_digits(digits);
+    n == digits.len() && (n == 1 || (n > 1 && !digits.starts_with('0')))
+}
+
+// An integer optionally followed by a fractional part and an
+// exponent, i.e. the text of an EDN number without suffix.
+pub(crate) fn is_edn_decimal(s: &str) -> bool {
+    let int_end = s.find(['.', 'e', 'E']).unwrap_or(s.len());
+    let (int, mut rest) = s.split_at(int_end);
+    if !is_edn_integer(int) {
+        return false
+    }
+    if let Some(r) = rest.strip_prefix('.') {
+        rest = &r[count_digits(r)..];
+    }
+    if let Some(r) = rest.strip_prefix(['e', 'E']) {
+        let r = r.strip_prefix(['+', '-']).unwrap_or(r);
+        let n = count_digits(r);
+        if n == 0 {
+            return false
+        }
+        rest = &r[n..];
+    }
+    rest.is_empty()
+}
+
+// Read an EDN number from all of s, see NumberSyntax::Edn. Ok(None)
+// if it's a symbol.
+fn read_edn_number(s: &str) -> Result<Option<EdnNumber>, ParseError> {
+    let not_supported = |what| Err(ParseError::NotSupportedByFormat(Box::new(what)));
+    if let Some(digits) = s.strip_suffix('N') {
+        if is_edn_integer(digits) {
+            return Ok(read_number(digits)?.map(EdnNumber::Number))
+        }
+    } else if let Some(text) = s.strip_suffix('M') {
+        if is_edn_decimal(text) {
+            return Ok(Some(EdnNumber::Decimal))
+        }
+    } else if is_edn_decimal(s) {
+        return match read_number(s)? {
+            Some(R5RSNumber::Real(x)) if !x.is_finite() => not_supported("infinite number"),
+            n => Ok(n.map(EdnNumber::Number))
+        }
+    }
+    if !starts_like_number(s) {
+        return Ok(None)
+    }
+    not_supported(match read_number(s) {
+        Ok(Some(R5RSNumber::Rational(_))) => "ratio",
+        Ok(Some(R5RSNumber::Complex(..) | R5RSNumber::Polar(..))) => "complex number",
+        _ => "symbol starting like a number"
+    })
+}
+
+// Read an Emacs Lisp number (integer or float) from all of s.
+pub(crate) fn read_elisp_number(s: &str) -> Option<R5RSNumber> {
+    let is_neg = s.starts_with('-');
+    let body = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let special = |mantissa: &str, x: f64| {
+        // The mantissa must be a float itself
+        match read_common_lisp_number(mantissa) {
+            Ok(Some(R5RSNumber::Real(_))) => Some(R5RSNumber::Real(x)),
+            _ => None
+        }
+    };
+    if let Some(mantissa) = body.strip_suffix("e+INF") {
+        return special(mantissa, if is_neg { f64::NEG_INFINITY } else { f64::INFINITY })
+    }
+    if let Some(mantissa) = body.strip_suffix("e+NaN") {
+        return special(mantissa, f64::NAN)
+    }
+    if body.contains(|c: char| !(c.is_ascii_digit() || ".eE+-".contains(c))) {
+        return None
+    }
+    // (No ratios, thus no errors)
+    read_common_lisp_number(s).ok().flatten()
+}
+
+// Whether `s` is an SMT-LIB decimal (`Some(true)`) or numeral
+// (`Some(false)`).
+pub(crate) fn smtlib_number_kind(s: &str) -> Option<bool> {
+    let is_numeral = |d: &str| {
+        !d.is_empty() && d.bytes().all(|b| b.is_ascii_digit())
+            && (d == "0" || !d.starts_with('0'))
+    };
+    match s.split_once('.') {
+        None => is_numeral(s).then_some(false),
+        Some((int, frac)) =>
+            (is_numeral(int) && !frac.is_empty() && frac.bytes().all(|b| b.is_ascii_digit()))
+            .then_some(true)
+    }
+}
+
+// Whether `s` is a WAT `num` (or `hexnum`, with `radix` 16): digits
+// with single `_` between them.
+fn is_wat_num(s: &str, radix: u32) -> bool {
+    !s.is_empty() && !s.starts_with('_') && !s.ends_with('_') && !s.contains("__")
+        && s.chars().all(|c| c == '_' || c.is_digit(radix))
+}
+
+// WAT integers and decimal floats, see NumberSyntax::Wat.
+fn read_wat_number(s: &str) -> Option<R5RSNumber> {
+    let (is_neg, body) =
+        if let Some(body) = s.strip_prefix('-') {
+            (true, body)
         } else {
-            // XXX: floating point, complex, and all the mixes.
+            (false, s.strip_prefix('+').unwrap_or(s))
+        };
+    let n = if body == "inf" {
+        R5RSNumber::Real(f64::INFINITY)
+    } else if body == "nan" {
+        R5RSNumber::Real(f64::NAN)
+    } else if let Some(hex) = body.strip_prefix("0x") {
+        if !is_wat_num(hex, 16) {
+            return None
+        }
+        let (n, _) = read_uinteger(&hex.replace('_', ""), 16)?;
+        R5RSNumber::Integer(n)
+    } else if is_wat_num(body, 10) {
+        let (n, _) = read_uinteger(&body.replace('_', ""), 10)?;
+        R5RSNumber::Integer(n)
+    } else {
+        let (mantissa, exponent) = match body.split_once(['e', 'E']) {
+            Some((m, e)) => (m, Some(e.strip_prefix(['+', '-']).unwrap_or(e))),
+            None => (body, None)
+        };
+        let (int, frac) = match mantissa.split_once('.') {
+            Some((i, f)) => (i, Some(f)),
+            None => (mantissa, None)
+        };
+        if !(is_wat_num(int, 10)
+             && frac.is_none_or(|f| f.is_empty() || is_wat_num(f, 10))
+             && exponent.is_none_or(|e| is_wat_num(e, 10))
+             && (frac.is_some() || exponent.is_some()))
+        {
             return None
         }
+        R5RSNumber::Real(body.replace('_', "").parse().ok()?)
+    };
+    Some(if is_neg { -n } else { n })
+}
+
+// Read a number with radix and/or exactness prefixes (R7RS `<prefix
+// R>`, in either order), `s` being the part after the first '#'.
+// Ok(None) if it's not a number.
+fn read_prefixed_number(s: &str) -> Result<Option<R5RSNumber>, ParseError> {
+    let mut radix = None;
+    let mut exactness = Exactness::Unspecified;
+    let mut rest = s;
+    loop {
+        let mut cs = rest.chars();
+        let Some(c) = cs.next() else {
+            return Ok(None)
+        };
+        match c.to_ascii_lowercase() {
+            'e' if exactness == Exactness::Unspecified =>
+                exactness = Exactness::Exact,
+            'i' if exactness == Exactness::Unspecified =>
+                exactness = Exactness::Inexact,
+            c if radix.is_none() => {
+                radix = Some(match c {
+                    'b' => 2,
+                    'o' => 8,
+                    'd' => 10,
+                    'x' => 16,
+                    _ => return Ok(None)
+                })
+            }
+            _ => return Ok(None)
+        }
+        rest = cs.as_str();
+        if let Some(r) = rest.strip_prefix('#') {
+            rest = r;
+        } else {
+            break
+        }
     }
-    Some(R5RSNumber::Integer(if is_neg { -n } else { n }))
+    check_denominators(|zero| read_complex(rest, radix.unwrap_or(10), exactness, zero))
 }
 
-fn delimiter2maybe_stringlike_constructor(c: char) -> Option<fn(KString) -> Atom> {
+fn delimiter2maybe_stringlike_constructor<S>(c: char) -> Option<fn(S) -> Atom<S>> {
     match c {
         '"' => Some(Atom::String),
         '|' => Some(Atom::Symbol),
@@ -262,7 +768,7 @@ fn parse_hexdigit(c: u32) -> Option<u32> {
     } else if 'a' as u32 <= c && c <= 'f' as u32 {
         Some(c - 'a' as u32 + 10)
     } else if 'A' as u32 <= c && c <= 'F' as u32 {
-        Some(c - 'F' as u32 + 10)
+        Some(c - 'A' as u32 + 10)
     } else {
         None
     }
@@ -289,7 +795,7 @@ fn parse_as_hexstr(s: &str) -> Option<u32> {
     Some(n)
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 enum ReadMode {
     /// Read exactly `numdigits` digits
     Exactlen,
@@ -299,56 +805,101 @@ enum ReadMode {
     Delimiter(char),
 }
 
+// After an error at `cp` in a hex escape in `readmode`, skip the
+// rest of the escape: the alphanumeric characters up to its length
+// (`remaining` more), or up to and including its delimiter. Returns
+// the character after it, if already read.
+fn skip_hex_escape_rest(
+    cs: &mut impl CharSource,
+    cp: (char, Pos),
+    readmode: ReadMode,
+    mut remaining: u32,
+) -> Result<Option<(char, Pos)>, ParseErrorWithPos> {
+    let mut next = Some(cp);
+    while let Some((c, pos)) = next {
+        if let ReadMode::Delimiter(delim) = readmode {
+            if c == delim {
+                return cs.next().transpose_io_at(pos)
+            }
+        }
+        if remaining == 0 || !c.is_ascii_alphanumeric() {
+            return Ok(Some((c, pos)))
+        }
+        remaining -= 1;
+        next = cs.next().transpose_io_at(pos)?;
+    }
+    Ok(None)
+}
+
 // Reads exactly numdigits digits, or up to the given delimiter, in
-// which case numdigits is the max digits allowed
+// which case numdigits is the max digits allowed. On errors in the
+// digits, the rest of the escape is skipped.
 fn read_hex_as_u32(
-    cs: &mut impl Iterator<Item = anyhow::Result<(char, Pos)>>,
+    cs: &mut impl CharSource,
     codestartpos: Pos,
     readmode: ReadMode,
     numdigits: u32,
-) -> Result<(u32, Option<(char, Pos)>), ParseErrorWithPos> {
+) -> Result<(u32, Option<(char, Pos)>), EscapeError> {
     let mut res: u32 = 0;
     let mut lastpos = codestartpos;
     let mut numdigits_seen = 0;
+    let io = |e| (e, None);
     loop {
-        if let Some((c, pos)) = cs.next().transpose_io_at(lastpos)? {
-            if let ReadMode::Delimiter(delim) = readmode {
+        if let Some((c, pos)) = cs.next().transpose_io_at(lastpos).map_err(io)? {
+            let e = if let ReadMode::Delimiter(delim) = readmode {
                 if c == delim {
-                    return Ok((res, cs.next().transpose_io_at(pos)?))
+                    return Ok((res, cs.next().transpose_io_at(pos).map_err(io)?))
                 } else if numdigits_seen == numdigits {
-                    return Err(ParseError::TooManyDigits.at(pos))
+                    Some(ParseError::TooManyDigits.at(pos))
+                } else {
+                    None
                 }
             } else if numdigits_seen == numdigits {
                 return Ok((res, Some((c, pos))))
-            }
-            if let Some(n) = parse_hexdigit(c as u32) {
-                res *= 16;
-                res += n;
-                numdigits_seen += 1;
-                lastpos = pos;
             } else {
-                return
-                    if readmode == ReadMode::FlexLen {
-                        Ok((res, Some((c, pos))))
-                    } else {
-                        Err(ParseError::NonHexDigit(c).at(pos))
-                    };
-            }
+                None
+            };
+            let e = match (e, parse_hexdigit(c as u32)) {
+                (None, Some(n)) => {
+                    res *= 16;
+                    res += n;
+                    numdigits_seen += 1;
+                    lastpos = pos;
+                    continue
+                }
+                (None, None) if readmode == ReadMode::FlexLen =>
+                    return Ok((res, Some((c, pos)))),
+                (None, None) => ParseError::NonHexDigit(c).at_char(c, pos),
+                (Some(e), _) => e,
+            };
+            let remaining = match readmode {
+                ReadMode::Exactlen => numdigits - numdigits_seen,
+                _ => u32::MAX
+            };
+            let mcp = skip_hex_escape_rest(cs, (c, pos), readmode, remaining).map_err(io)?;
+            return Err((e, mcp))
         } else {
-            return Err(ParseError::UnexpectedEOF(Context::Stringlike).at(lastpos));
+            return Err((ParseError::UnexpectedEOF(Context::Stringlike).at(lastpos), None));
         }
     }
 }
 
-// Read a hex number and convert to a char; used in read_delimited.
+// What to continue with after a (recoverable) error in an escape
+// sequence: the error and the next character, if already read.
+type EscapeError = (ParseErrorWithPos, Option<(char, Pos)>);
+
+// Read a hex number and convert to a char; used in read_escape.
 fn read_hex_as_char(
-    cs: &mut impl Iterator<Item = anyhow::Result<(char, Pos)>>,
+    cs: &mut impl CharSource,
     lastpos: Pos,
     readmode: ReadMode,
     numdigits: u32,
-) -> Result<(char, Option<(char, Pos)>), ParseErrorWithPos> {
+) -> Result<(char, Option<(char, Pos)>), EscapeError> {
     let (code, mcp) = read_hex_as_u32(cs, lastpos, readmode, numdigits)?;
-    Ok((try_u32_to_char(code).at(lastpos)?, mcp))
+    match try_u32_to_char(code) {
+        Ok(c) => Ok((c, mcp)),
+        Err(e) => Err((e.at(lastpos), mcp))
+    }
 }
 
 /// Returns `Some((c, None))` iff at EOF.
@@ -356,7 +907,7 @@ fn read_octalescape_char(
     startpos: Pos,
     c: char,
     pos: Pos,
-    cs: &mut impl Iterator<Item = anyhow::Result<(char, Pos)>>,
+    cs: &mut impl CharSource,
 ) -> Result<(char, Option<(char, Pos)>), ParseErrorWithPos>
 {
     if let Some(d) = parse_octaldigit(c as u32) {
@@ -388,12 +939,296 @@ fn read_octalescape_char(
     }
 }
 
+// Append `bytes` to `out`, decoded as UTF-8 where valid, other bytes
+// as the characters with their codes.
+fn push_bytes(out: &mut String, mut bytes: &[u8]) {
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => return out.push_str(s),
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                out.push_str(std::str::from_utf8(valid).expect("valid up to here"));
+                out.push(char::from(rest[0]));
+                bytes = &rest[1..];
+            }
+        }
+    }
+}
+
+// WAT's escapes: `\t \n \r \" \' \\`, `\u{hhhh}`, and `\hh` for
+// bytes, a run of which is decoded via push_bytes.
+fn read_wat_escape(
+    startpos: Pos,
+    c: char,
+    pos: Pos,
+    cs: &mut impl CharSource,
+    out: &mut String
+) -> Result<Option<(char, Pos)>, EscapeError>
+{
+    let replacement = match c {
+        't' => '\t',
+        'n' => '\n',
+        'r' => '\r',
+        '"' | '\'' | '\\' => c,
+        'u' => {
+            match cs.next().transpose_io_at(pos).map_err(|e| (e, None))? {
+                Some(('{', pos1)) => {
+                    let (code, mcp) = read_hex_as_u32(cs, pos1, ReadMode::Delimiter('}'), 6)?;
+                    out.push(try_u32_to_char(code).at(pos).map_err(|e| (e, mcp))?);
+                    return Ok(mcp)
+                }
+                Some((c1, pos1)) =>
+                    return Err((ParseError::MissingDelimiterForCodeSequence('{')
+                                .at(pos1), Some((c1, pos1)))),
+                None =>
+                    return Err((ParseError::UnexpectedEOF(Context::Stringlike)
+                                .at(startpos), None))
+            }
+        }
+        c if c.is_ascii_hexdigit() => {
+            let mut bytes = Vec::new();
+            let (mut c, mut pos) = (c, pos);
+            loop {
+                let (c1, pos1) = match cs.next().transpose_io_at(pos).map_err(|e| (e, None))? {
+                    Some((c1, pos1)) if c1.is_ascii_hexdigit() => (c1, pos1),
+                    Some((c1, pos1)) => {
+                        // The byte escape is two characters long
+                        let mcp = (!c1.is_ascii_alphanumeric()).then_some((c1, pos1));
+                        return Err((ParseError::NonHexDigit(c1).at_char(c1, pos1), mcp))
+                    }
+                    None =>
+                        return Err((ParseError::UnexpectedEOF(Context::Stringlike)
+                                    .at(startpos), None))
+                };
+                let hex = [c as u8, c1 as u8];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).expect("ascii"), 16)
+                           .expect("hex digits"));
+                // Continue while more byte escapes follow
+                let next = cs.next().transpose_io_at(pos1).map_err(|e| (e, None))?;
+                let Some(('\\', bpos)) = next else {
+                    push_bytes(out, &bytes);
+                    return Ok(next)
+                };
+                match cs.next().transpose_io_at(bpos).map_err(|e| (e, None))? {
+                    Some((c2, pos2)) if c2.is_ascii_hexdigit() => {
+                        (c, pos) = (c2, pos2);
+                    }
+                    Some((c2, pos2)) => {
+                        push_bytes(out, &bytes);
+                        return read_wat_escape(startpos, c2, pos2, cs, out)
+                    }
+                    None =>
+                        return Err((ParseError::UnexpectedEOF(Context::Stringlike)
+                                    .at(startpos), None))
+                }
+            }
+        }
+        _ => return Err((ParseError::InvalidEscapedChar(c).at_char(c, pos), None))
+    };
+    out.push(replacement);
+    Ok(None)
+}
+
+// OCaml's escapes as read by sexplib: `\\ \" \' \n \t \b \r \ `,
+// `\DDD` (decimal) and `\xHH` for bytes, and line continuations
+// skipping the spaces and tabs on the next line. A backslash before
+// anything else is kept as is.
+fn read_ocaml_escape(
+    startpos: Pos,
+    c: char,
+    pos: Pos,
+    cs: &mut impl CharSource,
+    out: &mut String
+) -> Result<Option<(char, Pos)>, EscapeError>
+{
+    let replacement = match c {
+        '\\' | '"' | '\'' | ' ' => c,
+        'n' => '\n',
+        't' => '\t',
+        'b' => '\x08',
+        'r' => '\r',
+        '\n' => {
+            let (_lastc, mcp) =
+                read_while(None, pos, cs, |c| c == ' ' || c == '\t', None)
+                .map_err(|e| (e, None))?;
+            if mcp.is_none() {
+                return Err((ParseError::UnexpectedEOF(
+                    Context::Stringlike).at(startpos), None))
+            }
+            return Ok(mcp)
+        }
+        'x' | '0'..='9' => {
+            let radix = if c == 'x' { 16 } else { 10 };
+            let mut digits = String::new();
+            if c != 'x' {
+                digits.push(c);
+            }
+            let mut lastpos = pos;
+            while digits.len() < if c == 'x' { 2 } else { 3 } {
+                match cs.next().transpose_io_at(lastpos).map_err(|e| (e, None))? {
+                    Some((d, dpos)) if d.is_digit(radix) => {
+                        digits.push(d);
+                        lastpos = dpos;
+                    }
+                    mcp => {
+                        // Not an escape sequence after all
+                        out.push('\\');
+                        if c == 'x' {
+                            out.push('x');
+                        }
+                        out.push_str(&digits);
+                        return Ok(mcp)
+                    }
+                }
+            }
+            let code = u32::from_str_radix(&digits, radix).expect("digits");
+            if code > 255 {
+                return Err((ParseError::InvalidCodePoint(code).at(pos), None))
+            }
+            char::from_u32(code).expect("a byte")
+        }
+        _ => {
+            out.push('\\');
+            c
+        }
+    };
+    out.push(replacement);
+    Ok(None)
+}
+
+// Handle the character `c` at `pos` after a backslash, pushing the
+// result to `out`. Returns the next character if it was read ahead.
+fn read_escape(
+    settings: &Settings, 
+    startpos: Pos,
+    c: char,
+    pos: Pos,
+    cs: &mut impl CharSource,
+    out: &mut String
+) -> Result<Option<(char, Pos)>, EscapeError>
+{
+    let push_hex = |cs: &mut _, out: &mut String, mode, numdigits| {
+        let (c, mcp) = read_hex_as_char(cs, pos, mode, numdigits)?;
+        out.push(c);
+        Ok(mcp)
+    };
+    if settings.format.backslash_quotes_next_char {
+        out.push(c);
+        return Ok(None)
+    }
+    if settings.format.ocaml_escapes {
+        return read_ocaml_escape(startpos, c, pos, cs, out)
+    }
+    if settings.format.wat_escapes {
+        return read_wat_escape(startpos, c, pos, cs, out)
+    }
+    // https://small.r7rs.org/attachment/r7rs.pdf 6.7. Strings
+    let replacement = match c {
+        'e' if settings.format.elisp_escapes => "\x1B",
+        's' if settings.format.elisp_escapes => " ",
+        'd' if settings.format.elisp_escapes => "\x7F",
+        // Separates a hex escape from a following hex digit
+        ' ' if settings.format.elisp_escapes => "",
+        // Line continuation that keeps the following whitespace
+        '\n' if settings.format.elisp_escapes => "",
+        'a' => "\x07", // alarm
+        'b' => "\x08", // backspace
+        't' => "\t",
+        'n' => "\n",
+        'r' => "\r",
+        // (Not in R7RS(?), but why not?: man ascii
+        'v' => "\x0B",
+        'f' => "\x0C",
+        // /Not in R7RS)
+        '\\' => "\\",
+        '"' => "\"", // possible delimiter
+        '\'' => "\'",
+        '|' => "|", // possible delimiter
+        'u' => return push_hex(cs, out, ReadMode::Exactlen, 4),
+        // Supported by Gambit, not Guile
+        'U' => return push_hex(cs, out, ReadMode::Exactlen, 8),
+        'x' => {
+            let mode =
+                if settings.format.
+                x_escape_terminated_by_semicolon_in_delimited {
+                    ReadMode::Delimiter(';')
+                } else {
+                    ReadMode::FlexLen
+                };
+            return push_hex(cs, out, mode, settings.format.x_escape_len as u32)
+        }
+        '\n' => {
+            // Line continuation
+            let (_lastc, mcp) =
+                read_while(Some(c), pos, cs, is_whitespace_char, None)
+                .map_err(|e| (e, None))?;
+            if mcp.is_none() {
+                return Err((ParseError::UnexpectedEOF(
+                    Context::Stringlike).at(startpos), None))
+            }
+            return Ok(mcp)
+        }
+        _ => {
+            if settings.format.octal_escapes_in_delimited {
+                // Not in R7RS(?), but supported
+                // by Gambit Scheme, but not by
+                // Guile.
+
+                // > (map char->integer (string->list "\322"))
+                // (210)
+                // > (map char->integer (string->list "\422"))
+                // (34 50)
+                // > (map char->integer (string->list "\0"))    
+                // (0)
+                // > (map char->integer (string->list "\00"))
+                // (0)
+                // > (map char->integer (string->list "\010"))
+                // (8)
+                // > (map char->integer (string->list "\10")) 
+                // (8)
+                // > "\0000"  
+                // "\0\60"
+                // > (string->list "\0000")
+                // (#\nul #\0)
+                // > (string->list "\34")  
+                // (#\x1c)
+                // > (string->list "\01a")
+                // (#\x01 #\a)
+                // > (string->list "\017")
+                // (#\x0f)
+                // > (string->list "\018")
+                // (#\x01 #\8)
+
+                // So, Gambit reads 1..3 octal digits; it
+                // makes up for the ambiguity by escaping the
+                // follow-up character when writing.
+                let (c, mcp) = read_octalescape_char(startpos, c, pos, cs)
+                    .map_err(|e| (e, None))?;
+                out.push(c);
+                return Ok(mcp)
+            } else if c == '0' {
+                // Supported by Guile (Gambit reads more
+                // digits, see branch above)
+                "\0"
+            } else {
+                return Err((ParseError::InvalidEscapedChar(c).at_char(c, pos), None))
+            }
+        }
+    };
+    out.push_str(replacement);
+    Ok(None)
+}
+
+// Read up to the given (unescaped) delimiter. In
+// `Modes::recover_from_errors` mode, errors in escape sequences are
+// added to `errors` and replaced with U+FFFD in the output.
 fn read_delimited(
     settings: &Settings, 
     startpos: Pos,
-    cs: &mut impl Iterator<Item = anyhow::Result<(char, Pos)>>,
+    cs: &mut impl CharSource,
     delimiter: char,
-    out: &mut String
+    out: &mut TokenText,
+    errors: &mut Vec<ParseErrorWithPos>,
 ) -> Result<(), ParseErrorWithPos>
 {
     out.clear();
@@ -406,154 +1241,141 @@ fn read_delimited(
         if let Some(cp) = maybe_next_c_pos {
             (c, pos) = cp;
             maybe_next_c_pos = None;
+        } else if let Some(cp) = cs.next_for(
+            if escaped { Wait::Any } else { Wait::Delimiter(delimiter, true) }
+        ).transpose_io_at(lastpos)? {
+            (c, pos) = cp;
         } else {
-            if let Some(cp) = cs.next().transpose_io_at(lastpos)? {
-                (c, pos) = cp;
-            } else {
-                return Err(ParseError::UnexpectedEOF(Context::Stringlike).at(startpos));
-            }
+            return Err(ParseError::UnexpectedEOF(Context::Stringlike).at(startpos));
         }
         lastpos = pos;
         if escaped {
-            // https://small.r7rs.org/attachment/r7rs.pdf 6.7. Strings
-            let replacement = match c {
-                'a' => "\x07", // alarm
-                'b' => "\x08", // backspace
-                't' => "\t",
-                'n' => "\n",
-                'r' => "\r",
-                // (Not in R7RS(?), but why not?: man ascii
-                'v' => "\x0B",
-                'f' => "\x0C",
-                // /Not in R7RS)
-                '\\' => "\\",
-                '"' => "\"", // possible delimiter
-                '\'' => "\'",
-                '|' => "|", // possible delimiter
-                'u' => {
-                    let (c, mcp) = read_hex_as_char(cs, pos, ReadMode::Exactlen, 4)?;
-                    out.push(c);
-                    maybe_next_c_pos = mcp;
-                    ""
-                }
-                'U' => {
-                    // Supported by Gambit, not Guile
-                    let (c, mcp) = read_hex_as_char(cs, pos, ReadMode::Exactlen, 8)?;
-                    out.push(c);
-                    maybe_next_c_pos = mcp;
-                    ""
-                }
-                'x' => {
-                    let mode =
-                        if settings.format.
-                        x_escape_terminated_by_semicolon_in_delimited {
-                            ReadMode::Delimiter(';')
-                        } else {
-                            ReadMode::FlexLen
-                        };
-                    let numdigits = settings.format.x_escape_len as u32;
-                    let (c, mcp) = read_hex_as_char(cs, pos, mode, numdigits)?;
-                    out.push(c);
-                    maybe_next_c_pos = mcp;
-                    ""
-                }
-                '\n' => {
-                    // Line continuation
-                    let (_lastc, mcp)=
-                        read_while(Some(c), pos, cs,
-                                   is_whitespace_char, None)?;
-                    if mcp.is_none() {
-                        return Err(ParseError::UnexpectedEOF(
-                            Context::Stringlike).at(startpos))
-                    }
+            match read_escape(settings, startpos, c, pos, cs, out.processed()) {
+                Ok(mcp) => {
                     maybe_next_c_pos = mcp;
-                    ""
                 }
-                _ => {
-                    if settings.format.octal_escapes_in_delimited {
-                        // Not in R7RS(?), but supported
-                        // by Gambit Scheme, but not by
-                        // Guile.
-
-                        // > (map char->integer (string->list "\322"))
-                        // (210)
-                        // > (map char->integer (string->list "\422"))
-                        // (34 50)
-                        // > (map char->integer (string->list "\0"))    
-                        // (0)
-                        // > (map char->integer (string->list "\00"))
-                        // (0)
-                        // > (map char->integer (string->list "\010"))
-                        // (8)
-                        // > (map char->integer (string->list "\10")) 
-                        // (8)
-                        // > "\0000"  
-                        // "\0\60"
-                        // > (string->list "\0000")
-                        // (#\nul #\0)
-                        // > (string->list "\34")  
-                        // (#\x1c)
-                        // > (string->list "\01a")
-                        // (#\x01 #\a)
-                        // > (string->list "\017")
-                        // (#\x0f)
-                        // > (string->list "\018")
-                        // (#\x01 #\8)
-
-                        // So, Gambit reads 1..3 octal digits; it
-                        // makes up for the ambiguity by escaping the
-                        // follow-up character when writing.
-                        let (c, mcp) = read_octalescape_char(startpos, c, pos, cs)?;
-                        out.push(c);
+                Err((e, mcp)) => {
+                    if settings.modes.recover_from_errors && e.err.is_recoverable() {
+                        out.processed().push(char::REPLACEMENT_CHARACTER);
+                        errors.push(e);
                         maybe_next_c_pos = mcp;
-                        ""
-                    } else if c == '0' {
-                        // Supported by Guile (Gambit reads more
-                        // digits, see branch above)
-                        "\0"
                     } else {
-                        return Err(ParseError::InvalidEscapedChar(c).at(pos))
+                        return Err(e)
                     }
                 }
-            };
-            out.push_str(replacement);
+            }
             escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter {
+            return Ok(());
         } else {
-            if c == '\\' {
-                escaped = true;
-            } else if c == delimiter {
-                return Ok(());
-            } else {
-                out.push(c);
+            out.push(c, pos);
+        }
+    }
+}
+
+// Read up to the given delimiter, without escapes; with `doubled`,
+// two delimiters in a row stand for one. Returns the character after
+// the delimiter if it had to be read ahead.
+fn read_verbatim(
+    startpos: Pos,
+    cs: &mut impl CharSource,
+    delimiter: char,
+    doubled: bool,
+    out: &mut TokenText,
+) -> Result<Option<(char, Pos)>, ParseErrorWithPos>
+{
+    out.clear();
+    let mut lastpos = startpos;
+    loop {
+        let wait = if doubled {
+            Wait::Doubled(delimiter, false)
+        } else {
+            Wait::Delimiter(delimiter, false)
+        };
+        let Some((c, pos)) = cs.next_for(wait).transpose_io_at(lastpos)? else {
+            return Err(ParseError::UnexpectedEOF(Context::Stringlike).at(startpos))
+        };
+        lastpos = pos;
+        if c != delimiter {
+            out.push(c, pos);
+        } else if !doubled {
+            return Ok(None)
+        } else {
+            match cs.next_for(Wait::Doubled(delimiter, true)).transpose_io_at(pos)? {
+                Some((c1, pos1)) if c1 == delimiter => {
+                    out.push(c1, pos1);
+                    lastpos = pos1;
+                }
+                mcp => return Ok(mcp)
             }
         }
     }
 }
 
+// The last character accepted (if any), and the next character (if
+// not at EOF).
+type LastAndNext = (Option<char>, Option<(char, Pos)>);
+
+// Same as read_while with is_symbol_or_number_char, starting with
+// `c`, but taking the character after a `\` as is; sets `escaped`
+// if there was any.
+fn read_symbol_with_escapes(
+    c: char,
+    startpos: Pos,
+    cs: &mut impl CharSource,
+    out: &mut TokenText,
+    escaped: &mut bool,
+) -> Result<LastAndNext, ParseErrorWithPos> {
+    out.clear();
+    let mut next = Some((c, startpos));
+    let mut lastc = None;
+    let mut lastpos;
+    while let Some((c, pos)) = next {
+        if c == '\\' {
+            let Some((c1, pos1)) = cs.next().transpose_io_at(pos)? else {
+                return Err(ParseError::UnexpectedEOF(Context::Stringlike).at(startpos))
+            };
+            *escaped = true;
+            out.push(c1, pos1);
+            lastc = Some(c1);
+            lastpos = pos1;
+        } else if is_symbol_or_number_char(c) || lastc.is_none() {
+            out.push(c, pos);
+            lastc = Some(c);
+            lastpos = pos;
+        } else {
+            return Ok((lastc, Some((c, pos))))
+        }
+        next = cs.next_for(Wait::SymbolWithEscapes).transpose_io_at(lastpos)?;
+    }
+    Ok((lastc, None))
+}
+
 // Returns (, None) iff reached EOF;
 // returns (None, ) iff reached EOF at the begin and no c was given.
 fn read_while(
     c: Option<char>,
     startpos: Pos,
-    cs: &mut impl Iterator<Item = anyhow::Result<(char, Pos)>>,
+    cs: &mut impl CharSource,
     accepted: fn(char) -> bool,
-    mut opt_out: Option<&mut String>,
-) -> Result<(Option<char>, Option<(char, Pos)>),
-            ParseErrorWithPos> {
+    mut opt_out: Option<&mut TokenText>,
+) -> Result<LastAndNext, ParseErrorWithPos> {
     if let Some(ref mut out) = opt_out {
         out.clear();
         if let Some(c) = c {
-            out.push(c);
+            out.push(c, startpos);
         }
     }
     let mut lastc = c;
     let mut lastpos = startpos;
     loop {
-        if let Some((c, pos)) = cs.next().transpose_io_at(lastpos)? {
+        if let Some((c, pos)) = cs.next_for(Wait::While(accepted)).transpose_io_at(lastpos)? {
             lastpos = pos;
             if accepted(c) {
                 if let Some(ref mut out) = opt_out {
-                    out.push(c);
+                    out.push(c, pos);
                 }
                 lastc = Some(c);
             } else {
@@ -567,16 +1389,20 @@ fn read_while(
 
 fn read_until(
     startpos: Pos,
-    cs: &mut impl Iterator<Item = anyhow::Result<(char, Pos)>>,
+    cs: &mut impl CharSource,
     needle: &[char],
-    out: &mut String,
+    out: &mut TokenText,
 ) -> Result<(),
             ParseErrorWithPos> {
     out.clear();
     let mut lastpos = startpos;
     let mut needle_i = 0;
+    let wait = match *needle {
+        [c0, c1] => Wait::Close([c0, c1]),
+        _ => Wait::Delimiter(needle[needle.len() - 1], false)
+    };
     loop {
-        if let Some((c, pos)) = cs.next().transpose_io_at(lastpos)? {
+        if let Some((c, pos)) = cs.next_for(wait).transpose_io_at(lastpos)? {
             lastpos = pos;
 
             if c == needle[needle_i] {
@@ -585,12 +1411,12 @@ fn read_until(
                     return Ok(())
                 }
             } else if needle_i > 0 {
-                for i in 0..needle_i {
-                    out.push(needle[i]);
+                for nc in &needle[0..needle_i] {
+                    out.processed().push(*nc);
                 }
                 needle_i = 0;
             } else {
-                out.push(c);
+                out.push(c, pos);
             }
         } else {
             return Err(ParseError::UnexpectedEOF(Context::Comment).at(startpos))
@@ -598,9 +1424,46 @@ fn read_until(
     }
 }
 
-
-fn char2special_token(c: char) -> Option<Token> {
-    match c {
+// Read up to the `close` sequence matching the `open` sequence before
+// `startpos`, taking inner pairs as part of the comment.
+fn read_nested_comment(
+    startpos: Pos,
+    cs: &mut impl CharSource,
+    open: [char; 2],
+    close: [char; 2],
+    out: &mut TokenText,
+) -> Result<(), ParseErrorWithPos> {
+    out.clear();
+    let mut lastpos = startpos;
+    let mut depth = 1;
+    let mut prev = None;
+    loop {
+        let Some((c, pos)) = cs.next_for(Wait::Close(close)).transpose_io_at(lastpos)? else {
+            return Err(ParseError::UnexpectedEOF(Context::Comment).at(startpos))
+        };
+        lastpos = pos;
+        out.push(c, pos);
+        match prev {
+            Some(p) if [p, c] == open => {
+                depth += 1;
+                prev = None;
+            }
+            Some(p) if [p, c] == close => {
+                depth -= 1;
+                if depth == 0 {
+                    out.truncate(out.len() - 2);
+                    return Ok(())
+                }
+                prev = None;
+            }
+            _ => prev = Some(c)
+        }
+    }
+}
+
+
+fn char2special_token<S>(c: char) -> Option<Token<S>> {
+    match c {
         '\'' => Some(Token::Quote),
         '`' => Some(Token::Quasiquote),
         ',' => Some(Token::Unquote),
@@ -612,423 +1475,1691 @@ fn char2special_token(c: char) -> Option<Token> {
     }
 }
 
-fn is_symbol_or_number_char(c: char) -> bool {
-    c.is_whitespace() == false
-        && char2special_token(c).is_none()
-        && delimiter2maybe_stringlike_constructor(c).is_none()
-        && maybe_open_close(c).is_none()
+pub(crate) fn is_symbol_or_number_char(c: char) -> bool {
+    !c.is_whitespace()
+        && char2special_token::<KString>(c).is_none()
+        && delimiter2maybe_stringlike_constructor::<KString>(c).is_none()
+        && maybe_open_close::<KString>(c).is_none()
         && c != '\\'
 }
 
+// WAT's `idchar`s.
+pub(crate) fn is_wat_idchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c)
+}
+
+// Sexplib's unquoted atoms.
+fn is_string_atom_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '"' | ';')
+}
+
 fn is_whitespace_char(c: char) -> bool {
     c.is_whitespace()
 }
 
+fn is_whitespace_or_comma_char(c: char) -> bool {
+    c.is_whitespace() || c == ','
+}
+
 fn is_digit(c: char) -> bool {
     c.is_ascii_digit()
 }
 
-/// Parse a stream of characters and their positions into a stream of
-/// tokens (atoms or opening/closing tokens).
-pub fn parse<'s>(
-    cs: impl Iterator<Item = anyhow::Result<(char, Pos)>> + 's,
-    settings: &'s Settings,
-)
-    -> impl Iterator<Item = Result<TokenWithPos, ParseErrorWithPos>> + 's
-{
-    Gen::new(|co| async move {
-        let mut cs = cs;
-        let mut tmp = String::new();
-        let mut maybe_next_c_pos = None;
-        let mut lastpos = Pos { line: 0, col: 0 };
-        loop {
-            let c;
-            let pos;
-            if let Some(cp) = maybe_next_c_pos {
-                (c, pos) = cp;
-                maybe_next_c_pos = None;
-            } else {
-                if let Some(r) = cs.next() {
-                    match r {
-                        Err(e) => {
-                            co.yield_(Err(
-                                ParseError::IOError(e).at(lastpos))).await;
-                            return;
-                        }
-                        Ok(cp) => {
-                            (c, pos) = cp;
-                        }
-                    }
+// What the tokenizer waits for when it asks for the next character
+// in the middle of a token: the token can't be completed before such
+// a character. Used by the push parser to avoid running the tokenizer
+// again while the input that arrived can't complete the token.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Wait {
+    // Any character may complete the token
+    Any,
+    // The token continues while the characters are accepted
+    While(fn(char) -> bool),
+    // The token continues up to the given character; with .1, a
+    // backslash escapes the character after it
+    Delimiter(char, bool),
+    // The token continues up to the given character, which is
+    // escaped by doubling it; .1 is true right after one
+    Doubled(char, bool),
+    // is_symbol_or_number_char characters, a backslash escaping the
+    // character after it
+    SymbolWithEscapes,
+    // The token continues up to the given pair of characters
+    Close([char; 2]),
+    // The token continues for the given number of bytes
+    Bytes(usize),
+    // The token continues up to the end of the input
+    End,
+}
+
+// Checks the input following the point where the tokenizer waited for
+// something, keeping the state needed for that across chunks.
+pub(crate) struct WaitScan {
+    wait: Wait,
+    // After a backslash
+    escaped: bool,
+    prev: Option<char>,
+}
+
+impl WaitScan {
+    // `prev` is the character before the point where the tokenizer
+    // waited.
+    pub(crate) fn new(wait: Wait, prev: Option<char>) -> WaitScan {
+        WaitScan { wait, escaped: false, prev }
+    }
+
+    // Whether `s`, following the text scanned so far, contains a
+    // character that may complete the token.
+    pub(crate) fn may_complete(&mut self, s: &str) -> bool {
+        for c in s.chars() {
+            let stop = match &mut self.wait {
+                Wait::Any => true,
+                Wait::While(accepted) => !accepted(c),
+                _ if self.escaped => {
+                    self.escaped = false;
+                    false
+                }
+                Wait::Delimiter(_, true) | Wait::SymbolWithEscapes if c == '\\' => {
+                    self.escaped = true;
+                    false
+                }
+                Wait::Delimiter(delimiter, _) => c == *delimiter,
+                Wait::Doubled(delimiter, after) => if c == *delimiter {
+                    *after = !*after;
+                    false
                 } else {
-                    return;
+                    *after
+                },
+                Wait::SymbolWithEscapes => !is_symbol_or_number_char(c),
+                Wait::Close([c0, c1]) => self.prev == Some(*c0) && c == *c1,
+                Wait::Bytes(n) => if *n == 0 {
+                    true
+                } else {
+                    *n = n.saturating_sub(c.len_utf8());
+                    false
+                },
+                Wait::End => false,
+            };
+            if stop {
+                return true
+            }
+            self.prev = Some(c);
+        }
+        false
+    }
+}
+
+// The characters for the tokenizer, which tells what it waits for
+// when asking for characters within tokens.
+pub(crate) trait CharSource: Iterator<Item = anyhow::Result<(char, Pos)>> {
+    fn next_for(&mut self, wait: Wait) -> Option<Self::Item>;
+}
+
+// Remembers where the last character taken from the stream ends, and
+// what the tokenizer waited for when the stream ran out.
+struct EndTracking<I> {
+    cs: I,
+    end: Pos,
+    wait_at_end: Option<Wait>,
+}
+
+impl<I: Iterator<Item = anyhow::Result<(char, Pos)>>> CharSource for EndTracking<I> {
+    fn next_for(&mut self, wait: Wait) -> Option<Self::Item> {
+        let r = self.cs.next();
+        match &r {
+            Some(Ok((c, pos))) => self.end = pos.after(*c),
+            Some(Err(_)) => {}
+            None => if self.wait_at_end.is_none() {
+                self.wait_at_end = Some(wait);
+            }
+        }
+        r
+    }
+}
+
+impl<I: Iterator<Item = anyhow::Result<(char, Pos)>>> Iterator for EndTracking<I> {
+    type Item = anyhow::Result<(char, Pos)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_for(Wait::Any)
+    }
+}
+
+// The text of the token being read. When parsing from a string, as
+// long as the text is the input verbatim (no escapes processed etc.),
+// only its place in the input is tracked; it's copied into `buf`
+// only once it differs.
+pub(crate) struct TokenText<'i> {
+    // The input, when parsing from a string
+    input: Option<&'i str>,
+    // Whether the text is input[start..end], otherwise it's `buf`
+    verbatim: bool,
+    start: usize,
+    end: usize,
+    buf: String,
+}
+
+impl<'i> TokenText<'i> {
+    fn new(input: Option<&'i str>) -> TokenText<'i> {
+        TokenText { input, verbatim: input.is_some(), start: 0, end: 0, buf: String::new() }
+    }
+
+    fn clear(&mut self) {
+        self.verbatim = self.input.is_some();
+        self.start = 0;
+        self.end = 0;
+        self.buf.clear();
+    }
+
+    // Append `c`, read from the input at `pos`.
+    fn push(&mut self, c: char, pos: Pos) {
+        if let (true, Some(input)) = (self.verbatim, self.input) {
+            if self.start == self.end {
+                self.start = pos.byte_offset;
+                self.end = pos.byte_offset;
+            }
+            if pos.byte_offset == self.end
+                && input.get(self.end..).is_some_and(|rest| rest.starts_with(c))
+            {
+                self.end += c.len_utf8();
+                return
+            }
+        }
+        self.processed().push(c)
+    }
+
+    // Prepend `c`, read from the input at `pos`.
+    fn push_front(&mut self, c: char, pos: Pos) {
+        if let (true, Some(input)) = (self.verbatim, self.input) {
+            if pos.byte_offset + c.len_utf8() == self.start
+                && input.get(pos.byte_offset..).is_some_and(|rest| rest.starts_with(c))
+            {
+                self.start = pos.byte_offset;
+                return
+            }
+        }
+        self.processed().insert(0, c)
+    }
+
+    // The text for appending text that isn't in the input as is
+    // (after copying the text so far into it).
+    fn processed(&mut self) -> &mut String {
+        if let (true, Some(input)) = (self.verbatim, self.input) {
+            self.buf.push_str(&input[self.start..self.end]);
+            self.verbatim = false;
+        }
+        &mut self.buf
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if self.verbatim {
+            self.end = self.start + len;
+        } else {
+            self.buf.truncate(len);
+        }
+    }
+
+    fn upcase(&mut self) {
+        if self.chars().any(|c| !c.to_uppercase().eq([c])) {
+            let upper = self.to_uppercase();
+            self.buf = upper;
+            self.verbatim = false;
+        }
+    }
+}
+
+impl Deref for TokenText<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match (self.verbatim, self.input) {
+            (true, Some(input)) => &input[self.start..self.end],
+            _ => &self.buf
+        }
+    }
+}
+
+// How the parser turns the text of tokens into strings.
+pub(crate) trait MakeStr {
+    type Str;
+
+    fn make_str(&self, text: &str) -> Self::Str;
+}
+
+// Copies the text.
+pub(crate) struct OwnedStrs;
+
+impl MakeStr for OwnedStrs {
+    type Str = KString;
+
+    fn make_str(&self, text: &str) -> KString {
+        KString::from_ref(text)
+    }
+}
+
+// Borrows the text if it is a part of the input, as TokenText leaves
+// it when it appears there verbatim (i.e. no escapes had to be
+// processed), copies it otherwise.
+struct BorrowedStrs<'a>(&'a str);
+
+impl<'a> MakeStr for BorrowedStrs<'a> {
+    type Str = KStringCow<'a>;
+
+    fn make_str(&self, text: &str) -> KStringCow<'a> {
+        let start = (text.as_ptr() as usize).wrapping_sub(self.0.as_ptr() as usize);
+        match self.0.get(start..).and_then(|rest| rest.get(..text.len())) {
+            Some(raw) => KStringCow::from_ref(raw),
+            None => KString::from_ref(text).into()
+        }
+    }
+}
+
+// The end of a token: the position of the character following it if
+// that was already read, otherwise the end of the last character
+// read.
+fn token_end(next: &Option<(char, Pos)>, end: Pos) -> Pos {
+    match next {
+        Some((_, pos)) => *pos,
+        None => end
+    }
+}
+
+/// The state of [parse](parse) between tokens.
+pub(crate) struct Parser<'s, 't, I: Iterator, M: MakeStr> {
+    // Fused since some branches continue after seeing EOF
+    cs: EndTracking<Fuse<I>>,
+    settings: &'s Settings<'t>,
+    strs: M,
+    // Escape errors found in a string and the string token itself,
+    // in recover mode
+    pending: VecDeque<Result<TokenWithPos<M::Str>, ParseErrorWithPos>>,
+    errors: Vec<ParseErrorWithPos>,
+    tmp: TokenText<'s>,
+    maybe_next_c_pos: Option<(char, Pos)>,
+    lastpos: Pos,
+    // Set after a fatal error
+    done: bool,
+}
+
+impl<'s, 't, I, M> Parser<'s, 't, I, M>
+    where I: Iterator<Item = anyhow::Result<(char, Pos)>>,
+          M: MakeStr
+{
+    // The position of the last character read outside of tokens,
+    // see parse_resuming.
+    pub(crate) fn lastpos(&self) -> Pos {
+        self.lastpos
+    }
+
+    // What the tokenizer waited for when it ran out of characters,
+    // if it did.
+    pub(crate) fn wait_at_end(&self) -> Option<Wait> {
+        self.cs.wait_at_end
+    }
+
+    // Report an error; stops parsing unless `recoverable` and
+    // `Modes::recover_from_errors` is set.
+    fn error(
+        &mut self,
+        e: ParseErrorWithPos,
+        recoverable: bool
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        if !(recoverable && self.settings.modes.recover_from_errors) {
+            self.done = true;
+        }
+        Some(Err(e))
+    }
+
+    // Report the errors collected by read_delimited, then `r`.
+    fn with_collected_errors(
+        &mut self,
+        r: Result<TokenWithPos<M::Str>, ParseErrorWithPos>
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        if self.errors.is_empty() {
+            return Some(r)
+        }
+        self.pending.extend(self.errors.drain(..).map(Err));
+        self.pending.push_back(r);
+        self.pending.pop_front()
+    }
+
+    // Continue after '#' at `pos`.
+    fn hash_token(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        // #f #t #true #false #\character #:keyword #!special #<structure >
+        let settings = self.settings;
+        let strs = &self.strs;
+        let cs = &mut self.cs;
+        let tmp = &mut self.tmp;
+        let c0;
+        if let Some(r) = cs.next() {
+            match r {
+                Err(e) => {
+                    let e = ParseError::IOError(e).at(self.lastpos).until(cs.end);
+                    return self.error(e, false)
+                }
+                Ok(cp) => {
+                    c0 = cp.0;
+                    self.lastpos = cp.1;
                 }
             }
-            lastpos = pos;
-            
-            if let Some(t) = maybe_open_close(c) {
-                co.yield_(Ok(TokenWithPos(t, pos))).await;
-            } else if c.is_whitespace() {
-                if settings.modes.retain_whitespace {
-                    match read_while(Some(c), pos, &mut cs, is_whitespace_char,
-                                     Some(&mut tmp)) {
-                        Err(e) => {
-                            co.yield_(Err(e)).await;
-                            return;
+        } else {
+            let e = ParseError::InvalidHashToken.at(pos).until(cs.end);
+            return self.error(e, false)
+        }
+
+        if (matches!(c0, '\\' | ';' | '|' | '!')
+            || (c0 == ':' && !settings.format.hash_colon_symbols))
+            && !settings.format.has_scheme_hash_syntax
+        {
+            let e = ParseError::InvalidHashToken.at(pos).until(cs.end);
+            self.error(e, true)
+        } else if c0 == '\\' {
+            // #\character
+            match read_while(None, pos, cs, is_symbol_or_number_char, Some(tmp)) {
+                Err(e) => {
+                    let e = e.until(cs.end);
+                    self.error(e, false)
+                }
+                Ok((_lastc, mut mcp)) => {
+                    if tmp.is_empty() {
+                        // `#\(`, `#\ ` etc.: the character is taken
+                        // as is
+                        if let Some((c, p)) = mcp.take() {
+                            tmp.push(c, p);
                         }
-                        Ok((_lastc, mcp)) => {
-                            co.yield_(
-                                Ok(
-                                    TokenWithPos(
-                                        Token::Whitespace(KString::from_ref(&tmp)),
-                                        pos))).await;
-                            if mcp.is_none() {
-                                // avoid calling next() again!
-                                return
-                            }
-                            maybe_next_c_pos = mcp;
+                    }
+                    let span = Span::new(pos, token_end(&mcp, cs.end));
+                    self.maybe_next_c_pos = mcp;
+                    let r = read_char_name(tmp, pos).or_else(|e| {
+                        if settings.format.case_insensitive_char_names {
+                            common_lisp_char_name(tmp).ok_or(e)
+                        } else {
+                            Err(e)
                         }
+                    });
+                    match r {
+                        Err(e) => self.error(e.until(span.end), true),
+                        Ok(c) => Some(Ok(TokenWithPos(Token::Atom(Atom::Char(c)), span)))
                     }
                 }
-            } else if c == ';' {
-                // line comments
-                match read_while(Some(c), pos, &mut cs, |c| c != '\n',
-                                 Some(&mut tmp)) {
+            }
+        } else if c0 == ';' || (c0 == '_' && settings.format.hash_underscore_discards) {
+            // #;
+            Some(Ok(TokenWithPos(Token::CommentExpr, Span::new(pos, cs.end))))
+        } else if c0 == '\''
+            && (settings.format.clojure_quoting || settings.format.hash_quote_is_function)
+        {
+            // #'var, #'function
+            let rm = if settings.format.clojure_quoting {
+                ReaderMacro::Var
+            } else {
+                ReaderMacro::Function
+            };
+            Some(Ok(TokenWithPos(Token::ReaderMacro(rm), Span::new(pos, cs.end))))
+        } else if (c0 == '{' && settings.format.hash_curly_is_set)
+            || (c0 == '(' && (settings.format.hash_paren_is_fn
+                              || settings.format.hash_paren_is_vector))
+        {
+            // #{set} #(fn) #(vector)
+            let (rm, pk) = if c0 == '{' {
+                (ReaderMacro::Set, Parenkind::Curly)
+            } else if settings.format.hash_paren_is_fn {
+                (ReaderMacro::AnonFn, Parenkind::Round)
+            } else {
+                (ReaderMacro::Vector, Parenkind::Round)
+            };
+            let open = TokenWithPos(Token::Open(pk), Span::new(self.lastpos, cs.end));
+            self.pending.push_back(Ok(open));
+            Some(Ok(TokenWithPos(Token::ReaderMacro(rm), Span::new(pos, self.lastpos))))
+        } else if c0 == '?' && settings.format.has_reader_conditionals {
+            self.reader_conditional(pos)
+        } else if (c0 == '+' || c0 == '-') && settings.format.has_feature_conditionals {
+            Some(Ok(TokenWithPos(Token::FeatureConditional(c0 == '+'),
+                                 Span::new(pos, cs.end))))
+        } else if c0 == '.' && settings.format.hash_dot_is_read_eval {
+            let span = Span::new(pos, cs.end);
+            if settings.format.allow_read_eval {
+                Some(Ok(TokenWithPos(Token::ReaderMacro(ReaderMacro::ReadEval), span)))
+            } else {
+                let e = ParseError::ReadEvalNotAllowed.at(pos).until(span.end);
+                self.error(e, true)
+            }
+        } else if c0 == 's' && settings.format.hash_s_is_record {
+            self.record(pos)
+        } else if c0 == '@' && settings.format.hash_at_skips {
+            self.skip_bytes(pos)
+        } else if c0.is_ascii_digit() && settings.format.has_datum_labels {
+            self.datum_label(c0, pos)
+        } else if (c0 == 'b' || c0 == 'x') && settings.format.hash_b_x_are_bit_vectors {
+            self.smtlib_bit_vector(c0, pos)
+        } else if c0 == '*' && settings.format.hash_star_is_bit_vector {
+            self.bit_vector(pos)
+        } else if (c0 == 'c' || c0 == 'C') && settings.format.hash_c_is_complex {
+            self.hash_complex(pos)
+        } else if c0 == '|' {
+            self.block_comment(pos)
+        } else if c0 == ':' {
+            let csn = match cs.next().transpose() {
+                Err(e) => {
+                    let e = ParseError::IOError(e).at(pos).until(cs.end);
+                    return self.error(e, false)
+                }
+                Ok(v) => v
+            };
+            if let Some((c1, pos1)) = csn {
+                let r =
+                    if c1 == '|' {
+                        read_delimited(settings, pos, cs, '|', tmp, &mut self.errors)
+                    } else {
+                        // Nonquoted symbol read. Gambit takes c1 no
+                        // matter what it is.
+                        read_while(Some(c1), pos1, cs, is_symbol_or_number_char,
+                                   Some(tmp))
+                            .map(|(_lastc, mcp)| {
+                                self.maybe_next_c_pos = mcp;
+                            })
+                    };
+                let r = match r {
                     Err(e) => {
-                        co.yield_(Err(e)).await;
-                        return;
+                        let e = e.until(cs.end);
+                        self.done = true;
+                        Err(e)
                     }
-                    Ok((_lastc, mcp)) => {
-                        if settings.modes.retain_comments {
-                            let (start, rest) =
-                                take_while_and_rest(&tmp, |c| c == ';');
-                            let nsemicolons = start.len();
-                            if let Ok(nsemi) = u8::try_from(nsemicolons) {
-                                co.yield_(
-                                    Ok(
-                                        TokenWithPos(
-                                            Token::Comment(
-                                                CommentStyle::Singleline(nsemi),
-                                                KString::from_ref(rest)),
-                                            pos))).await;
-                            } else {
-                                co.yield_(Err(ParseError::TooManySemicolons.at(pos)))
-                                    .await
-                            }
-                        }
-                        if mcp.is_none() {
-                            // avoid calling next() again!
-                            return
+                    Ok(()) => {
+                        if c1 != '|' && settings.format.upcase_symbols {
+                            tmp.upcase();
                         }
-                        maybe_next_c_pos = mcp;
+                        let constructor =
+                            if settings.format.hashcolon_is_keyword {
+                                Atom::Keyword1
+                            } else {
+                                Atom::UninternedSymbol
+                            };
+                        Ok(TokenWithPos(
+                            Token::Atom(constructor(strs.make_str(tmp))),
+                            Span::new(pos, token_end(&self.maybe_next_c_pos, cs.end))))
                     }
+                };
+                self.with_collected_errors(r)
+            } else {
+                let e = ParseError::UnexpectedEOF(
+                    Context::KeywordOrUninternedSymbol).at(pos).until(cs.end);
+                self.error(e, false)
+            }
+        } else if c0 == '!' {
+            // #!special
+            match read_while(None, pos, cs, |c| c.is_ascii_alphabetic(), Some(tmp)) {
+                Err(e) => {
+                    let e = e.until(cs.end);
+                    self.error(e, false)
                 }
-            } else if c == '#' {
-                // #f #t #true #false #\character #:keyword #!special #<structure >
-                let c0;
-                if let Some(r) = cs.next() {
-                    match r {
+                Ok((_lastc, mcp)) => {
+                    let span = Span::new(pos, token_end(&mcp, cs.end));
+                    self.maybe_next_c_pos = mcp;
+                    if let Ok(specialkind) = SpecialKind::try_from(&**tmp) {
+                        Some(Ok(TokenWithPos(Token::Atom(Atom::Special(specialkind)),
+                                             span)))
+                    } else {
+                        let e = ParseError::InvalidSpecialToken(
+                            Box::new(KString::from_ref(tmp))).at(pos).until(span.end);
+                        self.error(e, true)
+                    }
+                }
+            }
+        } else if c0.is_alphabetic() && settings.format.hash_symbol_is_tag {
+            // #tag
+            match read_while(Some(c0), self.lastpos, cs, is_symbol_or_number_char, Some(tmp)) {
+                Err(e) => {
+                    let e = e.until(cs.end);
+                    self.error(e, false)
+                }
+                Ok((_lastc, mcp)) => {
+                    let span = Span::new(pos, token_end(&mcp, cs.end));
+                    self.maybe_next_c_pos = mcp;
+                    let tag = strs.make_str(tmp);
+                    Some(Ok(TokenWithPos(Token::Tag(tag), span)))
+                }
+            }
+        } else if matches!(c0.to_ascii_lowercase(),
+                           'x' | 'b' | 'o' | 'd' | 'e' | 'i') {
+            // Numbers with radix or exactness prefix
+            match read_while(Some(c0), pos, cs, is_symbol_or_number_char, Some(tmp)) {
+                Err(e) => {
+                    let e = e.until(cs.end);
+                    self.error(e, false)
+                }
+                Ok((_lastc, mcp)) => {
+                    let span = Span::new(pos, token_end(&mcp, cs.end));
+                    self.maybe_next_c_pos = mcp;
+                    match read_prefixed_number(tmp) {
+                        Ok(Some(n)) =>
+                            Some(Ok(TokenWithPos(Token::Atom(Atom::Number(n)), span))),
+                        Ok(None) => {
+                            let e = ParseError::InvalidHashToken.at(pos).until(span.end);
+                            self.error(e, true)
+                        }
                         Err(e) => {
-                            co.yield_(Err(
-                                ParseError::IOError(e).at(lastpos))).await;
-                            return;
+                            let e = e.at(pos).until(span.end);
+                            self.error(e, true)
                         }
-                        Ok(cp) => {
-                            c0 = cp.0;
-                            lastpos = cp.1;
+                    }
+                }
+            }
+        } else {
+            // XX todo: #<structure >
+
+            // #t #f #true #false #u8( #f64( etc.
+            let has_vectors = settings.format.hash_u8_is_bytevector
+                || settings.format.has_srfi4_vectors;
+            let is_name_char: fn(char) -> bool = if has_vectors {
+                |c| c.is_ascii_alphanumeric()
+            } else {
+                |c| c.is_ascii_alphabetic()
+            };
+            match read_while(Some(c0), pos, cs, is_name_char, Some(tmp)) {
+                Err(e) => {
+                    let e = e.until(cs.end);
+                    self.error(e, false)
+                }
+                Ok((_lastc, mcp)) => {
+                    if let (Some(('(', _)), Some(kind)) = (mcp, NumericVectorKind::from_name(tmp)) {
+                        let enabled = if kind == NumericVectorKind::U8 {
+                            settings.format.hash_u8_is_bytevector
+                        } else {
+                            settings.format.has_srfi4_vectors
+                        };
+                        if enabled {
+                            return self.numeric_vector(kind, pos)
                         }
                     }
-                } else {
-                    co.yield_(Err(ParseError::InvalidHashToken.at(pos))).await;
-                    return;
+                    let span = Span::new(pos, token_end(&mcp, cs.end));
+                    self.maybe_next_c_pos = mcp;
+                    match read_hash_name(settings, c0, tmp, pos) {
+                        Err(e) => self.error(e.until(span.end), true),
+                        Ok(v) => Some(Ok(TokenWithPos(Token::Atom(v), span)))
+                    }
+                }
+            }
+        }
+    }
+
+    // Continue after `#|` at `pos`.
+    fn block_comment(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let r =
+            if self.settings.format.nested_block_comments {
+                read_nested_comment(pos, &mut self.cs, ['#', '|'], ['|', '#'], &mut self.tmp)
+            } else {
+                read_until(pos, &mut self.cs, &['|', '#'], &mut self.tmp)
+            };
+        match r {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok(()) =>
+                Some(Ok(TokenWithPos(
+                    Token::Comment(CommentStyle::Multiline,
+                                   self.strs.make_str(&self.tmp)),
+                    Span::new(pos, self.cs.end))))
+        }
+    }
+
+    // `(` at `pos`, which might start a `(; ;)` comment.
+    fn paren_or_comment(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        match self.cs.next().transpose() {
+            Err(e) => {
+                let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok(Some((';', _))) => {
+                match read_nested_comment(pos, &mut self.cs, ['(', ';'], [';', ')'],
+                                          &mut self.tmp) {
+                    Err(e) => {
+                        let e = e.until(self.cs.end);
+                        self.error(e, false)
+                    }
+                    Ok(()) =>
+                        Some(Ok(TokenWithPos(
+                            Token::Comment(CommentStyle::ParenSemicolon,
+                                           self.strs.make_str(&self.tmp)),
+                            Span::new(pos, self.cs.end))))
                 }
+            }
+            Ok(mcp) => {
+                self.maybe_next_c_pos = mcp;
+                let span = Span::new(pos, token_end(&self.maybe_next_c_pos, self.cs.end));
+                Some(Ok(TokenWithPos(Token::Open(Parenkind::Round), span)))
+            }
+        }
+    }
 
-                if c0 == '\\' {
-                    // #\character
-                    match read_while(None, pos, &mut cs, is_symbol_or_number_char,
-                                     Some(&mut tmp)) {
+    // Sexplib: continue after `#` at `pos`, which starts a comment or
+    // an atom.
+    fn sexplib_hash(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let cp = match self.cs.next().transpose() {
+            Err(e) => {
+                let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                return self.error(e, false)
+            }
+            Ok(cp) => cp
+        };
+        match cp {
+            Some(('|', _)) => self.block_comment(pos),
+            Some((';', _)) => Some(Ok(TokenWithPos(Token::CommentExpr,
+                                                   Span::new(pos, self.cs.end)))),
+            Some((c1, pos1)) if is_string_atom_char(c1) => {
+                let r = self.string_atom(c1, pos1);
+                // Include the `#`
+                self.tmp.push_front('#', pos);
+                r.map(|r| r.map(|TokenWithPos(_, span)| TokenWithPos(
+                    Token::Atom(Atom::String(self.strs.make_str(&self.tmp))),
+                    Span::new(pos, span.end))))
+            }
+            mcp => {
+                self.maybe_next_c_pos = mcp;
+                let span = Span::new(pos, token_end(&self.maybe_next_c_pos, self.cs.end));
+                let atom = Atom::String(self.strs.make_str("#"));
+                Some(Ok(TokenWithPos(Token::Atom(atom), span)))
+            }
+        }
+    }
+
+    // Sexplib: read the unquoted atom starting with `c` at `pos`.
+    fn string_atom(
+        &mut self,
+        c: char,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        match read_while(Some(c), pos, &mut self.cs, is_string_atom_char, Some(&mut self.tmp)) {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok((_lastc, mcp)) => {
+                let span = Span::new(pos, token_end(&mcp, self.cs.end));
+                self.maybe_next_c_pos = mcp;
+                let atom = Atom::String(self.strs.make_str(&self.tmp));
+                Some(Ok(TokenWithPos(Token::Atom(atom), span)))
+            }
+        }
+    }
+
+    // Continue after '#?' at `pos`: `#?(` or `#?@(`.
+    fn reader_conditional(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let mut rm = ReaderMacro::Conditional;
+        loop {
+            match self.cs.next().transpose() {
+                Err(e) => {
+                    let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                    return self.error(e, false)
+                }
+                Ok(Some(('@', pos1))) if rm == ReaderMacro::Conditional => {
+                    self.lastpos = pos1;
+                    rm = ReaderMacro::ConditionalSplicing;
+                }
+                Ok(Some(('(', pos1))) => {
+                    self.lastpos = pos1;
+                    let open = TokenWithPos(Token::Open(Parenkind::Round),
+                                            Span::new(pos1, self.cs.end));
+                    self.pending.push_back(Ok(open));
+                    return Some(Ok(TokenWithPos(Token::ReaderMacro(rm),
+                                                Span::new(pos, pos1))))
+                }
+                Ok(mcp) => {
+                    let end = token_end(&mcp, self.cs.end);
+                    self.maybe_next_c_pos = mcp;
+                    let e = ParseError::InvalidHashToken.at(pos).until(end);
+                    return self.error(e, true)
+                }
+            }
+        }
+    }
+
+    // Continue after '#s' at `pos`: `#s(`.
+    fn record(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        match self.cs.next().transpose() {
+            Err(e) => {
+                let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok(Some(('(', pos1))) => {
+                self.lastpos = pos1;
+                let open = TokenWithPos(Token::Open(Parenkind::Round),
+                                        Span::new(pos1, self.cs.end));
+                self.pending.push_back(Ok(open));
+                Some(Ok(TokenWithPos(Token::ReaderMacro(ReaderMacro::Record),
+                                     Span::new(pos, pos1))))
+            }
+            Ok(mcp) => {
+                let end = token_end(&mcp, self.cs.end);
+                self.maybe_next_c_pos = mcp;
+                let e = ParseError::InvalidHashToken.at(pos).until(end);
+                self.error(e, true)
+            }
+        }
+    }
+
+    // Continue after '#@' at `pos`: skip the number of bytes given
+    // by the digits, counting from the character after them (which
+    // is usually a space), or the rest of the input for `#@00`.
+    fn skip_bytes(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let mcp = match read_while(None, pos, &mut self.cs, is_digit, Some(&mut self.tmp)) {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                return self.error(e, false)
+            }
+            Ok((_lastc, mcp)) => mcp
+        };
+        // (The offset where skipping ends must fit, too)
+        let nskip = match self.tmp.parse::<usize>() {
+            Ok(n) if mcp.is_none_or(|(_, p)| p.byte_offset.checked_add(n).is_some()) => n,
+            _ => {
+                let e = ParseError::InvalidHashToken.at(pos).until(token_end(&mcp, self.cs.end));
+                self.maybe_next_c_pos = mcp;
+                return self.error(e, true)
+            }
+        };
+        if nskip == 0 && self.tmp.len() == 2 {
+            // #@00
+            while let Some(r) = self.cs.next_for(Wait::End) {
+                if let Err(e) = r {
+                    let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                    return self.error(e, false)
+                }
+            }
+            return None
+        }
+        if let Some((_, pos1)) = mcp {
+            if nskip == 0 {
+                self.maybe_next_c_pos = mcp;
+            } else {
+                let end = pos1.byte_offset + nskip;
+                loop {
+                    let wait = Wait::Bytes(end.saturating_sub(self.cs.end.byte_offset));
+                    match self.cs.next_for(wait).transpose() {
                         Err(e) => {
-                            co.yield_(Err(e)).await;
-                            return;
+                            let e = ParseError::IOError(e).at(self.lastpos)
+                                .until(self.cs.end);
+                            return self.error(e, false)
                         }
-                        Ok((_lastc, mcp)) => {
-                            maybe_next_c_pos = mcp;
-                            let r = (|| {
-                                let len = tmp.len();
-                                if len == 0 {
-                                    return Err(ParseError::InvalidHashToken.at(pos))
-                                }
-                                let c0 = tmp.chars().next().unwrap();
-                                if len == 1 {
-                                    return Ok(c0)
-                                }
-                                if c0 == 'x' || c0 == 'u' || c0 == 'U' {
-                                    // XX should we refuse lengths
-                                    // other than 4 for u and 8 for U?
-                                    // What about x?
-                                    return
-                                        if let Some(n) = parse_as_hexstr(&tmp[1..]) {
-                                            try_u32_to_char(n).at(pos)
-                                        } else {
-                                            Err(ParseError::InvalidHashToken.at(pos))
-                                        };
-                                }
-                                if let Some(c) = crate::value::name2char(&tmp) {
-                                    return Ok(c)
-                                }
-                                Err(ParseError::InvalidHashToken.at(pos))
-                            })();
-                            match r {
-                                Err(e) => {
-                                    co.yield_(Err(e)).await;
-                                    return;
-                                }
-                                Ok(c) => co.yield_(Ok(TokenWithPos(
-                                    Token::Atom(Atom::Char(c)),
-                                    pos))).await
-                            }
+                        Ok(Some((c, p))) if p.byte_offset >= end => {
+                            self.maybe_next_c_pos = Some((c, p));
+                            break
                         }
+                        Ok(Some(_)) => {}
+                        Ok(None) => break
                     }
+                }
+            }
+        }
+        self.next_token()
+    }
 
-                } else if c0 == ';' {
-                    // #;
-                    co.yield_(Ok(TokenWithPos(Token::CommentExpr, pos))).await
-                } else if c0 == '|' {
-                    // #| |#
-                    match read_until(pos, &mut cs, &['|', '#'], &mut tmp) {
-                        Err(e) => {
-                            co.yield_(Err(e)).await;
-                            return;
-                        }
-                        Ok(()) =>
-                            co.yield_(Ok(TokenWithPos(
-                                Token::Comment(CommentStyle::Multiline,
-                                               KString::from_ref(&tmp)),
-                                pos))).await
+    // Continue after '#' at `pos` with the digit `c0`: `#n=` or
+    // `#n#`.
+    fn datum_label(
+        &mut self,
+        c0: char,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        match read_while(Some(c0), pos, &mut self.cs, is_digit, Some(&mut self.tmp)) {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok((_lastc, mcp)) => {
+                let token = match (mcp, self.tmp.parse::<u32>()) {
+                    (Some(('=', _)), Ok(n)) => Some(Token::DatumLabel(n)),
+                    (Some(('#', _)), Ok(n)) => Some(Token::DatumRef(n)),
+                    _ => None
+                };
+                if let Some(t) = token {
+                    Some(Ok(TokenWithPos(t, Span::new(pos, self.cs.end))))
+                } else {
+                    let end = token_end(&mcp, self.cs.end);
+                    self.maybe_next_c_pos = mcp;
+                    let e = ParseError::InvalidHashToken.at(pos).until(end);
+                    self.error(e, true)
+                }
+            }
+        }
+    }
+
+    // The next character, read ahead or from the input; EOF is an
+    // error when reading what started at `pos` in `context`.
+    fn take_char(
+        &mut self,
+        pos: Pos,
+        context: Context
+    ) -> Result<(char, Pos), ParseErrorWithPos> {
+        if let Some(cp) = self.maybe_next_c_pos.take() {
+            return Ok(cp)
+        }
+        match self.cs.next().transpose_io_at(self.lastpos)? {
+            Some(cp) => {
+                self.lastpos = cp.1;
+                Ok(cp)
+            }
+            None => Err(ParseError::UnexpectedEOF(context).at(pos))
+        }
+    }
+
+    // Continue after '?' at `pos`: Emacs Lisp's character syntax.
+    fn question_char(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let mut text = String::new();
+        match self.elisp_char(pos, &mut text) {
+            Err(e) => {
+                let end = token_end(&self.maybe_next_c_pos, self.cs.end);
+                let recoverable = e.err.is_recoverable();
+                self.error(e.until(end), recoverable)
+            }
+            Ok(code) => {
+                let span = Span::new(pos, token_end(&self.maybe_next_c_pos, self.cs.end));
+                // Codes with modifier bits aren't characters
+                let atom = match char::from_u32(code) {
+                    Some(c) => Atom::Char(c),
+                    None => Atom::Number(R5RSNumber::Integer(code.into()))
+                };
+                Some(Ok(TokenWithPos(Token::Atom(atom), span)))
+            }
+        }
+    }
+
+    // The code of a character in Emacs Lisp syntax (after `?`),
+    // including modifier bits. A character read ahead is left in
+    // maybe_next_c_pos. `text` collects the input, for error messages.
+    fn elisp_char(&mut self, pos: Pos, text: &mut String) -> Result<u32, ParseErrorWithPos> {
+        let invalid = |text: &String| {
+            ParseError::InvalidCharName(Box::new(KString::from_ref(text))).at(pos)
+        };
+        // The modifiers (`\^` as `C`) before the character, in a loop
+        // rather than by recursion, as there can be any number of them
+        let mut modifiers = Vec::new();
+        let code = loop {
+            let (c, _) = self.take_char(pos, Context::Char)?;
+            text.push(c);
+            if c != '\\' {
+                break c as u32
+            }
+            let (c, _) = self.take_char(pos, Context::Char)?;
+            text.push(c);
+            match c {
+                '^' => modifiers.push('C'),
+                'A' | 's' | 'H' | 'S' | 'C' | 'M' => {
+                    let cp = self.take_char(pos, Context::Char)?;
+                    if cp.0 != '-' {
+                        self.maybe_next_c_pos = Some(cp);
+                        break if c == 's' { ' ' as u32 } else { c as u32 }
                     }
-                } else if c0 == ':' {
-                    let got_eof : bool;
-                    let csn = match cs.next().transpose() {
-                        Err(e) => {
-                            co.yield_(Err(ParseError::IOError(e).at(pos))).await;
-                            return;
+                    text.push('-');
+                    modifiers.push(c);
+                }
+                'a' => break 7,
+                'b' => break 8,
+                't' => break 9,
+                'n' => break 10,
+                'v' => break 11,
+                'f' => break 12,
+                'r' => break 13,
+                'e' => break 27,
+                'd' => break 127,
+                'x' | 'u' | 'U' | '0'..='7' => {
+                    let (radix, maxlen, exact) = match c {
+                        'x' => (16, 8, false),
+                        'u' => (16, 4, true),
+                        'U' => (16, 8, true),
+                        _ => (8, 2, false)
+                    };
+                    let mut code = c.to_digit(8).unwrap_or(0);
+                    let mut len = 0;
+                    while len < maxlen {
+                        let cp = match self.take_char(pos, Context::Char) {
+                            Ok(cp) => cp,
+                            Err(e) if matches!(e.err, ParseError::UnexpectedEOF(_)) => break,
+                            Err(e) => return Err(e)
+                        };
+                        if let Some(d) = cp.0.to_digit(radix) {
+                            text.push(cp.0);
+                            code = code * radix + d;
+                            len += 1;
+                        } else {
+                            self.maybe_next_c_pos = Some(cp);
+                            break
                         }
-                        Ok(v) => v
+                    }
+                    if (exact && len < maxlen) || (c == 'x' && len == 0) {
+                        return Err(invalid(text))
+                    }
+                    break code
+                }
+                c => break c as u32
+            }
+        };
+        // The innermost modifier applies first
+        Ok(modifiers.iter().rev().fold(code, |code, m| match m {
+            'C' => elisp_control(code),
+            'A' => code | 1 << 22,
+            's' => code | 1 << 23,
+            'H' => code | 1 << 24,
+            'S' => code | 1 << 25,
+            _ => code | 1 << 27
+        }))
+    }
+
+    // Continue after `#b` or `#x` (`c0`) at `pos`.
+    fn smtlib_bit_vector(
+        &mut self,
+        c0: char,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        match read_while(None, pos, &mut self.cs, is_symbol_or_number_char,
+                         Some(&mut self.tmp)) {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok((_lastc, mcp)) => {
+                let span = Span::new(pos, token_end(&mcp, self.cs.end));
+                self.maybe_next_c_pos = mcp;
+                let digits = &self.tmp;
+                let atom =
+                    if digits.is_empty() {
+                        None
+                    } else if c0 == 'b' {
+                        digits.chars().map(|c| match c {
+                            '0' => Some(false),
+                            '1' => Some(true),
+                            _ => None
+                        }).collect::<Option<Vec<bool>>>().map(Atom::BitVector)
+                    } else if digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        Some(Atom::Hexadecimal(
+                            self.strs.make_str(digits)))
+                    } else {
+                        None
                     };
-                    if let Some((c1, _pos1)) = csn {
-                        if c1 == '|' {
-                            match read_delimited(settings, pos, &mut cs, '|', &mut tmp) {
-                                Err(e) => {
-                                    co.yield_(Err(e)).await;
-                                    return;
-                                }
-                                Ok(()) => {
-                                    got_eof = false;
-                                }
-                            }
-                            
+                if let Some(atom) = atom {
+                    Some(Ok(TokenWithPos(Token::Atom(atom), span)))
+                } else {
+                    let e = ParseError::InvalidBitVector.at(pos).until(span.end);
+                    self.error(e, true)
+                }
+            }
+        }
+    }
+
+    // Continue after '#*' at `pos`.
+    fn bit_vector(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        match read_while(None, pos, &mut self.cs, is_symbol_or_number_char,
+                         Some(&mut self.tmp)) {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok((_lastc, mcp)) => {
+                let span = Span::new(pos, token_end(&mcp, self.cs.end));
+                self.maybe_next_c_pos = mcp;
+                let bits: Option<Vec<bool>> = self.tmp.chars().map(|c| match c {
+                    '0' => Some(false),
+                    '1' => Some(true),
+                    _ => None
+                }).collect();
+                if let Some(bits) = bits {
+                    Some(Ok(TokenWithPos(Token::Atom(Atom::BitVector(bits)), span)))
+                } else {
+                    let e = ParseError::InvalidBitVector.at(pos).until(span.end);
+                    self.error(e, true)
+                }
+            }
+        }
+    }
+
+    // Continue after `#u8(` and the like at `pos`: the numbers up to
+    // the closing paren, read as tokens so that whitespace and
+    // comments work as usual.
+    fn numeric_vector(
+        &mut self,
+        kind: NumericVectorKind,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let mut v = NumericVector::new(kind);
+        // An element out of range is reported after the closing
+        // paren, so that parsing can continue after the vector
+        let mut out_of_range = None;
+        let mut skip_next = false;
+        loop {
+            let TokenWithPos(t, span) = match self.next() {
+                None => {
+                    let e = ParseError::UnexpectedEOF(Context::NumericVector)
+                        .at(pos).until(self.cs.end);
+                    return self.error(e, false)
+                }
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e))
+                }
+                Some(Ok(t)) => t
+            };
+            match t {
+                Token::Whitespace(_) | Token::Comment(..) => (),
+                Token::CommentExpr => skip_next = true,
+                Token::Close(Parenkind::Round) => {
+                    let span = Span::new(pos, span.end);
+                    return match out_of_range {
+                        Some(e) => self.error(e, true),
+                        None => Some(Ok(TokenWithPos(
+                            Token::Atom(Atom::NumericVector(Box::new(v))), span)))
+                    }
+                }
+                Token::Atom(_) if skip_next => skip_next = false,
+                Token::Atom(Atom::Number(n)) => {
+                    if !v.push(&n) && out_of_range.is_none() {
+                        let right_type = match n {
+                            R5RSNumber::Integer(_) => true,
+                            R5RSNumber::Real(_) | R5RSNumber::Rational(_) => kind.is_float(),
+                            R5RSNumber::Complex(..) | R5RSNumber::Polar(..) => false
+                        };
+                        let err = if right_type {
+                            ParseError::NumericVectorElementOutOfRange(kind)
                         } else {
-                            // Nonquoted symbol read. Gambit takes c1
-                            // no matter what it is.
-                            match read_while(Some(c1),
-                                             pos,
-                                             &mut cs,
-                                             is_symbol_or_number_char,
-                                             Some(&mut tmp)) {
-                                Err(e) => {
-                                    co.yield_(Err(e)).await;
-                                    return;
-                                }
-                                Ok((_lastc, mcp)) => {
-                                    maybe_next_c_pos = mcp;
-                                    got_eof = mcp.is_none();
-                                }
+                            ParseError::InvalidNumericVectorElement(kind)
+                        };
+                        out_of_range = Some(err.at(span.start).until(span.end));
+                    }
+                }
+                _ => {
+                    let e = ParseError::InvalidNumericVectorElement(kind)
+                        .at(span.start).until(span.end);
+                    return self.error(e, false)
+                }
+            }
+        }
+    }
+
+    // Continue after '#c' at `pos`: `#c(real imag)`.
+    fn hash_complex(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let mut parts = None;
+        match read_while(None, pos, &mut self.cs, |c| c != ')', Some(&mut self.tmp)) {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                return self.error(e, false)
+            }
+            Ok((_lastc, None)) => {
+                let e = ParseError::InvalidComplex.at(pos).until(self.cs.end);
+                return self.error(e, false)
+            }
+            Ok((_lastc, Some(_close))) => {
+                // The ')' has been consumed, `cs.end` is after it
+                if let Some(inner) = self.tmp.trim_start().strip_prefix('(') {
+                    let mut ns = inner.split_whitespace().map(read_common_lisp_number);
+                    if let (Some(Ok(Some(re))), Some(Ok(Some(im))), None) =
+                        (ns.next(), ns.next(), ns.next())
+                    {
+                        parts = Some((re, im));
+                    }
+                }
+            }
+        }
+        let span = Span::new(pos, self.cs.end);
+        if let Some((re, im)) = parts {
+            let n = R5RSNumber::Complex(Box::new(re), Box::new(im));
+            Some(Ok(TokenWithPos(Token::Atom(Atom::Number(n)), span)))
+        } else {
+            let e = ParseError::InvalidComplex.at(pos).until(span.end);
+            self.error(e, true)
+        }
+    }
+
+    // Continue after '\\' at `pos`: `\c`, `\newline` etc.
+    fn backslash_char(
+        &mut self,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let (c0, pos0) = match self.cs.next() {
+            Some(Ok(cp)) => cp,
+            Some(Err(e)) => {
+                let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                return self.error(e, false)
+            }
+            None => {
+                let e = ParseError::InvalidCharName(Box::new(KString::from_static("")))
+                    .at(pos).until(self.cs.end);
+                return self.error(e, false)
+            }
+        };
+        self.lastpos = pos0;
+        let tmp = &mut self.tmp;
+        let end =
+            if c0.is_alphanumeric() {
+                match read_while(Some(c0), pos, &mut self.cs, is_symbol_or_number_char,
+                                 Some(tmp)) {
+                    Err(e) => {
+                        let e = e.until(self.cs.end);
+                        return self.error(e, false)
+                    }
+                    Ok((_lastc, mcp)) => {
+                        let end = token_end(&mcp, self.cs.end);
+                        self.maybe_next_c_pos = mcp;
+                        end
+                    }
+                }
+            } else {
+                tmp.clear();
+                tmp.push(c0, pos0);
+                self.cs.end
+            };
+        let span = Span::new(pos, end);
+        match read_char_name(tmp, pos) {
+            Ok(c) => Some(Ok(TokenWithPos(Token::Atom(Atom::Char(c)), span))),
+            Err(_) => {
+                let e = ParseError::InvalidCharName(Box::new(KString::from_ref(tmp)))
+                    .at(pos).until(end);
+                self.error(e, true)
+            }
+        }
+    }
+
+    // Clojure's reader macros `` ` `` `~` `~@` `@`, starting with `c`
+    // at `pos`.
+    fn clojure_prefix(
+        &mut self,
+        c: char,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let rm = match c {
+            '`' => ReaderMacro::SyntaxQuote,
+            '@' => ReaderMacro::Deref,
+            _ => match self.cs.next().transpose() {
+                Err(e) => {
+                    let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                    return self.error(e, false)
+                }
+                Ok(Some(('@', pos1))) => {
+                    self.lastpos = pos1;
+                    ReaderMacro::UnquoteSplicing
+                }
+                Ok(mcp) => {
+                    self.maybe_next_c_pos = mcp;
+                    ReaderMacro::Unquote
+                }
+            }
+        };
+        let end = token_end(&self.maybe_next_c_pos, self.cs.end);
+        Some(Ok(TokenWithPos(Token::ReaderMacro(rm), Span::new(pos, end))))
+    }
+
+    // Numbers, symbols, keywords, Dot, starting with `c` at `pos`.
+    fn symbol_or_number(
+        &mut self,
+        c: char,
+        pos: Pos
+    ) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let settings = self.settings;
+        let tmp = &mut self.tmp;
+        let mut escaped = false;
+        let r = if settings.format.backslash_escapes_in_symbols {
+            read_symbol_with_escapes(c, pos, &mut self.cs, tmp, &mut escaped)
+        } else if settings.format.wat_idchars {
+            read_while(Some(c), pos, &mut self.cs, is_wat_idchar, Some(tmp))
+        } else {
+            read_while(Some(c), pos, &mut self.cs, is_symbol_or_number_char, Some(tmp))
+        };
+        match r {
+            Err(e) => {
+                let e = e.until(self.cs.end);
+                self.error(e, false)
+            }
+            Ok((lastc, mcp)) => {
+                let lastc = lastc.unwrap();
+                let span = Span::new(pos, token_end(&mcp, self.cs.end));
+                self.maybe_next_c_pos = mcp;
+                if escaped {
+                    let atom =
+                        if c == ':' {
+                            Atom::Keyword1(self.strs.make_str(&tmp[1..]))
+                        } else {
+                            Atom::Symbol(self.strs.make_str(tmp))
+                        };
+                    return Some(Ok(TokenWithPos(Token::Atom(atom), span)))
+                }
+                if tmp.len() == 1 && lastc == '.' && settings.format.has_dotted_pairs {
+                    return Some(Ok(TokenWithPos(Token::Dot, span)))
+                }
+                if is_digit(c) || c == '-' || c == '+' || c == '.'
+                    || settings.format.numbers == NumberSyntax::Wat
+                {
+                    let n = match settings.format.numbers {
+                        NumberSyntax::Scheme => read_number(tmp),
+                        NumberSyntax::CommonLisp => read_common_lisp_number(tmp),
+                        NumberSyntax::EmacsLisp => Ok(read_elisp_number(tmp)),
+                        NumberSyntax::Wat => Ok(read_wat_number(tmp)),
+                        NumberSyntax::SmtLib => match smtlib_number_kind(tmp) {
+                            Some(true) => {
+                                let atom = Atom::Decimal(
+                                    self.strs.make_str(tmp));
+                                return Some(Ok(TokenWithPos(Token::Atom(atom), span)))
                             }
+                            Some(false) => read_number(tmp),
+                            // Simple symbols can't start with a digit
+                            None if is_digit(c) => Err(ParseError::NotSupportedByFormat(
+                                Box::new("symbol starting with a digit"))),
+                            None => Ok(None)
                         }
-                        let constructor =
-                            if settings.format.hashcolon_is_keyword {
-                                Atom::Keyword1
-                            } else {
-                                Atom::UninternedSymbol
-                            };
-                        co.yield_(Ok(TokenWithPos(
-                            Token::Atom(constructor(KString::from_ref(&tmp))),
-                            pos))).await;
-                        if got_eof {
-                            return;
+                        NumberSyntax::Edn => match read_edn_number(tmp) {
+                            Ok(Some(EdnNumber::Decimal)) => {
+                                let atom =
+                                    Atom::Decimal(self.strs.make_str(&tmp[..tmp.len() - 1]));
+                                return Some(Ok(TokenWithPos(Token::Atom(atom), span)))
+                            }
+                            Ok(Some(EdnNumber::Number(n))) => Ok(Some(n)),
+                            Ok(None) => Ok(None),
+                            Err(e) => Err(e)
                         }
-                    } else {
-                        co.yield_(Err(ParseError::UnexpectedEOF(
-                            Context::KeywordOrUninternedSymbol).at(pos))).await;
-                        return;
-                    }
-                } else if c0 == '!' {
-                    // #!special
-                    match read_while(None, pos, &mut cs, |c| c.is_ascii_alphabetic(),
-                                     Some(&mut tmp)) {
+                    };
+                    match n {
+                        Ok(Some(r)) =>
+                            return Some(Ok(TokenWithPos(Token::Atom(Atom::Number(r)), span))),
+                        Ok(None) => {}
                         Err(e) => {
-                            co.yield_(Err(e)).await;
-                            return;
+                            let e = e.at(pos).until(span.end);
+                            return self.error(e, true)
                         }
-                        Ok((_lastc, mcp)) => {
-                            maybe_next_c_pos = mcp;
-
-                            if let Ok(specialkind) = SpecialKind::try_from(&*tmp) {
-                                co.yield_(Ok(
-                                    TokenWithPos(
-                                        Token::Atom(Atom::Special(specialkind)),
-                                        pos))).await;
-                            } else {
-                                co.yield_(Err(
-                                    ParseError::InvalidSpecialToken(
-                                        Box::new(KString::from_ref(&tmp)))
-                                        .at(pos))).await;
-                                return;
-                            }
+                    }
+                }
+                let strs = &self.strs;
+                if settings.format.has_nil_true_false {
+                    match &**tmp {
+                        "nil" => return Some(Ok(TokenWithPos(Token::Atom(Atom::Nil), span))),
+                        "true" | "false" => {
+                            let atom = Atom::Bool(&**tmp == "true");
+                            return Some(Ok(TokenWithPos(Token::Atom(atom), span)))
                         }
+                        _ => {}
                     }
-                } else {
-                    // XX todo: #<structure >
+                }
+                if settings.format.upcase_symbols {
+                    tmp.upcase();
+                }
+                let atom =
+                    if c == ':' {
+                        Atom::Keyword1(strs.make_str(&tmp[1..]))
+                    } else if let Some(pkglen) = settings.format.package_qualified_symbols
+                        .then(|| package_name_len(tmp)).flatten()
+                    {
+                        Atom::PackageSymbol(strs.make_str(tmp), pkglen)
+                    } else if lastc == ':' && settings.format.trailing_colon_is_keyword {
+                        Atom::Keyword2(strs.make_str(&tmp[0..tmp.len()-1]))
+                    } else {
+                        Atom::Symbol(strs.make_str(tmp))
+                    };
+                Some(Ok(TokenWithPos(Token::Atom(atom), span)))
+            }
+        }
+    }
+
+    // Read up to the next token.
+    fn next_token(&mut self) -> Option<Result<TokenWithPos<M::Str>, ParseErrorWithPos>> {
+        let settings = self.settings;
+        loop {
+            let c;
+            let pos;
+            if let Some(cp) = self.maybe_next_c_pos.take() {
+                (c, pos) = cp;
+            } else {
+                match self.cs.next_for(Wait::While(is_whitespace_char))? {
+                    Err(e) => {
+                        let e = ParseError::IOError(e).at(self.lastpos).until(self.cs.end);
+                        return self.error(e, false)
+                    }
+                    Ok(cp) => {
+                        (c, pos) = cp;
+                    }
+                }
+            }
+            self.lastpos = pos;
 
-                    // #t #f #true #false
-                    match read_while(Some(c0), pos, &mut cs, |c| c.is_ascii_alphabetic(),
-                                     Some(&mut tmp)) {
+            if settings.format.atoms_are_strings && c != '#' && is_string_atom_char(c) {
+                return self.string_atom(c, pos)
+            } else if settings.format.wat_idchars && is_wat_idchar(c) {
+                return self.symbol_or_number(c, pos)
+            } else if c == '(' && settings.format.paren_semicolon_comments {
+                return self.paren_or_comment(pos)
+            } else if let Some(t) = maybe_open_close(c) {
+                return Some(Ok(TokenWithPos(t, Span::new(pos, self.cs.end))))
+            } else if c.is_whitespace() || (c == ',' && settings.format.comma_is_whitespace) {
+                if settings.modes.retain_whitespace {
+                    let is_whitespace =
+                        if settings.format.comma_is_whitespace {
+                            is_whitespace_or_comma_char
+                        } else {
+                            is_whitespace_char
+                        };
+                    match read_while(Some(c), pos, &mut self.cs, is_whitespace,
+                                     Some(&mut self.tmp)) {
                         Err(e) => {
-                            co.yield_(Err(e)).await;
-                            return;
+                            let e = e.until(self.cs.end);
+                            return self.error(e, false)
                         }
                         Ok((_lastc, mcp)) => {
-                            maybe_next_c_pos = mcp;
-                            let r = (|| {
-                                let len = tmp.len();
-                                if len == 0 {
-                                    return Err(ParseError::InvalidHashToken.at(pos))
-                                }
-                                if len == 1 {
-                                    match c0 {
-                                        'f' => return Ok(Atom::Bool(false)),
-                                        't' => return Ok(Atom::Bool(true)),
-                                        _ => {}
-                                    }
-                                }
-                                if settings.format.accept_long_false_true {
-                                    if tmp == "false" {
-                                        return Ok(Atom::Bool(false))
-                                    }
-                                    if tmp == "true" {
-                                        return Ok(Atom::Bool(true))
-                                    }
-                                }
-
-                                // XX others?
-                                Err(ParseError::InvalidHashToken.at(pos))
-                            })();
-                            match r {
-                                Err(e) => {
-                                    co.yield_(Err(e)).await;
-                                    return;
-                                }
-                                Ok(v) => co.yield_(Ok(TokenWithPos(
-                                    Token::Atom(v),
-                                    pos))).await
+                            let span = Span::new(pos, token_end(&mcp, self.cs.end));
+                            self.maybe_next_c_pos = mcp;
+                            return Some(Ok(TokenWithPos(
+                                Token::Whitespace(
+                                    self.strs.make_str(&self.tmp)),
+                                span)))
+                        }
+                    }
+                }
+            } else if c == ';' {
+                // line comments
+                match read_while(Some(c), pos, &mut self.cs, |c| c != '\n',
+                                 Some(&mut self.tmp)) {
+                    Err(e) => {
+                        let e = e.until(self.cs.end);
+                        return self.error(e, false)
+                    }
+                    Ok((_lastc, mcp)) => {
+                        let span = Span::new(pos, token_end(&mcp, self.cs.end));
+                        self.maybe_next_c_pos = mcp;
+                        if settings.modes.retain_comments {
+                            let (start, rest) =
+                                take_while_and_rest(&self.tmp, |c| c == ';');
+                            let nsemicolons = start.len();
+                            return if let Ok(nsemi) = u8::try_from(nsemicolons) {
+                                Some(Ok(TokenWithPos(
+                                    Token::Comment(
+                                        CommentStyle::Singleline(nsemi),
+                                        self.strs.make_str(rest)),
+                                    span)))
+                            } else {
+                                // (Parsing continued after this one
+                                // already before there was a
+                                // recovery mode)
+                                Some(Err(ParseError::TooManySemicolons.at(pos)
+                                         .until(span.end)))
                             }
                         }
                     }
                 }
+            } else if c == '#' && settings.format.atoms_are_strings {
+                return self.sexplib_hash(pos)
+            } else if c == '#' {
+                return self.hash_token(pos)
+            } else if c == '\\' && settings.format.backslash_chars {
+                return self.backslash_char(pos)
             } else if let Some(constructor) =
                 delimiter2maybe_stringlike_constructor(c)
             {
-                match read_delimited(settings, pos, &mut cs, c, &mut tmp) {
+                let r =
+                    if (c == '"' && settings.format.doubled_quote_escapes)
+                        || (c == '|' && settings.format.verbatim_quoted_symbols)
+                    {
+                        read_verbatim(pos, &mut self.cs, c, c == '"', &mut self.tmp)
+                            .map(|mcp| self.maybe_next_c_pos = mcp)
+                    } else {
+                        read_delimited(settings, pos, &mut self.cs, c, &mut self.tmp,
+                                       &mut self.errors)
+                    };
+                let r = match r {
                     Err(e) => {
-                        co.yield_(Err(e)).await;
-                        return;
-                    }
-                    Ok(()) => {
-                        co.yield_(Ok(
-                            TokenWithPos(
-                                Token::Atom(
-                                    constructor(KString::from_ref(&tmp))),
-                                pos))).await;
+                        self.done = true;
+                        Err(e.until(self.cs.end))
                     }
-                }
+                    Ok(()) => Ok(TokenWithPos(
+                        Token::Atom(constructor(
+                            self.strs.make_str(&self.tmp))),
+                        Span::new(pos, token_end(&self.maybe_next_c_pos, self.cs.end))))
+                };
+                return self.with_collected_errors(r)
+            } else if settings.format.clojure_quoting && matches!(c, '`' | '~' | '@') {
+                return self.clojure_prefix(c, pos)
+            } else if c == '?' && settings.format.question_mark_chars {
+                return self.question_char(pos)
             } else if let Some(t) = char2special_token(c) {
                 let t =
-                    if t == Token::Unquote {
-                        if let Some(r) = cs.next() {
-                            match r {
-                                Err(e) => {
-                                    co.yield_(Err(
-                                        ParseError::IOError(e).at(lastpos))).await;
-                                    return;
-                                }
-                                Ok(cp) => {
-                                    if cp.0 == '@' {
-                                        lastpos = cp.1;
-                                        Token::UnquoteSplicing
-                                    } else {
-                                        maybe_next_c_pos = Some(cp);
-                                        t
-                                    }
+                    if matches!(t, Token::Unquote) {
+                        match self.cs.next()? {
+                            Err(e) => {
+                                let e = ParseError::IOError(e).at(self.lastpos)
+                                    .until(self.cs.end);
+                                return self.error(e, false)
+                            }
+                            Ok(cp) => {
+                                if cp.0 == '@' {
+                                    self.lastpos = cp.1;
+                                    Token::UnquoteSplicing
+                                } else {
+                                    self.maybe_next_c_pos = Some(cp);
+                                    t
                                 }
                             }
-                        } else {
-                            return
                         }
                     } else {
                         t
                     };
-                co.yield_(Ok(TokenWithPos(t, pos))).await;
+                let end = token_end(&self.maybe_next_c_pos, self.cs.end);
+                return Some(Ok(TokenWithPos(t, Span::new(pos, end))))
             } else {
-                // Numbers, symbols, keywords, Dot
-                match read_while(Some(c), pos, &mut cs, is_symbol_or_number_char,
-                                 Some(&mut tmp)) {
-                    Err(e) => {
-                        co.yield_(Err(e)).await;
-                        return;
-                    }
-                    Ok((lastc, mcp)) => {
-                        let lastc = lastc.unwrap();
-                        let r = (|| {
-                            if tmp.len() == 1 && lastc == '.'
-                                && settings.format.has_dotted_pairs {
-                                    return Ok(TokenWithPos(Token::Dot, pos));
-                            }
-                            if is_digit(c) {
-                                if let Some(r) = read_number(false, &tmp) {
-                                    return Ok(TokenWithPos(
-                                        Token::Atom(Atom::Number(r)),
-                                        pos))
-                                }
-                            } else if c == '-' {
-                                if let Some(r) = read_number(true, &tmp[1..]) {
-                                    return Ok(TokenWithPos(
-                                        Token::Atom(Atom::Number(r)),
-                                        pos))
-                                }
-                            }
-                            let (constructor, s)
-                                : (fn(KString) -> Atom, &str) =
-                                if c == ':' {
-                                    (Atom::Keyword1, &tmp[1..])
-                                } else if lastc == ':' {
-                                    (Atom::Keyword2, &tmp[0..tmp.len()-1])
-                                } else {
-                                    (Atom::Symbol, &tmp[0..])
-                                };
-                            Ok(
-                                TokenWithPos(
-                                    Token::Atom(
-                                        constructor(KString::from_ref(s))),
-                                    pos))
-                        })();
-                        co.yield_(r).await;
-                        if mcp.is_none() {
-                            // avoid calling next() again!
-                            return
-                        }
-                        maybe_next_c_pos = mcp;
-                    }
-                }
+                return self.symbol_or_number(c, pos)
             }
         }
-    }).into_iter()
+    }
+}
+
+impl<'s, 't, I, M> Iterator for Parser<'s, 't, I, M>
+    where I: Iterator<Item = anyhow::Result<(char, Pos)>>,
+          M: MakeStr
+{
+    type Item = Result<TokenWithPos<M::Str>, ParseErrorWithPos>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(r) = self.pending.pop_front() {
+            return Some(r)
+        }
+        if self.done {
+            return None
+        }
+        let r = self.next_token();
+        if r.is_none() {
+            self.done = true;
+        }
+        r
+    }
+}
+
+impl<'s, 't, I, M> FusedIterator for Parser<'s, 't, I, M>
+    where I: Iterator<Item = anyhow::Result<(char, Pos)>>,
+          M: MakeStr {}
+
+// The character denoted by `name` in `#\name` at `pos`.
+fn read_char_name(name: &str, pos: Pos) -> Result<char, ParseErrorWithPos> {
+    let len = name.len();
+    if len == 0 {
+        return Err(ParseError::InvalidHashToken.at(pos))
+    }
+    let c0 = name.chars().next().unwrap();
+    if len == 1 {
+        return Ok(c0)
+    }
+    if c0 == 'x' || c0 == 'u' || c0 == 'U' {
+        // XX should we refuse lengths other than 4 for u and 8 for U?
+        // What about x?
+        return
+            if let Some(n) = parse_as_hexstr(&name[1..]) {
+                try_u32_to_char(n).at(pos)
+            } else {
+                Err(ParseError::InvalidHashToken.at(pos))
+            };
+    }
+    if let Some(c) = crate::value::name2char(name) {
+        return Ok(c)
+    }
+    Err(ParseError::InvalidHashToken.at(pos))
+}
+
+// Emacs Lisp's control modifier applied to `code`.
+fn elisp_control(code: u32) -> u32 {
+    const MODIFIERS: u32 = !((1 << 22) - 1);
+    let base = code & !MODIFIERS;
+    match char::from_u32(base) {
+        Some('?') => 127 | (code & MODIFIERS),
+        Some('@'..='_') | Some('a'..='z') => (base & 0x1f) | (code & MODIFIERS),
+        _ => code | 1 << 26
+    }
+}
+
+// Common Lisp's names for `#\\Name`, in any case, besides the ones
+// that read_char_name knows.
+fn common_lisp_char_name(name: &str) -> Option<char> {
+    let name = name.to_ascii_lowercase();
+    match &*name {
+        "rubout" => Some('\x7F'),
+        "page" => Some('\x0C'),
+        "linefeed" => Some('\n'),
+        "nul" => Some('\0'),
+        _ => crate::value::name2char(&name)
+    }
+}
+
+// The length of the package name if `s` is `pkg:name` or
+// `pkg::name`.
+pub(crate) fn package_name_len(s: &str) -> Option<u32> {
+    let (pkg, rest) = s.split_once(':')?;
+    let name = rest.strip_prefix(':').unwrap_or(rest);
+    if pkg.is_empty() || name.is_empty() || name.contains(':') {
+        return None
+    }
+    u32::try_from(pkg.len()).ok()
+}
+
+// The value denoted by `#name` at `pos`, `c0` being the first
+// character of name.
+fn read_hash_name<S>(
+    settings: &Settings,
+    c0: char,
+    name: &str,
+    pos: Pos
+) -> Result<Atom<S>, ParseErrorWithPos> {
+    let len = name.len();
+    if len == 0 {
+        return Err(ParseError::InvalidHashToken.at(pos))
+    }
+    if len == 1 {
+        match c0 {
+            'f' => return Ok(Atom::Bool(false)),
+            't' => return Ok(Atom::Bool(true)),
+            _ => {}
+        }
+    }
+    if settings.format.accept_long_false_true {
+        if name == "false" {
+            return Ok(Atom::Bool(false))
+        }
+        if name == "true" {
+            return Ok(Atom::Bool(true))
+        }
+    }
+
+    // XX others?
+    Err(ParseError::InvalidHashToken.at(pos))
+}
+
+/// Parse a stream of characters and their positions into a stream of
+/// tokens (atoms or opening/closing tokens). Stops after the first
+/// error unless `Modes::recover_from_errors` is set, in which case it
+/// continues after the erroneous token where possible (errors in
+/// escape sequences are reported before the token that contains
+/// them).
+pub fn parse<'s>(
+    cs: impl Iterator<Item = anyhow::Result<(char, Pos)>> + 's,
+    settings: &'s Settings,
+)
+    -> impl Iterator<Item = Result<TokenWithPos, ParseErrorWithPos>> + 's
+{
+    Parser {
+        cs: EndTracking { cs: cs.fuse(), end: Pos::default(), wait_at_end: None },
+        settings,
+        strs: OwnedStrs,
+        pending: VecDeque::new(),
+        errors: Vec::new(),
+        tmp: TokenText::new(None),
+        maybe_next_c_pos: None,
+        lastpos: Pos::default(),
+        done: false,
+    }
+}
+
+// Same as `parse` but starting at `start`, with `lastpos` as the
+// position of the last character read before (for IO errors), to
+// continue parsing after the token stream was cut off.
+pub(crate) fn parse_resuming<'s, 't, I>(
+    cs: I,
+    settings: &'s Settings<'t>,
+    start: Pos,
+    lastpos: Pos,
+) -> Parser<'s, 't, I, OwnedStrs>
+    where I: Iterator<Item = anyhow::Result<(char, Pos)>>
+{
+    Parser {
+        cs: EndTracking { cs: cs.fuse(), end: start, wait_at_end: None },
+        settings,
+        strs: OwnedStrs,
+        pending: VecDeque::new(),
+        errors: Vec::new(),
+        tmp: TokenText::new(None),
+        maybe_next_c_pos: None,
+        lastpos,
+        done: false,
+    }
+}
+
+/// Same as [parse](parse) but parsing from a string in memory; the
+/// strings in the tokens borrow from `s` wherever they appear there
+/// verbatim (i.e. unless escapes had to be processed).
+pub fn parse_str<'a: 's, 's>(
+    s: &'a str,
+    settings: &'s Settings,
+)
+    -> impl Iterator<Item = Result<TokenWithPos<KStringCow<'a>>, ParseErrorWithPos>> + 's
+{
+    Parser {
+        cs: EndTracking { cs: str_chars(s).fuse(), end: Pos::default(), wait_at_end: None },
+        settings,
+        strs: BorrowedStrs(s),
+        pending: VecDeque::new(),
+        errors: Vec::new(),
+        tmp: TokenText::new(Some(s)),
+        maybe_next_c_pos: None,
+        lastpos: Pos::default(),
+        done: false,
+    }
 }
@@ -7,19 +7,47 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! A position within a stream.
+//! A position within a stream, and a span between two positions.
 
 //! For building full context, also see [context](crate::context).
 
 //! Both line and col are zero based; Emacs uses 1-based line
-//! numbering, so line is incremented by 1 in Display.
+//! numbering, so line is incremented by 1 in Display. col as well as
+//! char_offset count unicode code points.
 
 use std::cmp::Eq;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
 pub struct Pos {
     pub line: u32,
     pub col: u32,
+    /// Offset from the start of the stream in bytes (of the UTF-8
+    /// encoding)
+    pub byte_offset: usize,
+    /// Offset from the start of the stream in characters
+    pub char_offset: usize,
+}
+
+impl Pos {
+    /// The position of the character following `c`, if `c` is at
+    /// self.
+    pub fn after(self, c: char) -> Pos {
+        if c == '\n' {
+            Pos {
+                line: self.line + 1,
+                col: 0,
+                byte_offset: self.byte_offset + 1,
+                char_offset: self.char_offset + 1,
+            }
+        } else {
+            Pos {
+                line: self.line,
+                col: self.col + 1,
+                byte_offset: self.byte_offset + c.len_utf8(),
+                char_offset: self.char_offset + 1,
+            }
+        }
+    }
 }
 
 impl std::fmt::Display for Pos {
@@ -31,3 +59,29 @@ impl std::fmt::Display for Pos {
     }
 }
 
+/// The extent of a piece of syntax: `end` is the position after its
+/// last character.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct Span {
+    pub start: Pos,
+    pub end: Pos,
+}
+
+impl Span {
+    pub fn new(start: Pos, end: Pos) -> Span {
+        Span { start, end }
+    }
+
+    /// A span covering from the start of self to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start, end: other.end }
+    }
+}
+
+/// Shows the start position only, to keep messages short.
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
+           -> Result<(), std::fmt::Error> {
+        self.start.fmt(f)
+    }
+}
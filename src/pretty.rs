@@ -0,0 +1,356 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Pretty-printing values within a given line width.
+
+//! [Pretty](Pretty) is [Writable](crate::write::Writable), thus can
+//! be passed to
+//! [AnysexprFormat::write_all](crate::settings::AnysexprFormat::write_all)
+//! and friends, which write the atoms in the syntax of the format; it
+//! also implements `Display`, which doesn't know the format.
+
+//! A list that fits on the rest of the line is written on one line.
+//! Otherwise, its items are put on separate lines (Oppen's
+//! consistent breaks): for special forms (see
+//! [SpecialForms](SpecialForms)), the distinguished arguments are
+//! kept on the first line while they fit (else indented by twice the
+//! indentation), and the body is indented by
+//! [PrettyOptions::indent](PrettyOptions::indent); in other lists
+//! starting with a symbol, the arguments are aligned with the first
+//! one; the items of other lists are aligned with the first item,
+//! except that lists of atoms only are filled up to the width. Atoms
+//! are never broken, thus lines can still be longer than the width.
+
+use crate::settings::AnysexprFormat;
+use crate::value::{Atom, Parenkind, VValue, VValueWithPos};
+use crate::write::{WriteError, WriteOptions, Writer};
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+
+/// Names of special forms (the head symbols of lists) with their
+/// number of distinguished arguments, as Emacs'
+/// `lisp-indent-function`: e.g. `("define", 1)` gives
+///
+/// ```text
+/// (define (foo x)
+///   (bar x))
+/// ```
+pub type SpecialForms<'t> = &'t [(&'t str, u32)];
+
+pub const SCHEME_SPECIAL_FORMS: SpecialForms = &[
+    ("begin", 0), ("case", 1), ("define", 1), ("define-record-type", 2),
+    ("define-syntax", 1), ("define-values", 1), ("do", 2), ("lambda", 1),
+    ("let", 1), ("let*", 1), ("let-syntax", 1), ("let-values", 1),
+    ("let*-values", 1), ("letrec", 1), ("letrec*", 1), ("letrec-syntax", 1),
+    ("parameterize", 1), ("syntax-rules", 1), ("unless", 1), ("when", 1),
+];
+
+pub const CLOJURE_SPECIAL_FORMS: SpecialForms = &[
+    ("binding", 1), ("def", 1), ("defmacro", 1), ("defn", 1), ("defn-", 1),
+    ("defprotocol", 1), ("defrecord", 2), ("deftype", 2), ("do", 0), ("doseq", 1),
+    ("dotimes", 1), ("fn", 1), ("if-let", 1), ("let", 1), ("loop", 1), ("ns", 1),
+    ("try", 0), ("when", 1), ("when-let", 1), ("when-not", 1),
+];
+
+/// Upper case, as [COMMON_LISP_FORMAT](crate::settings::COMMON_LISP_FORMAT)
+/// reads symbols.
+pub const COMMON_LISP_SPECIAL_FORMS: SpecialForms = &[
+    ("BLOCK", 1), ("DEFCLASS", 2), ("DEFMACRO", 2), ("DEFMETHOD", 2), ("DEFPACKAGE", 1),
+    ("DEFUN", 2), ("DESTRUCTURING-BIND", 2), ("DOLIST", 1), ("DOTIMES", 1),
+    ("FLET", 1), ("HANDLER-CASE", 1), ("LABELS", 1), ("LAMBDA", 1), ("LET", 1),
+    ("LET*", 1), ("MACROLET", 1), ("MULTIPLE-VALUE-BIND", 2), ("PROGN", 0),
+    ("UNLESS", 1), ("UNWIND-PROTECT", 1), ("WHEN", 1),
+];
+
+pub const ELISP_SPECIAL_FORMS: SpecialForms = &[
+    ("condition-case", 2), ("defcustom", 1), ("defmacro", 2), ("defun", 2),
+    ("defvar", 1), ("dolist", 1), ("dotimes", 1), ("if", 2), ("lambda", 1),
+    ("let", 1), ("let*", 1), ("progn", 0), ("save-excursion", 0), ("unless", 1),
+    ("when", 1), ("while", 1), ("with-temp-buffer", 0),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrettyOptions<'t> {
+    /// The maximum line length aimed for, in characters.
+    pub width: usize,
+    /// The indentation of the body of special forms.
+    pub indent: usize,
+    pub special_forms: SpecialForms<'t>,
+}
+
+impl<'t> PrettyOptions<'t> {
+    /// Width 80, indentation 2, and the special forms of `format`.
+    pub fn new(format: &AnysexprFormat<'t>) -> PrettyOptions<'t> {
+        PrettyOptions {
+            width: 80,
+            indent: 2,
+            special_forms: format.special_forms,
+        }
+    }
+
+    fn special_form(&self, name: &str) -> Option<usize> {
+        self.special_forms.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, nargs)| *nargs as usize)
+    }
+}
+
+/// Display `.0` pretty-printed according to the options in `.1`.
+pub struct Pretty<'t, T: ?Sized>(pub &'t T, pub &'t PrettyOptions<'t>);
+
+impl<S: Deref<Target = str>> Display for Pretty<'_, VValue<S>> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let mut out = String::new();
+        Printer { out: &mut out, col: 0, opts: self.1, writer: None }
+            .value(self.0, 0)
+            .map_err(|_| std::fmt::Error)?;
+        f.write_str(&out)
+    }
+}
+
+impl<S: Deref<Target = str>> Display for Pretty<'_, VValueWithPos<S>> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        Pretty(&self.0.0, self.1).fmt(f)
+    }
+}
+
+/// Write `v` pretty-printed in the syntax of `format` to `out`.
+pub(crate) fn write_to<S: Deref<Target = str>>(
+    out: &mut String,
+    v: &VValue<S>,
+    opts: &PrettyOptions,
+    format: &AnysexprFormat
+) -> Result<(), WriteError> {
+    let writer = Writer { format, opts: &WriteOptions::default() };
+    // Check the parts that are written without the writer
+    writer.value(&mut String::new(), v)?;
+    Printer { out, col: 0, opts, writer: Some(writer) }.value(v, 0)
+}
+
+struct Printer<'s, 'o, 'w> {
+    out: &'s mut String,
+    /// The current column
+    col: usize,
+    opts: &'o PrettyOptions<'o>,
+    /// Writes atoms in a format, if given, else they are displayed
+    writer: Option<Writer<'w, 'w>>,
+}
+
+impl Printer<'_, '_, '_> {
+    fn text(&mut self, s: &str) {
+        match s.rfind('\n') {
+            Some(i) => self.col = s[i + 1..].chars().count(),
+            None => self.col += s.chars().count(),
+        }
+        self.out.push_str(s)
+    }
+
+    fn newline(&mut self, col: usize) {
+        self.out.push('\n');
+        for _ in 0..col {
+            self.out.push(' ');
+        }
+        self.col = col;
+    }
+
+    /// `v` written on one line.
+    fn flat<S: Deref<Target = str>>(&self, v: &VValue<S>) -> Result<String, WriteError> {
+        match &self.writer {
+            Some(w) => {
+                let mut s = String::new();
+                w.value(&mut s, v)?;
+                Ok(s)
+            }
+            None => Ok(v.to_string())
+        }
+    }
+
+    /// The length of `v` written on one line, if at most `limit`.
+    fn flat_width<S: Deref<Target = str>>(
+        &self,
+        v: &VValue<S>,
+        limit: usize
+    ) -> Result<Option<usize>, WriteError> {
+        let w = match v {
+            VValue::List(_, impr, vs) => {
+                let mut w = 2 + vs.len().saturating_sub(1) + if impr.is_some() { 2 } else { 0 };
+                for item in vs {
+                    if w > limit {
+                        return Ok(None)
+                    }
+                    match self.flat_width(&item.0, limit - w)? {
+                        Some(iw) => w += iw,
+                        None => return Ok(None)
+                    }
+                }
+                w
+            }
+            VValue::ReaderMacro(rm, v) => match self.flat_width(&v.0, limit)? {
+                Some(w) => rm.prefix().len() + w,
+                None => return Ok(None)
+            }
+            VValue::Tagged(tag, v) => match self.flat_width(&v.0, limit)? {
+                Some(w) => tag.chars().count() + 2 + w,
+                None => return Ok(None)
+            }
+            VValue::Labeled(n, v) => match self.flat_width(&v.0, limit)? {
+                Some(w) => n.to_string().len() + 2 + w,
+                None => return Ok(None)
+            }
+            _ => {
+                let s = self.flat(v)?;
+                if s.contains('\n') {
+                    return Ok(None)
+                }
+                s.chars().count()
+            }
+        };
+        Ok((w <= limit).then_some(w))
+    }
+
+    /// Whether `v` fits on the current line, followed by `trail`
+    /// more characters.
+    fn fits<S: Deref<Target = str>>(
+        &self,
+        v: &VValue<S>,
+        trail: usize
+    ) -> Result<bool, WriteError> {
+        let limit = self.opts.width.saturating_sub(self.col + trail);
+        Ok(self.flat_width(v, limit)?.is_some())
+    }
+
+    /// Write `v`, which is followed by `trail` characters (closing
+    /// parens) on the same line.
+    fn value<S: Deref<Target = str>>(
+        &mut self,
+        v: &VValue<S>,
+        trail: usize
+    ) -> Result<(), WriteError> {
+        if self.fits(v, trail)? {
+            let s = self.flat(v)?;
+            self.text(&s);
+            return Ok(())
+        }
+        match v {
+            VValue::List(pk, impr, vs) => self.list(*pk, impr.is_some(), vs, trail),
+            VValue::ReaderMacro(rm, v) => {
+                self.text(rm.prefix());
+                match &v.0 {
+                    // (Written as part of the syntax, see ValuePieces.)
+                    VValue::List(pk, impr, vs) if rm.list_kind().is_some() =>
+                        self.list(*pk, impr.is_some(), vs, trail),
+                    v => self.value(v, trail)
+                }
+            }
+            VValue::Tagged(tag, v) => {
+                self.text(&format!("#{} ", &**tag));
+                self.value(&v.0, trail)
+            }
+            VValue::Labeled(n, v) => {
+                self.text(&format!("#{}=", n));
+                self.value(&v.0, trail)
+            }
+            _ => {
+                let s = self.flat(v)?;
+                self.text(&s);
+                Ok(())
+            }
+        }
+    }
+
+    fn list<S: Deref<Target = str>>(
+        &mut self,
+        pk: Parenkind,
+        improper: bool,
+        vs: &[VValueWithPos<S>],
+        trail: usize
+    ) -> Result<(), WriteError> {
+        let start = self.col;
+        self.text(&pk.opening().to_string());
+        let len = vs.len();
+        // Write item i at the current position
+        let item = |p: &mut Self, i: usize| {
+            if improper && i + 1 == len {
+                p.text(". ");
+            }
+            let t = if i + 1 == len { trail + 1 } else { 0 };
+            p.value(&vs[i].0, t)
+        };
+        let head_symbol = match vs.first() {
+            Some(VValueWithPos(VValue::Atom(Atom::Symbol(s)), _)) => Some(&**s),
+            _ => None
+        };
+        let special = head_symbol
+            .filter(|_| pk == Parenkind::Round)
+            .and_then(|s| self.opts.special_form(s));
+        if let Some(mut nargs) = special {
+            // Scheme's named let
+            if head_symbol == Some("let") && matches!(
+                vs.get(1), Some(VValueWithPos(VValue::Atom(Atom::Symbol(_)), _)))
+            {
+                nargs += 1;
+            }
+            let indent = self.opts.indent;
+            item(self, 0)?;
+            let ndistinguished = nargs.min(len - 1);
+            let mut broken = false;
+            for (i, v) in vs.iter().enumerate().take(ndistinguished + 1).skip(1) {
+                let t = if i + 1 == len { trail + 1 } else { 0 };
+                if !broken && (i == 1 || self.fits(&v.0, 1 + t)?) {
+                    self.text(" ");
+                } else {
+                    broken = true;
+                    self.newline(start + 2 * indent);
+                }
+                item(self, i)?;
+            }
+            for i in ndistinguished + 1..len {
+                self.newline(start + indent);
+                item(self, i)?;
+            }
+        } else if head_symbol.is_some() && len > 1 {
+            item(self, 0)?;
+            self.text(" ");
+            let argcol = self.col;
+            item(self, 1)?;
+            for i in 2..len {
+                self.newline(argcol);
+                item(self, i)?;
+            }
+        } else if vs.iter().all(|v| matches!(v.0, VValue::Atom(_))) {
+            // Fill lines with atoms
+            for (i, v) in vs.iter().enumerate() {
+                if i > 0 {
+                    let t = if i + 1 == len { trail + 1 } else { 0 };
+                    let dot = if improper && i + 1 == len { 2 } else { 0 };
+                    if self.fits(&v.0, 1 + dot + t)? {
+                        self.text(" ");
+                    } else {
+                        self.newline(start + 1);
+                    }
+                }
+                item(self, i)?;
+            }
+        } else {
+            for i in 0..len {
+                if i > 0 {
+                    self.newline(start + 1);
+                }
+                item(self, i)?;
+            }
+        }
+        self.text(&pk.closing().to_string());
+        Ok(())
+    }
+}
+
+/// `v` pretty-printed as a string.
+pub fn to_string<S: Deref<Target = str>>(v: &VValue<S>, opts: &PrettyOptions) -> String {
+    Pretty(v, opts).to_string()
+}
@@ -0,0 +1,498 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Push based (incremental) parsing, for input that arrives in
+//! chunks, e.g. from sockets or pipes, where pulling characters via
+//! [parse](crate::parse::parse) would block. Feed byte chunks to a
+//! [PushParser](PushParser) to get the tokens completed so far, or to
+//! a [PushReader](PushReader) to get completed top-level values.
+//! Chunks may split UTF-8 sequences and tokens anywhere.
+
+//! The input after the last completed token is kept. When the
+//! tokenizer runs out of input within a token, it tells what it
+//! waits for (e.g. the closing `"` of a string); later chunks are
+//! only checked for that, and the token is tokenized again only once
+//! one arrives that may complete it. Thus feeding a long token in
+//! many small chunks takes time linear in its length. (An error
+//! within such a token, e.g. an invalid escape in a string, is thus
+//! only reported once the token may be complete.)
+
+use crate::pos::{Pos, Span};
+use crate::parse::{parse_resuming, Token, TokenWithPos, ParseErrorWithPos, Wait, WaitScan};
+use crate::read::{TokensRead, ReadError, ReadErrorWithPos};
+use crate::builder::{TreeBuilder, VValueBuilder};
+use crate::settings::Settings;
+use std::cell::Cell;
+use anyhow::anyhow;
+
+type TokenResult = Result<TokenWithPos, ParseErrorWithPos>;
+
+// The characters of the buffered input, with positions continuing
+// from `pos`, then `error` if any; records in `hit_end` whether the
+// parser asked for more than is there.
+struct BufChars<'b> {
+    cs: std::str::Chars<'b>,
+    pos: Pos,
+    error: Option<anyhow::Error>,
+    hit_end: &'b Cell<bool>,
+}
+
+impl<'b> Iterator for BufChars<'b> {
+    type Item = anyhow::Result<(char, Pos)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(c) = self.cs.next() {
+            let pos = self.pos;
+            self.pos = pos.after(c);
+            Some(Ok((c, pos)))
+        } else if let Some(e) = self.error.take() {
+            Some(Err(e))
+        } else {
+            self.hit_end.set(true);
+            None
+        }
+    }
+}
+
+/// A tokenizer that is fed the input in chunks, giving the same
+/// tokens and errors as [parse](crate::parse::parse) would for the
+/// concatenated input.
+pub struct PushParser<'s, 't> {
+    settings: &'s Settings<'t>,
+    // An incomplete UTF-8 sequence at the end of the input so far
+    partial: Vec<u8>,
+    // The decoded input that hasn't been turned into tokens yet,
+    // starting at `start`
+    buf: String,
+    start: Pos,
+    // The tokenizer's last position when it was at `start`
+    lastpos: Pos,
+    // What the tokenizer waited for at the end of `buf`, if it ran
+    // out of input within a token and nothing has arrived since that
+    // may complete it
+    waiting: Option<WaitScan>,
+    // Whether `buf` contains anything but whitespace
+    mid_token: bool,
+    // The number of lists opened by the tokens given out so far and
+    // not closed yet
+    depth: usize,
+    // Set after a fatal error
+    failed: bool,
+}
+
+impl<'s, 't> PushParser<'s, 't> {
+    pub fn new(settings: &'s Settings<'t>) -> PushParser<'s, 't> {
+        PushParser {
+            settings,
+            partial: Vec::new(),
+            buf: String::new(),
+            start: Pos::default(),
+            lastpos: Pos::default(),
+            waiting: None,
+            mid_token: false,
+            depth: 0,
+            failed: false,
+        }
+    }
+
+    /// Feed the next chunk of input, get the tokens (and errors)
+    /// completed by it.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<TokenResult> {
+        if self.failed {
+            return Vec::new()
+        }
+        self.partial.extend_from_slice(bytes);
+        let buflen = self.buf.len();
+        let (valid, invalid_len) = match std::str::from_utf8(&self.partial) {
+            Ok(s) => {
+                self.buf.push_str(s);
+                (self.partial.len(), None)
+            }
+            Err(e) => {
+                let valid = e.valid_up_to();
+                self.buf.push_str(
+                    std::str::from_utf8(&self.partial[..valid])
+                        .expect("checked by valid_up_to"));
+                // error_len is None if the sequence is just incomplete
+                (valid, e.error_len())
+            }
+        };
+        self.partial.drain(..valid);
+        let new = &self.buf[buflen..];
+        self.mid_token = self.mid_token || new.contains(|c: char| !c.is_whitespace());
+        if let Some(invalid_len) = invalid_len {
+            // The tokenizer sees this as an IO error, just like with
+            // buffered_chars
+            let e = anyhow!("invalid byte sequence: {:02x?}",
+                            &self.partial[..invalid_len]);
+            self.fail(e)
+        } else if let Some(waiting) = &mut self.waiting {
+            if waiting.may_complete(new) {
+                self.tokenize(false, None)
+            } else {
+                Vec::new()
+            }
+        } else {
+            self.tokenize(false, None)
+        }
+    }
+
+    /// Signal that getting more input failed, get the remaining
+    /// tokens and `e` as a `ParseError::IOError`, at the position
+    /// where `parse` would report it.
+    pub fn fail(&mut self, e: anyhow::Error) -> Vec<TokenResult> {
+        if self.failed {
+            return Vec::new()
+        }
+        let out = self.tokenize(true, Some(e));
+        self.failed = true;
+        out
+    }
+
+    /// Signal the end of the input, get the remaining tokens (and
+    /// errors, e.g. about unfinished strings).
+    pub fn finish(&mut self) -> Vec<TokenResult> {
+        if self.failed {
+            return Vec::new()
+        }
+        let e = if self.partial.is_empty() {
+            None
+        } else {
+            Some(anyhow!("invalid byte sequence: {:02x?}", self.partial))
+        };
+        let out = self.tokenize(true, e);
+        self.failed = true;
+        out
+    }
+
+    /// Whether input has been fed that is not part of a token given
+    /// out yet (not counting whitespace between tokens).
+    pub fn is_mid_token(&self) -> bool {
+        !self.partial.is_empty() || self.mid_token
+    }
+
+    /// Whether the tokens given out so far have opened more lists
+    /// than they closed.
+    pub fn is_mid_list(&self) -> bool {
+        self.depth > 0
+    }
+
+    /// The number of lists opened and not yet closed by the tokens
+    /// given out so far.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    // Run the tokenizer over the buffered input followed by `error`,
+    // if given, keeping what wasn't completed unless `at_eof`.
+    fn tokenize(
+        &mut self,
+        at_eof: bool,
+        error: Option<anyhow::Error>
+    ) -> Vec<TokenResult> {
+        let mut out = Vec::new();
+        let hit_end = Cell::new(false);
+        let mut consumed = self.start;
+        let wait_at_end = {
+            let cs = BufChars {
+                cs: self.buf.chars(),
+                pos: self.start,
+                error,
+                hit_end: &hit_end
+            };
+            let mut ts = parse_resuming(cs, self.settings, self.start, self.lastpos);
+            while let Some(r) = ts.next() {
+                if hit_end.get() && !at_eof {
+                    // `r` may continue in the next chunk
+                    break
+                }
+                let end = match &r {
+                    Ok(TokenWithPos(_, span)) => span.end,
+                    Err(e) => e.span.end
+                };
+                if end.byte_offset > consumed.byte_offset {
+                    consumed = end;
+                    self.lastpos = ts.lastpos();
+                }
+                match &r {
+                    Ok(TokenWithPos(Token::Open(_), _)) => self.depth += 1,
+                    Ok(TokenWithPos(Token::Close(_), _)) => {
+                        self.depth = self.depth.saturating_sub(1)
+                    }
+                    _ => {}
+                }
+                out.push(r);
+            }
+            ts.wait_at_end()
+        };
+        if !hit_end.get() {
+            // The tokenizer stopped before the end of the input: it
+            // gave a fatal error.
+            self.failed = true;
+        }
+        if at_eof {
+            self.buf.clear();
+        } else {
+            self.buf.drain(..consumed.byte_offset - self.start.byte_offset);
+            self.start = consumed;
+        }
+        self.mid_token = self.buf.contains(|c: char| !c.is_whitespace());
+        self.waiting = Some(WaitScan::new(wait_at_end.unwrap_or(Wait::Any), self.buf.chars().next_back()));
+        out
+    }
+}
+
+
+/// A reader that is fed the input in chunks, giving the same values
+/// and errors as [read_all](crate::settings::AnysexprFormat::read_all)
+/// would for the concatenated input. Stops at the first error.
+pub struct PushReader<'s, 't, B: TreeBuilder = VValueBuilder> {
+    parser: PushParser<'s, 't>,
+    builder: B,
+    // The tokens of the current top-level value
+    tokens: Vec<TokenWithPos>,
+    // The nesting depth at the end of `tokens`
+    depth: usize,
+    // The number of values still needed to complete the top-level
+    // one (prefixes like `'` need one more value, `#;` two)
+    needed: usize,
+    // Whether a top-level value has been given out, for `.` handling
+    seen_value: bool,
+    dot: TopLevelDot,
+    failed: bool,
+}
+
+// Where we are after a `.` outside of lists, which read_all accepts
+// only if followed by one value and EOF (and then reports as an
+// error).
+#[derive(Clone, Copy)]
+enum TopLevelDot {
+    None,
+    // Reading the value after the dot at the given span
+    Tail(Span),
+    // Read it
+    AfterTail(Span),
+}
+
+impl<'s, 't> PushReader<'s, 't> {
+    pub fn new(settings: &'s Settings<'t>) -> PushReader<'s, 't> {
+        PushReader::with_builder(VValueBuilder, settings)
+    }
+}
+
+impl<'s, 't, B: TreeBuilder> PushReader<'s, 't, B> {
+    /// A reader building the values via `builder`.
+    pub fn with_builder(builder: B, settings: &'s Settings<'t>)
+                        -> PushReader<'s, 't, B> {
+        PushReader {
+            parser: PushParser::new(settings),
+            builder,
+            tokens: Vec::new(),
+            depth: 0,
+            needed: 1,
+            seen_value: false,
+            dot: TopLevelDot::None,
+            failed: false,
+        }
+    }
+
+    /// Feed the next chunk of input, get the top-level values (and
+    /// errors) completed by it.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Result<B::Value, ReadErrorWithPos>> {
+        let mut out = Vec::new();
+        for r in self.parser.feed(bytes) {
+            self.push_token(r, &mut out);
+        }
+        out
+    }
+
+    /// Signal the end of the input, get the remaining values (and
+    /// errors, e.g. about unclosed lists).
+    pub fn finish(&mut self) -> Vec<Result<B::Value, ReadErrorWithPos>> {
+        let mut out = Vec::new();
+        for r in self.parser.finish() {
+            self.push_token(r, &mut out);
+        }
+        // Leftovers of a read may start another value
+        while !self.tokens.is_empty() && !self.failed {
+            self.read_tokens(None, &mut out);
+        }
+        if let TopLevelDot::AfterTail(span) = self.dot {
+            self.error(ReadError::DotOutsideListContext.at(span), &mut out);
+        }
+        self.failed = true;
+        out
+    }
+
+    /// Signal that getting more input failed, get the remaining
+    /// values and the error (see [PushParser::fail](PushParser::fail)).
+    pub fn fail(&mut self, e: anyhow::Error) -> Vec<Result<B::Value, ReadErrorWithPos>> {
+        let mut out = Vec::new();
+        for r in self.parser.fail(e) {
+            self.push_token(r, &mut out);
+        }
+        self.failed = true;
+        out
+    }
+
+    /// Whether input has been fed that is not part of a token yet.
+    pub fn is_mid_token(&self) -> bool {
+        self.parser.is_mid_token()
+    }
+
+    /// Whether a top-level list has been started and not finished.
+    pub fn is_mid_list(&self) -> bool {
+        self.depth > 0
+    }
+
+    /// Whether tokens have been fed that don't make up a complete
+    /// top-level value yet (a list that's not closed, or a prefix
+    /// like `'` that's still missing its value).
+    pub fn is_mid_value(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    fn error(
+        &mut self,
+        e: ReadErrorWithPos,
+        out: &mut Vec<Result<B::Value, ReadErrorWithPos>>
+    ) {
+        if !self.failed {
+            out.push(Err(e));
+            self.failed = true;
+        }
+    }
+
+    fn push_token(
+        &mut self,
+        r: TokenResult,
+        out: &mut Vec<Result<B::Value, ReadErrorWithPos>>
+    ) {
+        if self.failed {
+            return
+        }
+        let t = match r {
+            Ok(t) => t,
+            Err(e) => {
+                // Let the reader decide whether an error in the
+                // tokens before it comes first
+                return self.read_tokens(Some(e), out)
+            }
+        };
+        if let TopLevelDot::AfterTail(_) = self.dot {
+            // Like read_all, look at the very next token
+            let e = match t.0 {
+                Token::Close(pk) => ReadError::UnexpectedClosingParen(pk),
+                _ => ReadError::ExpectingOneItemAfterDot
+            };
+            return self.error(e.at(t.1), out)
+        }
+        let completes_value = match &t.0 {
+            Token::Whitespace(_) | Token::Comment(_, _) => {
+                if self.tokens.is_empty() {
+                    return
+                }
+                false
+            }
+            Token::Open(_) => {
+                self.depth += 1;
+                false
+            }
+            Token::Close(_) => {
+                if self.depth == 0 {
+                    // An error, reported by the reader
+                    self.needed = 1;
+                    true
+                } else {
+                    self.depth -= 1;
+                    self.depth == 0
+                }
+            }
+            Token::Atom(_) | Token::DatumRef(_) => self.depth == 0,
+            Token::CommentExpr | Token::FeatureConditional(_) => {
+                // Two values to go: the one to discard (or the
+                // feature expression) and the next
+                if self.depth == 0 {
+                    self.needed += 1;
+                }
+                false
+            }
+            Token::Dot => {
+                if self.depth == 0 {
+                    // Any prefixes before it are dropped, as in
+                    // read_all
+                    self.tokens.clear();
+                    self.needed = 1;
+                    return self.top_level_dot(t.1, out)
+                }
+                false
+            }
+            Token::Quote | Token::Quasiquote | Token::Unquote
+                | Token::UnquoteSplicing | Token::ReaderMacro(_)
+                | Token::Tag(_) | Token::DatumLabel(_) => false,
+        };
+        self.tokens.push(t);
+        if completes_value {
+            self.needed -= 1;
+            if self.needed == 0 {
+                self.read_tokens(None, out);
+            }
+        }
+    }
+
+    fn top_level_dot(
+        &mut self,
+        span: Span,
+        out: &mut Vec<Result<B::Value, ReadErrorWithPos>>
+    ) {
+        if let TopLevelDot::Tail(_) = self.dot {
+            self.error(ReadError::ImproperlyPlacedDot.at(span), out)
+        } else if !self.seen_value {
+            self.error(ReadError::DotWithoutPrecedingItem.at(span), out)
+        } else {
+            self.dot = TopLevelDot::Tail(span);
+        }
+    }
+
+    // Read a value from `tokens` followed by `err`, then continue
+    // with what the reader didn't consume.
+    fn read_tokens(
+        &mut self,
+        err: Option<ParseErrorWithPos>,
+        out: &mut Vec<Result<B::Value, ReadErrorWithPos>>
+    ) {
+        let depth_fuel = 500;
+        // ^ the limit with default settings on Linux is around 1200
+        let modes = self.parser.settings.modes;
+        let tokens = std::mem::take(&mut self.tokens);
+        self.depth = 0;
+        self.needed = 1;
+        let mut ts = tokens.into_iter().map(Ok).chain(err.map(Err));
+        let r = ts.read_with(&mut self.builder, depth_fuel, modes);
+        match r {
+            Ok(Some(v)) => {
+                if let TopLevelDot::Tail(span) = self.dot {
+                    self.dot = TopLevelDot::AfterTail(span);
+                } else {
+                    out.push(Ok(v));
+                    self.seen_value = true;
+                }
+            }
+            Ok(None) => {}
+            Err(ReadErrorWithPos { err: ReadError::ImproperlyPlacedDot, span }) => {
+                // A dot that ended a list early reaches the top level
+                self.top_level_dot(span, out)
+            }
+            Err(e) => return self.error(e, out)
+        }
+        for r in ts {
+            self.push_token(r, out);
+        }
+    }
+}
@@ -12,15 +12,21 @@
 //! tree representation. See [parse](crate::parse) for using the
 //! underlying tokenizer directly.
 
-use crate::pos::Pos;
+use crate::pos::{Pos, Span};
 use crate::context::{self, Context};
-use crate::parse::{Token, TokenWithPos, parse,
+use crate::parse::{Token, TokenWithPos, parse, parse_str,
                    ParseError, ParseErrorWithPos};
 use crate::settings::{Settings, Modes, AnysexprFormat};
-use crate::value::{VValue, Parenkind, symbol, list2, VValueWithPos};
+use crate::value::{Atom, VValue, Parenkind, ReaderMacro, VValueWithPos};
+use crate::tokens::TokenWriter;
+use crate::lazy::{LazyValue, write_lazy};
+use crate::write::{Writable, WriteError, write_io};
+use kstring::{KString, KStringCow};
+use crate::builder::{TreeBuilder, VValueBuilder, splice_tail};
 use crate::buffered_chars::buffered_chars;
 use std::fmt::{Formatter, Display, Debug};
 use std::io::{Write, BufReader};
+use std::ops::Deref;
 use std::path::Path;
 use std::fs::File;
 use thiserror::Error;
@@ -59,32 +65,40 @@ pub enum ReadError {
     #[error("missing expression after {0}")]
     // MissingExpressionAfter(Token), // XX large because of Token, right?
     MissingExpressionAfter(Box<&'static str>),
+    #[error("map literal must contain an even number of forms")]
+    OddNumberOfMapForms,
+    #[error("invalid #{} element: {}", .0.0.as_str(), .0.1)]
+    InvalidTaggedElement(Box<(KString, anyhow::Error)>),
+    #[error("reference to undefined datum label #{0}#")]
+    UndefinedDatumLabel(u32),
+    #[error("datum label #{0}= defined twice")]
+    DuplicateDatumLabel(u32),
  }
 
 #[derive(Error, Debug)]
-#[error("{err} {pos}")]
+#[error("{err} {span}")]
 pub struct ReadErrorWithPos {
-    err: ReadError,
-    pos: Pos
+    pub err: ReadError,
+    pub span: Span
 }
 
 impl ReadError {
-    fn at(self, p: Pos) -> ReadErrorWithPos {
+    pub(crate) fn at(self, s: Span) -> ReadErrorWithPos {
         ReadErrorWithPos {
             err: self,
-            pos: p
+            span: s
         }
     }
 }
 
 trait At<T> {
-    fn at(self, p: Pos) -> Result<T, ReadErrorWithPos>;
+    fn at(self, s: Span) -> Result<T, ReadErrorWithPos>;
 }
 
 impl<T> At<T> for Result<T, ReadError> {
-    fn at(self, p: Pos) -> Result<T, ReadErrorWithPos> {
+    fn at(self, s: Span) -> Result<T, ReadErrorWithPos> {
         match self {
-            Err(e) => Err(e.at(p)),
+            Err(e) => Err(e.at(s)),
             Ok(v) => Ok(v)
         }
     }
@@ -100,17 +114,17 @@ impl Display for ReadErrorWithPosContext {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         f.write_fmt(format_args!("{} ",
                                  self.err_with_pos.err))?;
-        self.container.format_with_pos(self.err_with_pos.pos, f)?;
+        self.container.format_with_pos(self.err_with_pos.span.start, f)?;
         Ok(())
     }
 }
 
 impl From<ParseErrorWithPos> for ReadErrorWithPos {
     fn from(ep: ParseErrorWithPos) -> ReadErrorWithPos {
-        let ParseErrorWithPos { err, pos } = ep;
+        let ParseErrorWithPos { err, span } = ep;
         ReadErrorWithPos {
             err: ReadError::PE(Box::new(err)),
-            pos
+            span
         }
     }
 }
@@ -172,7 +186,318 @@ fn dec(fuel: u32) -> Result<u32, ReadError> {
 }
 
 
-pub trait TokensRead<T: Iterator<Item = Result<TokenWithPos, ParseErrorWithPos>>> {
+/// What the reader needs to remember about a value it has built, for
+/// handling `.` syntax and `Modes::allow_improper_lists`.
+#[derive(Clone, Copy)]
+enum Shape {
+    Other,
+    /// A list of the given kind, with the span of its dot if it is
+    /// improper.
+    List(Parenkind, Option<Span>),
+}
+
+/// The items of a list and, if there was a `.`, its span and the item
+/// following it.
+type Items<V> = (Vec<V>, Option<(Span, V)>);
+
+/// Same as `Items` but with the Shape of the item after the dot, and
+/// the span of the closing paren (None at EOF).
+type ShapedItems<V> = (Vec<V>, Option<(Span, V, Shape)>, Option<Span>);
+
+/// Prefix syntax, built via
+/// [TreeBuilder::prefixed](TreeBuilder::prefixed),
+/// [TreeBuilder::reader_macro](TreeBuilder::reader_macro),
+/// [TreeBuilder::tagged](TreeBuilder::tagged) or
+/// [TreeBuilder::labeled](TreeBuilder::labeled).
+enum Prefix<S> {
+    Symname(&'static str),
+    ReaderMacro(ReaderMacro),
+    Tag(S),
+    Label(u32),
+}
+
+impl<S> Prefix<S> {
+    // The prefixes other than tags
+    fn of_token(t: &Token<S>) -> Option<Prefix<S>> {
+        match t {
+            Token::Quote => Some(Prefix::Symname("quote")),
+            Token::Quasiquote => Some(Prefix::Symname("quasiquote")),
+            Token::Unquote => Some(Prefix::Symname("unquote")),
+            Token::UnquoteSplicing => Some(Prefix::Symname("unquote-splicing")),
+            Token::ReaderMacro(rm) => Some(Prefix::ReaderMacro(*rm)),
+            Token::DatumLabel(n) => Some(Prefix::Label(*n)),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Prefix::Symname(symname) => symname,
+            Prefix::ReaderMacro(rm) => rm.name(),
+            Prefix::Tag(_) => "tag",
+            Prefix::Label(_) => "datum label"
+        }
+    }
+
+    // Build the prefixed value and give its Shape.
+    fn build<B>(self, builder: &mut B, value: B::Value, span: Span)
+                   -> (B::Value, Shape)
+        where B: TreeBuilder<S>,
+              S: From<KString>
+    {
+        match self {
+            Prefix::Symname(symname) =>
+                (builder.prefixed(symname, value, span),
+                 Shape::List(Parenkind::Round, None)),
+            Prefix::ReaderMacro(rm) =>
+                (builder.reader_macro(rm, value, span), Shape::Other),
+            Prefix::Tag(tag) =>
+                (builder.tagged(tag, value, span), Shape::Other),
+            Prefix::Label(n) =>
+                (builder.labeled(n, value, span), Shape::Other)
+        }
+    }
+}
+
+/// Builds nothing, for reading the expressions commented out with
+/// `#;` without passing them to the user's builder.
+struct DiscardBuilder;
+
+impl<S> TreeBuilder<S> for DiscardBuilder {
+    type Value = ();
+
+    fn atom(&mut self, _atom: Atom<S>, _span: Span) {}
+
+    fn list(&mut self, _pk: Parenkind, _items: Vec<()>, _span: Span) {}
+
+    fn improper_list(
+        &mut self,
+        _pk: Parenkind,
+        _items: Vec<()>,
+        _dotspan: Span,
+        _tail: (),
+        _span: Span
+    ) {}
+
+    fn prefixed(&mut self, _symname: &'static str, _value: (), _span: Span) {}
+
+    fn tagged(&mut self, _tag: S, _value: (), _span: Span) {}
+
+    fn feature_conditional(&mut self, _positive: bool, _feature: (), _form: (), _span: Span) {}
+
+    fn labeled(&mut self, _n: u32, _value: (), _span: Span) {}
+
+    fn label_ref(&mut self, _n: u32, _span: Span) {}
+}
+
+// Read one expression, also returning its Shape and span.
+fn read_shaped<T, B, S>(
+    ts: &mut T,
+    builder: &mut B,
+    depth_fuel: u32,
+    modes: &Modes,
+) -> Result<Option<(B::Value, Shape, Span)>, ReadErrorWithPos>
+    where T: Iterator<Item = Result<TokenWithPos<S>, ParseErrorWithPos>>,
+          B: TreeBuilder<S>,
+          S: From<KString>
+{
+    let get_prefixing =
+        |ts: &mut T, builder: &mut B, quotespan: Span, prefix: Prefix<S>| ->
+        Result<Option<(B::Value, Shape, Span)>, ReadErrorWithPos> {
+            if let Some((expr, _, span)) = read_shaped(
+                ts, builder, dec(depth_fuel).at(quotespan)?, modes)?
+            {
+                let span = quotespan.to(span);
+                let (v, shape) = prefix.build(builder, expr, span);
+                Ok(Some((v, shape, span)))
+            } else {
+                Err(ReadError::MissingExpressionAfter(Box::new(prefix.name()))
+                    .at(quotespan))
+            }
+        };
+    while let Some(TokenWithPos(t, pos)) = ts.next().transpose()? {
+        match t {
+            Token::Dot => {
+                return Err(ReadError::ImproperlyPlacedDot.at(pos))
+            }
+            Token::Quote | Token::Quasiquote | Token::Unquote
+                | Token::UnquoteSplicing | Token::ReaderMacro(_) | Token::DatumLabel(_) => {
+                let prefix = Prefix::of_token(&t).expect("a prefix");
+                return get_prefixing(ts, builder, pos, prefix)
+            }
+            Token::DatumRef(n) => {
+                return Ok(Some((builder.label_ref(n, pos), Shape::Other, pos)))
+            }
+            Token::Tag(tag) => {
+                return get_prefixing(ts, builder, pos, Prefix::Tag(tag))
+            }
+            Token::FeatureConditional(positive) => {
+                let fuel = dec(depth_fuel).at(pos)?;
+                let missing = |what| ReadError::MissingExpressionAfter(Box::new(what)).at(pos);
+                let (feature, _, _) = read_shaped(ts, builder, fuel, modes)?
+                    .ok_or_else(|| missing("feature conditional"))?;
+                let (form, _, formspan) = read_shaped(ts, builder, fuel, modes)?
+                    .ok_or_else(|| missing("feature expression"))?;
+                let span = pos.to(formspan);
+                return Ok(Some((builder.feature_conditional(positive, feature, form, span),
+                                Shape::Other,
+                                span)))
+            }
+            Token::Whitespace(_) => {}
+            Token::CommentExpr => {
+                // read and ignore the next expression
+                read_shaped(ts, &mut DiscardBuilder, dec(depth_fuel).at(pos)?, modes)?;
+            }
+            Token::Comment(_, _) => {}
+            Token::Open(pk) => {
+                let (vs, tail, close) = read_items(
+                    ts, builder, Some((pk, pos)), dec(depth_fuel).at(pos)?, modes)?;
+                let pos = pos.to(close.unwrap_or(pos));
+                return match tail {
+                    None => Ok(Some((builder.list(pk, vs, pos),
+                                     Shape::List(pk, None),
+                                     pos))),
+                    Some((dotpos, v, shape)) => {
+                        // "Tail syntax optimization": a (..) list
+                        // after the dot is spliced in, hence whether
+                        // the result is improper depends on it.
+                        let improper = match shape {
+                            Shape::List(Parenkind::Round, improper1) => improper1,
+                            _ => Some(dotpos)
+                        };
+                        if let Some(p) = improper {
+                            if !modes.allow_improper_lists {
+                                return Err(
+                                    ReadError::ImproperListsNotAllowedByMode.at(p))
+                            }
+                        }
+                        Ok(Some((builder.improper_list(pk, vs, dotpos, v, pos),
+                                 Shape::List(pk, improper),
+                                 pos)))
+                    }
+                }
+            }
+            Token::Close(pk) => {
+                return Err(ReadError::UnexpectedClosingParen(pk).at(pos))
+            }
+            Token::Atom(a) => {
+                return Ok(Some((builder.atom(a, pos), Shape::Other, pos)))
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Read the items up to the expected end paren, and the span of a Dot
+// and the item after it along with its Shape, if any.
+fn read_items<T, B, S>(
+    ts: &mut T,
+    builder: &mut B,
+    opt_parenkind: Option<(Parenkind, Span)>,
+    depth_fuel: u32,
+    modes: &Modes,
+) -> Result<ShapedItems<B::Value>, ReadErrorWithPos>
+    where T: Iterator<Item = Result<TokenWithPos<S>, ParseErrorWithPos>>,
+          B: TreeBuilder<S>,
+          S: From<KString>
+{
+    let mut vs = Vec::new();
+    let on_eof = |vs| {
+        if let Some((parenkind, startpos)) = opt_parenkind {
+            Err(ReadError::PrematureEofExpectingClosingParen(parenkind)
+                .at(startpos))
+        } else {
+            Ok((vs, None, None))
+        }
+    };
+    while let Some(r) = read_shaped(ts, builder, depth_fuel, modes).transpose() {
+        match r {
+            Err(ep) => {
+                let ReadErrorWithPos { err, span: pos } = &ep;
+                match err {
+                    ReadError::IO(_) => return Err(ep),
+                    ReadError::ImproperlyPlacedDot => {
+                        if let Some((pk, _pos)) = opt_parenkind {
+                            if pk != Parenkind::Round {
+                                return Err(ReadError::DotInWrongListContext(pk)
+                                           .at(*pos))
+                            }
+                        }
+                        if vs.is_empty() {
+                            return Err(ReadError::DotWithoutPrecedingItem.at(*pos))
+                        }
+                        if let Some((v, shape, _)) = read_shaped(
+                            ts, builder, dec(depth_fuel).at(*pos)?, modes)?
+                        {
+                            // The next token must be a Close if we're
+                            // in a list, or none otherwise. Get just
+                            // one token: be lazy / report the error
+                            // *here* not some later one.
+                            // XX this is copying much of the end
+                            // paren check logic further down, sigh.
+                            return if let Some(TokenWithPos(t, endpos)) =
+                                ts.next().transpose()?
+                            {
+                                match t {
+                                    Token::Close(pk_end) => {
+                                        if let Some((pk, openpos)) = opt_parenkind {
+                                            if pk_end == pk {
+                                                Ok((vs, Some((*pos, v, shape)),
+                                                    Some(endpos)))
+                                            } else {
+                                                Err(ReadError::ParenMismatch(
+                                                    pk, openpos.start, pk_end)
+                                                    .at(endpos))
+                                            }
+                                        } else {
+                                            Err(ReadError::UnexpectedClosingParen(
+                                                pk_end).at(endpos))
+                                        }
+                                    }
+                                    _ => {
+                                        Err(ReadError::ExpectingOneItemAfterDot
+                                            .at(endpos))
+                                    }
+                                }
+                            } else if let Some((pk, openpos)) = opt_parenkind {
+                                Err(ReadError::PrematureEofExpectingClosingParen(
+                                    pk).at(openpos))
+                            } else {
+                                Ok((vs, Some((*pos, v, shape)), None))
+                            }
+                        } else {
+                            return on_eof(vs)
+                        }
+                    }
+                    ReadError::UnexpectedClosingParen(pk) => {
+                        if let Some((parenkind, startpos)) = opt_parenkind {
+                            if *pk == parenkind {
+                                return Ok((vs, None, Some(*pos)))
+                            } else {
+                                return Err(ReadError::ParenMismatch(
+                                    parenkind, startpos.start, *pk)
+                                           .at(*pos))
+                            }
+                        } else {
+                            return Err(ep)
+                        }
+                    }
+                    _ => return Err(ep)
+                }
+            }
+            Ok((v, _, _)) => {
+                vs.push(v);
+            }
+        }
+    }
+    on_eof(vs)
+}
+
+
+/// `S` is the string type in the tokens, see [value](crate::value).
+pub trait TokensRead<T, S = KString>
+    where T: Iterator<Item = Result<TokenWithPos<S>, ParseErrorWithPos>>
+{
 
     /// Read one expression. Returns None on EOF. Signals
     /// ReadError::UnexpectedClosingParen if there's no expression left in
@@ -181,209 +506,376 @@ pub trait TokensRead<T: Iterator<Item = Result<TokenWithPos, ParseErrorWithPos>>
         &mut self,
         depth_fuel: u32,
         modes: &Modes,
-    ) -> Result<Option<VValueWithPos>, ReadErrorWithPos>;
+    ) -> Result<Option<VValueWithPos<S>>, ReadErrorWithPos>;
 
     /// Read and fill a vector of values up to the expected end paren, and
-    /// return the vector and the position of a Dot, if any. Checking
+    /// return the vector and the span of a Dot, if any. Checking
     /// whether a dot is allowed is left to the caller.
     fn read_all(
         &mut self,
-        opt_parenkind: Option<(Parenkind, Pos)>,
+        opt_parenkind: Option<(Parenkind, Span)>,
+        depth_fuel: u32,
+        modes: &Modes,
+    ) -> Result<(Vec<VValueWithPos<S>>, Option<Span>), ReadErrorWithPos>;
+
+    /// Same as `read` but building the value via `builder`.
+    fn read_with<B: TreeBuilder<S>>(
+        &mut self,
+        builder: &mut B,
         depth_fuel: u32,
         modes: &Modes,
-    ) -> Result<(Vec<VValueWithPos>, Option<Pos>), ReadErrorWithPos>;
+    ) -> Result<Option<B::Value>, ReadErrorWithPos>;
+
+    /// Same as `read_all` but building the values via `builder`. The
+    /// item after a Dot, if any, is returned separately, together
+    /// with the Dot's span, instead of being added to the vector.
+    fn read_all_with<B: TreeBuilder<S>>(
+        &mut self,
+        builder: &mut B,
+        opt_parenkind: Option<(Parenkind, Span)>,
+        depth_fuel: u32,
+        modes: &Modes,
+    ) -> Result<Items<B::Value>, ReadErrorWithPos>;
 }
 
 
-impl<T: Iterator<Item = Result<TokenWithPos, ParseErrorWithPos>>> TokensRead<T> for T {
+impl<T, S> TokensRead<T, S> for T
+    where T: Iterator<Item = Result<TokenWithPos<S>, ParseErrorWithPos>>,
+          S: From<KString>
+{
 
     fn read(
         &mut self,
         depth_fuel: u32,
         modes: &Modes,
-    ) -> Result<Option<VValueWithPos>, ReadErrorWithPos>
+    ) -> Result<Option<VValueWithPos<S>>, ReadErrorWithPos>
     {
-        let get_prefixing =
-            |ts: &mut T, quotepos, symname| ->
-            Result<Option<VValueWithPos>, ReadErrorWithPos> {
-                if let Some(expr) = ts.read(dec(depth_fuel).at(quotepos)?, modes)? {
-                    Ok(Some(list2(symbol(symname).at(quotepos), expr).at(quotepos)))
-                } else {
-                    Err(ReadError::MissingExpressionAfter(Box::new(symname))
-                        .at(quotepos))
-                }
-            };
-        while let Some(TokenWithPos(t, pos)) = self.next().transpose()? {
+        self.read_with(&mut VValueBuilder, depth_fuel, modes)
+    }
+
+    fn read_all(
+        &mut self,
+        opt_parenkind: Option<(Parenkind, Span)>,
+        depth_fuel: u32,
+        modes: &Modes,
+    ) -> Result<(Vec<VValueWithPos<S>>, Option<Span>), ReadErrorWithPos>
+    {
+        let (mut vs, tail) = self.read_all_with(
+            &mut VValueBuilder, opt_parenkind, depth_fuel, modes)?;
+        if let Some((dotpos, v)) = tail {
+            let improper = splice_tail(&mut vs, dotpos, v);
+            Ok((vs, improper))
+        } else {
+            Ok((vs, None))
+        }
+    }
+
+    fn read_with<B: TreeBuilder<S>>(
+        &mut self,
+        builder: &mut B,
+        depth_fuel: u32,
+        modes: &Modes,
+    ) -> Result<Option<B::Value>, ReadErrorWithPos>
+    {
+        Ok(read_shaped(self, builder, depth_fuel, modes)?.map(|(v, _, _)| v))
+    }
+
+    fn read_all_with<B: TreeBuilder<S>>(
+        &mut self,
+        builder: &mut B,
+        opt_parenkind: Option<(Parenkind, Span)>,
+        depth_fuel: u32,
+        modes: &Modes,
+    ) -> Result<Items<B::Value>, ReadErrorWithPos>
+    {
+        let (vs, tail, _) = read_items(self, builder, opt_parenkind, depth_fuel, modes)?;
+        Ok((vs, tail.map(|(dotpos, v, _)| (dotpos, v))))
+    }
+}
+
+/// What [Recovering::read_value](Recovering::read_value) found.
+enum Next<V> {
+    /// A value, its Shape and span
+    Value(V, Shape, Span),
+    Dot(Span),
+    /// A closing paren; it is left in `pending_close` for the caller
+    /// to consume or not.
+    Close(Parenkind, Span),
+    Eof,
+}
+
+/// A reader that records errors and continues, to read as much as
+/// possible. Requires the token stream to come from a `parse` in
+/// `Modes::recover_from_errors` mode to see errors beyond the first
+/// parse error.
+struct Recovering<'m, T, B> {
+    ts: &'m mut T,
+    builder: &'m mut B,
+    modes: &'m Modes,
+    errors: Vec<ReadErrorWithPos>,
+    pending_close: Option<(Parenkind, Span)>,
+    /// The kinds of the lists currently being read
+    open: Vec<Parenkind>,
+}
+
+impl<'m, T, B, S> Recovering<'m, T, B>
+    where T: Iterator<Item = Result<TokenWithPos<S>, ParseErrorWithPos>>,
+          B: TreeBuilder<S>,
+          S: From<KString>
+{
+    fn next_token(&mut self) -> Option<TokenWithPos<S>> {
+        if let Some((pk, pos)) = self.pending_close.take() {
+            return Some(TokenWithPos(Token::Close(pk), pos))
+        }
+        loop {
+            match self.ts.next()? {
+                Ok(t) => return Some(t),
+                Err(e) => self.errors.push(e.into())
+            }
+        }
+    }
+
+    // Skip the rest of a list whose opening paren has been consumed.
+    fn skip_nested(&mut self) {
+        let mut depth = 1;
+        while let Some(TokenWithPos(t, _)) = self.next_token() {
             match t {
-                Token::Dot => {
-                    return Err(ReadError::ImproperlyPlacedDot.at(pos))
-                }
-                Token::Quote => {
-                    return get_prefixing(self, pos, "quote")
+                Token::Open(_) => depth += 1,
+                Token::Close(_) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return
+                    }
                 }
-                Token::Quasiquote => {
-                    return get_prefixing(self, pos, "quasiquote")
+                _ => {}
+            }
+        }
+    }
+
+    fn read_value(&mut self, depth_fuel: u32) -> Next<B::Value> {
+        while let Some(TokenWithPos(t, pos)) = self.next_token() {
+            let prefix = match t {
+                Token::Dot => return Next::Dot(pos),
+                Token::Quote | Token::Quasiquote | Token::Unquote
+                    | Token::UnquoteSplicing | Token::ReaderMacro(_)
+                    | Token::DatumLabel(_) => {
+                    Prefix::of_token(&t).expect("a prefix")
                 }
-                Token::Unquote => {
-                    return get_prefixing(self, pos, "unquote")
+                Token::DatumRef(n) => {
+                    return Next::Value(self.builder.label_ref(n, pos), Shape::Other, pos)
                 }
-                Token::UnquoteSplicing => {
-                    return get_prefixing(self, pos, "unquote-splicing")
+                Token::Tag(tag) => Prefix::Tag(tag),
+                Token::FeatureConditional(positive) => {
+                    match dec(depth_fuel) {
+                        Ok(fuel) => return self.read_feature_conditional(positive, pos, fuel),
+                        // Ignore the prefix
+                        Err(e) => {
+                            self.errors.push(e.at(pos));
+                            continue
+                        }
+                    }
                 }
-                Token::Whitespace(_) => {}
+                Token::Whitespace(_) => continue,
+                Token::Comment(_, _) => continue,
                 Token::CommentExpr => {
-                    // read and ignore the next expression
-                    self.read(dec(depth_fuel).at(pos)?, modes)?;
+                    match self.discard_value(depth_fuel) {
+                        Next::Value(..) => continue,
+                        Next::Dot(span) => return Next::Dot(span),
+                        Next::Close(pk, span) => return Next::Close(pk, span),
+                        Next::Eof => return Next::Eof
+                    }
                 }
-                Token::Comment(_, _) => {}
                 Token::Open(pk) => {
-                    let (e, maybedot) =
-                        self.read_all(Some((pk, pos)), dec(depth_fuel).at(pos)?, modes)?;
-                    if maybedot.is_some() && !modes.allow_improper_lists {
-                        return Err(ReadError::ImproperListsNotAllowedByMode.at(maybedot.unwrap()))
+                    match dec(depth_fuel) {
+                        Ok(fuel) => return self.read_list(pk, pos, fuel),
+                        Err(e) => {
+                            self.errors.push(e.at(pos));
+                            self.skip_nested();
+                            continue
+                        }
                     }
-                    return Ok(Some(VValue::List(pk, maybedot, e).at(pos)))
                 }
                 Token::Close(pk) => {
-                    return Err(ReadError::UnexpectedClosingParen(pk).at(pos))
+                    self.pending_close = Some((pk, pos));
+                    return Next::Close(pk, pos)
                 }
                 Token::Atom(a) => {
-                    return Ok(Some(VValue::Atom(a).at(pos)))
+                    return Next::Value(self.builder.atom(a, pos), Shape::Other, pos)
+                }
+            };
+            match dec(depth_fuel) {
+                Ok(fuel) => {
+                    return match self.read_value(fuel) {
+                        Next::Value(v, _, vspan) => {
+                            let span = pos.to(vspan);
+                            let (v, shape) = prefix.build(self.builder, v, span);
+                            Next::Value(v, shape, span)
+                        }
+                        other => {
+                            self.errors.push(
+                                ReadError::MissingExpressionAfter(
+                                    Box::new(prefix.name())).at(pos));
+                            other
+                        }
+                    }
                 }
-            }        
+                // Ignore the prefix
+                Err(e) => self.errors.push(e.at(pos))
+            }
         }
-        Ok(None)
+        Next::Eof
     }
-    
-    fn read_all(
+
+    // Read the expression after `#;` without building it.
+    fn discard_value(&mut self, depth_fuel: u32) -> Next<()> {
+        let mut r = Recovering {
+            ts: &mut *self.ts,
+            builder: &mut DiscardBuilder,
+            modes: self.modes,
+            errors: std::mem::take(&mut self.errors),
+            pending_close: self.pending_close.take(),
+            open: std::mem::take(&mut self.open),
+        };
+        let next = r.read_value(depth_fuel);
+        self.errors = r.errors;
+        self.pending_close = r.pending_close;
+        self.open = r.open;
+        next
+    }
+
+    // Read the feature expression and form after `#+` or `#-` at
+    // `pos`.
+    fn read_feature_conditional(
         &mut self,
-        opt_parenkind: Option<(Parenkind, Pos)>,
-        depth_fuel: u32,
-        modes: &Modes,
-    ) -> Result<(Vec<VValueWithPos>, Option<Pos>), ReadErrorWithPos>
-    {
-        let mut vs = Vec::new();
-        let on_eof = |vs| {
-            if let Some((parenkind, startpos)) = opt_parenkind {
-                Err(ReadError::PrematureEofExpectingClosingParen(parenkind)
-                    .at(startpos))
-            } else {
-                Ok((vs, None))
+        positive: bool,
+        pos: Span,
+        depth_fuel: u32
+    ) -> Next<B::Value> {
+        let feature = match self.read_value(depth_fuel) {
+            Next::Value(v, _, _) => v,
+            other => {
+                self.errors.push(ReadError::MissingExpressionAfter(
+                    Box::new("feature conditional")).at(pos));
+                return other
             }
         };
-        while let Some(r) = self.read(depth_fuel, modes).transpose() {
-            match r {
-                Err(ep) => {
-                    let ReadErrorWithPos { err, pos } = &ep;
-                    match err {
-                        ReadError::IO(_) => return Err(ep),
-                        ReadError::ImproperlyPlacedDot => {
-                            if let Some((pk, _pos)) = opt_parenkind {
-                                if pk != Parenkind::Round {
-                                    return Err(ReadError::DotInWrongListContext(pk)
-                                               .at(*pos))
-                                }
-                            }
-                            if vs.len() == 0 {
-                                return Err(ReadError::DotWithoutPrecedingItem.at(*pos))
-                            }
-                            if let Some(vp) = self.read(dec(depth_fuel).at(*pos)?,
-                                                        modes)? {
-                                // The next token must be a Close if we're
-                                // in a list, or none otherwise:
-                                let expecting_close = |ts: &mut T, result| {
-                                    // Use token_read or get just one
-                                    // token? Just one token: be lazy /
-                                    // report the error *here* not some
-                                    // later one.
-                                    // XX this is copying much of the end
-                                    // paren check logic further down,
-                                    // sigh.
-                                    if let Some(TokenWithPos(t, pos)) =
-                                        ts.next().transpose()?
-                                    {
-                                        match t {
-                                            Token::Close(pk_end) => {
-                                                if let Some((pk, openpos)) = opt_parenkind {
-                                                    if pk_end == pk {
-                                                        Ok(result)
-                                                    } else {
-                                                        Err(
-                                                            ReadError::ParenMismatch(
-                                                                pk, openpos, pk_end)
-                                                            .at(pos))
-                                                    }
-                                                } else {
-                                                    Err(
-                                                        ReadError::UnexpectedClosingParen(
-                                                            pk_end).at(pos))
-                                                }
-                                            }
-                                            _ => {
-                                                Err(ReadError::ExpectingOneItemAfterDot
-                                                    .at(pos))
-                                            }
-                                        }
-                                    } else {
-                                        if let Some((pk, openpos)) = opt_parenkind {
-                                            Err(ReadError::PrematureEofExpectingClosingParen(
-                                                pk).at(openpos))
-                                        } else {
-                                            Ok(result)
-                                        }
-                                    }
-                                };
-                                match vp.0 {
-                                    VValue::Atom(_) => {
-                                        vs.push(vp);
-                                        return expecting_close(self, (vs, Some(*pos)))
-                                    },
-                                    VValue::List(pk1, improper1, mut vs1) => {
-                                        // Perform "tail syntax
-                                        // optimization" if it's the same
-                                        // kind of list, ehr, also the
-                                        // Round kind (we already checked
-                                        // above that the context is
-                                        // Round)
-                                        if pk1 == Parenkind::Round {
-                                            vs.append(&mut vs1);
-                                            // Whether the current list
-                                            // context is proper now
-                                            // depends on whether vs1 was.
-                                            return expecting_close(self, (vs, improper1))
-                                        }
-                                        // Otherwise keep nested
-                                        vs.push(VValue::List(pk1, improper1, vs1)
-                                                .at(vp.1));
-                                        return expecting_close(self, (vs, Some(*pos)))
-                                    }
-                                }
-                            } else {
-                                return on_eof(vs)
+        match self.read_value(depth_fuel) {
+            Next::Value(form, _, formspan) => {
+                let span = pos.to(formspan);
+                Next::Value(self.builder.feature_conditional(positive, feature, form, span),
+                            Shape::Other,
+                            span)
+            }
+            other => {
+                self.errors.push(ReadError::MissingExpressionAfter(
+                    Box::new("feature expression")).at(pos));
+                other
+            }
+        }
+    }
+
+    fn read_list(
+        &mut self,
+        pk: Parenkind,
+        pos: Span,
+        depth_fuel: u32
+    ) -> Next<B::Value> {
+        self.open.push(pk);
+        let mut vs = Vec::new();
+        let mut tail: Option<(Span, B::Value, Shape)> = None;
+        let mut pending: Option<Next<B::Value>> = None;
+        // The end of what has been read of the list so far
+        let mut end = pos.end;
+        loop {
+            let next = pending.take().unwrap_or_else(|| self.read_value(depth_fuel));
+            match next {
+                Next::Value(v, _, vpos) => {
+                    if let Some((_, tv, _)) = tail.take() {
+                        self.errors.push(ReadError::ExpectingOneItemAfterDot.at(vpos));
+                        // Continue as if the dot wasn't there
+                        vs.push(tv);
+                    }
+                    vs.push(v);
+                    end = vpos.end;
+                }
+                Next::Dot(dotpos) => {
+                    if pk != Parenkind::Round {
+                        self.errors.push(ReadError::DotInWrongListContext(pk).at(dotpos));
+                    } else if tail.is_some() {
+                        self.errors.push(ReadError::ImproperlyPlacedDot.at(dotpos));
+                    } else if vs.is_empty() {
+                        self.errors.push(ReadError::DotWithoutPrecedingItem.at(dotpos));
+                    } else {
+                        match self.read_value(depth_fuel) {
+                            Next::Value(v, shape, vpos) => {
+                                tail = Some((dotpos, v, shape));
+                                end = vpos.end;
                             }
-                        }
-                        ReadError::UnexpectedClosingParen(pk) => {
-                            if let Some((parenkind, startpos)) = opt_parenkind {
-                                if *pk == parenkind {
-                                    return Ok((vs, None))
-                                } else {
-                                    return Err(ReadError::ParenMismatch(
-                                        parenkind, startpos, *pk)
-                                               .at(*pos))
-                                }
-                            } else {
-                                return Err(ep)
+                            other => {
+                                self.errors.push(ReadError::MissingItemAfterDot.at(dotpos));
+                                pending = Some(other);
                             }
                         }
-                        _ => return Err(ep)
                     }
                 }
-                Ok(v) => {
-                    vs.push(v);
+                Next::Close(pk_end, endpos) => {
+                    if pk_end == pk {
+                        self.pending_close = None;
+                        end = endpos.end;
+                        break
+                    }
+                    self.errors.push(ReadError::ParenMismatch(pk, pos.start, pk_end)
+                                     .at(endpos));
+                    let enclosing = &self.open[0..self.open.len() - 1];
+                    if enclosing.contains(&pk_end) {
+                        // Leave it to close the enclosing list
+                        break
+                    }
+                    // Otherwise drop it
+                    self.pending_close = None;
+                }
+                Next::Eof => {
+                    self.errors.push(
+                        ReadError::PrematureEofExpectingClosingParen(pk).at(pos));
+                    break
                 }
             }
         }
-        on_eof(vs)
+        self.open.pop();
+        let pos = Span::new(pos.start, end);
+        if let Some((dotpos, v, shape)) = tail {
+            let improper = match shape {
+                Shape::List(Parenkind::Round, improper1) => improper1,
+                _ => Some(dotpos)
+            };
+            if let Some(p) = improper {
+                if !self.modes.allow_improper_lists {
+                    self.errors.push(ReadError::ImproperListsNotAllowedByMode.at(p));
+                }
+            }
+            Next::Value(self.builder.improper_list(pk, vs, dotpos, v, pos),
+                        Shape::List(pk, improper),
+                        pos)
+        } else {
+            Next::Value(self.builder.list(pk, vs, pos), Shape::List(pk, None), pos)
+        }
+    }
+
+    fn read_toplevel(&mut self, depth_fuel: u32) -> Vec<B::Value> {
+        let mut vs = Vec::new();
+        loop {
+            match self.read_value(depth_fuel) {
+                Next::Value(v, _, _) => vs.push(v),
+                Next::Dot(pos) => {
+                    self.errors.push(ReadError::DotOutsideListContext.at(pos));
+                }
+                Next::Close(pk, pos) => {
+                    self.pending_close = None;
+                    self.errors.push(ReadError::UnexpectedClosingParen(pk).at(pos));
+                }
+                Next::Eof => return vs
+            }
+        }
     }
 }
 
@@ -397,6 +889,17 @@ impl<'f> AnysexprFormat<'f> {
         charswithpos: impl IntoIterator<Item = anyhow::Result<(char, Pos)>>,
         modes: &Modes,
     ) -> Result<Option<VValueWithPos>, ReadErrorWithPos>
+    {
+        self.read_with(&mut VValueBuilder, charswithpos, modes)
+    }
+
+    /// Same as `read` but building the value via `builder`.
+    pub fn read_with<B: TreeBuilder>(
+        &self,
+        builder: &mut B,
+        charswithpos: impl IntoIterator<Item = anyhow::Result<(char, Pos)>>,
+        modes: &Modes,
+    ) -> Result<Option<B::Value>, ReadErrorWithPos>
     {
         let settings = Settings {
             format: self,
@@ -405,7 +908,7 @@ impl<'f> AnysexprFormat<'f> {
         let depth_fuel = 500;
         // ^ the limit with default settings on Linux is around 1200
         let mut ts = parse(charswithpos.into_iter(), &settings);
-        ts.read(depth_fuel, settings.modes)
+        ts.read_with(builder, depth_fuel, settings.modes)
     }
 
     /// Read (deserialize) all of an input stream to a sequence
@@ -415,6 +918,17 @@ impl<'f> AnysexprFormat<'f> {
         charswithpos: impl IntoIterator<Item = anyhow::Result<(char, Pos)>>,
         modes: &Modes,
     ) -> Result<Vec<VValueWithPos>, ReadErrorWithPos>
+    {
+        self.read_all_with(&mut VValueBuilder, charswithpos, modes)
+    }
+
+    /// Same as `read_all` but building the values via `builder`.
+    pub fn read_all_with<B: TreeBuilder>(
+        &self,
+        builder: &mut B,
+        charswithpos: impl IntoIterator<Item = anyhow::Result<(char, Pos)>>,
+        modes: &Modes,
+    ) -> Result<Vec<B::Value>, ReadErrorWithPos>
     {
         let settings = Settings {
             format: self,
@@ -423,17 +937,101 @@ impl<'f> AnysexprFormat<'f> {
         let depth_fuel = 500;
         // ^ the limit with default settings on Linux is around 1200
         let mut ts = parse(charswithpos.into_iter(), &settings);
-        let (v, maybedot) = ts.read_all(
+        let (v, tail) = ts.read_all_with(
+            builder,
             None,
             depth_fuel,
             settings.modes)?;
-        if let Some(pos) = maybedot {
-            Err(ReadError::DotOutsideListContext.at(pos))
+        if let Some((span, _)) = tail {
+            Err(ReadError::DotOutsideListContext.at(span))
         } else {
             Ok(v)
         }
     }
 
+    /// Read all of a string in memory, without copying the text of
+    /// strings, symbols and keywords that appear verbatim in `s` (see
+    /// [parse_str](crate::parse::parse_str)). Use
+    /// [into_owned](VValueWithPos::into_owned) to detach a value from
+    /// `s`.
+    pub fn read_str<'a>(
+        &self,
+        s: &'a str,
+        modes: &Modes,
+    ) -> Result<Vec<VValueWithPos<KStringCow<'a>>>, ReadErrorWithPos>
+    {
+        self.read_str_with(&mut VValueBuilder, s, modes)
+    }
+
+    /// Same as `read_str` but building the values via `builder`.
+    pub fn read_str_with<'a, B: TreeBuilder<KStringCow<'a>>>(
+        &self,
+        builder: &mut B,
+        s: &'a str,
+        modes: &Modes,
+    ) -> Result<Vec<B::Value>, ReadErrorWithPos>
+    {
+        let settings = Settings {
+            format: self,
+            modes,
+        };
+        let depth_fuel = 500;
+        // ^ the limit with default settings on Linux is around 1200
+        let mut ts = parse_str(s, &settings);
+        let (v, tail) = ts.read_all_with(
+            builder,
+            None,
+            depth_fuel,
+            settings.modes)?;
+        if let Some((span, _)) = tail {
+            Err(ReadError::DotOutsideListContext.at(span))
+        } else {
+            Ok(v)
+        }
+    }
+
+    /// Read all of an input stream, continuing after errors instead
+    /// of stopping at the first one. Returns the values that could be
+    /// read (with the erroneous parts left out, unclosed lists closed
+    /// at EOF) and all errors found, in order.
+    pub fn read_all_recovering(
+        &self,
+        charswithpos: impl IntoIterator<Item = anyhow::Result<(char, Pos)>>,
+        modes: &Modes,
+    ) -> (Vec<VValueWithPos>, Vec<ReadErrorWithPos>)
+    {
+        self.read_all_recovering_with(&mut VValueBuilder, charswithpos, modes)
+    }
+
+    /// Same as `read_all_recovering` but building the values via
+    /// `builder`.
+    pub fn read_all_recovering_with<B: TreeBuilder>(
+        &self,
+        builder: &mut B,
+        charswithpos: impl IntoIterator<Item = anyhow::Result<(char, Pos)>>,
+        modes: &Modes,
+    ) -> (Vec<B::Value>, Vec<ReadErrorWithPos>)
+    {
+        let modes = Modes { recover_from_errors: true, ..*modes };
+        let settings = Settings {
+            format: self,
+            modes: &modes,
+        };
+        let depth_fuel = 500;
+        // ^ the limit with default settings on Linux is around 1200
+        let mut ts = parse(charswithpos.into_iter(), &settings);
+        let mut r = Recovering {
+            ts: &mut ts,
+            builder,
+            modes: &modes,
+            errors: Vec::new(),
+            pending_close: None,
+            open: Vec::new(),
+        };
+        let vs = r.read_toplevel(depth_fuel);
+        (vs, r.errors)
+    }
+
     /// Read (deserialize) the contents of a file to a sequence of
     /// [VValueWithPos](VValueWithPos).
     pub fn read_file(
@@ -441,43 +1039,64 @@ impl<'f> AnysexprFormat<'f> {
         path: &Path,
         modes: &Modes,
     ) -> Result<Vec<VValueWithPos>, ReadErrorWithLocation> {
+        self.read_file_with(&mut VValueBuilder, path, modes)
+    }
+
+    /// Same as `read_file` but building the values via `builder`.
+    pub fn read_file_with<B: TreeBuilder>(
+        &self,
+        builder: &mut B,
+        path: &Path,
+        modes: &Modes,
+    ) -> Result<Vec<B::Value>, ReadErrorWithLocation> {
         let fh = io_add_file(File::open(path), path)?;
         let cs = buffered_chars(BufReader::new(fh));
-        let v = rewp_add_file(self.read_all(cs, modes), path)?;
+        let v = rewp_add_file(self.read_all_with(builder, cs, modes), path)?;
         Ok(v)
     }
 
-    /// Write (serialize) a [VValue](VValue) or
-    /// [VValueWithPos](VValueWithPos) to an output stream.
-    pub fn write<'t, T: Display + 't>(
+    /// Serialize a [Writable](Writable) value (like [VValue](VValue)
+    /// or [VValueWithPos](VValueWithPos)) to a string in this format.
+    pub fn to_string<T: Writable + ?Sized>(&self, val: &T) -> Result<String, WriteError> {
+        let mut out = String::new();
+        val.write_to(&mut out, self)?;
+        Ok(out)
+    }
+
+    /// Write (serialize) a [Writable](Writable) value (like
+    /// [VValue](VValue) or [VValueWithPos](VValueWithPos)) to an
+    /// output stream. The text is written in chunks as it's
+    /// generated; if the value turns out not to be representable in
+    /// this format, the chunks before that point have been written.
+    pub fn write<'t, T: Writable + ?Sized + 't>(
         &self,
         out: &mut impl Write,
         val: &'t T
-    ) -> Result<(), std::io::Error> {
-        write!(out, "{}", val)
+    ) -> Result<(), WriteError> {
+        write_io(out, val, self, "")
     }
 
-    /// Write (serialize) a [VValue](VValue) or
-    /// [VValueWithPos](VValueWithPos) and a newline to an output stream.
-    pub fn writeln<'t, T: Display + 't>(
+    /// Write (serialize) a [Writable](Writable) value and a newline
+    /// to an output stream.
+    pub fn writeln<'t, T: Writable + ?Sized + 't>(
         &self,
         out: &mut impl Write,
         val: &'t T
-    ) -> Result<(), std::io::Error> {
-        write!(out, "{}\n", val)
+    ) -> Result<(), WriteError> {
+        write_io(out, val, self, "\n")
     }
 
-    /// Write (serialize) a sequence of [VValue](VValue) or
-    /// [VValueWithPos](VValueWithPos) to an output stream.
-    pub fn write_all<'t, T: Display + 't>(
+    /// Write (serialize) a sequence of [Writable](Writable) values
+    /// to an output stream, separated by empty lines.
+    pub fn write_all<'t, T: Writable + ?Sized + 't>(
         &self,
         out: &mut impl Write,
         vals: impl IntoIterator<Item = &'t T>
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<(), WriteError> {
         let mut seen_item = false;
         for v in vals.into_iter() {
             if seen_item {
-                write!(out, "\n")?;
+                writeln!(out)?;
             }
             self.writeln(out, v)?;
             seen_item = true;
@@ -490,8 +1109,36 @@ impl<'f> AnysexprFormat<'f> {
         &self,
         path: &Path,
         vals: impl IntoIterator<Item = &'t VValue>
-    ) -> Result<(), std::io::Error> {
-        self.write_all(&mut File::open(path)?, vals)
+    ) -> Result<(), WriteError> {
+        self.write_all(&mut File::create(path)?, vals)
+    }
+
+    /// Write a stream of tokens (e.g. from [parse](crate::parse::parse),
+    /// or [tokens](crate::tokens::tokens)) to an output stream,
+    /// separated as needed, see [TokenWriter](TokenWriter).
+    pub fn write_tokens<S: Deref<Target = str>>(
+        &self,
+        out: &mut impl Write,
+        tokens: impl IntoIterator<Item = Token<S>>
+    ) -> Result<(), WriteError> {
+        let mut writer = TokenWriter::new(self, out);
+        for token in tokens {
+            writer.write(&token)?;
+        }
+        writer.finish()?;
+        Ok(())
     }
 
+    /// Write a value that's generated while it's written to an
+    /// output stream, followed by a newline, see [lazy](crate::lazy).
+    pub fn write_lazy<S: Deref<Target = str> + Clone>(
+        &self,
+        out: &mut impl Write,
+        val: impl LazyValue<S>
+    ) -> Result<(), WriteError> {
+        let mut writer = TokenWriter::new(self, out);
+        write_lazy(&mut writer, val)?;
+        writer.finish()?;
+        Ok(())
+    }
 }
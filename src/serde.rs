@@ -0,0 +1,1082 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serializing Rust data to, and deserializing it from,
+//! s-expressions via Serde (with the `serde` feature).
+
+//! The mapping, in both directions:
+//!
+//! * structs as alists, `((port 8080) (host "x"))`, or plists, `(:port
+//!   8080 :host "x")`, see [StructStyle](StructStyle); field names are
+//!   keywords in plists if the format has them, symbols otherwise
+//! * maps as alists with any values as keys
+//! * sequences, tuples and tuple structs as lists, bytes as `#u8(..)`
+//!   where the format has bytevectors
+//! * unit variants as symbols, `Name`, other variants as lists tagged
+//!   with the variant name, `(Name value)`, `(Name a b)`, `(Name (a 1)
+//!   (b 2))`
+//! * `None` as `#f` or `()`, see [NoneStyle](NoneStyle), `Some(x)` as
+//!   `x`; `()` and unit structs as `()`
+//!
+//! The entries of alists are two-element lists rather than pairs, so
+//! that they read back the same when the value is a list, and in
+//! formats without dotted pairs. When deserializing, `#f`, `()` and
+//! `nil` (in formats that have it) are all taken as `None`, thus
+//! `Some(false)` and `Some` of an empty sequence don't survive the
+//! round trip.
+//!
+//! Deserialization errors carry the span of the offending node;
+//! [from_file](from_file) adds the path.
+
+use crate::buffered_chars::buffered_chars;
+use crate::context::{Context, FileContext};
+use crate::number::{Integer, NumericVector, R5RSNumber};
+use crate::pos::Span;
+use crate::read::{ReadErrorWithLocation, ReadErrorWithPos};
+use crate::settings::{AnysexprFormat, Modes};
+use crate::value::{Atom, Parenkind, ReaderMacro, VValue, VValueWithPos};
+use crate::write::WriteError;
+use ::serde::de::{self, DeserializeOwned, DeserializeSeed, Visitor};
+use ::serde::ser::{self, Serialize};
+use kstring::KString;
+use num::{BigInt, ToPrimitive};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+use thiserror::Error;
+
+/// How structs (and the fields of struct variants) are represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructStyle {
+    /// `((name value) ..)`
+    Alist,
+    /// `(:name value ..)`
+    Plist,
+}
+
+/// How `None` is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoneStyle {
+    /// `#f`
+    False,
+    /// `()`
+    EmptyList,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SerdeOptions<'t> {
+    /// Decides about keywords and bytevectors, and is used for
+    /// reading in [from_str](from_str) and [from_file](from_file)
+    pub format: &'t AnysexprFormat<'t>,
+    pub struct_style: StructStyle,
+    pub none_style: NoneStyle,
+}
+
+impl<'t> SerdeOptions<'t> {
+    /// Structs as alists, `None` as `#f`.
+    pub fn new(format: &'t AnysexprFormat<'t>) -> SerdeOptions<'t> {
+        SerdeOptions {
+            format,
+            struct_style: StructStyle::Alist,
+            none_style: NoneStyle::False,
+        }
+    }
+
+    fn field_name<S: Deref<Target = str>>(&self, name: S) -> Atom<S> {
+        if self.struct_style == StructStyle::Plist && has_keywords(self.format) {
+            if self.format.trailing_colon_is_keyword {
+                Atom::Keyword2(name)
+            } else {
+                Atom::Keyword1(name)
+            }
+        } else {
+            Atom::Symbol(name)
+        }
+    }
+}
+
+/// Whether `:foo` (or `foo:`) is read as a keyword in `format`.
+fn has_keywords(format: &AnysexprFormat) -> bool {
+    !(format.atoms_are_strings || format.wat_idchars)
+}
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+
+// ---- Serializing -------------------------------------------------------
+
+#[derive(Error, Debug)]
+pub enum SerializeError {
+    #[error("{0}")]
+    Write(#[from] WriteError),
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl ser::Error for SerializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerializeError::Custom(msg.to_string())
+    }
+}
+
+fn at(v: VValue) -> VValueWithPos {
+    v.at(Span::default())
+}
+
+fn list(items: Vec<VValueWithPos>) -> VValue {
+    VValue::List(Parenkind::Round, None, items)
+}
+
+fn integer(i: impl Into<BigInt>) -> VValue {
+    VValue::Atom(Atom::Number(R5RSNumber::Integer(Integer::from(i.into()))))
+}
+
+/// `value` as an s-expression.
+pub fn to_value<T: Serialize + ?Sized>(
+    value: &T,
+    opts: &SerdeOptions
+) -> Result<VValue, SerializeError> {
+    value.serialize(Serializer { opts })
+}
+
+/// `value` as s-expression text.
+pub fn to_string<T: Serialize + ?Sized>(
+    value: &T,
+    opts: &SerdeOptions
+) -> Result<String, SerializeError> {
+    Ok(opts.format.to_string(&to_value(value, opts)?)?)
+}
+
+/// Write `value` as an s-expression to an output stream. Nothing is
+/// written if serialization fails.
+pub fn to_writer<T: Serialize + ?Sized>(
+    out: &mut impl Write,
+    value: &T,
+    opts: &SerdeOptions
+) -> Result<(), SerializeError> {
+    let v = to_value(value, opts)?;
+    opts.format.write(out, &v)?;
+    Ok(())
+}
+
+/// A Serde serializer building a [VValue](VValue), see
+/// [to_value](to_value).
+pub struct Serializer<'o> {
+    pub opts: &'o SerdeOptions<'o>,
+}
+
+/// Collects the items of sequences, tuples and variants; for tuple
+/// and struct variants, the variant name is the first item.
+pub struct SerializeList<'o> {
+    opts: &'o SerdeOptions<'o>,
+    items: Vec<VValueWithPos>,
+}
+
+pub struct SerializeAlist<'o> {
+    opts: &'o SerdeOptions<'o>,
+    items: Vec<VValueWithPos>,
+    key: Option<VValueWithPos>,
+}
+
+impl<'o> Serializer<'o> {
+    fn list(self, head: Option<&str>, len: Option<usize>) -> SerializeList<'o> {
+        let mut items = Vec::with_capacity(len.unwrap_or(0) + 1);
+        if let Some(name) = head {
+            items.push(at(VValue::Atom(Atom::Symbol(KString::from_ref(name)))));
+        }
+        SerializeList { opts: self.opts, items }
+    }
+}
+
+impl<'o> ser::Serializer for Serializer<'o> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    type SerializeSeq = SerializeList<'o>;
+    type SerializeTuple = SerializeList<'o>;
+    type SerializeTupleStruct = SerializeList<'o>;
+    type SerializeTupleVariant = SerializeList<'o>;
+    type SerializeMap = SerializeAlist<'o>;
+    type SerializeStruct = SerializeList<'o>;
+    type SerializeStructVariant = SerializeList<'o>;
+
+    fn serialize_bool(self, v: bool) -> Result<VValue, SerializeError> {
+        Ok(VValue::Atom(Atom::Bool(v)))
+    }
+    fn serialize_i8(self, v: i8) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_i16(self, v: i16) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_i32(self, v: i32) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_i128(self, v: i128) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_u16(self, v: u16) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_u32(self, v: u32) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_u64(self, v: u64) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_u128(self, v: u128) -> Result<VValue, SerializeError> {
+        Ok(integer(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<VValue, SerializeError> {
+        self.serialize_f64(v.into())
+    }
+    fn serialize_f64(self, v: f64) -> Result<VValue, SerializeError> {
+        Ok(VValue::Atom(Atom::Number(R5RSNumber::Real(v))))
+    }
+    fn serialize_char(self, v: char) -> Result<VValue, SerializeError> {
+        Ok(VValue::Atom(Atom::Char(v)))
+    }
+    fn serialize_str(self, v: &str) -> Result<VValue, SerializeError> {
+        Ok(VValue::Atom(Atom::String(KString::from_ref(v))))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<VValue, SerializeError> {
+        if self.opts.format.hash_u8_is_bytevector {
+            Ok(VValue::Atom(Atom::NumericVector(Box::new(NumericVector::U8(v.to_vec())))))
+        } else {
+            Ok(list(v.iter().map(|b| at(integer(*b))).collect()))
+        }
+    }
+    fn serialize_none(self) -> Result<VValue, SerializeError> {
+        match self.opts.none_style {
+            NoneStyle::False => Ok(VValue::Atom(Atom::Bool(false))),
+            NoneStyle::EmptyList => Ok(list(Vec::new())),
+        }
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<VValue, SerializeError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<VValue, SerializeError> {
+        Ok(list(Vec::new()))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<VValue, SerializeError> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str
+    ) -> Result<VValue, SerializeError> {
+        Ok(VValue::Atom(Atom::Symbol(KString::from_static(variant))))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T
+    ) -> Result<VValue, SerializeError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T
+    ) -> Result<VValue, SerializeError> {
+        let v = value.serialize(Serializer { opts: self.opts })?;
+        Ok(list(vec![at(VValue::Atom(Atom::Symbol(KString::from_static(variant)))),
+                     at(v)]))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList<'o>, SerializeError> {
+        Ok(self.list(None, len))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList<'o>, SerializeError> {
+        Ok(self.list(None, Some(len)))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize
+    ) -> Result<SerializeList<'o>, SerializeError> {
+        Ok(self.list(None, Some(len)))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<SerializeList<'o>, SerializeError> {
+        Ok(self.list(Some(variant), Some(len)))
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeAlist<'o>, SerializeError> {
+        Ok(SerializeAlist {
+            opts: self.opts,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize
+    ) -> Result<SerializeList<'o>, SerializeError> {
+        Ok(self.list(None, Some(len)))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize
+    ) -> Result<SerializeList<'o>, SerializeError> {
+        Ok(self.list(Some(variant), Some(len)))
+    }
+}
+
+impl SerializeList<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        let v = value.serialize(Serializer { opts: self.opts })?;
+        self.items.push(at(v));
+        Ok(())
+    }
+
+    fn push_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T
+    ) -> Result<(), SerializeError> {
+        let name = at(VValue::Atom(self.opts.field_name(KString::from_static(key))));
+        let v = at(value.serialize(Serializer { opts: self.opts })?);
+        match self.opts.struct_style {
+            StructStyle::Alist => self.items.push(at(list(vec![name, v]))),
+            StructStyle::Plist => {
+                self.items.push(name);
+                self.items.push(v);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SerializeList<'_> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<VValue, SerializeError> {
+        Ok(list(self.items))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList<'_> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<VValue, SerializeError> {
+        Ok(list(self.items))
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList<'_> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<VValue, SerializeError> {
+        Ok(list(self.items))
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList<'_> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<VValue, SerializeError> {
+        Ok(list(self.items))
+    }
+}
+
+impl ser::SerializeStruct for SerializeList<'_> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T
+    ) -> Result<(), SerializeError> {
+        self.push_field(key, value)
+    }
+    fn end(self) -> Result<VValue, SerializeError> {
+        Ok(list(self.items))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeList<'_> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T
+    ) -> Result<(), SerializeError> {
+        self.push_field(key, value)
+    }
+    fn end(self) -> Result<VValue, SerializeError> {
+        Ok(list(self.items))
+    }
+}
+
+impl ser::SerializeMap for SerializeAlist<'_> {
+    type Ok = VValue;
+    type Error = SerializeError;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerializeError> {
+        self.key = Some(at(key.serialize(Serializer { opts: self.opts })?));
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        let key = self.key.take().expect("serialize_key called before serialize_value");
+        let v = at(value.serialize(Serializer { opts: self.opts })?);
+        self.items.push(at(list(vec![key, v])));
+        Ok(())
+    }
+    fn end(self) -> Result<VValue, SerializeError> {
+        Ok(list(self.items))
+    }
+}
+
+
+// ---- Deserializing -----------------------------------------------------
+
+/// A deserialization error, with the span of the node it is about
+/// (None only for errors that Serde raises outside of any node).
+#[derive(Error, Debug)]
+pub struct DeserializeError {
+    pub msg: String,
+    pub span: Option<Span>,
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.write_str(&self.msg)?;
+        if let Some(span) = self.span {
+            f.write_fmt(format_args!(" {}", span))?;
+        }
+        Ok(())
+    }
+}
+
+impl de::Error for DeserializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        DeserializeError { msg: msg.to_string(), span: None }
+    }
+}
+
+impl DeserializeError {
+    fn at(msg: impl Display, span: Span) -> DeserializeError {
+        DeserializeError { msg: msg.to_string(), span: Some(span) }
+    }
+}
+
+/// A [DeserializeError](DeserializeError) with the file it is from.
+#[derive(Error, Debug)]
+pub struct DeserializeErrorWithContext {
+    pub err: DeserializeError,
+    pub container: Box<dyn Context>,
+}
+
+impl Display for DeserializeErrorWithContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        if let Some(span) = self.err.span {
+            f.write_fmt(format_args!("{} ", self.err.msg))?;
+            self.container.format_with_pos(span.start, f)
+        } else {
+            self.container.format_without_pos(f)?;
+            f.write_fmt(format_args!(": {}", self.err.msg))
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FromStrError {
+    #[error("{0}")]
+    Read(#[from] ReadErrorWithPos),
+    #[error("{0}")]
+    Deserialize(#[from] DeserializeError),
+}
+
+#[derive(Error, Debug)]
+pub enum FromFileError {
+    #[error("{0}")]
+    Read(#[from] ReadErrorWithLocation),
+    #[error("{0}")]
+    Deserialize(#[from] DeserializeErrorWithContext),
+}
+
+/// Deserialize a `T` from a value, borrowing strings from it where `T`
+/// allows.
+pub fn from_value<'a, T: de::Deserialize<'a>, S: Deref<Target = str>>(
+    val: &'a VValueWithPos<S>,
+    opts: &SerdeOptions
+) -> Result<T, DeserializeError> {
+    T::deserialize(Deserializer::new(val, opts))
+}
+
+fn single_value(
+    mut vals: Vec<VValueWithPos>,
+    input_end: impl FnOnce() -> Span
+) -> Result<VValueWithPos, DeserializeError> {
+    match vals.len() {
+        1 => Ok(vals.pop().unwrap()),
+        0 => Err(DeserializeError::at("expecting a value, got none", input_end())),
+        _ => Err(DeserializeError::at("expecting a single value, got more", vals[1].1)),
+    }
+}
+
+/// Deserialize a `T` from text in `opts.format` holding exactly one
+/// value.
+pub fn from_str<T: DeserializeOwned>(
+    s: &str,
+    opts: &SerdeOptions
+) -> Result<T, FromStrError> {
+    let vals = opts.format.read_all(buffered_chars(s.as_bytes()), &MODES)?;
+    let val = single_value(vals, Span::default)?;
+    Ok(from_value(&val, opts)?)
+}
+
+/// Deserialize a `T` from a file in `opts.format` holding exactly
+/// one value.
+pub fn from_file<T: DeserializeOwned>(
+    path: &Path,
+    opts: &SerdeOptions
+) -> Result<T, FromFileError> {
+    let with_path = |err| DeserializeErrorWithContext {
+        err,
+        container: Box::new(FileContext { path: path.to_path_buf() })
+    };
+    let vals = opts.format.read_file(path, &MODES)?;
+    let val = single_value(vals, Span::default).map_err(with_path)?;
+    Ok(from_value(&val, opts).map_err(with_path)?)
+}
+
+/// A Serde deserializer reading from a
+/// [VValueWithPos](VValueWithPos), see [from_value](from_value).
+pub struct Deserializer<'a, 'o, S> {
+    val: &'a VValueWithPos<S>,
+    opts: &'o SerdeOptions<'o>,
+}
+
+impl<'a, 'o, S: Deref<Target = str>> Deserializer<'a, 'o, S> {
+    pub fn new(val: &'a VValueWithPos<S>, opts: &'o SerdeOptions<'o>) -> Self {
+        Deserializer { val, opts }
+    }
+
+    fn span(&self) -> Span {
+        self.val.1
+    }
+
+    /// Give errors that don't have a span yet the span of this node.
+    fn at<T>(&self, r: Result<T, DeserializeError>) -> Result<T, DeserializeError> {
+        r.map_err(|mut e| {
+            if e.span.is_none() {
+                e.span = Some(self.span());
+            }
+            e
+        })
+    }
+
+    fn invalid<T>(&self, expected: &str) -> Result<T, DeserializeError> {
+        Err(DeserializeError::at(
+            format_args!("expecting {}, got: {}", expected, &self.val.0), self.span()))
+    }
+
+    /// The items of a proper list (or `#(..)` vector).
+    fn items(&self) -> Option<&'a [VValueWithPos<S>]> {
+        match &self.val.0 {
+            VValue::List(_, None, vs) => Some(vs),
+            VValue::ReaderMacro(ReaderMacro::Vector, v) => match &v.0 {
+                VValue::List(_, None, vs) => Some(vs),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    fn is_none(&self) -> bool {
+        match &self.val.0 {
+            VValue::Atom(Atom::Bool(false)) | VValue::Atom(Atom::Nil) => true,
+            VValue::List(Parenkind::Round, None, vs) => vs.is_empty(),
+            _ => false
+        }
+    }
+
+    fn text(&self) -> Option<&'a str> {
+        match &self.val.0 {
+            VValue::Atom(Atom::String(s)) | VValue::Atom(Atom::Symbol(s))
+                | VValue::Atom(Atom::Keyword1(s)) | VValue::Atom(Atom::Keyword2(s)) =>
+                Some(s),
+            _ => None
+        }
+    }
+
+    fn visit_seq<V: Visitor<'a>>(&self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match self.items() {
+            Some(vs) => {
+                let mut seq = SeqAccess { items: vs.iter(), opts: self.opts, span: self.span() };
+                let v = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(v)
+            }
+            None => self.invalid("a list")
+        }
+    }
+
+    fn visit_struct<V: Visitor<'a>>(
+        &self,
+        items: &'a [VValueWithPos<S>],
+        style: StructStyle,
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_map(MapAccess {
+            items: items.iter(),
+            style,
+            opts: self.opts,
+            value: None,
+        })
+    }
+}
+
+fn visit_integer<'a, V: Visitor<'a>>(i: &Integer, visitor: V) -> Result<V::Value, DeserializeError> {
+    match i {
+        Integer::Small(i) => visitor.visit_i64(*i),
+        Integer::Big(b) => {
+            if let Some(u) = b.to_u64() {
+                visitor.visit_u64(u)
+            } else if let Some(i) = b.to_i128() {
+                visitor.visit_i128(i)
+            } else if let Some(u) = b.to_u128() {
+                visitor.visit_u128(u)
+            } else {
+                Err(de::Error::custom(format_args!("integer out of range: {}", b)))
+            }
+        }
+    }
+}
+
+impl<'a, 'o, S: Deref<Target = str>> de::Deserializer<'a> for Deserializer<'a, 'o, S> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        let r = match &self.val.0 {
+            VValue::Atom(a) => match a {
+                Atom::Nil => visitor.visit_unit(),
+                Atom::Bool(b) => visitor.visit_bool(*b),
+                Atom::Char(c) => visitor.visit_char(*c),
+                Atom::String(s) | Atom::Symbol(s) | Atom::Keyword1(s) | Atom::Keyword2(s) =>
+                    visitor.visit_borrowed_str(s),
+                Atom::Number(R5RSNumber::Integer(i)) => visit_integer(i, visitor),
+                Atom::Number(R5RSNumber::Real(x)) => visitor.visit_f64(*x),
+                Atom::Number(R5RSNumber::Rational(r)) => visitor.visit_f64(r.to_f64()),
+                Atom::NumericVector(v) => match &**v {
+                    NumericVector::U8(bytes) => visitor.visit_borrowed_bytes(bytes),
+                    _ => return self.invalid("a value with a Serde representation")
+                }
+                _ => return self.invalid("a value with a Serde representation")
+            }
+            _ => return self.visit_seq(visitor)
+        };
+        self.at(r)
+    }
+
+    fn deserialize_bool<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match &self.val.0 {
+            VValue::Atom(Atom::Bool(b)) => self.at(visitor.visit_bool(*b)),
+            _ => self.invalid("a boolean")
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match &self.val.0 {
+            VValue::Atom(Atom::Number(R5RSNumber::Integer(i))) =>
+                self.at(visit_integer(i, visitor)),
+            _ => self.invalid("an integer")
+        }
+    }
+    fn deserialize_i128<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+    fn deserialize_u128<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_f64(visitor)
+    }
+    fn deserialize_f64<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        let x = match &self.val.0 {
+            VValue::Atom(Atom::Number(R5RSNumber::Real(x))) => *x,
+            VValue::Atom(Atom::Number(R5RSNumber::Rational(r))) => r.to_f64(),
+            VValue::Atom(Atom::Number(R5RSNumber::Integer(i))) => i.to_f64(),
+            _ => return self.invalid("a real number")
+        };
+        self.at(visitor.visit_f64(x))
+    }
+
+    fn deserialize_char<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match &self.val.0 {
+            VValue::Atom(Atom::Char(c)) => self.at(visitor.visit_char(*c)),
+            _ => self.invalid("a character")
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match &self.val.0 {
+            VValue::Atom(Atom::String(s)) => self.at(visitor.visit_borrowed_str(s)),
+            _ => self.invalid("a string")
+        }
+    }
+    fn deserialize_string<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match &self.val.0 {
+            VValue::Atom(Atom::NumericVector(v)) => match &**v {
+                NumericVector::U8(bytes) => self.at(visitor.visit_borrowed_bytes(bytes)),
+                _ => self.invalid("a bytevector")
+            }
+            _ => self.visit_seq(visitor)
+        }
+    }
+    fn deserialize_byte_buf<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        if self.is_none() {
+            self.at(visitor.visit_none())
+        } else {
+            let span = self.span();
+            visitor.visit_some(self).map_err(|mut e| {
+                e.span.get_or_insert(span);
+                e
+            })
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match &self.val.0 {
+            VValue::Atom(Atom::Nil) => self.at(visitor.visit_unit()),
+            VValue::List(Parenkind::Round, None, vs) if vs.is_empty() =>
+                self.at(visitor.visit_unit()),
+            _ => self.invalid("()")
+        }
+    }
+    fn deserialize_unit_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        let span = self.span();
+        visitor.visit_newtype_struct(self).map_err(|mut e| {
+            e.span.get_or_insert(span);
+            e
+        })
+    }
+
+    fn deserialize_seq<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        let r = self.visit_seq(visitor);
+        self.at(r)
+    }
+    fn deserialize_tuple<V: Visitor<'a>>(
+        self,
+        _len: usize,
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_tuple_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match self.items() {
+            Some(vs) => {
+                let r = self.visit_struct(vs, StructStyle::Alist, visitor);
+                self.at(r)
+            }
+            None => self.invalid("an alist")
+        }
+    }
+    fn deserialize_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        match self.items() {
+            Some(vs) => {
+                let r = self.visit_struct(vs, self.opts.struct_style, visitor);
+                self.at(r)
+            }
+            None => self.invalid(match self.opts.struct_style {
+                StructStyle::Alist => "an alist",
+                StructStyle::Plist => "a plist",
+            })
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        let (tag, rest) = match &self.val.0 {
+            VValue::Atom(Atom::Symbol(_)) => (self.val, None),
+            VValue::List(Parenkind::Round, None, vs) => match vs.split_first() {
+                Some((tag @ VValueWithPos(VValue::Atom(Atom::Symbol(_)), _), rest)) =>
+                    (tag, Some(rest)),
+                _ => return self.invalid("a list starting with a variant name")
+            }
+            _ => return self.invalid("a variant name or a list starting with one")
+        };
+        let r = visitor.visit_enum(EnumAccess {
+            tag,
+            rest,
+            opts: self.opts,
+            span: self.span(),
+        });
+        self.at(r)
+    }
+
+    fn deserialize_identifier<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match self.text() {
+            Some(s) => self.at(visitor.visit_borrowed_str(s)),
+            None => self.invalid("a name")
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_unit()
+    }
+}
+
+struct SeqAccess<'a, 'o, S> {
+    items: std::slice::Iter<'a, VValueWithPos<S>>,
+    opts: &'o SerdeOptions<'o>,
+    span: Span,
+}
+
+impl<S> SeqAccess<'_, '_, S> {
+    /// Check that all items have been taken.
+    fn end(&mut self) -> Result<(), DeserializeError> {
+        match self.items.next() {
+            Some(v) => Err(DeserializeError::at("too many items in list", v.1)),
+            None => Ok(())
+        }
+    }
+}
+
+impl<'a, 'o, S: Deref<Target = str>> de::SeqAccess<'a> for SeqAccess<'a, 'o, S> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'a>>(
+        &mut self,
+        seed: T
+    ) -> Result<Option<T::Value>, DeserializeError> {
+        match self.items.next() {
+            Some(v) => seed.deserialize(Deserializer::new(v, self.opts)).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+impl<S> SeqAccess<'_, '_, S> {
+    fn missing<T>(&self, what: &str) -> Result<T, DeserializeError> {
+        Err(DeserializeError::at(format_args!("missing {}", what), self.span))
+    }
+}
+
+struct MapAccess<'a, 'o, S> {
+    items: std::slice::Iter<'a, VValueWithPos<S>>,
+    style: StructStyle,
+    opts: &'o SerdeOptions<'o>,
+    value: Option<&'a VValueWithPos<S>>,
+}
+
+impl<'a, 'o, S: Deref<Target = str>> de::MapAccess<'a> for MapAccess<'a, 'o, S> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'a>>(
+        &mut self,
+        seed: K
+    ) -> Result<Option<K::Value>, DeserializeError> {
+        let Some(item) = self.items.next() else {
+            return Ok(None)
+        };
+        let key = match self.style {
+            StructStyle::Alist => match &item.0 {
+                VValue::List(_, None, kv) if kv.len() == 2 => {
+                    self.value = Some(&kv[1]);
+                    &kv[0]
+                }
+                _ => return Err(DeserializeError::at(
+                    format_args!("expecting a (key value) entry, got: {}", item.0), item.1))
+            }
+            StructStyle::Plist => match self.items.next() {
+                Some(v) => {
+                    self.value = Some(v);
+                    item
+                }
+                None => return Err(DeserializeError::at("missing value after key", item.1))
+            }
+        };
+        seed.deserialize(Deserializer::new(key, self.opts)).map(Some)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'a>>(
+        &mut self,
+        seed: T
+    ) -> Result<T::Value, DeserializeError> {
+        let v = self.value.take().expect("next_key_seed called before next_value_seed");
+        seed.deserialize(Deserializer::new(v, self.opts))
+    }
+}
+
+struct EnumAccess<'a, 'o, S> {
+    tag: &'a VValueWithPos<S>,
+    /// The items after the tag, None if there was no list
+    rest: Option<&'a [VValueWithPos<S>]>,
+    opts: &'o SerdeOptions<'o>,
+    span: Span,
+}
+
+impl<'a, 'o, S: Deref<Target = str>> de::EnumAccess<'a> for EnumAccess<'a, 'o, S> {
+    type Error = DeserializeError;
+    type Variant = Self;
+
+    fn variant_seed<T: DeserializeSeed<'a>>(
+        self,
+        seed: T
+    ) -> Result<(T::Value, Self), DeserializeError> {
+        let v = seed.deserialize(Deserializer::new(self.tag, self.opts))?;
+        Ok((v, self))
+    }
+}
+
+impl<'a, 'o, S> EnumAccess<'a, 'o, S> {
+    fn seq(&self) -> SeqAccess<'a, 'o, S> {
+        SeqAccess {
+            items: self.rest.unwrap_or(&[]).iter(),
+            opts: self.opts,
+            span: self.span,
+        }
+    }
+}
+
+impl<'a, 'o, S: Deref<Target = str>> de::VariantAccess<'a> for EnumAccess<'a, 'o, S> {
+    type Error = DeserializeError;
+
+    fn unit_variant(self) -> Result<(), DeserializeError> {
+        self.seq().end()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'a>>(
+        self,
+        seed: T
+    ) -> Result<T::Value, DeserializeError> {
+        let mut seq = self.seq();
+        let v = match de::SeqAccess::next_element_seed(&mut seq, seed)? {
+            Some(v) => v,
+            None => return seq.missing("variant value")
+        };
+        seq.end()?;
+        Ok(v)
+    }
+
+    fn tuple_variant<V: Visitor<'a>>(
+        self,
+        _len: usize,
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        let mut seq = self.seq();
+        let v = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(v)
+    }
+
+    fn struct_variant<V: Visitor<'a>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, DeserializeError> {
+        visitor.visit_map(MapAccess {
+            items: self.rest.unwrap_or(&[]).iter(),
+            style: self.opts.struct_style,
+            opts: self.opts,
+            value: None,
+        })
+    }
+}
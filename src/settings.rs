@@ -10,6 +10,9 @@
 //! Settings for both reading (parsing) and writing (serializing)
 //! data.
 
+use crate::pretty::{SpecialForms, CLOJURE_SPECIAL_FORMS, COMMON_LISP_SPECIAL_FORMS,
+                    ELISP_SPECIAL_FORMS, SCHEME_SPECIAL_FORMS};
+
 #[derive(Debug)]
 pub struct AnysexprFormat<'t> {
     pub name: &'t str,
@@ -19,6 +22,87 @@ pub struct AnysexprFormat<'t> {
     pub x_escape_len: u8,
     pub accept_long_false_true: bool,
     pub hashcolon_is_keyword: bool, // #:foo, keyword vs. uninterned symbol
+    pub trailing_colon_is_keyword: bool, // foo:, keyword vs. symbol
+    pub comma_is_whitespace: bool, // instead of unquote
+    /// `` ` `` `~` `~@` `@` `#'` as Clojure's reader macros
+    /// (syntax-quote, unquote, unquote-splicing, deref, var) instead
+    /// of Scheme's quasiquotation
+    pub clojure_quoting: bool,
+    pub hash_underscore_discards: bool, // #_ like #;
+    pub hash_curly_is_set: bool, // #{..}
+    pub hash_paren_is_fn: bool, // #(..)
+    pub has_reader_conditionals: bool, // #?(..) #?@(..)
+    pub has_scheme_hash_syntax: bool, // #\c #; #| |# #:foo #!foo
+    pub backslash_chars: bool, // \c \newline
+    pub hash_symbol_is_tag: bool, // #inst "..", EDN's tagged elements
+    pub has_nil_true_false: bool, // as atoms instead of symbols
+    pub upcase_symbols: bool, // unescaped symbols, Common Lisp's readtable case
+    pub package_qualified_symbols: bool, // pkg:sym pkg::sym
+    pub case_insensitive_char_names: bool, // #\Space
+    pub hash_quote_is_function: bool, // #'f
+    pub hash_paren_is_vector: bool, // #(..)
+    pub hash_star_is_bit_vector: bool, // #*1010
+    pub hash_u8_is_bytevector: bool, // #u8(1 2 255)
+    pub has_srfi4_vectors: bool, // #s16(..) #f64(..) etc.
+    pub hash_c_is_complex: bool, // #c(1 2)
+    pub has_feature_conditionals: bool, // #+feature form, #-feature form
+    pub backslash_quotes_next_char: bool, // "\n" is "n", no escape sequences
+    pub numbers: NumberSyntax,
+    pub hash_dot_is_read_eval: bool, // #.form
+    /// If false, `#.` is rejected with
+    /// [ParseError::ReadEvalNotAllowed](crate::parse::ParseError::ReadEvalNotAllowed)
+    /// (like Common Lisp's `*read-eval*`)
+    pub allow_read_eval: bool,
+    pub hash_colon_symbols: bool, // #:foo without the rest of has_scheme_hash_syntax
+    pub question_mark_chars: bool, // ?a ?\n ?\C-x
+    pub elisp_escapes: bool, // "\e" "\s" "\d", "\ " ignored
+    pub backslash_escapes_in_symbols: bool, // foo\ bar
+    pub hash_s_is_record: bool, // #s(..)
+    pub hash_at_skips: bool, // #@N skips N bytes, #@00 the rest of the input
+    pub has_datum_labels: bool, // #0= #0#
+    /// Sexplib: all atoms are read as
+    /// [Atom::String](crate::value::Atom::String), quoted or not;
+    /// only whitespace, `(`, `)`, `"`, `;`, `#|` and `#;` are syntax
+    pub atoms_are_strings: bool,
+    pub ocaml_escapes: bool, // "\065" decimal, "\xhh", "\q" is `\q`
+    pub nested_block_comments: bool, // #| #| |# |#
+    pub doubled_quote_escapes: bool, // "a""b" is a"b, no backslash escapes
+    pub verbatim_quoted_symbols: bool, // |..| without escapes
+    pub hash_b_x_are_bit_vectors: bool, // #b0101 #x1F (SMT-LIB)
+    pub wat_idchars: bool, // $x i32.add @annot, with ' ` | \ # as symbol characters
+    pub wat_escapes: bool, // only \t \n \r \" \' \\ \hh \u{hhhh}
+    pub paren_semicolon_comments: bool, // (; (; ;) ;)
+    /// For indenting when pretty-printing, see [pretty](crate::pretty)
+    pub special_forms: SpecialForms<'t>,
+}
+
+/// The syntax of numbers (other than those with `#` prefixes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberSyntax {
+    /// R7RS: integers, ratios, decimals, complex numbers,
+    /// `+inf.0`, `+nan.0`
+    Scheme,
+    /// Exponent markers `s f d l` besides `e`, `1.` is an integer, no
+    /// complex, infinity or NaN syntax (such text is read as symbols)
+    CommonLisp,
+    /// Like Common Lisp but without ratios and other exponent
+    /// markers than `e`; `1.0e+INF` and `0.0e+NaN`
+    EmacsLisp,
+    /// SMT-LIB: numerals (integers without sign or leading zeros) and
+    /// decimals, read as [Atom::Decimal](crate::value::Atom::Decimal);
+    /// `-1` is a symbol, other text starting with a digit (`1x`,
+    /// `01`) is an error
+    SmtLib,
+    /// WebAssembly text: integers (decimal or `0x` hex) and decimal
+    /// floats, all with `_` allowed between digits, `inf`, `nan`;
+    /// hex floats and `nan:0x..` are read as symbols
+    Wat,
+    /// EDN: integers and floats, with an `N` suffix (arbitrary
+    /// precision integer) or an `M` suffix (exact decimal, read as
+    /// [Atom::Decimal](crate::value::Atom::Decimal)); other text
+    /// starting like a number (ratios, `1+`, `-1a`) is an error, as
+    /// EDN symbols can't start like that
+    Edn,
 }
 
 pub const GAMBIT_FORMAT : AnysexprFormat = AnysexprFormat {
@@ -29,6 +113,48 @@ pub const GAMBIT_FORMAT : AnysexprFormat = AnysexprFormat {
     x_escape_len: 8,
     accept_long_false_true: false,
     hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: true,
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: true,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: true,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: true,
+    has_srfi4_vectors: true,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::Scheme,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: true,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: SCHEME_SPECIAL_FORMS,
 };
 
 pub const R7RS_FORMAT : AnysexprFormat = AnysexprFormat {
@@ -39,6 +165,48 @@ pub const R7RS_FORMAT : AnysexprFormat = AnysexprFormat {
     x_escape_len: 8, // XX check
     accept_long_false_true: false, // XX check
     hashcolon_is_keyword: true, // XX check
+    trailing_colon_is_keyword: true, // XX check
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: true,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: true,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: true,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::Scheme,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: true,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: SCHEME_SPECIAL_FORMS,
 };
 
 pub const GUILE_FORMAT : AnysexprFormat = AnysexprFormat {
@@ -49,6 +217,462 @@ pub const GUILE_FORMAT : AnysexprFormat = AnysexprFormat {
     x_escape_len: 2,
     accept_long_false_true: true,
     hashcolon_is_keyword: true,
+    trailing_colon_is_keyword: true,
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: true,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: true,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: true,
+    has_srfi4_vectors: true,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::Scheme,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: false,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: SCHEME_SPECIAL_FORMS,
+};
+
+/// `[..]` and `{..}` are read as lists of `Parenkind::Square` and
+/// `Parenkind::Curly`, i.e. Clojure's vectors and maps (the entries
+/// of maps are not checked to come in pairs); `#{..}`, `#(..)`,
+/// `#?(..)` and the prefixes `` ` `` `~` `~@` `@` `#'` are read as
+/// [ReaderMacro](crate::value::ReaderMacro)s, `#inst ".."` and the
+/// like as tagged elements. There are no dotted pairs, `'x` is read
+/// as `(quote x)` as in Clojure itself.
+pub const CLOJURE_FORMAT : AnysexprFormat = AnysexprFormat {
+    name: "Clojure",
+    has_dotted_pairs: false,
+    octal_escapes_in_delimited: true,
+    x_escape_terminated_by_semicolon_in_delimited: false,
+    x_escape_len: 8,
+    accept_long_false_true: false,
+    hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: false,
+    comma_is_whitespace: true,
+    clojure_quoting: true,
+    hash_underscore_discards: true,
+    hash_curly_is_set: true,
+    hash_paren_is_fn: true,
+    has_reader_conditionals: true,
+    has_scheme_hash_syntax: false,
+    backslash_chars: true,
+    hash_symbol_is_tag: true,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: false,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: false,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::Scheme,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: false,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: CLOJURE_SPECIAL_FORMS,
+};
+
+/// [EDN](https://github.com/edn-format/edn): like
+/// [CLOJURE_FORMAT](CLOJURE_FORMAT) without the reader macros other
+/// than `#{..}`, and with `nil`, `true` and `false` read as
+/// atoms, and EDN's numbers. See [edn](crate::edn) for reading with
+/// tag handlers and the checks the format requires.
+pub const EDN_FORMAT : AnysexprFormat = AnysexprFormat {
+    name: "EDN",
+    has_dotted_pairs: false,
+    octal_escapes_in_delimited: false,
+    x_escape_terminated_by_semicolon_in_delimited: false,
+    x_escape_len: 8,
+    accept_long_false_true: false,
+    hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: false,
+    comma_is_whitespace: true,
+    clojure_quoting: false,
+    hash_underscore_discards: true,
+    hash_curly_is_set: true,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: false,
+    backslash_chars: true,
+    hash_symbol_is_tag: true,
+    has_nil_true_false: true,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: false,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: false,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::Edn,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: false,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: &[],
+};
+
+/// Common Lisp data with the standard readtable: unescaped symbols
+/// are upcased, `pkg:sym` and `pkg::sym` are read as
+/// [Atom::PackageSymbol](crate::value::Atom::PackageSymbol)s, `#:foo`
+/// as uninterned symbols, `#'f`, `#(..)` and `#.x` as
+/// [ReaderMacro](crate::value::ReaderMacro)s, `#+feature form` as
+/// [VValue::FeatureConditional](crate::value::VValue::FeatureConditional),
+/// `#*1010` as bit vectors, `#c(1 2)` as complex numbers and `#1=` /
+/// `#1#` as datum labels. `#.` is rejected; use `AnysexprFormat {
+/// allow_read_eval: true, ..COMMON_LISP_FORMAT }` to accept it.
+pub const COMMON_LISP_FORMAT : AnysexprFormat = AnysexprFormat {
+    name: "Common Lisp",
+    has_dotted_pairs: true,
+    octal_escapes_in_delimited: false,
+    x_escape_terminated_by_semicolon_in_delimited: false,
+    x_escape_len: 2,
+    accept_long_false_true: false,
+    hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: false,
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: true,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: true,
+    package_qualified_symbols: true,
+    case_insensitive_char_names: true,
+    hash_quote_is_function: true,
+    hash_paren_is_vector: true,
+    hash_star_is_bit_vector: true,
+    hash_u8_is_bytevector: false,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: true,
+    has_feature_conditionals: true,
+    backslash_quotes_next_char: true,
+    numbers: NumberSyntax::CommonLisp,
+    hash_dot_is_read_eval: true,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: true,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: true,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: COMMON_LISP_SPECIAL_FORMS,
+};
+
+/// Emacs Lisp as read by `read` and written by `prin1`: `?a`,
+/// `?\n`, `?\C-x` as characters (or integers, for those with
+/// modifier bits beyond the character range), `[..]` as lists of
+/// `Parenkind::Square` (vectors), `#s(..)` records and `#'f` as
+/// [ReaderMacro](crate::value::ReaderMacro)s, `\` escapes in symbols,
+/// datum labels `#1=` / `#1#`; `#@N` skips input. `nil` and `t` are
+/// read as symbols.
+pub const ELISP_FORMAT : AnysexprFormat = AnysexprFormat {
+    name: "Emacs Lisp",
+    has_dotted_pairs: true,
+    octal_escapes_in_delimited: true,
+    x_escape_terminated_by_semicolon_in_delimited: false,
+    x_escape_len: 8,
+    accept_long_false_true: false,
+    hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: false,
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: false,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: true,
+    hash_paren_is_vector: false,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: false,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::EmacsLisp,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: true,
+    question_mark_chars: true,
+    elisp_escapes: true,
+    backslash_escapes_in_symbols: true,
+    hash_s_is_record: true,
+    hash_at_skips: true,
+    has_datum_labels: true,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: ELISP_SPECIAL_FORMS,
+};
+
+/// The s-expressions of OCaml's sexplib and dune: every atom is read
+/// as an [Atom::String](crate::value::Atom::String), whether quoted or
+/// not (`1`, `true`, `a.b` and `'x` are all just atoms), quoted atoms
+/// take OCaml's escapes, `#| |#` comments nest, `#;` comments out the
+/// next expression, and there are no dotted pairs. `[`, `]`, `{`, `}`
+/// are ordinary atom characters. Decimal and `\x` escapes denote
+/// bytes; those above 127 are read as the characters U+0080 to
+/// U+00FF. See [sexplib](crate::sexplib) for when atoms are written
+/// in quotes.
+pub const SEXPLIB_FORMAT : AnysexprFormat = AnysexprFormat {
+    name: "Sexplib",
+    has_dotted_pairs: false,
+    octal_escapes_in_delimited: false,
+    x_escape_terminated_by_semicolon_in_delimited: false,
+    x_escape_len: 2,
+    accept_long_false_true: false,
+    hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: false,
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: false,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: false,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: false,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::Scheme,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: false,
+    atoms_are_strings: true,
+    ocaml_escapes: true,
+    nested_block_comments: true,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: &[],
+};
+
+/// SMT-LIB v2 scripts and solver output: `|..|` symbols without
+/// escapes, strings with `""` as the only escape, `#b0101` as
+/// [Atom::BitVector](crate::value::Atom::BitVector), `#x1F` as
+/// [Atom::Hexadecimal](crate::value::Atom::Hexadecimal), decimals as
+/// [Atom::Decimal](crate::value::Atom::Decimal) (all keeping their
+/// digits as written), `:named` keywords, no dotted pairs.
+pub const SMTLIB_FORMAT : AnysexprFormat = AnysexprFormat {
+    name: "SMT-LIB",
+    has_dotted_pairs: false,
+    octal_escapes_in_delimited: false,
+    x_escape_terminated_by_semicolon_in_delimited: false,
+    x_escape_len: 2,
+    accept_long_false_true: false,
+    hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: false,
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: false,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: false,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: false,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::SmtLib,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: false,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: true,
+    verbatim_quoted_symbols: true,
+    hash_b_x_are_bit_vectors: true,
+    wat_idchars: false,
+    wat_escapes: false,
+    paren_semicolon_comments: false,
+    special_forms: &[],
+};
+
+/// The WebAssembly text format (`.wat`), for tokenizing: `;;` line
+/// and nestable `(; ;)` block comments, `$name`, `i32.add` and the
+/// like as symbols (annotations `(@name ..)` as lists starting with
+/// the symbol `@name`), and strings with `\hh` and `\u{..}` escapes.
+/// A run of `\hh` escapes is decoded as UTF-8 where valid; other bytes
+/// above 0x7f are read as the characters U+0080 to U+00FF. No dotted
+/// pairs and no quoting syntax.
+pub const WAT_FORMAT : AnysexprFormat = AnysexprFormat {
+    name: "WAT",
+    has_dotted_pairs: false,
+    octal_escapes_in_delimited: false,
+    x_escape_terminated_by_semicolon_in_delimited: false,
+    x_escape_len: 2,
+    accept_long_false_true: false,
+    hashcolon_is_keyword: false,
+    trailing_colon_is_keyword: false,
+    comma_is_whitespace: false,
+    clojure_quoting: false,
+    hash_underscore_discards: false,
+    hash_curly_is_set: false,
+    hash_paren_is_fn: false,
+    has_reader_conditionals: false,
+    has_scheme_hash_syntax: false,
+    backslash_chars: false,
+    hash_symbol_is_tag: false,
+    has_nil_true_false: false,
+    upcase_symbols: false,
+    package_qualified_symbols: false,
+    case_insensitive_char_names: false,
+    hash_quote_is_function: false,
+    hash_paren_is_vector: false,
+    hash_star_is_bit_vector: false,
+    hash_u8_is_bytevector: false,
+    has_srfi4_vectors: false,
+    hash_c_is_complex: false,
+    has_feature_conditionals: false,
+    backslash_quotes_next_char: false,
+    numbers: NumberSyntax::Wat,
+    hash_dot_is_read_eval: false,
+    allow_read_eval: false,
+    hash_colon_symbols: false,
+    question_mark_chars: false,
+    elisp_escapes: false,
+    backslash_escapes_in_symbols: false,
+    hash_s_is_record: false,
+    hash_at_skips: false,
+    has_datum_labels: false,
+    atoms_are_strings: false,
+    ocaml_escapes: false,
+    nested_block_comments: false,
+    doubled_quote_escapes: false,
+    verbatim_quoted_symbols: false,
+    hash_b_x_are_bit_vectors: false,
+    wat_idchars: true,
+    wat_escapes: true,
+    paren_semicolon_comments: true,
+    special_forms: &[],
 };
 
 
@@ -64,6 +688,10 @@ pub struct Modes {
     /// `(a . (b))` is still allowed if the format supports the
     /// syntax.
     pub allow_improper_lists: bool,
+    /// Whether `parse` should continue after reporting an error
+    /// (where possible) instead of stopping. See also
+    /// `AnysexprFormat::read_all_recovering`.
+    pub recover_from_errors: bool,
 }
 
 #[derive(Debug)]
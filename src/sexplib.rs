@@ -0,0 +1,49 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The atoms of OCaml's sexplib (and dune), for writing with
+//! [SEXPLIB_FORMAT](crate::settings::SEXPLIB_FORMAT).
+
+//! Atoms are written without quotes unless sexplib requires them
+//! (same as `Sexplib.Sexp.to_string`). Strings, symbols and numbers
+//! are all written as atoms with their text.
+
+use std::fmt::Write as _;
+
+/// Whether sexplib writes `s` in quotes (`Sexplib.Sexp.must_escape`).
+pub fn must_quote(s: &str) -> bool {
+    s.is_empty()
+        || s.contains("#|")
+        || s.contains("|#")
+        || s.chars().any(|c| c <= ' ' || c >= '\x7F' || "\"();\\".contains(c))
+}
+
+pub(crate) fn write_atom(out: &mut String, s: &str) {
+    if !must_quote(s) {
+        out.push_str(s);
+        return
+    }
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\x08' => out.push_str("\\b"),
+            c if c.is_ascii_control() => {
+                let _ = write!(out, "\\{:03}", c as u32);
+            }
+            // Sexplib takes the UTF-8 bytes as they are
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
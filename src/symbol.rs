@@ -0,0 +1,276 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Interned symbols: a [SymbolTable](SymbolTable) hands out one
+//! [Symbol](Symbol) per distinct name, which compares and hashes in
+//! O(1) and carries a small [SymbolId](SymbolId). Read with
+//! [InterningBuilder](InterningBuilder) to get trees with interned
+//! symbols; a table can be shared between any number of reads (and
+//! threads).
+
+use crate::builder::{TreeBuilder, VValueBuilder};
+use crate::pos::Span;
+use crate::value::{Atom, Parenkind, ReaderMacro, VValueWithPos};
+use kstring::KString;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// The number of a symbol in its table, counting from 0 in the order
+/// of interning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(pub u32);
+
+struct SymbolEntry {
+    id: SymbolId,
+    name: KString,
+}
+
+/// A symbol interned in a [SymbolTable](SymbolTable). Equality and
+/// hashing are by identity (symbols from different tables never
+/// compare equal); derefs to the name.
+#[derive(Clone)]
+pub struct Symbol(Arc<SymbolEntry>);
+
+impl Symbol {
+    pub fn id(&self) -> SymbolId {
+        self.0.id
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0.name
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0.name
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.write_str(&self.0.name)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.write_fmt(format_args!("Symbol({:?}, {})", self.0.name.as_str(), self.0.id.0))
+    }
+}
+
+#[derive(Default)]
+struct Symbols {
+    by_name: HashMap<KString, SymbolId>,
+    by_id: Vec<Symbol>,
+}
+
+/// The set of interned symbols. Interning takes `&self`, thus a table
+/// can be shared, e.g. via `Arc`, between readers in different
+/// threads.
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: Mutex<Symbols>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Symbols> {
+        // The data is consistent even if another thread panicked
+        // while holding the lock
+        self.symbols.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The symbol with the given name, added to the table if it's
+    /// not there yet.
+    pub fn intern(&self, name: &str) -> Symbol {
+        let mut symbols = self.lock();
+        if let Some(id) = symbols.by_name.get(name) {
+            return symbols.by_id[id.0 as usize].clone()
+        }
+        let id = SymbolId(u32::try_from(symbols.by_id.len())
+                          .expect("less than 2^32 symbols"));
+        let name = KString::from_ref(name);
+        let sym = Symbol(Arc::new(SymbolEntry { id, name: name.clone() }));
+        symbols.by_name.insert(name, id);
+        symbols.by_id.push(sym.clone());
+        sym
+    }
+
+    /// The symbol with the given name, if it has been interned.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        let symbols = self.lock();
+        symbols.by_name.get(name).map(|id| symbols.by_id[id.0 as usize].clone())
+    }
+
+    /// The symbol with the given id, if it is from this table.
+    pub fn symbol(&self, id: SymbolId) -> Option<Symbol> {
+        self.lock().by_id.get(id.0 as usize).cloned()
+    }
+
+    /// The number of symbols interned so far.
+    pub fn len(&self) -> usize {
+        self.lock().by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Intern the text of symbols and keywords in `atom`; strings and
+    /// uninterned symbols are kept as they are. For using interned
+    /// symbols with the tokens from [parse](crate::parse::parse).
+    pub fn intern_atom(&self, atom: Atom) -> Atom<IStr> {
+        match atom {
+            Atom::Symbol(s) => Atom::Symbol(IStr::Sym(self.intern(&s))),
+            Atom::Keyword1(s) => Atom::Keyword1(IStr::Sym(self.intern(&s))),
+            Atom::Keyword2(s) => Atom::Keyword2(IStr::Sym(self.intern(&s))),
+            Atom::PackageSymbol(s, pkglen) =>
+                Atom::PackageSymbol(IStr::Sym(self.intern(&s)), pkglen),
+            a => a.map_str(IStr::Str)
+        }
+    }
+}
+
+/// The text of atoms in trees with interned symbols: symbols and
+/// keywords are [Symbol](Symbol)s, strings and uninterned symbols
+/// stay plain.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IStr {
+    Sym(Symbol),
+    Str(KString),
+}
+
+impl IStr {
+    /// The interned symbol, if this is one.
+    pub fn symbol(&self) -> Option<&Symbol> {
+        match self {
+            IStr::Sym(s) => Some(s),
+            IStr::Str(_) => None
+        }
+    }
+}
+
+impl Deref for IStr {
+    type Target = str;
+    fn deref(&self) -> &str {
+        match self {
+            IStr::Sym(s) => s,
+            IStr::Str(s) => s
+        }
+    }
+}
+
+impl From<KString> for IStr {
+    fn from(s: KString) -> IStr {
+        IStr::Str(s)
+    }
+}
+
+/// Builds [VValueWithPos](VValueWithPos) trees with the symbols and
+/// keywords interned in a [SymbolTable](SymbolTable).
+pub struct InterningBuilder<'t> {
+    table: &'t SymbolTable,
+}
+
+impl<'t> InterningBuilder<'t> {
+    pub fn new(table: &'t SymbolTable) -> InterningBuilder<'t> {
+        InterningBuilder { table }
+    }
+}
+
+impl<'t> TreeBuilder for InterningBuilder<'t> {
+    type Value = VValueWithPos<IStr>;
+
+    fn atom(&mut self, atom: Atom, span: Span) -> VValueWithPos<IStr> {
+        VValueBuilder.atom(self.table.intern_atom(atom), span)
+    }
+
+    fn list(
+        &mut self,
+        pk: Parenkind,
+        items: Vec<VValueWithPos<IStr>>,
+        span: Span
+    ) -> VValueWithPos<IStr> {
+        VValueBuilder.list(pk, items, span)
+    }
+
+    fn improper_list(
+        &mut self,
+        pk: Parenkind,
+        items: Vec<VValueWithPos<IStr>>,
+        dotspan: Span,
+        tail: VValueWithPos<IStr>,
+        span: Span
+    ) -> VValueWithPos<IStr> {
+        VValueBuilder.improper_list(pk, items, dotspan, tail, span)
+    }
+
+    fn reader_macro(
+        &mut self,
+        rm: ReaderMacro,
+        value: VValueWithPos<IStr>,
+        span: Span
+    ) -> VValueWithPos<IStr> {
+        VValueBuilder.reader_macro(rm, value, span)
+    }
+
+    fn tagged(
+        &mut self,
+        tag: KString,
+        value: VValueWithPos<IStr>,
+        span: Span
+    ) -> VValueWithPos<IStr> {
+        VValueBuilder.tagged(IStr::Sym(self.table.intern(&tag)), value, span)
+    }
+
+    fn feature_conditional(
+        &mut self,
+        positive: bool,
+        feature: VValueWithPos<IStr>,
+        form: VValueWithPos<IStr>,
+        span: Span
+    ) -> VValueWithPos<IStr> {
+        VValueBuilder.feature_conditional(positive, feature, form, span)
+    }
+
+    fn labeled(
+        &mut self,
+        n: u32,
+        value: VValueWithPos<IStr>,
+        span: Span
+    ) -> VValueWithPos<IStr> {
+        VValueBuilder.labeled(n, value, span)
+    }
+
+    fn label_ref(&mut self, n: u32, span: Span) -> VValueWithPos<IStr> {
+        VValueBuilder.label_ref(n, span)
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Turning trees into token streams, and writing token streams.
+
+//! [tokens](tokens) walks a [VValue](VValue) and yields the
+//! [Token](Token)s that [parse](crate::parse::parse) gives for its
+//! text. [TokenWriter](TokenWriter) (or
+//! [AnysexprFormat::write_tokens](crate::settings::AnysexprFormat::write_tokens))
+//! writes any stream of tokens in the syntax of a format, adding
+//! spaces where tokens need to be separated (and newlines between
+//! top-level values), thus token-level filters built on `parse` can
+//! write their results back out.
+
+use crate::parse::{CommentStyle, Token};
+use crate::settings::AnysexprFormat;
+use crate::value::{Atom, Parenkind, ReaderMacro, VValue, VValueWithPos};
+use crate::write::{WriteError, Writer, DEFAULT_OPTIONS};
+use std::fmt::{Display, Write as _};
+use std::io::Write;
+use std::ops::Deref;
+
+enum Work<'t, S> {
+    Value(&'t VValue<S>),
+    Token(Token<S>),
+}
+
+/// The iterator returned by [tokens](tokens).
+pub struct Tokens<'t, S> {
+    /// What's left to do, last first
+    stack: Vec<Work<'t, S>>,
+    abbreviate_quotes: bool,
+}
+
+/// The tokens of `v`, generated while iterating. If
+/// `abbreviate_quotes` is true, `(quote x)`, `(quasiquote x)`,
+/// `(unquote x)` and `(unquote-splicing x)` lists give
+/// [Token::Quote](Token::Quote) etc. followed by the tokens of `x`,
+/// i.e. they are written as `'x` etc.
+pub fn tokens<S>(v: &VValue<S>, abbreviate_quotes: bool) -> Tokens<'_, S> {
+    Tokens {
+        stack: vec![Work::Value(v)],
+        abbreviate_quotes,
+    }
+}
+
+// The token abbreviating a `(quote x)` list and the like.
+fn quote_token<S: Deref<Target = str>>(items: &[VValueWithPos<S>]) -> Option<Token<S>> {
+    if items.len() != 2 {
+        return None
+    }
+    match &items[0].0 {
+        VValue::Atom(Atom::Symbol(s)) => match &**s {
+            "quote" => Some(Token::Quote),
+            "quasiquote" => Some(Token::Quasiquote),
+            "unquote" => Some(Token::Unquote),
+            "unquote-splicing" => Some(Token::UnquoteSplicing),
+            _ => None
+        }
+        _ => None
+    }
+}
+
+impl<S: Deref<Target = str> + Clone> Iterator for Tokens<'_, S> {
+    type Item = Token<S>;
+
+    fn next(&mut self) -> Option<Token<S>> {
+        let v = match self.stack.pop()? {
+            Work::Token(t) => return Some(t),
+            Work::Value(v) => v
+        };
+        Some(match v {
+            VValue::Atom(a) => Token::Atom(a.clone()),
+            VValue::List(pk, impr, vs) => {
+                if let (Parenkind::Round, None, true) = (pk, impr, self.abbreviate_quotes) {
+                    if let Some(t) = quote_token(vs) {
+                        self.stack.push(Work::Value(&vs[1].0));
+                        return Some(t)
+                    }
+                }
+                self.stack.push(Work::Token(Token::Close(*pk)));
+                for (i, item) in vs.iter().enumerate().rev() {
+                    self.stack.push(Work::Value(&item.0));
+                    if impr.is_some() && i + 1 == vs.len() {
+                        self.stack.push(Work::Token(Token::Dot));
+                    }
+                }
+                Token::Open(*pk)
+            }
+            VValue::ReaderMacro(rm, v) => {
+                self.stack.push(Work::Value(&v.0));
+                Token::ReaderMacro(*rm)
+            }
+            VValue::Tagged(tag, v) => {
+                self.stack.push(Work::Value(&v.0));
+                Token::Tag(tag.clone())
+            }
+            VValue::FeatureConditional(positive, b) => {
+                self.stack.push(Work::Value(&b.1.0));
+                self.stack.push(Work::Value(&b.0.0));
+                Token::FeatureConditional(*positive)
+            }
+            VValue::Labeled(n, v) => {
+                self.stack.push(Work::Value(&v.0));
+                Token::DatumLabel(*n)
+            }
+            VValue::LabelRef(n) => Token::DatumRef(*n),
+        })
+    }
+}
+
+/// Writes tokens in the syntax of a format to an output stream, as
+/// they are passed to [write](TokenWriter::write). Atoms are written
+/// as described in [write](crate::write); tokens that the format
+/// doesn't have (e.g. [Token::Dot](Token::Dot) in formats without
+/// dotted pairs) give
+/// [WriteError::NotRepresentable](WriteError::NotRepresentable).
+/// Whitespace and comment tokens are written as they are, and no
+/// spaces are added next to whitespace tokens.
+pub struct TokenWriter<'f, W> {
+    writer: Writer<'f, 'static>,
+    out: W,
+    /// Buffer for the text of the current token
+    text: String,
+    /// Whether the previous token must be separated from the next
+    need_space: bool,
+    /// Whether the previous token is a `,` or `~` (a following `@`
+    /// would change its meaning)
+    after_unquote: bool,
+    after_line_comment: bool,
+    /// Whether a top-level value has been completed
+    after_value: bool,
+    ends_with_newline: bool,
+    depth: usize,
+    /// The prefix tokens at the top level that still need values:
+    /// the number of values, and whether the prefix is `#;`
+    prefixes: Vec<(u8, bool)>,
+}
+
+impl<'f, W: Write> TokenWriter<'f, W> {
+    pub fn new(format: &'f AnysexprFormat<'f>, out: W) -> Self {
+        TokenWriter {
+            writer: Writer { format, opts: &DEFAULT_OPTIONS },
+            out,
+            text: String::new(),
+            need_space: false,
+            after_unquote: false,
+            after_line_comment: false,
+            after_value: false,
+            ends_with_newline: true,
+            depth: 0,
+            prefixes: Vec::new(),
+        }
+    }
+
+    // Put the text for `token` into self.text.
+    fn token_text<S: Deref<Target = str>>(&mut self, token: &Token<S>) -> Result<(), WriteError> {
+        let w = &self.writer;
+        let f = w.format;
+        let available = match token {
+            Token::Atom(a) => return w.atom(&mut self.text, a),
+            Token::Open(pk) | Token::Close(pk) => {
+                w.list(*pk, false)?;
+                true
+            }
+            Token::Dot => {
+                w.list(Parenkind::Round, true)?;
+                true
+            }
+            Token::Quote => !f.atoms_are_strings,
+            Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing =>
+                !(f.atoms_are_strings || f.clojure_quoting || f.comma_is_whitespace),
+            Token::CommentExpr => {
+                if f.hash_underscore_discards {
+                    self.text.push_str("#_");
+                    return Ok(())
+                }
+                f.has_scheme_hash_syntax
+            }
+            Token::Comment(CommentStyle::Multiline, _) =>
+                f.has_scheme_hash_syntax || f.nested_block_comments,
+            Token::Comment(CommentStyle::ParenSemicolon, _) => f.paren_semicolon_comments,
+            Token::Whitespace(_) | Token::Comment(CommentStyle::Singleline(_), _) => true,
+            Token::ReaderMacro(rm) => {
+                w.reader_macro(*rm)?;
+                true
+            }
+            Token::Tag(_) => f.hash_symbol_is_tag,
+            Token::FeatureConditional(_) => f.has_feature_conditionals,
+            Token::DatumLabel(_) | Token::DatumRef(_) => f.has_datum_labels,
+        };
+        if !available {
+            return Err(w.not_representable(format_args!("{:?}", token.to_string())))
+        }
+        let _ = write!(self.text, "{}", token);
+        Ok(())
+    }
+
+    // A value at the top level is complete.
+    fn value_done(&mut self) {
+        while let Some((n, is_comment)) = self.prefixes.last_mut() {
+            *n -= 1;
+            if *n > 0 {
+                return
+            }
+            let is_comment = *is_comment;
+            self.prefixes.pop();
+            if is_comment {
+                // The value was commented out
+                return
+            }
+        }
+        self.after_value = true;
+    }
+
+    /// Write `token`. On errors, nothing is written.
+    pub fn write<S: Deref<Target = str>>(&mut self, token: &Token<S>) -> Result<(), WriteError> {
+        self.text.clear();
+        self.token_text(token)?;
+        let sep =
+            if self.after_line_comment {
+                match token {
+                    Token::Whitespace(s) if s.starts_with('\n') => "",
+                    _ => "\n"
+                }
+            } else if !self.need_space || matches!(token, Token::Whitespace(_) | Token::Close(_)) {
+                if self.after_unquote && self.text.starts_with('@') { " " } else { "" }
+            } else if self.after_value && self.depth == 0 {
+                "\n"
+            } else {
+                " "
+            };
+        self.out.write_all(sep.as_bytes())?;
+        self.out.write_all(self.text.as_bytes())?;
+        if let Some(c) = self.text.chars().last().or(sep.chars().last()) {
+            self.ends_with_newline = c == '\n';
+        }
+
+        self.after_line_comment = matches!(token, Token::Comment(CommentStyle::Singleline(_), _));
+        self.after_unquote = matches!(token, Token::Unquote
+                                      | Token::ReaderMacro(ReaderMacro::Unquote));
+        self.need_space = !matches!(
+            token,
+            Token::Open(_) | Token::Quote | Token::Quasiquote | Token::Unquote
+                | Token::UnquoteSplicing | Token::ReaderMacro(_) | Token::FeatureConditional(_)
+                | Token::DatumLabel(_) | Token::CommentExpr | Token::Whitespace(_));
+        if matches!(token, Token::Whitespace(_) | Token::Comment(..)) {
+            return Ok(())
+        }
+        if self.depth == 0 {
+            self.after_value = false;
+        }
+        match token {
+            Token::Open(_) => self.depth += 1,
+            Token::Close(_) => {
+                self.depth = self.depth.saturating_sub(1);
+                if self.depth == 0 {
+                    self.value_done()
+                }
+            }
+            Token::Atom(_) | Token::DatumRef(_) if self.depth == 0 => self.value_done(),
+            Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing
+                | Token::ReaderMacro(_) | Token::Tag(_) | Token::DatumLabel(_)
+                if self.depth == 0 => self.prefixes.push((1, false)),
+            Token::CommentExpr if self.depth == 0 => self.prefixes.push((1, true)),
+            Token::FeatureConditional(_) if self.depth == 0 => self.prefixes.push((2, false)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub(crate) fn not_representable(&self, what: impl Display) -> WriteError {
+        self.writer.not_representable(what)
+    }
+
+    /// End the output with a newline (unless it's empty or already
+    /// does), and give back the output stream.
+    pub fn finish(mut self) -> Result<W, WriteError> {
+        if !self.ends_with_newline {
+            self.out.write_all(b"\n")?;
+        }
+        Ok(self.out)
+    }
+}
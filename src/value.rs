@@ -10,12 +10,22 @@
 //! Runtime data types representing an S-expression value.
 
 //! Whereas [Atom](Atom) does not include lists, [VValue](VValue) adds
-//! lists implemented using Rust vectors. [VValue](VValue) can
-//! represent improper lists, but no cycles.
+//! lists implemented using Rust vectors, and Clojure's
+//! [ReaderMacro](ReaderMacro)s. [VValue](VValue) can represent
+//! improper lists, but no cycles; datum labels are kept as written,
+//! see [graph](crate::graph) for resolving them.
 
-use crate::{number::R5RSNumber, pos::Pos};
+//! The type parameter `S` is the type used for the text of strings,
+//! symbols and keywords: [KString](KString) by default, or
+//! [KStringCow](KStringCow) for values borrowing from the input (see
+//! [read_str](crate::settings::AnysexprFormat::read_str)), or
+//! [IStr](crate::symbol::IStr) for interned symbols (see
+//! [symbol](crate::symbol)).
+
+use crate::{number::{NumericVector, R5RSNumber}, pos::Span};
 use std::fmt::Write;
-use kstring::KString;
+use std::ops::Deref;
+use kstring::{KString, KStringCow};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SpecialKind {
@@ -54,21 +64,77 @@ impl From<SpecialKind> for &str {
 
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Atom {
+pub enum Atom<S = KString> {
+    Nil, // EDN's nil
     Bool(bool),
     Char(char),
-    String(KString),
-    Symbol(KString),
-    UninternedSymbol(KString), // (gensym)
+    String(S),
+    Symbol(S),
+    UninternedSymbol(S), // (gensym)
     Special(SpecialKind), // #!rest etc.
-    Keyword1(KString), // :foo
-    Keyword2(KString), // foo:
+    Keyword1(S), // :foo
+    Keyword2(S), // foo:
     Number(R5RSNumber),
+    /// `pkg:sym` or `pkg::sym` (Common Lisp): the whole text, and the
+    /// length of the package name in bytes; see
+    /// [package_symbol_parts](package_symbol_parts).
+    PackageSymbol(S, u32),
+    BitVector(Vec<bool>), // #*1010, #b1010
+    /// `1.50` (SMT-LIB), `1.50M` (EDN): an exact decimal, the text
+    /// as written (without the `M`)
+    Decimal(S),
+    /// `#x1F` (SMT-LIB): the hex digits as written, their number
+    /// giving the bit width
+    Hexadecimal(S),
+    /// `#u8(1 2 255)`, `#f64(1.5)` etc.
+    NumericVector(Box<NumericVector>),
+}
+
+/// The package name, whether the symbol is internal (`::`), and the
+/// symbol name, of the text `s` of an
+/// [Atom::PackageSymbol](Atom::PackageSymbol) with package name length
+/// `pkglen`.
+pub fn package_symbol_parts(s: &str, pkglen: u32) -> (&str, bool, &str) {
+    let (pkg, rest) = s.split_at(pkglen as usize);
+    if let Some(name) = rest.strip_prefix("::") {
+        (pkg, true, name)
+    } else {
+        (pkg, false, &rest[1..])
+    }
+}
+
+impl<S> Atom<S> {
+    /// Convert the text of strings, symbols and keywords via `f`.
+    pub fn map_str<T>(self, f: impl FnOnce(S) -> T) -> Atom<T> {
+        match self {
+            Atom::Nil => Atom::Nil,
+            Atom::Bool(b) => Atom::Bool(b),
+            Atom::Char(c) => Atom::Char(c),
+            Atom::String(s) => Atom::String(f(s)),
+            Atom::Symbol(s) => Atom::Symbol(f(s)),
+            Atom::UninternedSymbol(s) => Atom::UninternedSymbol(f(s)),
+            Atom::Special(k) => Atom::Special(k),
+            Atom::Keyword1(s) => Atom::Keyword1(f(s)),
+            Atom::Keyword2(s) => Atom::Keyword2(f(s)),
+            Atom::Number(n) => Atom::Number(n),
+            Atom::PackageSymbol(s, pkglen) => Atom::PackageSymbol(f(s), pkglen),
+            Atom::BitVector(bits) => Atom::BitVector(bits),
+            Atom::Decimal(s) => Atom::Decimal(f(s)),
+            Atom::Hexadecimal(s) => Atom::Hexadecimal(f(s)),
+            Atom::NumericVector(v) => Atom::NumericVector(v),
+        }
+    }
+}
+
+impl Atom<KStringCow<'_>> {
+    pub fn into_owned(self) -> Atom {
+        self.map_str(KStringCow::into_owned)
+    }
 }
 
 fn fmt_stringlike(f: &mut std::fmt::Formatter<'_>,
                   quote: char,
-                  s: &KString,
+                  s: &str,
                   quote_required: bool,
                   colon_before: bool,
                   colon_after: bool)
@@ -143,10 +209,11 @@ pub fn name2char(s: &str) -> Option<char> {
 }
 
 
-impl std::fmt::Display for Atom {
+impl<S: Deref<Target = str>> std::fmt::Display for Atom<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
            -> Result<(), std::fmt::Error> {
         match self {
+            Atom::Nil => f.write_str("nil"),
             Atom::Bool(b) => f.write_fmt(format_args!("#{}", if *b { "t" } else { "f" })),
             Atom::Char(c) => {
                 f.write_str("#\\")?;
@@ -169,6 +236,25 @@ impl std::fmt::Display for Atom {
             Atom::Keyword1(s) => fmt_stringlike(f, '|', s, false, true, false), // :foo
             Atom::Keyword2(s) => fmt_stringlike(f, '|', s, false, false, true), // foo:
             Atom::Number(n) => n.fmt(f),
+            Atom::PackageSymbol(s, pkglen) => {
+                let (pkg, internal, name) = package_symbol_parts(s, *pkglen);
+                fmt_stringlike(f, '|', pkg, false, false, false)?;
+                f.write_str(if internal { "::" } else { ":" })?;
+                fmt_stringlike(f, '|', name, false, false, false)
+            }
+            Atom::BitVector(bits) => {
+                f.write_str("#*")?;
+                for b in bits {
+                    f.write_char(if *b { '1' } else { '0' })?;
+                }
+                Ok(())
+            }
+            Atom::Decimal(s) => f.write_str(s),
+            Atom::Hexadecimal(s) => {
+                f.write_str("#x")?;
+                f.write_str(s)
+            }
+            Atom::NumericVector(v) => v.fmt(f),
         }
     }
 }
@@ -197,16 +283,126 @@ impl Parenkind {
     }
 }
 
+/// Prefix syntax that Clojure calls reader macros (and the like from
+/// Common Lisp). Unlike `'` and Scheme's quasiquotation, these are
+/// kept distinct in trees instead of being turned into `(name value)`
+/// lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReaderMacro {
+    SyntaxQuote, // `x
+    Unquote, // ~x
+    UnquoteSplicing, // ~@x
+    Deref, // @x
+    Var, // #'x
+    Set, // #{..}
+    AnonFn, // #(..)
+    Conditional, // #?(..)
+    ConditionalSplicing, // #?@(..)
+    Function, // #'f in Common Lisp
+    Vector, // #(..)
+    ReadEval, // #.x
+    Record, // #s(..) in Emacs Lisp
+}
+
+impl ReaderMacro {
+    /// The text written before the value (for `Set`, `AnonFn`,
+    /// `Vector`, `Record` and the conditionals, the value is the list
+    /// that follows).
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ReaderMacro::SyntaxQuote => "`",
+            ReaderMacro::Unquote => "~",
+            ReaderMacro::UnquoteSplicing => "~@",
+            ReaderMacro::Deref => "@",
+            ReaderMacro::Var => "#'",
+            ReaderMacro::Set => "#",
+            ReaderMacro::AnonFn => "#",
+            ReaderMacro::Conditional => "#?",
+            ReaderMacro::ConditionalSplicing => "#?@",
+            ReaderMacro::Function => "#'",
+            ReaderMacro::Vector => "#",
+            ReaderMacro::ReadEval => "#.",
+            ReaderMacro::Record => "#s",
+        }
+    }
+
+    /// The kind of the list that follows the prefix as part of the
+    /// syntax, for the reader macros that have one.
+    pub fn list_kind(self) -> Option<Parenkind> {
+        match self {
+            ReaderMacro::Set => Some(Parenkind::Curly),
+            ReaderMacro::AnonFn | ReaderMacro::Conditional | ReaderMacro::ConditionalSplicing
+                | ReaderMacro::Vector | ReaderMacro::Record => Some(Parenkind::Round),
+            _ => None
+        }
+    }
+
+    /// A name for the reader macro, used in error messages and when
+    /// representing it as a list.
+    pub fn name(self) -> &'static str {
+        match self {
+            ReaderMacro::SyntaxQuote => "syntax-quote",
+            ReaderMacro::Unquote => "unquote",
+            ReaderMacro::UnquoteSplicing => "unquote-splicing",
+            ReaderMacro::Deref => "deref",
+            ReaderMacro::Var => "var",
+            ReaderMacro::Set => "set",
+            ReaderMacro::AnonFn => "fn",
+            ReaderMacro::Conditional => "reader-conditional",
+            ReaderMacro::ConditionalSplicing => "reader-conditional-splicing",
+            ReaderMacro::Function => "function",
+            ReaderMacro::Vector => "vector",
+            ReaderMacro::ReadEval => "read-eval",
+            ReaderMacro::Record => "record",
+        }
+    }
+}
+
 /// Vec-based version of values; for now, hard-coded to contain
 /// VValueWithPos in recursive places.
 #[derive(Debug)]
-pub enum VValue {
-    Atom(Atom),
-    /// .1 is he position of the Dot, if any
-    List(Parenkind, Option<Pos>, Vec<VValueWithPos>),
+pub enum VValue<S = KString> {
+    Atom(Atom<S>),
+    /// .1 is the span of the Dot, if any (boxed, as improper lists
+    /// are rare and it would double the size of every value)
+    List(Parenkind, Option<Box<Span>>, Vec<VValueWithPos<S>>),
+    /// A reader macro applied to the value following it
+    ReaderMacro(ReaderMacro, Box<VValueWithPos<S>>),
+    /// A tagged element as in EDN, `#tag value`; .0 is the tag
+    /// without the `#`
+    Tagged(S, Box<VValueWithPos<S>>),
+    /// Common Lisp's `#+feature form` (.0 true) or `#-feature form`:
+    /// the feature expression and the form
+    FeatureConditional(bool, Box<(VValueWithPos<S>, VValueWithPos<S>)>),
+    /// `#n=value`, a datum label as written (references to it are
+    /// not resolved)
+    Labeled(u32, Box<VValueWithPos<S>>),
+    /// `#n#`, a reference to the value labeled `n`
+    LabelRef(u32),
+}
+
+impl VValue<KStringCow<'_>> {
+    pub fn into_owned(self) -> VValue {
+        match self {
+            VValue::Atom(a) => VValue::Atom(a.into_owned()),
+            VValue::List(pk, impr, vs) =>
+                VValue::List(pk, impr, vs.into_iter().map(|v| v.into_owned()).collect()),
+            VValue::ReaderMacro(rm, v) =>
+                VValue::ReaderMacro(rm, Box::new(v.into_owned())),
+            VValue::Tagged(tag, v) =>
+                VValue::Tagged(tag.into_owned(), Box::new(v.into_owned())),
+            VValue::FeatureConditional(positive, b) => {
+                let (feature, form) = *b;
+                VValue::FeatureConditional(
+                    positive, Box::new((feature.into_owned(), form.into_owned())))
+            }
+            VValue::Labeled(n, v) => VValue::Labeled(n, Box::new(v.into_owned())),
+            VValue::LabelRef(n) => VValue::LabelRef(n),
+        }
+    }
 }
 
-impl std::fmt::Display for VValue {
+impl<S: Deref<Target = str>> std::fmt::Display for VValue<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
            -> Result<(), std::fmt::Error> {
         match self {
@@ -214,39 +410,70 @@ impl std::fmt::Display for VValue {
                 t.fmt(f)
             }
             VValue::List(pk, impr, v) => {
-                f.write_char(pk.opening())?;
-                let len = v.len();
-                for (i, item) in v.iter().enumerate() {
-                    item.fmt(f)?;
-                    if i + 2 < len {
-                        f.write_char(' ')?;
-                    } else if i + 1 < len {
-                        if impr.is_some() {
-                            f.write_str(" . ")?;
-                        } else {
-                            f.write_char(' ')?;
-                        }
-                    }
-                }
-                f.write_char(pk.closing())
+                fmt_list(f, *pk, impr.is_some(), v, |item, f| item.fmt(f))
+            }
+            VValue::ReaderMacro(rm, v) => {
+                f.write_str(rm.prefix())?;
+                v.fmt(f)
+            }
+            VValue::Tagged(tag, v) => {
+                f.write_fmt(format_args!("#{} ", &**tag))?;
+                v.fmt(f)
+            }
+            VValue::FeatureConditional(positive, b) => {
+                f.write_fmt(format_args!("#{}{} {}", if *positive { '+' } else { '-' },
+                                         b.0, b.1))
             }
+            VValue::Labeled(n, v) => f.write_fmt(format_args!("#{}={}", n, v)),
+            VValue::LabelRef(n) => f.write_fmt(format_args!("#{}#", n)),
         }
     }
 }
 
+/// Write a list, formatting the items via `fmt_item`.
+pub(crate) fn fmt_list<T>(
+    f: &mut std::fmt::Formatter<'_>,
+    pk: Parenkind,
+    improper: bool,
+    items: &[T],
+    fmt_item: impl Fn(&T, &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>
+) -> Result<(), std::fmt::Error> {
+    f.write_char(pk.opening())?;
+    let len = items.len();
+    for (i, item) in items.iter().enumerate() {
+        fmt_item(item, f)?;
+        if i + 2 < len {
+            f.write_char(' ')?;
+        } else if i + 1 < len {
+            if improper {
+                f.write_str(" . ")?;
+            } else {
+                f.write_char(' ')?;
+            }
+        }
+    }
+    f.write_char(pk.closing())
+}
+
 #[derive(Debug)]
-pub struct VValueWithPos(pub VValue, pub Pos);
+pub struct VValueWithPos<S = KString>(pub VValue<S>, pub Span);
+
+impl VValueWithPos<KStringCow<'_>> {
+    pub fn into_owned(self) -> VValueWithPos {
+        VValueWithPos(self.0.into_owned(), self.1)
+    }
+}
 
-impl std::fmt::Display for VValueWithPos {
+impl<S: Deref<Target = str>> std::fmt::Display for VValueWithPos<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>)
            -> Result<(), std::fmt::Error> {
         self.0.fmt(f)
     }
 }
 
-impl VValue {
-    pub fn at(self, p: Pos) -> VValueWithPos {
-        VValueWithPos(self, p)
+impl<S> VValue<S> {
+    pub fn at(self, s: Span) -> VValueWithPos<S> {
+        VValueWithPos(self, s)
     }
 }
 
@@ -0,0 +1,957 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Writing values in the syntax of a given format, and with options
+//! that plain `Display` doesn't offer.
+
+//! [Writable](Writable) values are written by
+//! [AnysexprFormat::write](crate::settings::AnysexprFormat::write)
+//! and friends as the format reads them: booleans as `#t`, `t`, `T`
+//! or `true`, characters as `#\newline`, `\newline` or `?\n`,
+//! keywords as `foo:`, `:foo` or `#:foo`, control characters in
+//! strings as octal, `\x..;` or `\u..` escapes, and symbols in `|..|`
+//! or with `\` escapes only where they wouldn't read back as the same
+//! symbol otherwise. Values that the format can't represent (e.g.
+//! improper lists in formats without dotted pairs) give
+//! [WriteError::NotRepresentable](WriteError::NotRepresentable)
+//! instead of output.
+
+//! [WithOptions](WithOptions) implements `Display` as well, which
+//! (like the `Display` of values) doesn't know the format.
+
+use crate::number::{NumericVector, R5RSNumber, Radix};
+use crate::parse::{parse_str, Token, TokenWithPos, is_edn_decimal,
+                   is_symbol_or_number_char, is_wat_idchar, package_name_len,
+                   smtlib_number_kind};
+use crate::pretty::{Pretty, PrettyOptions};
+use crate::settings::{AnysexprFormat, Modes, NumberSyntax, Settings};
+use crate::value::{VValue, VValueWithPos, Atom, Parenkind, ReaderMacro,
+                   char2name, fmt_list, package_symbol_parts};
+use crate::{common_lisp, edn, elisp, sexplib};
+use kstring::KStringCow;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Write};
+use std::io;
+use std::iter::Chain;
+use std::ops::Deref;
+use std::{option, slice};
+use thiserror::Error;
+
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// The radix to write exact numbers in (inexact ones are always
+    /// written in decimal). Only used by formats with radix prefixes
+    /// (Scheme and Common Lisp number syntax).
+    pub number_radix: Radix,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        DEFAULT_OPTIONS
+    }
+}
+
+pub(crate) const DEFAULT_OPTIONS: WriteOptions = WriteOptions {
+    number_radix: Radix::Decimal,
+};
+
+/// Display `.0` according to the options in `.1`.
+pub struct WithOptions<'t, T: ?Sized>(pub &'t T, pub &'t WriteOptions);
+
+impl<S: Deref<Target = str>> Display for WithOptions<'_, Atom<S>> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self.0 {
+            Atom::Number(n) => n.fmt_radix(self.1.number_radix, f),
+            a => a.fmt(f)
+        }
+    }
+}
+
+impl<S: Deref<Target = str>> Display for WithOptions<'_, VValue<S>> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self.0 {
+            VValue::Atom(a) => WithOptions(a, self.1).fmt(f),
+            VValue::List(pk, impr, v) => {
+                fmt_list(f, *pk, impr.is_some(), v,
+                         |item, f| WithOptions(item, self.1).fmt(f))
+            }
+            VValue::ReaderMacro(rm, v) => {
+                f.write_str(rm.prefix())?;
+                WithOptions(&**v, self.1).fmt(f)
+            }
+            VValue::Tagged(tag, v) => {
+                f.write_fmt(format_args!("#{} ", &**tag))?;
+                WithOptions(&**v, self.1).fmt(f)
+            }
+            VValue::FeatureConditional(positive, b) => {
+                f.write_fmt(format_args!("#{}{} {}", if *positive { '+' } else { '-' },
+                                         WithOptions(&b.0, self.1),
+                                         WithOptions(&b.1, self.1)))
+            }
+            VValue::Labeled(n, v) =>
+                f.write_fmt(format_args!("#{}={}", n, WithOptions(&**v, self.1))),
+            VValue::LabelRef(n) => f.write_fmt(format_args!("#{}#", n)),
+        }
+    }
+}
+
+impl<S: Deref<Target = str>> Display for WithOptions<'_, VValueWithPos<S>> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        WithOptions(&self.0.0, self.1).fmt(f)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WriteError {
+    #[error("{0}")]
+    IO(#[from] std::io::Error),
+    /// The name of the format, and what can't be written
+    #[error("not representable in {0}: {1}")]
+    NotRepresentable(String, String),
+    /// The [fmt::Write](std::fmt::Write) that was written to failed
+    #[error("formatter error")]
+    Fmt(#[from] std::fmt::Error),
+}
+
+/// Values that can be written in the syntax of a format.
+pub trait Writable {
+    /// The text of such a value, see [pieces](Writable::pieces).
+    type Pieces<'a>: Pieces where Self: 'a;
+
+    /// The text for `self` in `format`, generated piece by piece so
+    /// that it can be passed on while it's generated (the async
+    /// writers wait for the output stream between the pieces).
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> Self::Pieces<'a>;
+
+    /// Write the text for `self` in `format` to `out`. On errors,
+    /// part of it may have been written.
+    fn write_to<W: Write>(&self, out: &mut W, format: &AnysexprFormat)
+                          -> Result<(), WriteError> {
+        let mut pieces = self.pieces(format);
+        while pieces.write_next(out)? {}
+        Ok(())
+    }
+}
+
+/// A text that is generated piece by piece, see
+/// [Writable::pieces](Writable::pieces).
+pub trait Pieces {
+    /// Write the next piece of the text to `out`. Gives false if
+    /// there was none left.
+    fn write_next<W: Write>(&mut self, out: &mut W) -> Result<bool, WriteError>;
+}
+
+/// The text of an atom, in one piece.
+pub struct AtomPieces<'a, S> {
+    writer: Writer<'a, 'a>,
+    atom: Option<&'a Atom<S>>,
+}
+
+impl<S: Deref<Target = str>> Pieces for AtomPieces<'_, S> {
+    fn write_next<W: Write>(&mut self, out: &mut W) -> Result<bool, WriteError> {
+        let Some(a) = self.atom.take() else {
+            return Ok(false)
+        };
+        self.writer.atom(out, a)?;
+        Ok(true)
+    }
+}
+
+// The items of a list (or the two parts of a feature conditional)
+// still to be written.
+struct Frame<'a, S> {
+    items: Chain<slice::Iter<'a, VValueWithPos<S>>, option::IntoIter<&'a VValueWithPos<S>>>,
+    left: usize,
+    started: bool,
+    improper: bool,
+    close: Option<char>,
+}
+
+/// The text of a value: each atom, and the text before or after the
+/// items of each list, is a piece. The memory used is bounded by the
+/// nesting depth.
+pub struct ValuePieces<'a, S> {
+    writer: Writer<'a, 'a>,
+    next: Option<&'a VValue<S>>,
+    stack: Vec<Frame<'a, S>>,
+}
+
+impl<'a, S> ValuePieces<'a, S> {
+    fn new(writer: Writer<'a, 'a>, v: &'a VValue<S>) -> Self {
+        ValuePieces { writer, next: Some(v), stack: Vec::new() }
+    }
+
+    fn open(
+        &mut self,
+        out: &mut impl Write,
+        pk: Parenkind,
+        improper: bool,
+        vs: &'a [VValueWithPos<S>]
+    ) -> Result<(), WriteError> {
+        out.write_char(pk.opening())?;
+        self.stack.push(Frame {
+            items: vs.iter().chain(None),
+            left: vs.len(),
+            started: false,
+            improper,
+            close: Some(pk.closing()),
+        });
+        Ok(())
+    }
+}
+
+impl<S: Deref<Target = str>> Pieces for ValuePieces<'_, S> {
+    fn write_next<W: Write>(&mut self, out: &mut W) -> Result<bool, WriteError> {
+        if let Some(v) = self.next.take() {
+            self.writer.check_syntax(v)?;
+            match v {
+                VValue::Atom(a) => self.writer.atom(out, a)?,
+                VValue::List(pk, impr, vs) => self.open(out, *pk, impr.is_some(), vs)?,
+                VValue::ReaderMacro(rm, v) => {
+                    out.write_str(rm.prefix())?;
+                    match &v.0 {
+                        // Part of the syntax, not checked as a list by
+                        // itself (a set isn't a map)
+                        VValue::List(pk, impr, vs) if rm.list_kind().is_some() =>
+                            self.open(out, *pk, impr.is_some(), vs)?,
+                        v => self.next = Some(v)
+                    }
+                }
+                VValue::Tagged(tag, v) => {
+                    write!(out, "#{} ", &**tag)?;
+                    self.next = Some(&v.0);
+                }
+                VValue::FeatureConditional(positive, b) => {
+                    out.write_str(if *positive { "#+" } else { "#-" })?;
+                    self.stack.push(Frame {
+                        items: slice::from_ref(&b.0).iter().chain(Some(&b.1)),
+                        left: 2,
+                        started: false,
+                        improper: false,
+                        close: None,
+                    });
+                }
+                VValue::Labeled(n, v) => {
+                    write!(out, "#{}=", n)?;
+                    self.next = Some(&v.0);
+                }
+                VValue::LabelRef(n) => {
+                    write!(out, "#{}#", n)?;
+                }
+            }
+            return Ok(true)
+        }
+        let Some(frame) = self.stack.last_mut() else {
+            return Ok(false)
+        };
+        if let Some(item) = frame.items.next() {
+            if frame.started {
+                out.write_char(' ')?;
+                if frame.improper && frame.left == 1 {
+                    out.write_str(". ")?;
+                }
+            }
+            frame.started = true;
+            frame.left -= 1;
+            self.next = Some(&item.0);
+        } else {
+            if let Some(c) = frame.close {
+                out.write_char(c)?;
+            }
+            self.stack.pop();
+        }
+        Ok(true)
+    }
+}
+
+/// The text of a pretty-printed value, in one piece (the layout
+/// needs the whole text).
+pub struct PrettyPieces<'a, S> {
+    val: Option<(&'a VValue<S>, &'a PrettyOptions<'a>)>,
+    format: &'a AnysexprFormat<'a>,
+}
+
+impl<S: Deref<Target = str>> Pieces for PrettyPieces<'_, S> {
+    fn write_next<W: Write>(&mut self, out: &mut W) -> Result<bool, WriteError> {
+        let Some((v, opts)) = self.val.take() else {
+            return Ok(false)
+        };
+        let mut s = String::new();
+        crate::pretty::write_to(&mut s, v, opts, self.format)?;
+        out.write_str(&s)?;
+        Ok(true)
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for Atom<S> {
+    type Pieces<'a> = AtomPieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> AtomPieces<'a, S> {
+        AtomPieces { writer: Writer { format, opts: &DEFAULT_OPTIONS }, atom: Some(self) }
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for VValue<S> {
+    type Pieces<'a> = ValuePieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> ValuePieces<'a, S> {
+        ValuePieces::new(Writer { format, opts: &DEFAULT_OPTIONS }, self)
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for VValueWithPos<S> {
+    type Pieces<'a> = ValuePieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> ValuePieces<'a, S> {
+        self.0.pieces(format)
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for WithOptions<'_, Atom<S>> {
+    type Pieces<'a> = AtomPieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> AtomPieces<'a, S> {
+        AtomPieces { writer: Writer { format, opts: self.1 }, atom: Some(self.0) }
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for WithOptions<'_, VValue<S>> {
+    type Pieces<'a> = ValuePieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> ValuePieces<'a, S> {
+        ValuePieces::new(Writer { format, opts: self.1 }, self.0)
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for WithOptions<'_, VValueWithPos<S>> {
+    type Pieces<'a> = ValuePieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> ValuePieces<'a, S> {
+        ValuePieces::new(Writer { format, opts: self.1 }, &self.0.0)
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for Pretty<'_, VValue<S>> {
+    type Pieces<'a> = PrettyPieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> PrettyPieces<'a, S> {
+        PrettyPieces { val: Some((self.0, self.1)), format }
+    }
+}
+
+impl<S: Deref<Target = str>> Writable for Pretty<'_, VValueWithPos<S>> {
+    type Pieces<'a> = PrettyPieces<'a, S> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> PrettyPieces<'a, S> {
+        PrettyPieces { val: Some((&self.0.0, self.1)), format }
+    }
+}
+
+impl<T: Writable + ?Sized> Writable for &T {
+    type Pieces<'a> = T::Pieces<'a> where Self: 'a;
+
+    fn pieces<'a>(&'a self, format: &'a AnysexprFormat<'a>) -> T::Pieces<'a> {
+        (**self).pieces(format)
+    }
+}
+
+// The size of the chunks that `write_io` and the async writers pass
+// on.
+pub(crate) const CHUNK_SIZE: usize = 8192;
+
+/// Write `val` in `format` followed by `end` to `out`, passing the
+/// text on in chunks as it's generated. On errors, the chunks before
+/// the error have been written.
+pub(crate) fn write_io<T: Writable + ?Sized>(
+    out: &mut (impl io::Write + ?Sized),
+    val: &T,
+    format: &AnysexprFormat,
+    end: &str
+) -> Result<(), WriteError> {
+    let mut pieces = val.pieces(format);
+    let mut buf = String::new();
+    while pieces.write_next(&mut buf)? {
+        if buf.len() >= CHUNK_SIZE {
+            out.write_all(buf.as_bytes())?;
+            buf.clear();
+        }
+    }
+    buf.push_str(end);
+    Ok(out.write_all(buf.as_bytes())?)
+}
+
+const MODES: Modes = Modes {
+    allow_improper_lists: false,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+// An exact number in a radix, with the prefix.
+struct Radixed<'t>(&'t R5RSNumber, Radix);
+
+impl Display for Radixed<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        self.0.fmt_radix(self.1, f)
+    }
+}
+
+pub(crate) struct Writer<'f, 'o> {
+    pub format: &'f AnysexprFormat<'f>,
+    pub opts: &'o WriteOptions,
+}
+
+// What a name read by the tokenizer becomes.
+#[derive(PartialEq)]
+enum NameKind {
+    Symbol,
+    Keyword,
+    Uninterned,
+}
+
+// What the tokenizer reads a text as, as far as writing names is
+// concerned.
+enum Reads<'a> {
+    Name(NameKind, Cow<'a, str>),
+    // Anything else, or more than one token
+    Other,
+    // Text that the tokenizer hands to the number syntax of the
+    // format, which then decides
+    MaybeNumber,
+}
+
+// Whether `s` stays the same when upcased.
+fn is_upcase(s: &str) -> bool {
+    s.chars().all(|c| c.to_uppercase().eq(std::iter::once(c)))
+}
+
+impl Writer<'_, '_> {
+    pub fn not_representable(&self, what: impl Display) -> WriteError {
+        WriteError::NotRepresentable(self.format.name.into(), what.to_string())
+    }
+
+    // Whether `text` reads back as `atom` (or, for keywords, as
+    // either kind of keyword with the same name).
+    fn reads_as<S: Deref<Target = str>>(&self, text: &str, atom: &Atom<S>) -> bool {
+        match self.read_name(text) {
+            Reads::Name(kind, name) => match atom {
+                Atom::Symbol(s) => kind == NameKind::Symbol && *name == **s,
+                Atom::UninternedSymbol(s) => kind == NameKind::Uninterned && *name == **s,
+                Atom::Keyword1(s) | Atom::Keyword2(s) =>
+                    kind == NameKind::Keyword && *name == **s,
+                _ => false
+            }
+            Reads::Other => false,
+            Reads::MaybeNumber => self.parses_as(text, atom)
+        }
+    }
+
+    // `reads_as` by running the tokenizer.
+    fn parses_as<S: Deref<Target = str>>(&self, text: &str, atom: &Atom<S>) -> bool {
+        let settings = Settings { format: self.format, modes: &MODES };
+        let mut tokens = parse_str(text, &settings);
+        let read: Atom<KStringCow> = match (tokens.next(), tokens.next()) {
+            (Some(Ok(TokenWithPos(Token::Atom(a), _))), None) => a,
+            _ => return false
+        };
+        match (&read, atom) {
+            (Atom::Symbol(a), Atom::Symbol(b))
+                | (Atom::UninternedSymbol(a), Atom::UninternedSymbol(b))
+                | (Atom::Keyword1(a) | Atom::Keyword2(a), Atom::Keyword1(b) | Atom::Keyword2(b))
+                => **a == **b,
+            _ => false
+        }
+    }
+
+    // What the tokenizer reads `text` as, judged from the character
+    // classes of the format (following the dispatch in
+    // `Parser::next_token`).
+    fn read_name<'a>(&self, text: &'a str) -> Reads<'a> {
+        let f = self.format;
+        let Some(c) = text.chars().next() else { return Reads::Other };
+        if f.atoms_are_strings {
+            return Reads::Other
+        }
+        if f.wat_idchars && is_wat_idchar(c) {
+            return self.read_symbol_or_number(text)
+        }
+        match c {
+            ';' => Reads::Other,
+            '#' => self.read_hash_colon_name(text),
+            '\\' if f.backslash_chars => Reads::Other,
+            '\\' => self.read_symbol_or_number(text),
+            '~' | '@' if f.clojure_quoting => Reads::Other,
+            '?' if f.question_mark_chars => Reads::Other,
+            c if is_symbol_or_number_char(c) => self.read_symbol_or_number(text),
+            _ => Reads::Other
+        }
+    }
+
+    // Same as `read_name` for `text` starting with a character that
+    // the tokenizer reads as the start of a symbol or number (see
+    // `Parser::symbol_or_number`).
+    fn read_symbol_or_number<'a>(&self, text: &'a str) -> Reads<'a> {
+        let f = self.format;
+        let mut cs = text.chars();
+        let c = cs.next().expect("non-empty");
+        if f.backslash_escapes_in_symbols && text.contains('\\') {
+            let mut name = String::new();
+            let mut cs = text.chars();
+            while let Some(c) = cs.next() {
+                if c == '\\' {
+                    let Some(c1) = cs.next() else { return Reads::Other };
+                    name.push(c1);
+                } else if name.is_empty() || is_symbol_or_number_char(c) {
+                    name.push(c);
+                } else {
+                    return Reads::Other
+                }
+            }
+            return if c == ':' {
+                Reads::Name(NameKind::Keyword, Cow::Owned(name[1..].into()))
+            } else {
+                Reads::Name(NameKind::Symbol, Cow::Owned(name))
+            }
+        }
+        let accepted = if f.wat_idchars { is_wat_idchar } else { is_symbol_or_number_char };
+        if !cs.all(accepted) {
+            return Reads::Other
+        }
+        if text == "." && f.has_dotted_pairs {
+            return Reads::Other
+        }
+        if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') {
+            return Reads::MaybeNumber
+        }
+        if f.numbers == NumberSyntax::Wat && matches!(text, "inf" | "nan") {
+            return Reads::Other
+        }
+        if f.has_nil_true_false && matches!(text, "nil" | "true" | "false") {
+            return Reads::Other
+        }
+        if f.upcase_symbols && !is_upcase(text) {
+            return Reads::Other
+        }
+        if c == ':' {
+            Reads::Name(NameKind::Keyword, Cow::Borrowed(&text[1..]))
+        } else if f.package_qualified_symbols && package_name_len(text).is_some() {
+            Reads::Other
+        } else if f.trailing_colon_is_keyword && text.ends_with(':') {
+            Reads::Name(NameKind::Keyword, Cow::Borrowed(&text[..text.len() - 1]))
+        } else {
+            Reads::Name(NameKind::Symbol, Cow::Borrowed(text))
+        }
+    }
+
+    // Same as `read_name` for `text` starting with `#`: only `#:name`
+    // is of interest (see `Parser::hash_token`).
+    fn read_hash_colon_name<'a>(&self, text: &'a str) -> Reads<'a> {
+        let f = self.format;
+        let Some(name) = text.strip_prefix("#:") else { return Reads::Other };
+        if !(f.hash_colon_symbols || f.has_scheme_hash_syntax) {
+            return Reads::Other
+        }
+        let mut cs = name.chars();
+        // (The first character is taken whatever it is, `|` starts a
+        // quoted name.)
+        if cs.next().is_none_or(|c1| c1 == '|') || !cs.all(is_symbol_or_number_char) {
+            return Reads::Other
+        }
+        if f.upcase_symbols && !is_upcase(name) {
+            return Reads::Other
+        }
+        let kind = if f.hashcolon_is_keyword { NameKind::Keyword } else { NameKind::Uninterned };
+        Reads::Name(kind, Cow::Borrowed(name))
+    }
+
+    // Whether `prefix` followed by `inner` in `|..|` reads back as
+    // `name`, where `inner` is `name` with `|` and `\` escaped if the
+    // format has Scheme's hash syntax.
+    fn bars_read_as(&self, prefix: &str, inner: &str, name: &str) -> bool {
+        let f = self.format;
+        // Whether the text between the bars is taken as is
+        let verbatim = inner == name && !name.contains('|');
+        if f.wat_idchars {
+            return false
+        }
+        match prefix {
+            "" => !f.verbatim_quoted_symbols || verbatim,
+            // (`#:|..|` is read with escapes)
+            "#:" => f.has_scheme_hash_syntax
+                || (f.hash_colon_symbols && verbatim && !name.contains('\\')),
+            _ => false
+        }
+    }
+
+    // The text for `name` between `prefix` and `suffix` that reads
+    // back as `atom`: plain, in `|..|`, or with `\` escapes, as
+    // available in the format.
+    fn quoted_name<S: Deref<Target = str>>(
+        &self,
+        prefix: &str,
+        name: &str,
+        suffix: &str,
+        atom: &Atom<S>
+    ) -> Option<String> {
+        let f = self.format;
+        // (The reader may skip whitespace after a prefix.)
+        if !name.contains(|c: char| c.is_whitespace() || c.is_control()) {
+            let text = format!("{}{}{}", prefix, name, suffix);
+            if self.reads_as(&text, atom) {
+                return Some(text)
+            }
+        }
+        if (f.has_scheme_hash_syntax || f.verbatim_quoted_symbols) && suffix.is_empty() {
+            let mut inner = String::new();
+            for c in name.chars() {
+                if (c == '|' || c == '\\') && f.has_scheme_hash_syntax {
+                    inner.push('\\');
+                }
+                inner.push(c);
+            }
+            if self.bars_read_as(prefix, &inner, name) {
+                let kind = match prefix {
+                    "" => NameKind::Symbol,
+                    _ if f.hashcolon_is_keyword => NameKind::Keyword,
+                    _ => NameKind::Uninterned
+                };
+                let matches = match atom {
+                    Atom::Symbol(_) => kind == NameKind::Symbol,
+                    Atom::UninternedSymbol(_) => kind == NameKind::Uninterned,
+                    Atom::Keyword1(_) | Atom::Keyword2(_) => kind == NameKind::Keyword,
+                    _ => false
+                };
+                if matches {
+                    return Some(format!("{}|{}|", prefix, inner))
+                }
+            }
+        }
+        if f.backslash_escapes_in_symbols && !name.is_empty() {
+            let mut text = String::from(prefix);
+            elisp::write_symbol_name(&mut text, name);
+            text.push_str(suffix);
+            if self.reads_as(&text, atom) {
+                return Some(text)
+            }
+        }
+        None
+    }
+
+    fn char(&self, out: &mut impl Write, c: char) -> Result<(), WriteError> {
+        let f = self.format;
+        let mut text = String::new();
+        if f.question_mark_chars {
+            elisp::write_char(&mut text, c)
+        } else if f.backslash_chars {
+            edn::write_char(&mut text, c)
+        } else if f.case_insensitive_char_names {
+            common_lisp::write_char(&mut text, c)
+        } else if f.has_scheme_hash_syntax {
+            out.write_str("#\\")?;
+            if let Some(name) = char2name(c) {
+                out.write_str(name)?
+            } else if c.is_control() || c.is_whitespace() {
+                write!(out, "x{:x}", c as u32)?;
+            } else {
+                out.write_char(c)?
+            }
+        } else {
+            return Err(self.not_representable(Atom::<&str>::Char(c)))
+        }
+        Ok(out.write_str(&text)?)
+    }
+
+    fn string(&self, out: &mut impl Write, s: &str) -> Result<(), WriteError> {
+        let f = self.format;
+        if f.atoms_are_strings || f.doubled_quote_escapes || f.backslash_quotes_next_char {
+            let mut text = String::new();
+            if f.atoms_are_strings {
+                sexplib::write_atom(&mut text, s)
+            } else if f.doubled_quote_escapes {
+                text.push('"');
+                text.push_str(&s.replace('"', "\"\""));
+                text.push('"')
+            } else {
+                common_lisp::write_string(&mut text, s)
+            }
+            return Ok(out.write_str(&text)?)
+        }
+        out.write_char('"')?;
+        for c in s.chars() {
+            match c {
+                '"' => out.write_str("\\\"")?,
+                '\\' => out.write_str("\\\\")?,
+                '\n' => out.write_str("\\n")?,
+                '\t' => out.write_str("\\t")?,
+                '\r' => out.write_str("\\r")?,
+                // Always 3 digits, to keep following digits apart
+                c if c.is_control() && f.octal_escapes_in_delimited => {
+                    write!(out, "\\{:03o}", c as u32)?;
+                }
+                c if c.is_control() && f.x_escape_terminated_by_semicolon_in_delimited => {
+                    write!(out, "\\x{:x};", c as u32)?;
+                }
+                c if c.is_control() && f.wat_escapes => {
+                    write!(out, "\\u{{{:x}}}", c as u32)?;
+                }
+                c if c.is_control() && f.backslash_chars => {
+                    write!(out, "\\u{:04x}", c as u32)?;
+                }
+                c => out.write_char(c)?
+            }
+        }
+        Ok(out.write_char('"')?)
+    }
+
+    fn number(&self, out: &mut impl Write, n: &R5RSNumber) -> Result<(), WriteError> {
+        let radix = self.opts.number_radix;
+        let mut text = String::new();
+        match self.format.numbers {
+            NumberSyntax::Scheme => {
+                write!(out, "{}", Radixed(n, radix))?;
+            }
+            NumberSyntax::CommonLisp => match n {
+                R5RSNumber::Integer(_) | R5RSNumber::Rational(_) => {
+                    write!(out, "{}", Radixed(n, radix))?;
+                }
+                R5RSNumber::Complex(re, im) if self.format.hash_c_is_complex => {
+                    text.push_str("#C(");
+                    let written = common_lisp::write_real(&mut text, re) && {
+                        text.push(' ');
+                        common_lisp::write_real(&mut text, im)
+                    };
+                    if !written {
+                        return Err(self.not_representable(n))
+                    }
+                    text.push(')')
+                }
+                _ => if !common_lisp::write_real(&mut text, n) {
+                    return Err(self.not_representable(n))
+                }
+            }
+            NumberSyntax::EmacsLisp => if !elisp::write_number(&mut text, n) {
+                return Err(self.not_representable(n))
+            }
+            NumberSyntax::SmtLib => match n {
+                R5RSNumber::Integer(i) if !i.is_negative() => {
+                    write!(out, "{}", i)?;
+                }
+                _ => return Err(self.not_representable(n))
+            }
+            NumberSyntax::Wat => match n {
+                R5RSNumber::Integer(i) => {
+                    write!(out, "{}", i)?;
+                }
+                R5RSNumber::Real(x) => {
+                    if x.is_nan() {
+                        out.write_str(if x.is_sign_negative() { "-nan" } else { "nan" })?
+                    } else if x.is_infinite() {
+                        out.write_str(if *x > 0.0 { "inf" } else { "-inf" })?
+                    } else {
+                        // Debug always gives a fractional part or exponent
+                        write!(out, "{:?}", x)?;
+                    }
+                }
+                _ => return Err(self.not_representable(n))
+            }
+            NumberSyntax::Edn => match n {
+                R5RSNumber::Integer(i) => {
+                    write!(out, "{}", i)?;
+                }
+                R5RSNumber::Real(x) if x.is_finite() => {
+                    // Debug always gives a fractional part or exponent
+                    write!(out, "{:?}", x)?;
+                }
+                _ => return Err(self.not_representable(n))
+            }
+        }
+        Ok(out.write_str(&text)?)
+    }
+
+    pub fn atom<S: Deref<Target = str>>(
+        &self,
+        out: &mut impl Write,
+        a: &Atom<S>
+    ) -> Result<(), WriteError> {
+        let f = self.format;
+        if f.atoms_are_strings {
+            // Sexplib: everything is a string
+            let mut text = String::new();
+            match a {
+                Atom::String(s) | Atom::Symbol(s) => sexplib::write_atom(&mut text, s),
+                Atom::Number(n) => sexplib::write_atom(&mut text, &n.to_string()),
+                _ => return Err(self.not_representable(a))
+            }
+            return Ok(out.write_str(&text)?)
+        }
+        let bits = |out: &mut dyn Write, prefix: &str, bits: &[bool]| {
+            out.write_str(prefix)?;
+            bits.iter().try_for_each(|b| out.write_char(if *b { '1' } else { '0' }))
+        };
+        match a {
+            Atom::Nil | Atom::Bool(false) if f.upcase_symbols => out.write_str("NIL")?,
+            Atom::Nil | Atom::Bool(false) if f.question_mark_chars => out.write_str("nil")?,
+            Atom::Bool(true) if f.upcase_symbols => out.write_char('T')?,
+            Atom::Bool(true) if f.question_mark_chars => out.write_char('t')?,
+            Atom::Nil if f.has_nil_true_false || f.clojure_quoting => out.write_str("nil")?,
+            Atom::Bool(b) if f.has_nil_true_false || f.clojure_quoting
+                || f.numbers == NumberSyntax::SmtLib =>
+                out.write_str(if *b { "true" } else { "false" })?,
+            Atom::Bool(b) if f.has_scheme_hash_syntax =>
+                out.write_str(if *b { "#t" } else { "#f" })?,
+            Atom::Char(c) => self.char(out, *c)?,
+            Atom::String(s) => self.string(out, s)?,
+            Atom::Symbol(s) => {
+                let text = self.quoted_name("", s, "", a)
+                    .ok_or_else(|| self.not_representable(format_args!("symbol {:?}", &**s)))?;
+                out.write_str(&text)?
+            }
+            Atom::Keyword1(s) | Atom::Keyword2(s) => {
+                // The format's keyword style first
+                let styles: &[(&str, &str)] =
+                    if f.trailing_colon_is_keyword && !f.hashcolon_is_keyword {
+                        &[("", ":"), (":", "")]
+                    } else if f.hashcolon_is_keyword {
+                        &[("#:", ""), (":", "")]
+                    } else {
+                        &[(":", "")]
+                    };
+                let text = styles.iter()
+                    .find_map(|(prefix, suffix)| self.quoted_name(prefix, s, suffix, a))
+                    .ok_or_else(|| self.not_representable(format_args!("keyword {:?}", &**s)))?;
+                out.write_str(&text)?
+            }
+            Atom::UninternedSymbol(s) if f.hash_colon_symbols
+                || (f.has_scheme_hash_syntax && !f.hashcolon_is_keyword) =>
+            {
+                let text = self.quoted_name("#:", s, "", a)
+                    .ok_or_else(|| self.not_representable(a))?;
+                out.write_str(&text)?
+            }
+            Atom::Special(kind) if f.has_scheme_hash_syntax && !f.upcase_symbols => {
+                out.write_str("#!")?;
+                out.write_str(<&str>::from(*kind))?
+            }
+            Atom::Number(n) => self.number(out, n)?,
+            Atom::PackageSymbol(s, pkglen) if f.package_qualified_symbols => {
+                let (pkg, internal, name) = package_symbol_parts(s, *pkglen);
+                let mut text = String::new();
+                common_lisp::write_symbol_name(&mut text, pkg);
+                text.push_str(if internal { "::" } else { ":" });
+                common_lisp::write_symbol_name(&mut text, name);
+                out.write_str(&text)?
+            }
+            Atom::BitVector(bs) if f.hash_star_is_bit_vector => bits(out, "#*", bs)?,
+            Atom::BitVector(bs) if f.hash_b_x_are_bit_vectors && !bs.is_empty() =>
+                bits(out, "#b", bs)?,
+            Atom::Hexadecimal(s) if f.hash_b_x_are_bit_vectors => {
+                out.write_str("#x")?;
+                out.write_str(s)?
+            }
+            Atom::Decimal(s) if f.numbers == NumberSyntax::SmtLib
+                && smtlib_number_kind(s) == Some(true) => out.write_str(s)?,
+            Atom::Decimal(s) if f.numbers == NumberSyntax::Edn && is_edn_decimal(s) => {
+                out.write_str(s)?;
+                out.write_char('M')?
+            }
+            Atom::NumericVector(v) if f.has_srfi4_vectors
+                || (f.hash_u8_is_bytevector && matches!(**v, NumericVector::U8(_))) =>
+            {
+                write!(out, "{}", v)?;
+            }
+            _ => return Err(self.not_representable(a))
+        }
+        Ok(())
+    }
+
+    /// Check whether `rm` is available in the format.
+    pub fn reader_macro(&self, rm: ReaderMacro) -> Result<(), WriteError> {
+        let f = self.format;
+        let available = match rm {
+            ReaderMacro::SyntaxQuote | ReaderMacro::Unquote
+                | ReaderMacro::UnquoteSplicing | ReaderMacro::Deref
+                | ReaderMacro::Var => f.clojure_quoting,
+            ReaderMacro::Set => f.hash_curly_is_set,
+            ReaderMacro::AnonFn => f.hash_paren_is_fn,
+            ReaderMacro::Conditional | ReaderMacro::ConditionalSplicing =>
+                f.has_reader_conditionals,
+            ReaderMacro::Function => f.hash_quote_is_function,
+            ReaderMacro::Vector => f.hash_paren_is_vector,
+            ReaderMacro::ReadEval => f.hash_dot_is_read_eval,
+            ReaderMacro::Record => f.hash_s_is_record,
+        };
+        if available {
+            Ok(())
+        } else {
+            Err(self.not_representable(rm.name()))
+        }
+    }
+
+    /// Check whether a list of kind `pk`, improper if `improper`, is
+    /// available in the format.
+    pub fn list(&self, pk: Parenkind, improper: bool) -> Result<(), WriteError> {
+        if improper && !self.format.has_dotted_pairs {
+            return Err(self.not_representable("improper list"))
+        }
+        if pk != Parenkind::Round && self.format.atoms_are_strings {
+            return Err(self.not_representable(
+                format_args!("{}..{} list", pk.opening(), pk.closing())))
+        }
+        Ok(())
+    }
+
+    /// Check whether the list-like parts of `v` (i.e. not looking
+    /// into atoms) are representable.
+    pub fn check_syntax<S: Deref<Target = str>>(&self, v: &VValue<S>) -> Result<(), WriteError> {
+        let f = self.format;
+        match v {
+            VValue::Atom(_) => {}
+            VValue::List(pk, impr, vs) => {
+                self.list(*pk, impr.is_some())?;
+                if *pk == Parenkind::Curly && f.hash_curly_is_set && vs.len() % 2 != 0 {
+                    return Err(self.not_representable("map with an odd number of forms"))
+                }
+            }
+            VValue::ReaderMacro(rm, v) => {
+                self.reader_macro(*rm)?;
+                if let Some(pk) = rm.list_kind() {
+                    match &v.0 {
+                        VValue::List(k, impr, _) if *k == pk => self.list(pk, impr.is_some())?,
+                        _ => return Err(self.not_representable(format_args!(
+                            "{} that is not a {}..{} list", rm.name(), pk.opening(),
+                            pk.closing())))
+                    }
+                }
+            }
+            VValue::Tagged(tag, _) => if !(f.hash_symbol_is_tag
+                                           && tag.starts_with(char::is_alphabetic)
+                                           && self.reads_as(tag, &Atom::Symbol(&**tag)))
+            {
+                return Err(self.not_representable(format_args!("#{} tag", &**tag)))
+            }
+            VValue::FeatureConditional(..) => if !f.has_feature_conditionals {
+                return Err(self.not_representable("feature conditional"))
+            }
+            VValue::Labeled(..) | VValue::LabelRef(_) => if !f.has_datum_labels {
+                return Err(self.not_representable("datum label"))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn value<S: Deref<Target = str>>(
+        &self,
+        out: &mut impl Write,
+        v: &VValue<S>
+    ) -> Result<(), WriteError> {
+        let mut pieces = ValuePieces::new(Writer { format: self.format, opts: self.opts }, v);
+        while pieces.write_next(out)? {}
+        Ok(())
+    }
+}
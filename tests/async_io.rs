@@ -0,0 +1,155 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(feature = "async")]
+
+use anyhow::Result;
+use anysexpr::{async_io::{parse_async, read_async},
+               buffered_chars::buffered_chars,
+               parse::parse,
+               read::ReadError,
+               settings::{GAMBIT_FORMAT, Modes, Settings},
+               value::{Atom, VValue}};
+use futures_util::StreamExt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{duplex, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+const INPUT: &str = include_str!("t-input.scm");
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+const SETTINGS: Settings = Settings { format: &GAMBIT_FORMAT, modes: &MODES };
+
+#[tokio::test]
+async fn tokens_through_pipe() -> Result<()> {
+    let expected: Vec<String> = parse(buffered_chars(INPUT.as_bytes()), &SETTINGS)
+        .map(|r| format!("{:?}", r)).collect();
+    let (mut tx, rx) = duplex(7);
+    let write = async move {
+        tx.write_all(INPUT.as_bytes()).await?;
+        tx.shutdown().await
+    };
+    let read = parse_async(BufReader::new(rx), &SETTINGS)
+        .map(|r| format!("{:?}", r))
+        .collect::<Vec<_>>();
+    let (written, got) = tokio::join!(write, read);
+    written?;
+    assert_eq!(got, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_same_as_sync() -> Result<()> {
+    let vals = GAMBIT_FORMAT.read_all(buffered_chars(INPUT.as_bytes()), &MODES)?;
+    let mut expected = Vec::new();
+    GAMBIT_FORMAT.write_all(&mut expected, &vals)?;
+    let (mut tx, mut rx) = duplex(64);
+    let write = async {
+        GAMBIT_FORMAT.write_all_async(&mut tx, &vals).await?;
+        tx.shutdown().await?;
+        anyhow::Ok(())
+    };
+    let mut got = Vec::new();
+    let (written, read) = tokio::join!(write, rx.read_to_end(&mut got));
+    written?;
+    read?;
+    assert_eq!(got, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn values_roundtrip() -> Result<()> {
+    let s = "(a \"b c\" (1 . 2)) 'ä #;(x) (#t 3/4 |d e|)";
+    let vals = GAMBIT_FORMAT.read_all(buffered_chars(s.as_bytes()), &MODES)?;
+    let (mut tx, rx) = duplex(5);
+    let write = async {
+        GAMBIT_FORMAT.write_all_async(&mut tx, &vals).await?;
+        tx.shutdown().await?;
+        anyhow::Ok(())
+    };
+    let read = read_async(BufReader::new(rx), &SETTINGS)
+        .collect::<Vec<_>>();
+    let (written, got) = tokio::join!(write, read);
+    written?;
+    let got = got.into_iter().map(|r| r.map(|v| v.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(got, vals.iter().map(|v| v.to_string()).collect::<Vec<_>>());
+    assert_eq!(got.len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn errors() -> Result<()> {
+    let (mut tx, rx) = duplex(64);
+    tx.write_all(b"(a b) (c \"d").await?;
+    drop(tx);
+    let got: Vec<_> = read_async(BufReader::new(rx), &SETTINGS).collect().await;
+    assert_eq!(got.len(), 2);
+    assert_eq!(got[0].as_ref().unwrap().to_string(), "(a b)");
+    let e = got[1].as_ref().unwrap_err();
+    assert!(matches!(e.err, ReadError::PE(_)));
+    assert_eq!(e.span.start.col, 9);
+    Ok(())
+}
+
+#[tokio::test]
+async fn long_token_in_small_chunks() -> Result<()> {
+    // Each feed of 16 bytes must not rescan the token read so far
+    let n = 2_000_000;
+    let s = format!("\"{}\" x", "a".repeat(n));
+    let tokens: Vec<_> = parse_async(BufReader::with_capacity(16, s.as_bytes()), &SETTINGS)
+        .collect().await;
+    assert_eq!(tokens.len(), 2);
+    let vals: Vec<_> = read_async(BufReader::with_capacity(16, s.as_bytes()), &SETTINGS)
+        .collect().await;
+    assert_eq!(vals.len(), 2);
+    match &vals[0].as_ref().unwrap().0 {
+        VValue::Atom(Atom::String(s)) => assert_eq!(s.len(), n),
+        v => panic!("not a string: {}", v),
+    }
+    Ok(())
+}
+
+/// Records the sizes of the writes.
+struct Chunks {
+    sizes: Vec<usize>,
+}
+
+impl AsyncWrite for Chunks {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8])
+                  -> Poll<io::Result<usize>> {
+        self.get_mut().sizes.push(buf.len());
+        Poll::Ready(Ok(buf.len()))
+    }
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn write_in_chunks() -> Result<()> {
+    let s = format!("({})", ["abc"; 100_000].join(" "));
+    let v = &GAMBIT_FORMAT.read_all(buffered_chars(s.as_bytes()), &MODES)?[0];
+    let mut out = Chunks { sizes: Vec::new() };
+    GAMBIT_FORMAT.writeln_async(&mut out, v).await?;
+    assert_eq!(out.sizes.iter().sum::<usize>(), s.len() + 1);
+    assert!(out.sizes.len() > 1);
+    assert!(out.sizes.iter().all(|size| *size < 10_000));
+    Ok(())
+}
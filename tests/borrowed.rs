@@ -0,0 +1,120 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use std::borrow::Cow;
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::{parse_str, Token},
+               settings::{COMMON_LISP_FORMAT, EDN_FORMAT, GAMBIT_FORMAT, Modes, Settings},
+               value::{Atom, VValue}};
+use kstring::KStringCow;
+
+const INPUT: &str = include_str!("t-input.scm");
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+fn is_borrowed(s: &KStringCow) -> bool {
+    matches!(s.clone().into_cow_str(), Cow::Borrowed(_))
+}
+
+#[test]
+fn same_as_read_all() -> Result<()> {
+    let borrowed = GAMBIT_FORMAT.read_str(INPUT, &MODES)?;
+    let owned = GAMBIT_FORMAT.read_all(buffered_chars(INPUT.as_bytes()), &MODES)?;
+    assert_eq!(borrowed.len(), owned.len());
+    for (b, o) in borrowed.into_iter().zip(owned) {
+        assert_eq!(b.to_string(), o.to_string());
+        assert_eq!(b.1, o.1);
+        assert_eq!(format!("{:?}", b.into_owned()), format!("{:?}", o));
+    }
+    Ok(())
+}
+
+#[test]
+fn atoms_borrow_unless_escaped() -> Result<()> {
+    let s = "(foo \"bar baz\" \"a\\nb\" :kw1 kw2: #:sym |x y| |x\\|y|)";
+    let vals = GAMBIT_FORMAT.read_str(s, &MODES)?;
+    let items = match &vals[0].0 {
+        VValue::List(_, _, items) => items,
+        _ => panic!("not a list")
+    };
+    let atoms: Vec<(String, bool)> = items.iter().map(|v| match &v.0 {
+        VValue::Atom(Atom::Symbol(s) | Atom::String(s) | Atom::Keyword1(s)
+                     | Atom::Keyword2(s) | Atom::UninternedSymbol(s)) =>
+            (s.to_string(), is_borrowed(s)),
+        _ => panic!("unexpected item {}", v)
+    }).collect();
+    assert_eq!(atoms, vec![
+        ("foo".into(), true),
+        ("bar baz".into(), true),
+        ("a\nb".into(), false),
+        ("kw1".into(), true),
+        ("kw2".into(), true),
+        ("sym".into(), true),
+        ("x y".into(), true),
+        ("x|y".into(), false),
+    ]);
+    Ok(())
+}
+
+#[test]
+fn tokens_borrow() -> Result<()> {
+    let s = "a ;; note\n  #|multi\nline|# \"s\"";
+    let modes = Modes { retain_whitespace: true, retain_comments: true, ..MODES };
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &modes };
+    let mut seen = Vec::new();
+    for t in parse_str(s, &settings) {
+        let t = t?;
+        match &t.0 {
+            Token::Whitespace(s) | Token::Comment(_, s)
+                | Token::Atom(Atom::Symbol(s) | Atom::String(s)) =>
+                seen.push((s.to_string(), is_borrowed(s))),
+            _ => panic!("unexpected token {}", t.0)
+        }
+    }
+    assert_eq!(seen, vec![
+        ("a".into(), true),
+        (" ".into(), true),
+        (" note".into(), true),
+        ("\n  ".into(), true),
+        ("multi\nline".into(), true),
+        (" ".into(), true),
+        ("s".into(), true),
+    ]);
+    Ok(())
+}
+
+#[test]
+fn borrow_unless_changed() -> Result<()> {
+    // Case folding changes the text, too
+    let vals = COMMON_LISP_FORMAT.read_str("FOO foo #:BAR |baz| |a\\|b|", &MODES)?;
+    let atoms: Vec<(String, bool)> = vals.iter().map(|v| match &v.0 {
+        VValue::Atom(Atom::Symbol(s) | Atom::UninternedSymbol(s)) =>
+            (s.to_string(), is_borrowed(s)),
+        _ => panic!("unexpected value {}", v)
+    }).collect();
+    assert_eq!(atoms, vec![
+        ("FOO".into(), true),
+        ("FOO".into(), false),
+        ("BAR".into(), true),
+        ("baz".into(), true),
+        ("a|b".into(), false),
+    ]);
+    let vals = EDN_FORMAT.read_str("#inst \"2020\"", &MODES)?;
+    match &vals[0].0 {
+        VValue::Tagged(tag, _) => assert!(is_borrowed(tag)),
+        v => panic!("not tagged: {}", v)
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::{buffered_chars, str_chars},
+               pos::Pos};
+use std::io::BufReader;
+
+fn chars_with_capacity(bytes: &[u8], capacity: usize) -> Vec<Result<(char, Pos), String>> {
+    buffered_chars(BufReader::with_capacity(capacity, bytes))
+        .map(|r| r.map_err(|e| e.to_string()))
+        .collect()
+}
+
+#[test]
+fn split_characters() -> Result<()> {
+    let s = "a\u{fc}\n\u{3bb}x \u{1f600}.";
+    let expected: Vec<_> = str_chars(s).map(|r| r.map_err(|e| e.to_string())).collect();
+    // Capacities that cut multi-byte characters at every place
+    for capacity in 1..6 {
+        assert_eq!(chars_with_capacity(s.as_bytes(), capacity), expected);
+    }
+    let last = expected.last().unwrap().as_ref().unwrap();
+    assert_eq!(last.1, Pos { line: 1, col: 4, byte_offset: 12, char_offset: 7 });
+    Ok(())
+}
+
+#[test]
+fn invalid_utf8() -> Result<()> {
+    for capacity in [1, 2, 100] {
+        let cs = chars_with_capacity(b"ab\xe2\x82z", capacity);
+        assert_eq!(cs.len(), 3);
+        assert_eq!(cs[2], Err("buffered_chars: invalid byte sequence: [e2, 82]".into()));
+        // Truncated at the end of the input
+        let cs = chars_with_capacity(b"a\xf0\x9f", capacity);
+        assert_eq!(cs[1], Err("buffered_chars: invalid byte sequence: [f0, 9f]".into()));
+        assert_eq!(chars_with_capacity(b"\xff", capacity),
+                   vec![Err("buffered_chars: invalid byte sequence: [ff]".into())]);
+    }
+    Ok(())
+}
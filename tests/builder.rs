@@ -0,0 +1,107 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               builder::TreeBuilder,
+               pos::Span,
+               settings::{GAMBIT_FORMAT, Modes},
+               value::{Atom, Parenkind}};
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+/// A classic Lisp representation.
+#[derive(Debug, PartialEq)]
+enum Cell {
+    Nil,
+    Atom(String),
+    Pair(Box<Cell>, Box<Cell>),
+}
+
+struct CellBuilder {
+    lines_seen: Vec<u32>,
+}
+
+impl TreeBuilder for CellBuilder {
+    type Value = Cell;
+
+    fn atom(&mut self, atom: Atom, span: Span) -> Cell {
+        self.lines_seen.push(span.start.line);
+        Cell::Atom(atom.to_string())
+    }
+
+    fn list(&mut self, _pk: Parenkind, items: Vec<Cell>, _span: Span) -> Cell {
+        items.into_iter().rev().fold(
+            Cell::Nil, |tail, v| Cell::Pair(Box::new(v), Box::new(tail)))
+    }
+
+    fn improper_list(
+        &mut self, _pk: Parenkind, items: Vec<Cell>, _dotspan: Span, tail: Cell, _span: Span
+    ) -> Cell {
+        items.into_iter().rev().fold(
+            tail, |tail, v| Cell::Pair(Box::new(v), Box::new(tail)))
+    }
+}
+
+fn read_cells(s: &str) -> Result<(Vec<Cell>, Vec<u32>)> {
+    let mut b = CellBuilder { lines_seen: Vec::new() };
+    let vals = GAMBIT_FORMAT.read_all_with(&mut b, buffered_chars(s.as_bytes()), &MODES)?;
+    Ok((vals, b.lines_seen))
+}
+
+fn a(s: &str) -> Box<Cell> {
+    Box::new(Cell::Atom(s.into()))
+}
+
+#[test]
+fn cells() -> Result<()> {
+    let (vals, lines) = read_cells("(a . (b . c))\n'x")?;
+    assert_eq!(vals, vec![
+        Cell::Pair(a("a"), Box::new(Cell::Pair(a("b"), a("c")))),
+        Cell::Pair(a("quote"),
+                   Box::new(Cell::Pair(a("x"), Box::new(Cell::Nil)))),
+    ]);
+    assert_eq!(lines, vec![0, 0, 0, 1, 1]);
+    Ok(())
+}
+
+#[test]
+fn improper_lists_checked_by_reader() {
+    let modes = Modes { allow_improper_lists: false, ..MODES };
+    let mut b = CellBuilder { lines_seen: Vec::new() };
+    assert!(GAMBIT_FORMAT.read_all_with(
+        &mut b, buffered_chars("(a . (b c))".as_bytes()), &modes).is_ok());
+    let e = GAMBIT_FORMAT.read_all_with(
+        &mut b, buffered_chars("(a . (b . c))".as_bytes()), &modes).unwrap_err();
+    assert_eq!(e.to_string(), "improper lists disallowed in given mode @1.8");
+}
+
+#[test]
+fn commented_out_not_built() -> Result<()> {
+    let s = "(a #;(b c) d)\n#;'e\nf #;#;g h";
+    let (vals, lines) = read_cells(s)?;
+    assert_eq!(vals, vec![
+        Cell::Pair(a("a"), Box::new(Cell::Pair(a("d"), Box::new(Cell::Nil)))),
+        Cell::Atom("f".into()),
+    ]);
+    assert_eq!(lines, vec![0, 0, 2]);
+
+    let mut b = CellBuilder { lines_seen: Vec::new() };
+    let (vals, errors) = GAMBIT_FORMAT.read_all_recovering_with(
+        &mut b, buffered_chars(s.as_bytes()), &MODES);
+    assert!(errors.is_empty());
+    assert_eq!(vals.len(), 2);
+    assert_eq!(b.lines_seen, vec![0, 0, 2]);
+    Ok(())
+}
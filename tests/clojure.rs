@@ -0,0 +1,100 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::parse,
+               settings::{CLOJURE_FORMAT, GAMBIT_FORMAT, Modes, Settings},
+               value::{Atom, Parenkind, ReaderMacro, VValue, VValueWithPos}};
+
+mod common;
+use common::{MODES, read};
+
+fn write(vals: &[VValueWithPos]) -> String {
+    vals.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn reader_macro(v: &VValueWithPos) -> (ReaderMacro, &VValue) {
+    match &v.0 {
+        VValue::ReaderMacro(rm, v) => (*rm, &v.0),
+        _ => panic!("not a reader macro: {}", v)
+    }
+}
+
+#[test]
+fn collections() -> Result<()> {
+    let vals = read(&CLOJURE_FORMAT, "[1, 2] {:a 1, :b 2} #{x y} (a,b)")?;
+    assert!(matches!(&vals[0].0, VValue::List(Parenkind::Square, None, vs)
+                     if vs.len() == 2));
+    assert!(matches!(&vals[1].0, VValue::List(Parenkind::Curly, None, vs)
+                     if vs.len() == 4
+                     && matches!(&vs[0].0, VValue::Atom(Atom::Keyword1(k)) if k == "a")));
+    let (rm, set) = reader_macro(&vals[2]);
+    assert_eq!(rm, ReaderMacro::Set);
+    assert!(matches!(set, VValue::List(Parenkind::Curly, None, vs) if vs.len() == 2));
+    assert!(matches!(&vals[3].0, VValue::List(Parenkind::Round, None, vs)
+                     if vs.len() == 2));
+    assert_eq!(write(&vals), "[1 2] {:a 1 :b 2} #{x y} (a b)");
+    Ok(())
+}
+
+#[test]
+fn reader_macros() -> Result<()> {
+    let s = "`(a ~b ~@c) @x #'f #(inc x) #?(:clj 1 :cljs 2) [#?@(:clj [a])] 'q";
+    let vals = read(&CLOJURE_FORMAT, s)?;
+    let rms: Vec<ReaderMacro> = vals[0..5].iter().map(|v| reader_macro(v).0).collect();
+    assert_eq!(rms, vec![ReaderMacro::SyntaxQuote, ReaderMacro::Deref,
+                         ReaderMacro::Var, ReaderMacro::AnonFn,
+                         ReaderMacro::Conditional]);
+    match reader_macro(&vals[0]).1 {
+        VValue::List(_, _, vs) => {
+            assert_eq!(reader_macro(&vs[1]).0, ReaderMacro::Unquote);
+            assert_eq!(reader_macro(&vs[2]).0, ReaderMacro::UnquoteSplicing);
+        }
+        v => panic!("not a list: {}", v)
+    }
+    assert_eq!(write(&vals),
+               "`(a ~b ~@c) @x #'f #(inc x) #?(:clj 1 :cljs 2) [#?@(:clj [a])] \
+                (quote q)");
+    assert_eq!(vals[5].1.start.col, 47);
+    assert_eq!(vals[5].1.end.col, 62);
+    Ok(())
+}
+
+#[test]
+fn discard_and_symbols() -> Result<()> {
+    let vals = read(&CLOJURE_FORMAT, "(a #_ b c) #_#_ d e f a.b foo: x@y")?;
+    assert_eq!(write(&vals), "(a c) f |a.b| |foo:| |x@y|");
+    // The same input means something else in Gambit
+    let vals = read(&GAMBIT_FORMAT, "`(a ,b) foo:")?;
+    assert_eq!(write(&vals), "(quasiquote (a (unquote b))) foo:");
+    Ok(())
+}
+
+#[test]
+fn tokens() -> Result<()> {
+    let modes = Modes { retain_whitespace: true, ..MODES };
+    let settings = Settings { format: &CLOJURE_FORMAT, modes: &modes };
+    let s = "#{a}, ~@b #?@(c) #_d";
+    let tokens: Vec<String> = parse(buffered_chars(s.as_bytes()), &settings)
+        .map(|t| t.map(|t| t.0.to_string()))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(tokens[0..4], ["#", "{", "a", "}"]);
+    assert_eq!(tokens[4], ", ");
+    // Token doesn't know the format, `#_` is written as `#;`
+    assert_eq!(tokens.concat(), "#{a}, ~@b #?@(c) #;d");
+    Ok(())
+}
+
+#[test]
+fn errors() {
+    let err = |s: &str| read(&CLOJURE_FORMAT, s).unwrap_err().to_string();
+    assert_eq!(err("#?[a]"), "invalid '#' token @1.0");
+    assert_eq!(err("[~@"), "missing expression after unquote-splicing @1.1");
+}
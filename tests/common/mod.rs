@@ -0,0 +1,57 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers shared by the format tests.
+
+// Each test crate only uses some of these.
+#![allow(dead_code)]
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               settings::{AnysexprFormat, Modes},
+               value::{Atom, VValue, VValueWithPos},
+               write::Writable};
+
+pub const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+/// The values in `s`, read in `format`.
+pub fn read(format: &AnysexprFormat, s: &str) -> Result<Vec<VValueWithPos>> {
+    Ok(format.read_all(buffered_chars(s.as_bytes()), &MODES)?)
+}
+
+/// The values in `s`, read in `format`, without their positions.
+pub fn read_values(format: &AnysexprFormat, s: &str) -> Result<Vec<VValue>> {
+    Ok(read(format, s)?.into_iter().map(|v| v.0).collect())
+}
+
+/// `vals` written in `format`, separated by spaces.
+pub fn write<T: Writable>(format: &AnysexprFormat, vals: &[T]) -> Result<String> {
+    let written: Vec<String> = vals.iter().map(|v| format.to_string(v))
+        .collect::<Result<_, _>>()?;
+    Ok(written.join(" "))
+}
+
+pub fn atom(v: &VValueWithPos) -> &Atom {
+    match &v.0 {
+        VValue::Atom(a) => a,
+        _ => panic!("not an atom: {}", v)
+    }
+}
+
+pub fn items(v: &VValueWithPos) -> &[VValueWithPos] {
+    match &v.0 {
+        VValue::List(_, None, vs) => vs,
+        _ => panic!("not a proper list: {}", v)
+    }
+}
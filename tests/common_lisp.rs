@@ -0,0 +1,137 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{number::R5RSNumber,
+               settings::{AnysexprFormat, CLOJURE_FORMAT, COMMON_LISP_FORMAT, GAMBIT_FORMAT},
+               value::{Atom, ReaderMacro, VValue, package_symbol_parts}};
+
+mod common;
+use common::{atom, read, write};
+
+#[test]
+fn symbols() -> Result<()> {
+    let vals = read(&COMMON_LISP_FORMAT,
+                    "foo |foo| cl-user::Bar alexandria:curry :key #:g Ä")?;
+    assert!(matches!(atom(&vals[0]), Atom::Symbol(s) if s == "FOO"));
+    assert!(matches!(atom(&vals[1]), Atom::Symbol(s) if s == "foo"));
+    match atom(&vals[2]) {
+        Atom::PackageSymbol(s, pkglen) =>
+            assert_eq!(package_symbol_parts(s, *pkglen), ("CL-USER", true, "BAR")),
+        a => panic!("not a package symbol: {}", a)
+    }
+    match atom(&vals[3]) {
+        Atom::PackageSymbol(s, pkglen) =>
+            assert_eq!(package_symbol_parts(s, *pkglen), ("ALEXANDRIA", false, "CURRY")),
+        a => panic!("not a package symbol: {}", a)
+    }
+    assert!(matches!(atom(&vals[4]), Atom::Keyword1(s) if s == "KEY"));
+    assert!(matches!(atom(&vals[5]), Atom::UninternedSymbol(s) if s == "G"));
+    assert!(matches!(atom(&vals[6]), Atom::Symbol(s) if s == "Ä"));
+    assert_eq!(write(&COMMON_LISP_FORMAT, &vals)?,
+               "FOO |foo| CL-USER::BAR ALEXANDRIA:CURRY :KEY #:G Ä");
+    Ok(())
+}
+
+#[test]
+fn numbers_and_chars() -> Result<()> {
+    let vals = read(&COMMON_LISP_FORMAT,
+                    "1. -2 3/4 1.5d0 2.5e3 1f2 #c(1 -2.5) #*1011 #* \
+                     #\\a #\\Space #\\NEWLINE #\\( \"a\\b\\\"\" 1+2i +inf.0")?;
+    let nums: Vec<String> = vals[0..6].iter().map(|v| v.to_string()).collect();
+    assert_eq!(nums, vec!["1", "-2", "3/4", "1.5", "2500.0", "100.0"]);
+    assert!(matches!(atom(&vals[6]), Atom::Number(R5RSNumber::Complex(_, _))));
+    assert!(matches!(atom(&vals[7]), Atom::BitVector(bs) if bs == &[true, false, true, true]));
+    assert!(matches!(atom(&vals[8]), Atom::BitVector(bs) if bs.is_empty()));
+    let chars: Vec<char> = vals[9..13].iter().map(|v| match atom(v) {
+        Atom::Char(c) => *c,
+        a => panic!("not a char: {}", a)
+    }).collect();
+    assert_eq!(chars, vec!['a', ' ', '\n', '(']);
+    // `\` only quotes the next character
+    assert!(matches!(atom(&vals[13]), Atom::String(s) if s == "ab\""));
+    // Scheme syntax that Common Lisp reads as symbols
+    assert!(matches!(atom(&vals[14]), Atom::Symbol(s) if s == "1+2I"));
+    assert!(matches!(atom(&vals[15]), Atom::Symbol(s) if s == "+INF.0"));
+    let written: Vec<String> = vals.iter().map(|v| COMMON_LISP_FORMAT.to_string(v))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(written.join(" "),
+               "1 -2 3/4 1.5d0 2500.0d0 100.0d0 #C(1 -2.5d0) #*1011 #* \
+                #\\a #\\Space #\\Newline #\\( \"ab\\\"\" 1+2I +INF.0");
+    // And reads back the same
+    let vals2 = read(&COMMON_LISP_FORMAT, &written.join(" "))?;
+    assert_eq!(vals2.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+               vals.iter().map(|v| v.to_string()).collect::<Vec<_>>());
+    Ok(())
+}
+
+#[test]
+fn hash_syntax() -> Result<()> {
+    let vals = read(&COMMON_LISP_FORMAT,
+                    "#'car #(1 (2) x) #+sbcl (a . b) #-(or ccl ecl) c (#+x 1 2) '#:foo")?;
+    assert!(matches!(&vals[0].0, VValue::ReaderMacro(ReaderMacro::Function, _)));
+    assert!(matches!(&vals[1].0, VValue::ReaderMacro(ReaderMacro::Vector, v)
+                     if matches!(&v.0, VValue::List(_, None, vs) if vs.len() == 3)));
+    match &vals[2].0 {
+        VValue::FeatureConditional(true, b) => {
+            assert!(matches!(&b.0.0, VValue::Atom(Atom::Symbol(s)) if s == "SBCL"));
+            assert_eq!(b.1.to_string(), "(A . B)");
+        }
+        v => panic!("not a feature conditional: {}", v)
+    }
+    assert!(matches!(&vals[3].0, VValue::FeatureConditional(false, _)));
+    assert_eq!(vals[3].1.to_string(), "@1.32");
+    assert_eq!(vals.len(), 6);
+    assert_eq!(write(&COMMON_LISP_FORMAT, &vals)?,
+               "#'CAR #(1 (2) X) #+SBCL (A . B) #-(OR CCL ECL) C (#+X 1 2) (|quote| #:FOO)");
+    Ok(())
+}
+
+#[test]
+fn read_eval() -> Result<()> {
+    let e = read(&COMMON_LISP_FORMAT, "(a #.(+ 1 2))").unwrap_err();
+    assert_eq!(e.to_string(), "'#.' (read-time evaluation) not allowed @1.3");
+
+    let format = AnysexprFormat { allow_read_eval: true, ..COMMON_LISP_FORMAT };
+    let vals = read(&format, "#.(+ 1 2)")?;
+    assert!(matches!(&vals[0].0, VValue::ReaderMacro(ReaderMacro::ReadEval, _)));
+    assert_eq!(write(&COMMON_LISP_FORMAT, &vals)?, "#.(+ 1 2)");
+    Ok(())
+}
+
+#[test]
+fn errors() -> Result<()> {
+    let msgs: Vec<String> = ["#*102", "#c(1)", "#+x", "#\\nosuchname"].iter().map(|s| {
+        read(&COMMON_LISP_FORMAT, s).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(msgs, vec![
+        "invalid bit vector @1.0",
+        "invalid '#c' complex number @1.0",
+        "missing expression after feature expression @1.0",
+        "invalid '#' token @1.0",
+    ]);
+    Ok(())
+}
+
+#[test]
+fn unrepresentable() -> Result<()> {
+    let clojure = read(&CLOJURE_FORMAT, "@x #inst \"2020\"")?;
+    let gambit = read(&GAMBIT_FORMAT, "#!eof 1@2 +inf.0")?;
+    let errs: Vec<String> = clojure.iter().chain(&gambit).map(|v| {
+        COMMON_LISP_FORMAT.to_string(v).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(errs, vec![
+        "not representable in Common Lisp: deref",
+        "not representable in Common Lisp: #inst tag",
+        "not representable in Common Lisp: #!eof",
+        "not representable in Common Lisp: 1@2",
+        "not representable in Common Lisp: +inf.0",
+    ]);
+    Ok(())
+}
@@ -0,0 +1,156 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               edn::EdnReader,
+               number::{Integer, R5RSNumber},
+               read::{ReadError, ReadErrorWithPos},
+               settings::{EDN_FORMAT, GAMBIT_FORMAT},
+               value::{Atom, VValue, VValueWithPos}};
+use kstring::KString;
+
+mod common;
+use common::write;
+
+fn read(reader: &EdnReader, s: &str) -> Result<Vec<VValueWithPos>, String> {
+    reader.read_all(buffered_chars(s.as_bytes())).map_err(|e| e.to_string())
+}
+
+#[test]
+fn roundtrip() -> Result<()> {
+    let s = r#"{:a [1 -2.5 1e100 "x\"\n"], :b #{nil true false}, "c" (\a \newline ä)}
+#inst "1985-04-12T23:20:50.52Z" #uuid "f81d4fae-7dec-11d0-a765-00a0c91e6bf6"
+#myapp/Person {:first "Fred"} foo/bar #_ ignored :ns/kw"#;
+    let reader = EdnReader::new();
+    let vals = read(&reader, s).unwrap();
+    assert_eq!(vals.len(), 6);
+    let written = write(&EDN_FORMAT, &vals)?;
+    assert_eq!(written, concat!(
+        r#"{:a [1 -2.5 1e100 "x\"\n"] :b #{nil true false} "c" (\a \newline ä)} "#,
+        r#"#inst "1985-04-12T23:20:50.52Z" #uuid "f81d4fae-7dec-11d0-a765-00a0c91e6bf6" "#,
+        r#"#myapp/Person {:first "Fred"} foo/bar :ns/kw"#));
+    let vals2 = read(&reader, &written).unwrap();
+    assert_eq!(write(&EDN_FORMAT, &vals2)?, written);
+    match &vals[2].0 {
+        VValue::Tagged(tag, v) => {
+            assert_eq!(tag, "uuid");
+            assert!(matches!(&v.0, VValue::Atom(Atom::String(_))));
+        }
+        v => panic!("not tagged: {}", v)
+    }
+    Ok(())
+}
+
+#[test]
+fn tag_handlers() -> Result<()> {
+    let mut reader = EdnReader::new();
+    reader.register_tag("myapp/upper", |v| match v.0 {
+        VValue::Atom(Atom::String(s)) =>
+            Ok(VValue::Atom(Atom::String(KString::from_string(s.to_uppercase())))),
+        _ => anyhow::bail!("expecting a string")
+    });
+    let vals = read(&reader, "[#myapp/upper \"abc\" #other 1]").unwrap();
+    assert_eq!(write(&EDN_FORMAT, &vals)?, "[\"ABC\" #other 1]");
+    match &vals[0].0 {
+        VValue::List(_, _, vs) => {
+            assert_eq!(vs[0].1.start.col, 1);
+            assert_eq!(vs[0].1.end.col, 19);
+        }
+        _ => panic!()
+    }
+    assert_eq!(read(&reader, "#myapp/upper 1").unwrap_err(),
+               "invalid #myapp/upper element: expecting a string @1.0");
+    assert_eq!(read(&reader, "#inst \"1985-13-01\"").unwrap_err(),
+               "invalid #inst element: not a RFC 3339 timestamp: \"1985-13-01\" @1.0");
+    assert_eq!(read(&reader, "#uuid \"f81d4fae\"").unwrap_err(),
+               "invalid #uuid element: not a UUID: \"f81d4fae\" @1.0");
+    for ok in ["1985", "1985-04-12", "1985-04-12T23:20", "1985-04-12T23:20:50-01:30"] {
+        read(&reader, &format!("#inst \"{}\"", ok)).unwrap();
+    }
+    Ok(())
+}
+
+#[test]
+fn not_edn() {
+    let reader = EdnReader::new();
+    let err = |s: &str| read(&reader, s).unwrap_err();
+    assert_eq!(err("(a 'b)"), "quote not supported by the format @1.3");
+    assert_eq!(err("[1/2]"), "ratio not supported by the format @1.1");
+    assert_eq!(err("{:a}"), "map literal must contain an even number of forms @1.0");
+    assert_eq!(err("#(a)"), "invalid '#' token @1.0");
+    assert_eq!(err("#!eof"), "invalid '#' token @1.0");
+    assert_eq!(err("\\foo"), "invalid character name \"foo\" @1.0");
+    // Plain read_all with the format doesn't check
+    let e = common::read(&EDN_FORMAT, "{:a} 'b #!eof").unwrap_err();
+    assert!(matches!(e.downcast_ref::<ReadErrorWithPos>(),
+                     Some(e) if matches!(e.err, ReadError::PE(_)) && e.span.start.col == 8));
+}
+
+#[test]
+fn unrepresentable() -> Result<()> {
+    let vals = common::read(&GAMBIT_FORMAT, "(a . b) |a b| #!eof 1/2 {a} #:g")?;
+    let errs: Vec<String> = vals.iter()
+        .map(|v| EDN_FORMAT.to_string(v).unwrap_err().to_string())
+        .collect();
+    assert_eq!(errs, vec![
+        "not representable in EDN: improper list",
+        "not representable in EDN: symbol \"a b\"",
+        "not representable in EDN: #!eof",
+        "not representable in EDN: 1/2",
+        "not representable in EDN: map with an odd number of forms",
+        "not representable in EDN: #:g",
+    ]);
+    Ok(())
+}
+
+#[test]
+fn suffixed_numbers() -> Result<()> {
+    let reader = EdnReader::new();
+    let vals = read(&reader, "[42N -0N 123456789012345678901234567890N 3.14M 42M -1.5e-3M]")
+        .unwrap();
+    let VValue::List(_, _, vs) = &vals[0].0 else { panic!() };
+    assert!(matches!(&vs[0].0, VValue::Atom(Atom::Number(R5RSNumber::Integer(
+        Integer::Small(42))))));
+    assert!(matches!(&vs[2].0, VValue::Atom(Atom::Number(R5RSNumber::Integer(
+        Integer::Big(_))))));
+    assert!(matches!(&vs[3].0, VValue::Atom(Atom::Decimal(s)) if s == "3.14"));
+    assert_eq!(write(&EDN_FORMAT, &vals)?,
+               "[42 0 123456789012345678901234567890 3.14M 42M -1.5e-3M]");
+
+    let err = |s: &str| read(&reader, s).unwrap_err();
+    for s in ["1.5N", "01N", "1e5N", "3.M5", "1eM", "42x", "-1a"] {
+        assert_eq!(err(s), "symbol starting like a number not supported by the format @1.0");
+    }
+    // Still symbols
+    for s in ["N", "M", "-N", "a42N"] {
+        assert!(matches!(&read(&reader, s).unwrap()[0].0, VValue::Atom(Atom::Symbol(_))));
+    }
+    Ok(())
+}
+
+#[test]
+fn format_writer_roundtrip() -> Result<()> {
+    // What EDN_FORMAT writes, EdnReader reads back as the same values
+    let reader = EdnReader::new();
+    let s = r#"{:a [1 -2.5 1e100 "x\"\n"], :b #{nil true false}, "c" (\a \newline ä)}
+#inst "1985-04-12T23:20:50.52Z" #myapp/Person {:first "Fred"} foo/bar :ns/kw
+[42N 3.14M -1.5e-3M +a - .b a1 +inf.0]"#;
+    let vals = read(&reader, s).unwrap();
+    let vals2 = read(&reader, &write(&EDN_FORMAT, &vals)?).unwrap();
+    let strings = |vs: &[VValueWithPos]| vs.iter().map(|v| v.0.to_string()).collect::<Vec<_>>();
+    assert_eq!(strings(&vals2), strings(&vals));
+
+    // Symbols that EDN would read as (invalid) numbers
+    for s in ["1+", "-1x", ".5a", "1/2"] {
+        let e = EDN_FORMAT.to_string(&Atom::Symbol(KString::from_ref(s))).unwrap_err();
+        assert_eq!(e.to_string(), format!("not representable in EDN: symbol {:?}", s));
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{number::R5RSNumber,
+               settings::{CLOJURE_FORMAT, ELISP_FORMAT, GAMBIT_FORMAT},
+               value::{Atom, Parenkind, ReaderMacro, VValue}};
+
+mod common;
+use common::{atom, read, write};
+
+#[test]
+fn chars() -> Result<()> {
+    let vals = read(&ELISP_FORMAT,
+                    "?a ?\\n ?\\s ?\\( ?( ?\\C-x ?\\^I ?\\x41 ?\\101 ?\\u00e4 ?\\M-a ?\\C-\\M-b")?;
+    let chars: Vec<char> = vals[0..10].iter().map(|v| match atom(v) {
+        Atom::Char(c) => *c,
+        a => panic!("not a char: {}", a)
+    }).collect();
+    assert_eq!(chars, vec!['a', '\n', ' ', '(', '(', '\x18', '\t', 'A', 'A', 'ä']);
+    // Modifier bits beyond the character range give integers
+    let ints: Vec<String> = vals[10..].iter().map(|v| match atom(v) {
+        Atom::Number(n @ R5RSNumber::Integer(_)) => n.to_string(),
+        a => panic!("not an integer: {}", a)
+    }).collect();
+    assert_eq!(ints, vec![(97 + (1 << 27)).to_string(), (2 + (1 << 27)).to_string()]);
+    assert_eq!(write(&ELISP_FORMAT, &vals[..10])?,
+               "?a ?\\n ?\\s ?\\( ?\\( ?\\x18 ?\\t ?A ?A ?ä");
+    // Any number of modifiers
+    let vals = read(&ELISP_FORMAT, &format!("?{}a", "\\M-".repeat(200_000)))?;
+    assert_eq!(vals[0].to_string(), (97 + (1 << 27)).to_string());
+    Ok(())
+}
+
+#[test]
+fn symbols_and_numbers() -> Result<()> {
+    let vals = read(&ELISP_FORMAT,
+                    "foo-bar a\\ b \\1 \\? :key #:g nil t 1. -2 1.5 1e3 1.0e+INF 1/2 +1")?;
+    let syms: Vec<String> = vals[0..8].iter().map(|v| match atom(v) {
+        Atom::Symbol(s) => s.to_string(),
+        Atom::Keyword1(s) => format!(":{}", s),
+        Atom::UninternedSymbol(s) => format!("#:{}", s),
+        a => panic!("not a symbol: {}", a)
+    }).collect();
+    assert_eq!(syms, vec!["foo-bar", "a b", "1", "?", ":key", "#:g", "nil", "t"]);
+    let nums: Vec<String> = vals[8..13].iter().map(|v| v.to_string()).collect();
+    assert_eq!(nums, vec!["1", "-2", "1.5", "1000.0", "+inf.0"]);
+    assert!(matches!(atom(&vals[13]), Atom::Symbol(s) if s == "1/2"));
+    assert!(matches!(atom(&vals[14]), Atom::Number(R5RSNumber::Integer(_))));
+    let written = write(&ELISP_FORMAT, &vals)?;
+    assert_eq!(written,
+               "foo-bar a\\ b \\1 \\? :key #:g nil t 1 -2 1.5 1000.0 1.0e+INF 1/2 1");
+    // And reads back the same
+    let reread = read(&ELISP_FORMAT, &written)?;
+    assert_eq!(reread.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+               vals.iter().map(|v| v.to_string()).collect::<Vec<_>>());
+    Ok(())
+}
+
+#[test]
+fn strings() -> Result<()> {
+    let vals = read(&ELISP_FORMAT, "\"a\\tb\\\n c\\e\\s\\x41\\\\ \\101\\\"\"")?;
+    assert!(matches!(atom(&vals[0]), Atom::String(s) if s == "a\tb c\x1b A\\ A\""));
+    assert_eq!(write(&ELISP_FORMAT, &vals)?, "\"a\\tb c\\033 A\\\\ A\\\"\"");
+    Ok(())
+}
+
+#[test]
+fn hash_syntax() -> Result<()> {
+    let vals = read(&ELISP_FORMAT,
+                    "[1 (2) x] #'car #s(hash-table data (a 1)) '(a . b) #@5 skip (c) \
+                     #1=(a . #1#) `(,x ,@y)")?;
+    assert!(matches!(&vals[0].0, VValue::List(Parenkind::Square, None, vs) if vs.len() == 3));
+    assert!(matches!(&vals[1].0, VValue::ReaderMacro(ReaderMacro::Function, _)));
+    assert!(matches!(&vals[2].0, VValue::ReaderMacro(ReaderMacro::Record, v)
+                     if matches!(&v.0, VValue::List(Parenkind::Round, None, vs)
+                                 if vs.len() == 3)));
+    // `#@5` skipped " skip"
+    assert_eq!(vals[4].to_string(), "(c)");
+    match &vals[5].0 {
+        VValue::Labeled(1, v) => match &v.0 {
+            VValue::List(_, Some(_), vs) =>
+                assert!(matches!(vs[1].0, VValue::LabelRef(1))),
+            v => panic!("not an improper list: {}", v)
+        }
+        v => panic!("not labeled: {}", v)
+    }
+    assert_eq!(write(&ELISP_FORMAT, &vals)?,
+               "[1 (2) x] #'car #s(hash-table data (a 1)) (quote (a . b)) (c) \
+                #1=(a . #1#) (quasiquote ((unquote x) (unquote-splicing y)))");
+    Ok(())
+}
+
+#[test]
+fn skip_to_end() -> Result<()> {
+    let vals = read(&ELISP_FORMAT, "a #@00 (b")?;
+    assert_eq!(vals.len(), 1);
+    // Counts beyond the end of the input
+    let vals = read(&ELISP_FORMAT, "a #@4294967290 (b")?;
+    assert_eq!(vals.len(), 1);
+    assert_eq!(read(&ELISP_FORMAT, "a #@18446744073709551615 (b").unwrap_err().to_string(),
+               "invalid '#' token @1.2");
+    Ok(())
+}
+
+#[test]
+fn errors() -> Result<()> {
+    let msgs: Vec<String> = ["?", "#s", "#1=", "#(a)"].iter().map(|s| {
+        read(&ELISP_FORMAT, s).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(msgs, vec![
+        "unexpected EOF reading character starting @1.0",
+        "invalid '#' token @1.0",
+        "missing expression after datum label @1.0",
+        "invalid '#' token @1.0",
+    ]);
+    Ok(())
+}
+
+#[test]
+fn unrepresentable() -> Result<()> {
+    let clojure = read(&CLOJURE_FORMAT, "@x #inst \"2020\"")?;
+    let gambit = read(&GAMBIT_FORMAT, "#!eof 3/4 ||")?;
+    let errs: Vec<String> = clojure.iter().chain(&gambit).map(|v| {
+        ELISP_FORMAT.to_string(v).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(errs, vec![
+        "not representable in Emacs Lisp: deref",
+        "not representable in Emacs Lisp: #inst tag",
+        "not representable in Emacs Lisp: #!eof",
+        "not representable in Emacs Lisp: 3/4",
+        "not representable in Emacs Lisp: symbol \"\"",
+    ]);
+    Ok(())
+}
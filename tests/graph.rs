@@ -0,0 +1,109 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{graph::{Graph, Node, NodeId},
+               read::ReadError,
+               settings::{COMMON_LISP_FORMAT, R7RS_FORMAT},
+               value::Atom};
+
+mod common;
+use common::read;
+
+fn read_graph(s: &str) -> Result<(Graph, Vec<NodeId>)> {
+    Ok(Graph::from_values(read(&R7RS_FORMAT, s)?)?)
+}
+
+fn items(g: &Graph, id: NodeId) -> &[NodeId] {
+    match g.node(id) {
+        Node::List(_, _, ids) => ids,
+        n => panic!("not a list: {:?}", n)
+    }
+}
+
+fn roundtrip(s: &str) -> Result<String> {
+    let (g, ids) = read_graph(s)?;
+    Ok(ids.iter().map(|id| g.to_value(*id).to_string()).collect::<Vec<_>>().join(" "))
+}
+
+#[test]
+fn cycles() -> Result<()> {
+    let (g, ids) = read_graph("#0=(a b . #0#)")?;
+    let l = items(&g, ids[0]);
+    assert_eq!(l.len(), 3);
+    assert_eq!(l[2], ids[0]);
+    assert_eq!(g.to_value(ids[0]).to_string(), "#0=(a b . #0#)");
+
+    let (g, ids) = read_graph("#5=(x #6=(y #5#) #6#)")?;
+    let l = items(&g, ids[0]);
+    assert_eq!(l[1], l[2]);
+    assert_eq!(items(&g, l[1])[1], ids[0]);
+    // Renumbered in order of appearance
+    assert_eq!(g.to_value(ids[0]).to_string(), "#0=(x #1=(y #0#) #1#)");
+    Ok(())
+}
+
+#[test]
+fn sharing() -> Result<()> {
+    let (g, ids) = read_graph("(#1=(a) #1# (a))")?;
+    let l = items(&g, ids[0]);
+    assert_eq!(l[0], l[1]);
+    assert_ne!(l[0], l[2]);
+    assert_eq!(g.to_value(l[0]).to_string(), g.to_value(l[2]).to_string());
+    assert_eq!(g.span(l[0]).start.col, 4);
+
+    // Several labels for the same value, and labels on atoms
+    let (g, ids) = read_graph("(#0=#1=\"s\" #1# #0#)")?;
+    let l = items(&g, ids[0]);
+    assert!(matches!(g.node(l[2]), Node::Atom(Atom::String(s)) if s == "s"));
+    assert!(l.iter().all(|id| *id == l[0]));
+
+    assert_eq!(roundtrip("(#1=(a) #1# (a)) (#0=#1=\"s\" #1# #0#) (a (b) . #7=(c))")?,
+               "(#0=(a) #0# (a)) (#0=\"s\" #0# #0#) (a (b) . (c))");
+    // Labels are scoped to the top-level datum
+    assert_eq!(roundtrip("#0=(a) #0=(b #0#)")?, "(a) #0=(b #0#)");
+    Ok(())
+}
+
+#[test]
+fn nested_forms() -> Result<()> {
+    let vals = read(&COMMON_LISP_FORMAT, "#1='#1# #2=#(1 #2#) (#+sbcl #3=x #3#)")?;
+    let (g, ids) = Graph::from_values(vals)?;
+    let written: Vec<String> = ids.iter()
+        .map(|id| COMMON_LISP_FORMAT.to_string(&g.to_value(*id)))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(written, ["#0=(|quote| #0#)", "#0=#(1 #0#)", "(#+SBCL #0=X #0#)"]);
+    Ok(())
+}
+
+#[test]
+fn errors() -> Result<()> {
+    let err = |s: &str| -> Result<String> {
+        let vals = read(&R7RS_FORMAT, s)?;
+        let mut g = Graph::new();
+        let mut e = None;
+        for v in vals {
+            let len = g.len();
+            if let Err(err) = g.add_value(v) {
+                // The graph is left unchanged
+                assert_eq!(g.len(), len);
+                e = Some(err);
+            }
+        }
+        let e = e.expect("error");
+        assert!(matches!(e.err, ReadError::UndefinedDatumLabel(_)
+                         | ReadError::DuplicateDatumLabel(_)));
+        Ok(e.to_string())
+    };
+    assert_eq!(err("(#0# #0=a)")?, "reference to undefined datum label #0# @1.1");
+    assert_eq!(err("#0=#0#")?, "reference to undefined datum label #0# @1.3");
+    assert_eq!(err("#0=(a #0=b)")?, "datum label #0= defined twice @1.6");
+    assert_eq!(err("(#0=a) #0#")?, "reference to undefined datum label #0# @1.7");
+    Ok(())
+}
@@ -0,0 +1,150 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{lazy::{write_lazy, LazyValue, Node},
+               number::{Integer, R5RSNumber},
+               settings::{CLOJURE_FORMAT, ELISP_FORMAT, GAMBIT_FORMAT},
+               tokens::TokenWriter,
+               value::{Atom, Parenkind, VValue, VValueWithPos}};
+use kstring::KString;
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// A result set generated on demand: `(rows (row i (i . i*i)) ...)`.
+enum Gen {
+    Rows(i64),
+    Row(i64),
+    Int(i64),
+    Sym(&'static str),
+    Pair(i64),
+    Tree(VValue),
+    Empty,
+}
+
+fn items<T>(items: impl Iterator<Item = T> + 'static) -> Box<dyn Iterator<Item = T>> {
+    Box::new(items)
+}
+
+impl LazyValue<KString> for Gen {
+    type Items = Box<dyn Iterator<Item = Gen>>;
+
+    fn node(self) -> Node<KString, Gen> {
+        match self {
+            Gen::Rows(n) => Node::List(
+                Parenkind::Round,
+                items(std::iter::once(Gen::Sym("rows")).chain((0..n).map(Gen::Row)))),
+            Gen::Row(i) => Node::List(
+                Parenkind::Square,
+                items([Gen::Sym("row"), Gen::Int(i), Gen::Pair(i)].into_iter())),
+            Gen::Int(i) => Node::Atom(Atom::Number(R5RSNumber::Integer(Integer::from(i)))),
+            Gen::Sym(s) => Node::Atom(Atom::Symbol(s.into())),
+            Gen::Pair(i) => Node::ImproperList(items(std::iter::once(Gen::Int(i))),
+                                               Gen::Int(i * i)),
+            Gen::Tree(v) => Node::Value(v),
+            Gen::Empty => Node::ImproperList(items(std::iter::empty()), Gen::Int(0)),
+        }
+    }
+}
+
+fn sym(s: &str) -> VValueWithPos {
+    VValueWithPos(VValue::Atom(Atom::Symbol(KString::from_ref(s))), Default::default())
+}
+
+#[test]
+fn write_generated() -> Result<()> {
+    let mut out = Vec::new();
+    GAMBIT_FORMAT.write_lazy(&mut out, Gen::Rows(3))?;
+    assert_eq!(String::from_utf8(out)?,
+               "(rows [row 0 (0 . 0)] [row 1 (1 . 1)] [row 2 (2 . 4)])\n");
+
+    // Several values, and trees in memory
+    let tree = VValue::List(Parenkind::Round, None,
+                            vec![sym("quote"), sym("x")]);
+    let mut writer = TokenWriter::new(&ELISP_FORMAT, Vec::new());
+    write_lazy(&mut writer, Gen::Row(2))?;
+    write_lazy(&mut writer, Gen::Tree(tree))?;
+    assert_eq!(String::from_utf8(writer.finish()?)?, "[row 2 (2 . 4)]\n(quote x)\n");
+    Ok(())
+}
+
+#[test]
+fn not_representable() -> Result<()> {
+    let mut out = Vec::new();
+    let err = CLOJURE_FORMAT.write_lazy(&mut out, Gen::Rows(3)).unwrap_err();
+    assert_eq!(err.to_string(), "not representable in Clojure: improper list");
+    assert_eq!(String::from_utf8(out)?, "(rows [row 0 (0");
+    let err = GAMBIT_FORMAT.write_lazy(&mut io::sink(), Gen::Empty).unwrap_err();
+    assert_eq!(err.to_string(), "not representable in Gambit: improper list without items");
+    Ok(())
+}
+
+/// Counts the bytes written.
+struct Counter {
+    written: usize,
+}
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Rows that track how many of them exist at the same time.
+struct Tracked {
+    i: Option<i64>,
+    alive: Rc<Cell<usize>>,
+    max_alive: Rc<Cell<usize>>,
+}
+
+impl Tracked {
+    fn new(i: Option<i64>, alive: &Rc<Cell<usize>>, max_alive: &Rc<Cell<usize>>) -> Self {
+        alive.set(alive.get() + 1);
+        max_alive.set(max_alive.get().max(alive.get()));
+        Tracked { i, alive: alive.clone(), max_alive: max_alive.clone() }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.alive.set(self.alive.get() - 1);
+    }
+}
+
+impl LazyValue<KString> for Tracked {
+    type Items = Box<dyn Iterator<Item = Tracked>>;
+
+    fn node(self) -> Node<KString, Tracked> {
+        match self.i {
+            None => {
+                let (alive, max_alive) = (self.alive.clone(), self.max_alive.clone());
+                Node::List(Parenkind::Round, items(
+                    (0..100_000).map(move |i| Tracked::new(Some(i), &alive, &max_alive))))
+            }
+            Some(i) => Node::Atom(Atom::Number(R5RSNumber::Integer(Integer::from(i)))),
+        }
+    }
+}
+
+#[test]
+fn bounded_memory() -> Result<()> {
+    let alive = Rc::new(Cell::new(0));
+    let max_alive = Rc::new(Cell::new(0));
+    let mut out = Counter { written: 0 };
+    GAMBIT_FORMAT.write_lazy(&mut out, Tracked::new(None, &alive, &max_alive))?;
+    assert_eq!(out.written, 588_892);
+    assert_eq!(alive.get(), 0);
+    assert!(max_alive.get() <= 2);
+    Ok(())
+}
@@ -0,0 +1,136 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               number::{R5RSNumber, Radix},
+               write::{WriteOptions, WithOptions},
+               settings::{R7RS_FORMAT, Modes},
+               value::{VValue, Atom}};
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    retain_whitespace: false,
+    retain_comments: false,
+    recover_from_errors: false,
+};
+
+fn read_one(s: &str) -> Result<VValue> {
+    Ok(R7RS_FORMAT.read(buffered_chars(s.as_bytes()), &MODES)?.unwrap().0)
+}
+
+fn real(s: &str) -> Result<f64> {
+    match read_one(s)? {
+        VValue::Atom(Atom::Number(R5RSNumber::Real(x))) => Ok(x),
+        v => panic!("not a real: {v:?}")
+    }
+}
+
+fn rewrite(s: &str) -> Result<String> {
+    Ok(read_one(s)?.to_string())
+}
+
+#[test]
+fn reals() -> Result<()> {
+    assert_eq!(real("1.5")?, 1.5);
+    assert_eq!(real("-1.5")?, -1.5);
+    assert_eq!(real("+.5")?, 0.5);
+    assert_eq!(real(".5")?, 0.5);
+    assert_eq!(real("1.")?, 1.0);
+    assert_eq!(real("1e10")?, 1e10);
+    assert_eq!(real("12.5e-3")?, 0.0125);
+    assert_eq!(real("+inf.0")?, f64::INFINITY);
+    assert_eq!(real("-inf.0")?, f64::NEG_INFINITY);
+    assert!(real("+nan.0")?.is_nan());
+    Ok(())
+}
+
+#[test]
+fn not_reals() -> Result<()> {
+    for s in ["1e", "1.5.", "e10", "..5", "-", "+", "...", "inf.0", "1.5x"] {
+        assert!(matches!(read_one(s)?, VValue::Atom(Atom::Symbol(ref n)) if n == s),
+                "{s}");
+    }
+    Ok(())
+}
+
+#[test]
+fn reals_roundtrip() -> Result<()> {
+    for (inp, out) in [("1.5", "1.5"), ("1.", "1.0"), (".5", "0.5"), ("-0.0", "-0.0"),
+                       ("1e10", "10000000000.0"), ("1e21", "1e21"),
+                       ("0.1", "0.1"), ("0.30000000000000004", "0.30000000000000004"),
+                       ("1e-7", "1e-7"), ("-inf.0", "-inf.0"), ("-nan.0", "+nan.0"),
+                       ("+5", "5")] {
+        assert_eq!(rewrite(inp)?, out);
+    }
+    Ok(())
+}
+
+#[test]
+fn complex() -> Result<()> {
+    for s in ["1+2i", "-i", "+i", "1-i", "3@1.57", "-2.5+inf.0i", "1/2-3/4i",
+              "+2i", "1e3-1.5i", "-3@-1"] {
+        let n = read_one(s)?;
+        assert!(matches!(n, VValue::Atom(Atom::Number(
+            R5RSNumber::Complex(_, _) | R5RSNumber::Polar(_, _)))), "{s}");
+        assert_eq!(n.to_string(), s.replace("1e3", "1000.0"));
+    }
+    assert_eq!(rewrite("0-i")?, "-i");
+    assert_eq!(rewrite("1+1i")?, "1+i");
+    for s in ["2i", "1+2", "1+2j", "i", "1@", "@1", "1+i+i"] {
+        assert!(matches!(read_one(s)?, VValue::Atom(Atom::Symbol(_))), "{s}");
+    }
+    Ok(())
+}
+
+#[test]
+fn prefixes() -> Result<()> {
+    for (inp, out) in [("#x1F", "31"), ("#b1010", "10"), ("#o17", "15"), ("#d10", "10"),
+                       ("#x-ff/a", "-51/2"), ("#e1.5", "3/2"), ("#e1e3", "1000"),
+                       ("#e.125e-1", "1/80"), ("#e1.0", "1"), ("#i1/4", "0.25"),
+                       ("#i3", "3.0"), ("#x#e1F", "31"), ("#e#x1F", "31"),
+                       ("#i#x10", "16.0"), ("#X#I10", "16.0"), ("#x1+fi", "1+15i"),
+                       ("#e1.5+.5i", "3/2+1/2i"), ("#i+i", "0.0+1.0i"),
+                       ("4/2", "2")] {
+        assert_eq!(rewrite(inp)?, out, "{inp}");
+    }
+    for s in ["#x1.5", "#b102", "#e#e1", "#x#d1", "#e+inf.0", "#e1e5000", "#z1", "#x"] {
+        assert!(read_one(s).is_err(), "{s}");
+    }
+    Ok(())
+}
+
+#[test]
+fn zero_denominators() -> Result<()> {
+    for s in ["0/0", "1/0", "-1/0", "#x0/0", "#e1/0", "#i1/0", "1+1/0i"] {
+        let e = read_one(s).unwrap_err();
+        assert_eq!(e.to_string(), "zero denominator in rational number @1.0", "{s}");
+    }
+    // Not number syntax otherwise, thus symbols
+    for s in ["1/0x", "1/0/2"] {
+        assert!(matches!(read_one(s)?, VValue::Atom(Atom::Symbol(ref n)) if n == s), "{s}");
+    }
+    assert_eq!(rewrite("0/5")?, "0");
+    Ok(())
+}
+
+#[test]
+fn write_radix() -> Result<()> {
+    let val = read_one("(255 -255 #x-FFFFFFFFFFFFFFFFFF 1/10 1.5 2+3i -7/2-i foo)")?;
+    let opts = WriteOptions { number_radix: Radix::Hexadecimal };
+    assert_eq!(WithOptions(&val, &opts).to_string(),
+               "(#xff #x-ff #x-ffffffffffffffffff #x1/a 1.5 #x2+3i #x-7/2-i foo)");
+    let opts = WriteOptions { number_radix: Radix::Binary };
+    let mut out = Vec::new();
+    R7RS_FORMAT.write(&mut out, &WithOptions(&val, &opts))?;
+    assert_eq!(String::from_utf8(out)?,
+               "(#b11111111 #b-11111111 #b-111111111111111111111111111111111111111111111111111111111111111111111111 \
+                #b1/1010 1.5 #b10+11i #b-111/10-i foo)");
+    Ok(())
+}
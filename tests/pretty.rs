@@ -0,0 +1,97 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{pretty::{Pretty, PrettyOptions},
+               settings::{AnysexprFormat, COMMON_LISP_FORMAT, ELISP_FORMAT, GAMBIT_FORMAT}};
+
+mod common;
+use common::read;
+
+fn pretty(format: &AnysexprFormat, width: usize, s: &str) -> Result<String> {
+    let vals = read(format, s)?;
+    let opts = PrettyOptions { width, ..PrettyOptions::new(format) };
+    let pretties: Vec<_> = vals.iter().map(|v| Pretty(v, &opts)).collect();
+    let mut out = Vec::new();
+    format.write_all(&mut out, &pretties)?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[test]
+fn fits_on_one_line() -> Result<()> {
+    assert_eq!(pretty(&GAMBIT_FORMAT, 80, "(define (f x)\n\n  (g x))  #(1 2)")?,
+               "(define (f x) (g x))\n\n#(1 2)\n");
+    // The closing parens count
+    assert_eq!(pretty(&GAMBIT_FORMAT, 11, "((1 2 3 4))")?, "((1 2 3 4))\n");
+    assert_eq!(pretty(&GAMBIT_FORMAT, 10, "((1 2 3 4))")?, "((1 2 3\n  4))\n");
+    Ok(())
+}
+
+#[test]
+fn special_forms() -> Result<()> {
+    assert_eq!(pretty(&GAMBIT_FORMAT, 30, "(define (fact n) (if (zero n) 1 (mul n (fact (sub n 1)))))")?,
+               "(define (fact n)
+  (if (zero n)
+      1
+      (mul n
+           (fact (sub n 1)))))
+");
+    assert_eq!(pretty(&GAMBIT_FORMAT, 30, "(let loop ((i 0)) (when (less i 10) (display i) (loop (add1 i))))")?,
+               "(let loop ((i 0))
+  (when (less i 10)
+    (display i)
+    (loop (add1 i))))
+");
+    assert_eq!(pretty(&COMMON_LISP_FORMAT, 30, "(defun foo (a b) (let ((x (add a b)) (y (mul a b))) (list x y)))")?,
+               "(DEFUN FOO (A B)
+  (LET ((X (ADD A B))
+        (Y (MUL A B)))
+    (LIST X Y)))
+");
+    assert_eq!(pretty(&ELISP_FORMAT, 30, "(if (gt a b) (message \"a: %s\" a) (message \"b\") (other))")?,
+               "(if (gt a b)
+    (message \"a: %s\" a)
+  (message \"b\")
+  (other))
+");
+    let opts = PrettyOptions { width: 20, indent: 4, special_forms: &[("lambda", 1)] };
+    let vals = read(&GAMBIT_FORMAT, "(lambda (x) (f x) (g x))")?;
+    assert_eq!(Pretty(&vals[0], &opts).to_string(), "(lambda (x)\n    (f x)\n    (g x))");
+    Ok(())
+}
+
+#[test]
+fn data() -> Result<()> {
+    assert_eq!(pretty(&GAMBIT_FORMAT, 16, "((alpha . 1) (beta . 2) (gamma 3 . 4))")?,
+               "((alpha . 1)
+ (beta . 2)
+ (gamma 3 . 4))
+");
+    assert_eq!(pretty(&GAMBIT_FORMAT, 16, "#(1 2 3 4 5 6 7 8 9 10 11 12 (13))")?,
+               "#(1
+  2
+  3
+  4
+  5
+  6
+  7
+  8
+  9
+  10
+  11
+  12
+  (13))
+");
+    assert_eq!(pretty(&GAMBIT_FORMAT, 16, "#(1 2 3 4 5 6 7 8 9 10 11 12 . 13)")?,
+               "#(1 2 3 4 5 6 7
+  8 9 10 11 12
+  . 13)
+");
+    Ok(())
+}
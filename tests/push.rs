@@ -0,0 +1,154 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::{parse, ParseError, Token},
+               push::{PushParser, PushReader},
+               read::ReadError,
+               settings::{GAMBIT_FORMAT, Modes, Settings}};
+
+const INPUT: &str = include_str!("t-input.scm");
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: true,
+    retain_comments: true,
+};
+
+const SETTINGS: Settings = Settings { format: &GAMBIT_FORMAT, modes: &MODES };
+
+#[test]
+fn tokens_same_as_parse() {
+    let expected: Vec<String> = parse(buffered_chars(INPUT.as_bytes()), &SETTINGS)
+        .map(|r| format!("{:?}", r)).collect();
+    for chunksize in [1, 2, 3, 7, 100, 4096] {
+        let mut p = PushParser::new(&SETTINGS);
+        let mut got = Vec::new();
+        for chunk in INPUT.as_bytes().chunks(chunksize) {
+            got.extend(p.feed(chunk).into_iter().map(|r| format!("{:?}", r)));
+        }
+        got.extend(p.finish().into_iter().map(|r| format!("{:?}", r)));
+        assert_eq!(got, expected, "chunk size {chunksize}");
+    }
+}
+
+#[test]
+fn long_tokens_in_small_chunks() {
+    // Tokenizing the buffered part of a token again on every chunk
+    // would take hours here
+    let n = 1_000_000;
+    let input = format!("(\"{}\" {} ;{}\n#|{}|# |{}| x)",
+                        "ab\\\"c".repeat(n / 5), "sym".repeat(n / 3),
+                        "c".repeat(n), "(|)#".repeat(n / 4), "y".repeat(n));
+    let expected: Vec<String> = parse(buffered_chars(input.as_bytes()), &SETTINGS)
+        .map(|r| format!("{:?}", r)).collect();
+    assert_eq!(expected.len(), 13);
+    let mut p = PushParser::new(&SETTINGS);
+    let mut got = Vec::new();
+    for chunk in input.as_bytes().chunks(7) {
+        got.extend(p.feed(chunk).into_iter().map(|r| format!("{:?}", r)));
+    }
+    got.extend(p.finish().into_iter().map(|r| format!("{:?}", r)));
+    assert!(got == expected);
+}
+
+#[test]
+fn values_same_as_read_all() -> Result<()> {
+    let expected: Vec<String> = GAMBIT_FORMAT.read_all(
+        buffered_chars(INPUT.as_bytes()), &MODES)?
+        .iter().map(|v| format!("{:?}", v)).collect();
+    for chunksize in [1, 5, 64] {
+        let mut r = PushReader::new(&SETTINGS);
+        let mut got = Vec::new();
+        for chunk in INPUT.as_bytes().chunks(chunksize) {
+            for v in r.feed(chunk) {
+                got.push(format!("{:?}", v?));
+            }
+        }
+        for v in r.finish() {
+            got.push(format!("{:?}", v?));
+        }
+        assert_eq!(got, expected, "chunk size {chunksize}");
+    }
+    Ok(())
+}
+
+#[test]
+fn partial_input() -> Result<()> {
+    let mut p = PushParser::new(&SETTINGS);
+    let ts: Vec<Token> = p.feed(b"(foo (ba").into_iter()
+        .map(|r| r.map(|t| t.0)).collect::<Result<_, _>>()?;
+    assert_eq!(ts.len(), 4); // ( foo <space> (
+    assert!(p.is_mid_token());
+    assert_eq!(p.depth(), 2);
+    // "ä" split between chunks
+    let ts = p.feed(b"r \xc3").into_iter()
+        .map(|r| r.map(|t| t.0.to_string())).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(ts, vec!["bar"]);
+    assert!(p.is_mid_token());
+    let ts = p.feed(b"\xa4))").into_iter()
+        .map(|r| r.map(|t| (t.0.to_string(), t.1.start.col)))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(ts, vec![(" ".into(), 9), ("|ä|".into(), 10), (")".into(), 11),
+                        (")".into(), 12)]);
+    assert!(!p.is_mid_token());
+    assert!(!p.is_mid_list());
+    assert!(p.finish().is_empty());
+
+    let mut r = PushReader::new(&SETTINGS);
+    assert!(r.feed(b"'(a #;b").is_empty());
+    assert!(r.is_mid_list());
+    let vs = r.feed(b" c)");
+    assert_eq!(vs.len(), 1);
+    assert_eq!(vs[0].as_ref().unwrap().to_string(), "(quote (a c))");
+    assert!(!r.is_mid_list());
+    assert!(!r.is_mid_value());
+    assert!(r.feed(b" #;x").is_empty());
+    assert!(r.is_mid_value());
+    assert!(r.feed(b" ").is_empty());
+    let vs = r.feed(b" 1 ");
+    assert_eq!(vs.len(), 1);
+    assert_eq!(vs[0].as_ref().unwrap().to_string(), "1");
+    assert!(!r.is_mid_value());
+    assert!(r.finish().is_empty());
+    Ok(())
+}
+
+#[test]
+fn errors() {
+    let mut p = PushParser::new(&SETTINGS);
+    assert!(p.feed(b"a \"b").len() == 2);
+    let rs = p.finish();
+    assert_eq!(rs.len(), 1);
+    let e = rs.into_iter().next().unwrap().unwrap_err();
+    assert!(matches!(e.err, ParseError::UnexpectedEOF(_)));
+    assert_eq!((e.span.start.col, e.span.end.col), (2, 4));
+
+    let mut p = PushParser::new(&SETTINGS);
+    let rs = p.feed(b"a \xff b");
+    assert_eq!(rs.len(), 2);
+    assert!(matches!(rs[1], Err(ref e) if matches!(e.err, ParseError::IOError(_))));
+    assert!(p.feed(b"c").is_empty());
+
+    let mut r = PushReader::new(&SETTINGS);
+    assert_eq!(r.feed(b"(a ").len(), 0);
+    let rs = r.feed(b"]) b");
+    assert_eq!(rs.len(), 1);
+    let e = rs.into_iter().next().unwrap().unwrap_err();
+    assert!(matches!(e.err, ReadError::ParenMismatch(..)));
+    assert!(r.feed(b" c ").is_empty());
+
+    let mut r = PushReader::new(&SETTINGS);
+    assert_eq!(r.feed(b"(a").len(), 0);
+    let rs = r.finish();
+    assert!(matches!(&rs[..], [Err(e)]
+                     if matches!(e.err, ReadError::PrematureEofExpectingClosingParen(_))));
+}
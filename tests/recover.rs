@@ -0,0 +1,102 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::parse,
+               settings::{AnysexprFormat, GAMBIT_FORMAT, R7RS_FORMAT, WAT_FORMAT, Modes,
+                          Settings},
+               value::{Atom, VValue}};
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    retain_whitespace: false,
+    retain_comments: false,
+    recover_from_errors: false,
+};
+
+fn read_recovering(s: &str) -> (String, Vec<String>) {
+    let (vals, errs) = GAMBIT_FORMAT.read_all_recovering(
+        buffered_chars(s.as_bytes()), &MODES);
+    (vals.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" "),
+     errs.iter().map(|e| e.to_string()).collect())
+}
+
+#[test]
+fn all_errors_reported() {
+    let (vals, errs) = read_recovering(
+        "(a #\\foo b)\n(c \"x\\qy\" ]\n#!bar d)\n(e . f g) 'h");
+    assert_eq!(vals, "(a b) (c \"x\u{FFFD}y\" d) (e f g) (quote h)");
+    assert_eq!(errs, vec![
+        "invalid '#' token @1.3",
+        "invalid escaped character 'q' @2.3",
+        "'(' @2.0 expects ')', got ']' @2.10",
+        "invalid '#!' name \"bar\" @3.0",
+        "expecting exactly one item after '.' @4.7",
+    ]);
+}
+
+#[test]
+fn unclosed_lists_at_eof() {
+    let (vals, errs) = read_recovering("(a [b \"c");
+    assert_eq!(vals, "(a [b])");
+    assert_eq!(errs, vec![
+        "unexpected EOF reading string/symbol/keyword starting @1.6",
+        "premature EOF while expecting closing character ']' for '[' @1.3",
+        "premature EOF while expecting closing character ')' for '(' @1.0",
+    ]);
+}
+
+#[test]
+fn no_errors() {
+    let (vals, errs) = read_recovering("(a . (b . c)) #;(x) y");
+    assert_eq!(vals, "(a b . c) y");
+    assert!(errs.is_empty());
+}
+
+#[test]
+fn parse_continues() {
+    let modes = Modes { recover_from_errors: true, ..MODES };
+    let settings = Settings { format: &R7RS_FORMAT, modes: &modes };
+    let results: Vec<String> =
+        parse(buffered_chars("\"\\x41;\" \"\\xZZ;\" #tru x".as_bytes()), &settings)
+        .map(|r| match r {
+            Ok(t) => t.0.to_string(),
+            Err(e) => format!("error: {}", e)
+        })
+        .collect();
+    assert_eq!(results, vec![
+        "\"A\"",
+        "error: not a hex digit: 'Z' @1.11",
+        "\"\u{FFFD}\"",
+        "error: invalid '#' token @1.16",
+        "x",
+    ]);
+}
+
+#[test]
+fn malformed_escapes_skipped() {
+    // The strings read, and the errors
+    let strings = |format: &AnysexprFormat, s: &str| -> (Vec<String>, Vec<String>) {
+        let (vals, errs) = format.read_all_recovering(buffered_chars(s.as_bytes()), &MODES);
+        (vals.iter().map(|v| match &v.0 {
+            VValue::Atom(Atom::String(s)) => s.to_string(),
+            v => panic!("not a string: {v}")
+        }).collect(),
+         errs.iter().map(|e| e.to_string()).collect())
+    };
+    assert_eq!(strings(&GAMBIT_FORMAT, r#""a\u12G4b" "c\u12" "d\U0011FFFFe""#),
+               (vec!["a\u{FFFD}b".into(), "c\u{FFFD}".into(), "d\u{FFFD}e".into()],
+                vec!["not a hex digit: 'G' @1.6".into(),
+                     "not a hex digit: '\"' @1.17".into(),
+                     "invalid code point 1179647 @1.22".into()]));
+    assert_eq!(strings(&R7RS_FORMAT, r##""a\x12G4;b" "c\x123456789;d" "e\x4""##).0,
+               vec!["a\u{FFFD}b", "c\u{FFFD}d", "e\u{FFFD}"]);
+    assert_eq!(strings(&WAT_FORMAT, r#""a\u{ZZ}b" "c\4Zd""#).0,
+               vec!["a\u{FFFD}b", "c\u{FFFD}d"]);
+}
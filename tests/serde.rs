@@ -0,0 +1,116 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(feature = "serde")]
+
+use anyhow::Result;
+use anysexpr::{serde::{from_file, from_str, to_string, NoneStyle, SerdeOptions, StructStyle},
+               settings::{CLOJURE_FORMAT, GAMBIT_FORMAT, SEXPLIB_FORMAT}};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Mode {
+    Fast,
+    Limit(u32),
+    Range(i8, i8),
+    Named { level: u8 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Config {
+    name: String,
+    port: u16,
+    ratio: f64,
+    verbose: bool,
+    tags: Vec<String>,
+    parent: Option<Box<Config>>,
+    modes: Vec<Mode>,
+}
+
+fn config() -> Config {
+    Config {
+        name: "main".into(),
+        port: 8080,
+        ratio: 0.5,
+        verbose: true,
+        tags: vec!["a".into(), "b c".into()],
+        parent: None,
+        modes: vec![Mode::Fast, Mode::Limit(3), Mode::Range(-1, 1),
+                    Mode::Named { level: 2 }],
+    }
+}
+
+#[test]
+fn alist_round_trip() -> Result<()> {
+    let opts = SerdeOptions::new(&GAMBIT_FORMAT);
+    let s = to_string(&config(), &opts)?;
+    assert_eq!(s, r#"((name "main") (port 8080) (ratio 0.5) (verbose #t) (tags ("a" "b c")) (parent #f) (modes (Fast (Limit 3) (Range -1 1) (Named (level 2)))))"#);
+    let c: Config = from_str(&s, &opts)?;
+    assert_eq!(c, config());
+    Ok(())
+}
+
+#[test]
+fn plist_with_keywords() -> Result<()> {
+    let c = Config { parent: Some(Box::new(config())), ..config() };
+    let gambit = SerdeOptions {
+        struct_style: StructStyle::Plist,
+        none_style: NoneStyle::EmptyList,
+        ..SerdeOptions::new(&GAMBIT_FORMAT)
+    };
+    let s = to_string(&Mode::Named { level: 2 }, &gambit)?;
+    assert_eq!(s, "(Named level: 2)");
+    let s = to_string(&c, &gambit)?;
+    assert_eq!(from_str::<Config>(&s, &gambit)?, c);
+    let clojure = SerdeOptions { format: &CLOJURE_FORMAT, ..gambit };
+    assert_eq!(to_string(&Mode::Named { level: 2 }, &clojure)?, "(Named :level 2)");
+    let sexplib = SerdeOptions { format: &SEXPLIB_FORMAT, ..gambit };
+    assert_eq!(to_string(&Mode::Named { level: 2 }, &sexplib)?, "(Named level 2)");
+    Ok(())
+}
+
+#[test]
+fn maps_and_options() -> Result<()> {
+    let opts = SerdeOptions::new(&GAMBIT_FORMAT);
+    let mut m = BTreeMap::new();
+    m.insert(1, vec![Some('x'), None]);
+    m.insert(2, vec![]);
+    let s = to_string(&m, &opts)?;
+    assert_eq!(s, r"((1 (#\x #f)) (2 ()))");
+    assert_eq!(from_str::<BTreeMap<i32, Vec<Option<char>>>>(&s, &opts)?, m);
+    assert_eq!(from_str::<Vec<Option<u8>>>("(1 #f ())", &opts)?, vec![Some(1), None, None]);
+    assert_eq!(from_str::<(u64, i128)>("(18446744073709551615 -170141183460469231731687303715884105728)",
+                                       &opts)?,
+               (u64::MAX, i128::MIN));
+    Ok(())
+}
+
+#[test]
+fn errors_with_positions() -> Result<()> {
+    let opts = SerdeOptions::new(&GAMBIT_FORMAT);
+    let err = |s: &str| from_str::<Config>(s, &opts).unwrap_err().to_string();
+    assert_eq!(err("((name \"x\")\n (port \"80\"))"), "expecting an integer, got: \"80\" @2.7");
+    assert_eq!(err("((name \"x\")\n (port 80))"), "missing field `ratio` @1.0");
+    assert_eq!(err("((name \"x\") (port 70000))"),
+               "invalid value: integer `70000`, expected u16 @1.18");
+    assert_eq!(err("((modes ((Limit 1 2))))"), "too many items in list @1.18");
+    assert_eq!(err("((modes (Slow)))"),
+               "unknown variant `Slow`, expected one of `Fast`, `Limit`, `Range`, `Named` @1.9");
+    assert_eq!(err("((name \"x\") (port 80)"),
+               "premature EOF while expecting closing character ')' for '(' @1.0");
+    assert_eq!(err("() ()"), "expecting a single value, got more @1.3");
+
+    let path = std::env::temp_dir().join("anysexpr-serde-test.scm");
+    std::fs::write(&path, "((name \"x\")\n (port #t))")?;
+    let e = from_file::<Config>(&path, &opts).unwrap_err().to_string();
+    std::fs::remove_file(&path)?;
+    assert_eq!(e, format!("expecting an integer, got: #t in {:?}@2.7", path));
+    Ok(())
+}
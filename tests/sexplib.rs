@@ -0,0 +1,107 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::{parse, Token},
+               sexplib,
+               settings::{CLOJURE_FORMAT, COMMON_LISP_FORMAT, GAMBIT_FORMAT,
+                          SEXPLIB_FORMAT, Settings},
+               value::{Atom, VValue}};
+
+mod common;
+use common::{MODES, read, write};
+
+#[test]
+fn only_string_atoms() -> Result<()> {
+    let input = "(library (name foo) (flags (:standard -w +a-4)) (deps a.b 'x [c] #t 1.5 nil))";
+    let settings = Settings { format: &SEXPLIB_FORMAT, modes: &MODES };
+    let mut atoms = Vec::new();
+    for t in parse(buffered_chars(input.as_bytes()), &settings) {
+        match t?.0 {
+            Token::Atom(Atom::String(s)) => atoms.push(s.to_string()),
+            Token::Open(_) | Token::Close(_) => (),
+            t => panic!("unexpected token: {}", t)
+        }
+    }
+    assert_eq!(atoms, vec!["library", "name", "foo", "flags", ":standard", "-w", "+a-4",
+                           "deps", "a.b", "'x", "[c]", "#t", "1.5", "nil"]);
+    let vals = read(&SEXPLIB_FORMAT, input)?;
+    assert_eq!(write(&SEXPLIB_FORMAT, &vals)?, input);
+    Ok(())
+}
+
+#[test]
+fn quoted_atoms() -> Result<()> {
+    let vals = read(&SEXPLIB_FORMAT,
+                    r#"("a b" "\065\x42\n\t\"\\\q" "line \
+                     continued" "" "ä" a"b" "\1x" x#y)"#)?;
+    let atoms: Vec<String> = match &vals[0].0 {
+        VValue::List(_, _, vs) => vs.iter().map(|v| match &v.0 {
+            VValue::Atom(Atom::String(s)) => s.to_string(),
+            v => panic!("not a string: {}", v)
+        }).collect(),
+        v => panic!("not a list: {}", v)
+    };
+    assert_eq!(atoms, vec!["a b", "AB\n\t\"\\\\q", "line continued", "", "ä", "a", "b",
+                           "\\1x", "x#y"]);
+    assert_eq!(write(&SEXPLIB_FORMAT, &vals)?,
+               r#"("a b" "AB\n\t\"\\\\q" "line continued" "" "ä" a b "\\1x" x#y)"#);
+    let e = read(&SEXPLIB_FORMAT, "\"\\300\"").unwrap_err();
+    assert_eq!(e.to_string(), "invalid code point 300 @1.2");
+    Ok(())
+}
+
+#[test]
+fn comments() -> Result<()> {
+    let vals = read(&SEXPLIB_FORMAT,
+                    "; line\n(a #| outer #| inner |# still comment |# b #;(c d) e) #;x # #a")?;
+    assert_eq!(write(&SEXPLIB_FORMAT, &vals)?, "(a b e) # #a");
+    assert!(read(&SEXPLIB_FORMAT, "a #| #| |#").is_err());
+    // The same nesting in Common Lisp
+    let vals = read(&COMMON_LISP_FORMAT, "a #| #| |# |# b")?;
+    assert_eq!(vals.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn no_dotted_pairs() -> Result<()> {
+    let vals = read(&SEXPLIB_FORMAT, "(a . b)")?;
+    assert_eq!(write(&SEXPLIB_FORMAT, &vals)?, "(a . b)");
+    Ok(())
+}
+
+#[test]
+fn quoting_when_needed() -> Result<()> {
+    let atoms = ["foo", "", "a b", "#|", "a|#", "|", "#", "x;y", "(", "\u{7f}", "\u{1}",
+                 "é"];
+    let written: Vec<bool> = atoms.iter().map(|s| sexplib::must_quote(s)).collect();
+    assert_eq!(written, vec![false, true, true, true, true, false, false, true, true, true,
+                             true, true]);
+    let v = VValue::<&str>::Atom(Atom::String("\u{1}\u{7f}"));
+    assert_eq!(SEXPLIB_FORMAT.to_string(&v)?, "\"\\001\\127\"");
+    Ok(())
+}
+
+#[test]
+fn unrepresentable() -> Result<()> {
+    let gambit = read(&GAMBIT_FORMAT, "(a 1 \"s\" 3/4) (1 . 2) #t")?;
+    assert_eq!(SEXPLIB_FORMAT.to_string(&gambit[0])?, "(a 1 s 3/4)");
+    let clojure = read(&CLOJURE_FORMAT, "[a] :k")?;
+    let errs: Vec<String> = gambit[1..].iter().chain(&clojure).map(|v| {
+        SEXPLIB_FORMAT.to_string(v).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(errs, vec![
+        "not representable in Sexplib: improper list",
+        "not representable in Sexplib: #t",
+        "not representable in Sexplib: [..] list",
+        "not representable in Sexplib: :k",
+    ]);
+    Ok(())
+}
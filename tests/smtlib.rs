@@ -0,0 +1,107 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{settings::{GAMBIT_FORMAT, SMTLIB_FORMAT},
+               value::Atom};
+
+mod common;
+use common::{atom, items, read, write};
+
+const SCRIPT: &str = "\
+(set-option :produce-models true)
+(set-logic QF_BV)
+(declare-const |x y| (_ BitVec 8))
+(declare-const s String)
+(assert (! (= (bvadd |x y| #x0F) #b00010000) :named a1))
+(assert (= s \"say \"\"hi\"\" \\n\"))
+(assert (> 1.50 0.0 |1st| - -5))
+(check-sat)
+";
+
+#[test]
+fn script_roundtrip() -> Result<()> {
+    let vals = read(&SMTLIB_FORMAT, SCRIPT)?;
+    assert_eq!(write(&SMTLIB_FORMAT, &vals)?, SCRIPT.trim_end().replace('\n', " "));
+
+    let assert_ = items(&items(&vals[4])[1]);
+    assert!(matches!(atom(&assert_[0]), Atom::Symbol(s) if s == "!"));
+    assert!(matches!(atom(&assert_[2]), Atom::Keyword1(s) if s == "named"));
+    let eq = items(&assert_[1]);
+    let bvadd = items(&eq[1]);
+    assert!(matches!(atom(&bvadd[1]), Atom::Symbol(s) if s == "x y"));
+    assert!(matches!(atom(&bvadd[2]), Atom::Hexadecimal(s) if s == "0F"));
+    assert!(matches!(atom(&eq[2]), Atom::BitVector(bits) if bits.len() == 8 && bits[3]));
+
+    // No escapes other than `""`
+    let s = &items(&items(&vals[5])[1])[2];
+    assert!(matches!(atom(s), Atom::String(s) if s == "say \"hi\" \\n"));
+
+    let gt = items(&items(&vals[6])[1]);
+    assert!(matches!(atom(&gt[1]), Atom::Decimal(s) if s == "1.50"));
+    assert!(matches!(atom(&gt[2]), Atom::Decimal(s) if s == "0.0"));
+    assert!(matches!(atom(&gt[3]), Atom::Symbol(s) if s == "1st"));
+    assert!(matches!(atom(&gt[5]), Atom::Symbol(s) if s == "-5"));
+    Ok(())
+}
+
+#[test]
+fn model_roundtrip() -> Result<()> {
+    let model = "\
+sat
+(
+  (define-fun x () (_ BitVec 8) #x01)
+  (define-fun r () Real (/ 1.0 3.0))
+  (define-fun n () Int (- 12345678901234567890))
+)
+";
+    let vals = read(&SMTLIB_FORMAT, model)?;
+    assert_eq!(vals.len(), 2);
+    assert_eq!(write(&SMTLIB_FORMAT, &vals)?,
+               "sat ((define-fun x () (_ BitVec 8) #x01) \
+                (define-fun r () Real (/ 1.0 3.0)) \
+                (define-fun n () Int (- 12345678901234567890)))");
+    Ok(())
+}
+
+#[test]
+fn errors() -> Result<()> {
+    let inputs = ["#b012", "#x", "#xg", "(a . b", "\"abc", "|a", "1x", "1+", "01"];
+    let msgs: Vec<String> = inputs.iter().map(|s| {
+        read(&SMTLIB_FORMAT, s).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(msgs, vec![
+        "invalid bit vector @1.0",
+        "invalid bit vector @1.0",
+        "invalid bit vector @1.0",
+        "premature EOF while expecting closing character ')' for '(' @1.0",
+        "unexpected EOF reading string/symbol/keyword starting @1.0",
+        "unexpected EOF reading string/symbol/keyword starting @1.0",
+        "symbol starting with a digit not supported by the format @1.0",
+        "symbol starting with a digit not supported by the format @1.0",
+        "symbol starting with a digit not supported by the format @1.0",
+    ]);
+    Ok(())
+}
+
+#[test]
+fn unrepresentable() -> Result<()> {
+    let gambit = read(&GAMBIT_FORMAT, "|a\\|b| -1 1.5 #\\a #!eof")?;
+    let errs: Vec<String> = gambit.iter().map(|v| {
+        SMTLIB_FORMAT.to_string(v).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(errs, vec![
+        "not representable in SMT-LIB: symbol \"a|b\"",
+        "not representable in SMT-LIB: -1",
+        "not representable in SMT-LIB: 1.5",
+        "not representable in SMT-LIB: #\\a",
+        "not representable in SMT-LIB: #!eof",
+    ]);
+    Ok(())
+}
@@ -0,0 +1,89 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::parse,
+               pos::Span,
+               settings::{GAMBIT_FORMAT, Modes, Settings},
+               value::{VValue, VValueWithPos}};
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    retain_whitespace: false,
+    retain_comments: false,
+    recover_from_errors: false,
+};
+
+// The source text covered by `span`, cut by byte and by char offsets
+// (which must agree).
+fn text(s: &str, span: Span) -> &str {
+    let t = &s[span.start.byte_offset..span.end.byte_offset];
+    let c: String = s.chars().skip(span.start.char_offset)
+        .take(span.end.char_offset - span.start.char_offset).collect();
+    assert_eq!(t, c);
+    t
+}
+
+#[test]
+fn token_spans() -> Result<()> {
+    let s = "(bär \"ö\\x41;\"\n  #\\a) ,@x 'y #t ;c\n12";
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &MODES };
+    let mut tokens = Vec::new();
+    for t in parse(buffered_chars(s.as_bytes()), &settings) {
+        let t = t?;
+        tokens.push((text(s, t.1), t.1.start.line, t.1.start.col, t.1.end.col));
+    }
+    assert_eq!(tokens, vec![
+        ("(", 0, 0, 1),
+        ("bär", 0, 1, 4),
+        ("\"ö\\x41;\"", 0, 5, 13),
+        ("#\\a", 1, 2, 5),
+        (")", 1, 5, 6),
+        (",@", 1, 7, 9),
+        ("x", 1, 9, 10),
+        ("'", 1, 11, 12),
+        ("y", 1, 12, 13),
+        ("#t", 1, 14, 16),
+        ("12", 2, 0, 2),
+    ]);
+    Ok(())
+}
+
+#[test]
+fn value_spans() -> Result<()> {
+    let s = "(a . [b]) 'ü\n#;(x) \"s\"";
+    let vals = GAMBIT_FORMAT.read_all(buffered_chars(s.as_bytes()), &MODES)?;
+    let texts: Vec<&str> = vals.iter().map(|v| text(s, v.1)).collect();
+    assert_eq!(texts, vec!["(a . [b])", "'ü", "\"s\""]);
+    if let VValueWithPos(VValue::List(_, Some(dot), items), _) = &vals[0] {
+        assert_eq!(text(s, **dot), ".");
+        let texts: Vec<&str> = items.iter().map(|v| text(s, v.1)).collect();
+        assert_eq!(texts, vec!["a", "[b]"]);
+    } else {
+        panic!("not an improper list: {}", vals[0]);
+    }
+    assert_eq!(vals[2].1.start.line, 1);
+    Ok(())
+}
+
+#[test]
+fn error_spans() {
+    let check = |s: &str, expected: &str| {
+        let e = GAMBIT_FORMAT.read_all(buffered_chars(s.as_bytes()), &MODES)
+            .unwrap_err();
+        assert_eq!(text(s, e.span), expected, "{e}");
+    };
+    check("(a #\\foo b)", "#\\foo");
+    check("x #!bar", "#!bar");
+    check("(a\n  \"b\\q\")", "\"b\\q");
+    check("(a (b]", "]");
+    check("(a . b c)", "c");
+    check("(\"abc", "\"abc");
+}
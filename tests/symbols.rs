@@ -0,0 +1,122 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use std::path::Path;
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::{parse, Token},
+               settings::{GAMBIT_FORMAT, Modes, Settings},
+               symbol::{IStr, InterningBuilder, Symbol, SymbolTable},
+               value::{Atom, VValue, VValueWithPos}};
+
+const MODES: Modes = Modes {
+    allow_improper_lists: true,
+    recover_from_errors: false,
+    retain_whitespace: false,
+    retain_comments: false,
+};
+
+fn items(v: &VValueWithPos<IStr>) -> &[VValueWithPos<IStr>] {
+    match &v.0 {
+        VValue::List(_, _, items) => items,
+        _ => panic!("not a list: {}", v)
+    }
+}
+
+fn sym(v: &VValueWithPos<IStr>) -> &Symbol {
+    match &v.0 {
+        VValue::Atom(Atom::Symbol(s) | Atom::Keyword1(s) | Atom::Keyword2(s)) =>
+            s.symbol().expect("interned"),
+        _ => panic!("not a symbol: {}", v)
+    }
+}
+
+#[test]
+fn shared_between_files() -> Result<()> {
+    let path = std::env::temp_dir().join(
+        format!("anysexpr-symbols-{}.scm", std::process::id()));
+    std::fs::write(&path, "(define (f x) 'x) a")?;
+    let table = SymbolTable::new();
+    let mut builder = InterningBuilder::new(&table);
+    let vals1 = GAMBIT_FORMAT.read_file_with(
+        &mut builder, Path::new("tests/t-input.scm"), &MODES)?;
+    let n = table.len();
+    let new_names = ["define", "f", "x", "quote"].iter()
+        .filter(|name| table.get(name).is_none()).count();
+    let a = table.get("a").expect("in t-input.scm");
+    let vals2 = GAMBIT_FORMAT.read_file_with(&mut builder, &path, &MODES)?;
+    std::fs::remove_file(&path)?;
+
+    // Written out the same as without interning
+    let plain = GAMBIT_FORMAT.read_all(
+        buffered_chars(include_bytes!("t-input.scm").as_slice()), &MODES)?;
+    assert_eq!(vals1.len(), plain.len());
+    for (v, p) in vals1.iter().zip(&plain) {
+        assert_eq!(v.to_string(), p.to_string());
+    }
+
+    assert_eq!(table.len(), n + new_names);
+    assert_eq!(sym(&vals2[1]), &a);
+    let define = table.get("define").expect("interned");
+    let items2 = items(&vals2[0]);
+    assert_eq!(sym(&items2[0]), &define);
+    assert_eq!(sym(&items2[0]).id(), define.id());
+    let x = sym(&items(&items2[1])[1]);
+    let quoted = items(&items2[2]);
+    assert_eq!(sym(&quoted[0]), &table.intern("quote"));
+    assert_eq!(sym(&quoted[1]), x);
+    assert_eq!(table.symbol(x.id()).as_ref(), Some(x));
+    assert_eq!(&**x, "x");
+    Ok(())
+}
+
+#[test]
+fn what_is_interned() -> Result<()> {
+    let table = SymbolTable::new();
+    let vals = GAMBIT_FORMAT.read_all_with(
+        &mut InterningBuilder::new(&table),
+        buffered_chars("(a \"a\" #:a a: |a|)".as_bytes()),
+        &MODES)?;
+    let kinds: Vec<(String, bool)> = items(&vals[0]).iter().map(|v| match &v.0 {
+        VValue::Atom(a) => (format!("{:?}", a.clone().map_str(|_| ())),
+                            matches!(a, Atom::Symbol(IStr::Sym(_))
+                                     | Atom::Keyword2(IStr::Sym(_)))),
+        _ => panic!()
+    }).collect();
+    assert_eq!(kinds, vec![
+        ("Symbol(())".into(), true),
+        ("String(())".into(), false),
+        ("UninternedSymbol(())".into(), false),
+        ("Keyword2(())".into(), true),
+        ("Symbol(())".into(), true),
+    ]);
+    // `a`, `a:` and `|a|` are all the same name
+    assert_eq!(table.len(), 1);
+
+    let other = SymbolTable::new();
+    assert_ne!(other.intern("a"), table.intern("a"));
+    Ok(())
+}
+
+#[test]
+fn interning_tokens() -> Result<()> {
+    let table = SymbolTable::new();
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &MODES };
+    let mut syms = Vec::new();
+    for t in parse(buffered_chars("(foo bar foo)".as_bytes()), &settings) {
+        if let Token::Atom(a) = t?.0 {
+            if let Atom::Symbol(IStr::Sym(s)) = table.intern_atom(a) {
+                syms.push(s);
+            }
+        }
+    }
+    assert_eq!(syms.iter().map(|s| s.id().0).collect::<Vec<_>>(), vec![0, 1, 0]);
+    assert_eq!(syms[0], syms[2]);
+    Ok(())
+}
@@ -1,9 +1,9 @@
 (line 2)
 (list (symbol 97) (symbol 98) (symbol 99))
 (line 3)
-(|improper-list| (number 1) (number 2) (number 3) (number 4))
+(improper-list (number 1) (number 2) (number 3) (number 4))
 (line 4)
-(|improper-list| (string 97) (string 98))
+(improper-list (string 97) (string 98))
 (line 5)
 (list (string 97) (symbol 46 98))
 (line 11)
@@ -11,9 +11,9 @@
 (line 12)
 (list (symbol 113 117 111 116 101) (list (symbol 97) (list (symbol 113 117 111 116 101) (symbol 98)) (symbol 99)))
 (line 13)
-(list (symbol 113 117 111 116 101) (|improper-list| (symbol 97) (list (symbol 113 117 111 116 101) (symbol 98)) (symbol 99)))
+(list (symbol 113 117 111 116 101) (improper-list (symbol 97) (list (symbol 113 117 111 116 101) (symbol 98)) (symbol 99)))
 (line 15)
-(list (symbol 97) (list (symbol 113 117 111 116 101) (|improper-list| (symbol 98) (symbol 99))))
+(list (symbol 97) (list (symbol 113 117 111 116 101) (improper-list (symbol 98) (symbol 99))))
 (line 16)
 (list (symbol 97) (list (symbol 113 117 111 116 101) (symbol 98)) (symbol 113 117 111 116 101) (symbol 99))
 (line 17)
@@ -21,11 +21,11 @@
 (line 18)
 (list (symbol 97) (symbol 98) (symbol 99) (symbol 100))
 (line 19)
-(|improper-list| (symbol 97) (symbol 98) (symbol 99) (symbol 100))
+(improper-list (symbol 97) (symbol 98) (symbol 99) (symbol 100))
 (line 20)
 (list (symbol 97) (symbol 98) (symbol 99) (symbol 100) (symbol 101) (symbol 102))
 (line 21)
-(|improper-list| (symbol 97) (symbol 98) (symbol 99) (symbol 100) (symbol 101) (symbol 102))
+(improper-list (symbol 97) (symbol 98) (symbol 99) (symbol 100) (symbol 101) (symbol 102))
 (line 23)
 (symbol 110 111 116 99 111 109 109 101 110 116 101 100)
 (line 25)
@@ -63,11 +63,11 @@
 (line 54)
 (symbol 246)
 (line 56)
-(|uninterned-symbol| 102 111 111)
+(uninterned-symbol 102 111 111)
 (line 56)
-(|uninterned-symbol| 32 98 97 114 32 98 97 122)
+(uninterned-symbol 32 98 97 114 32 98 97 122)
 (line 56)
-(|uninterned-symbol| 10 98 105 122 97 114 114)
+(uninterned-symbol 10 98 105 122 97 114 114)
 (line 59)
 (list (special 118 111 105 100) (special 101 111 102) (special 111 112 116 105 111 110 97 108) (special 114 101 115 116) (special 107 101 121))
 (line 62)
@@ -75,18 +75,18 @@ true
 (line 62)
 false
 (line 65)
-(|improper-list| false true false)
+(improper-list false true false)
 (line 66)
 (list false true (symbol 46 35 102))
 (line 68)
-(|integer->char| 97)
+(integer->char 97)
 (line 69)
-(list (|integer->char| 10))
+(list (integer->char 10))
 (line 71)
-(|integer->char| 4660)
+(integer->char 4660)
 (line 73)
-(|integer->char| 4660)
+(integer->char 4660)
 (line 75)
-(|integer->char| 4660)
+(integer->char 4660)
 (line 83)
 (list (symbol 99 118) (list (symbol 101 110 116 114 121) (keyword2 119 104 101 110) (string 50 48 49 54 8211 50 48 49 56) (keyword2 116 105 116 108 101) (string 32 83 111 102 116 119 97 114 101 10 9 68 101 118 101 108 111 112 101 114 32) (keyword2 98 111 100 121) (string 10 42 32 83 111 109 101 116 104 105 110 103 32 99 111 109 112 108 101 116 101 108 121 32 100 105 102 102 101 114 101 110 116 10)) (list (symbol 101 110 116 114 121) (keyword2 119 104 101 110) (string 50 48 49 48 8211 50 48 49 54) (keyword2 116 105 116 108 101) (string 80 105 110 107 32 80 97 110 116 104 101 114 32 105 115 32 118 101 114 121 32 102 105 116 116 105 110 103) (keyword2 116 105 116 108 101 50) (string 80 105 110 107 32 80 97 110 116 104 101 114 32 105 115 32 118 101 114 121 32 102 105 116 116 105 110 103) (keyword2 116 105 116 108 101 51) (string 80 105 110 107 32 80 97 110 116 104 101 114 32 105 115 32 118 101 114 121 32 102 105 116 116 105 110 103) (keyword2 98 111 100 121) (string 10 42 32 73 109 112 108 101 109 101 110 116 101 100 32 98 97 122 97 97 114 10 42 32 68 105 100 32 118 111 100 111 111 10)))
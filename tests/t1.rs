@@ -10,7 +10,9 @@
 use anyhow::Result;
 use std::io::Write;
 use std::str;
-use anysexpr::{buffered_chars::buffered_chars, settings::{GAMBIT_FORMAT, Modes}};
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::parse,
+               settings::{GAMBIT_FORMAT, Modes, Settings}};
 
 const INPUT: &[u8] = include_bytes!("t-input.scm");
 const WRITE: &[u8] = include_bytes!("t-write.scm");
@@ -18,6 +20,7 @@ const DUMP: &[u8] = include_bytes!("t-dump.scm");
 
 const MODES: Modes = Modes {
     allow_improper_lists: true,
+    recover_from_errors: false,
     retain_whitespace: false,
     retain_comments: false,
 };
@@ -38,9 +41,28 @@ fn dump() -> Result<()> {
     // Copy from examples/main.rs, keep in sync!
     for val in vals {
         // Print line information as s-expression
-        write!(&mut out, "(line {})\n", val.1.line + 1)?;
+        writeln!(&mut out, "(line {})", val.1.start.line + 1)?;
         GAMBIT_FORMAT.writeln(&mut out, &val.dump())?;
     }
     assert_eq!(str::from_utf8(&out), str::from_utf8(DUMP));
     Ok(())
 }
+
+#[test]
+fn parse_across_threads() -> Result<()> {
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &MODES };
+    let all: Vec<String> = parse(buffered_chars(INPUT), &settings)
+        .map(|t| Ok(t?.0.to_string()))
+        .collect::<Result<_>>()?;
+    let mut ts = parse(buffered_chars(INPUT), &settings);
+    let mut seen: Vec<String> = ts.by_ref().take(10)
+        .map(|t| Ok(t?.0.to_string()))
+        .collect::<Result<_>>()?;
+    let rest = std::thread::scope(|s| {
+        s.spawn(move || ts.map(|t| Ok(t?.0.to_string())).collect::<Result<Vec<_>>>())
+            .join().unwrap()
+    })?;
+    seen.extend(rest);
+    assert_eq!(seen, all);
+    Ok(())
+}
@@ -0,0 +1,108 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{parse::{parse_str, Token},
+               settings::{AnysexprFormat, CLOJURE_FORMAT, COMMON_LISP_FORMAT, GAMBIT_FORMAT,
+                          Modes, Settings},
+               tokens::tokens,
+               value::{Atom, Parenkind}};
+use kstring::KStringCow;
+
+mod common;
+use common::{MODES, read_values};
+
+/// The tokens of the values in `s` written back out.
+fn rewrite(format: &AnysexprFormat, s: &str, abbreviate_quotes: bool) -> Result<String> {
+    let mut out = Vec::new();
+    let vals = read_values(format, s)?;
+    format.write_tokens(&mut out, vals.iter().flat_map(|v| tokens(v, abbreviate_quotes)))?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[test]
+fn tree_to_tokens() -> Result<()> {
+    let s = "(a (b . c) #(1 \"x\") 'd) #1=(#1#)";
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &MODES };
+    let parsed = parse_str(s, &settings)
+        .map(|t| t.map(|t| t.0.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // The reader turns `'d` into `(quote d)`
+    let expected = parsed.join(" ").replace("' d", "( quote d )");
+    let vals = read_values(&GAMBIT_FORMAT, s)?;
+    let got: Vec<String> = vals.iter()
+        .flat_map(|v| tokens(v, false))
+        .map(|t| t.to_string())
+        .collect();
+    assert_eq!(got.join(" "), expected);
+    let abbreviated: Vec<Token> = tokens(&vals[0], true).collect();
+    assert_eq!(abbreviated[abbreviated.len() - 3..],
+               [Token::Quote, Token::Atom(Atom::Symbol("d".into())), Token::Close(Parenkind::Round)]);
+    Ok(())
+}
+
+#[test]
+fn write_tokens() -> Result<()> {
+    assert_eq!(rewrite(&GAMBIT_FORMAT, "(a  b . c)#(1 2)\n'x #1=(#1#) \"s\\n\"", false)?,
+               "(a b . c)\n#(1 2)\n(quote x)\n#1=(#1#)\n\"s\\n\"\n");
+    assert_eq!(rewrite(&GAMBIT_FORMAT, "(quasiquote (a (unquote b) (unquote-splicing c) 'd))",
+                       true)?,
+               "`(a ,b ,@c 'd)\n");
+    assert_eq!(rewrite(&COMMON_LISP_FORMAT, "#+sbcl (foo) #-(or a b) bar #'car #(1 2)", false)?,
+               "#+SBCL (FOO)\n#-(OR A B) BAR\n#'CAR\n#(1 2)\n");
+    assert_eq!(rewrite(&CLOJURE_FORMAT, "#{1 2} `(a ~b ~@c @d) #(+ % 1) [x {:k v}]", false)?,
+               "#{1 2}\n`(a ~b ~@c @d)\n#(+ % 1)\n[x {:k v}]\n");
+    // `~` followed by `@d` would be `~@`
+    assert_eq!(rewrite(&CLOJURE_FORMAT, "~ @d", false)?, "~ @d\n");
+    assert_eq!(rewrite(&GAMBIT_FORMAT, "", false)?, "");
+    Ok(())
+}
+
+#[test]
+fn filter_tokens() -> Result<()> {
+    // Upcase symbols, keeping whitespace and comments
+    let s = "(define (f x) ; comment\n  #| block |# (g x))\n#;(ignored) 'done";
+    let modes = Modes { retain_whitespace: true, retain_comments: true, ..MODES };
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &modes };
+    let filtered = parse_str(s, &settings).map(|t| match t.expect("valid").0 {
+        Token::Atom(Atom::Symbol(s)) =>
+            Token::Atom(Atom::Symbol(KStringCow::from(s.to_uppercase()))),
+        t => t
+    });
+    let mut out = Vec::new();
+    GAMBIT_FORMAT.write_tokens(&mut out, filtered)?;
+    assert_eq!(String::from_utf8(out)?,
+               "(DEFINE (F X) ; comment\n  #| block |# (G X))\n#;(IGNORED) 'DONE\n");
+
+    // Without whitespace tokens, line comments are ended
+    let modes = Modes { retain_comments: true, ..MODES };
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &modes };
+    let mut out = Vec::new();
+    GAMBIT_FORMAT.write_tokens(&mut out, parse_str(s, &settings).map(|t| t.expect("valid").0))?;
+    assert_eq!(String::from_utf8(out)?,
+               "(define (f x) ; comment\n#| block |# (g x))\n#;(ignored) 'done\n");
+    Ok(())
+}
+
+#[test]
+fn not_representable() -> Result<()> {
+    assert_eq!(rewrite(&CLOJURE_FORMAT, "(a b)", false)?, "(a b)\n");
+    let vals = read_values(&GAMBIT_FORMAT, "(a . b) (quasiquote x) #!eof")?;
+    let mut out = Vec::new();
+    let err = |v| CLOJURE_FORMAT.write_tokens(&mut Vec::new(), tokens(v, true))
+        .unwrap_err().to_string();
+    assert_eq!(err(&vals[0]), "not representable in Clojure: improper list");
+    assert_eq!(err(&vals[1]), "not representable in Clojure: \"`\"");
+    assert_eq!(err(&vals[2]), "not representable in Clojure: #!eof");
+    // Tokens before the error are written
+    let e = CLOJURE_FORMAT.write_tokens(&mut out, tokens(&vals[0], false));
+    assert!(e.is_err());
+    assert_eq!(String::from_utf8(out)?, "(a");
+    Ok(())
+}
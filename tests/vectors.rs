@@ -0,0 +1,109 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               number::{NumericVector, NumericVectorKind},
+               parse::{parse, ParseError},
+               read::{ReadError, ReadErrorWithPos},
+               settings::{AnysexprFormat, GAMBIT_FORMAT, GUILE_FORMAT, Modes,
+                          R7RS_FORMAT, Settings},
+               value::{Atom, ReaderMacro, VValue, VValueWithPos}};
+
+mod common;
+use common::{MODES, read};
+
+fn rewrite(format: &AnysexprFormat, s: &str) -> Result<String> {
+    let vals = read(format, s)?;
+    Ok(vals.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" "))
+}
+
+fn numeric_vector(v: &VValueWithPos) -> &NumericVector {
+    match &v.0 {
+        VValue::Atom(Atom::NumericVector(v)) => v,
+        _ => panic!("not a numeric vector: {}", v)
+    }
+}
+
+#[test]
+fn vectors() -> Result<()> {
+    for format in [&GAMBIT_FORMAT, &R7RS_FORMAT, &GUILE_FORMAT] {
+        let vals = read(format, "#(1 (a) #(b))")?;
+        assert!(matches!(&vals[0].0, VValue::ReaderMacro(ReaderMacro::Vector, _)));
+        assert_eq!(rewrite(format, "#(1 (a) #(b)) #()")?, "#(1 (a) #(b)) #()");
+    }
+    Ok(())
+}
+
+#[test]
+fn bytevectors() -> Result<()> {
+    let vals = read(&R7RS_FORMAT, "#u8(1 2 ; two\n 255) #u8()")?;
+    assert_eq!(numeric_vector(&vals[0]), &NumericVector::U8(vec![1, 2, 255]));
+    assert!(numeric_vector(&vals[1]).is_empty());
+    assert_eq!(vals[0].1.end.col, 5);
+    assert_eq!(rewrite(&R7RS_FORMAT, "#u8( #x10 #;7 0 )")?, "#u8(16 0)");
+    // Still booleans
+    assert_eq!(rewrite(&GUILE_FORMAT, "(#t #f #true #false)")?, "(#t #f #t #f)");
+    // SRFI 4 is not part of R7RS
+    assert!(read(&R7RS_FORMAT, "#s8(1)").is_err());
+    Ok(())
+}
+
+#[test]
+fn srfi4() -> Result<()> {
+    let vals = read(&GAMBIT_FORMAT, "#s8(-128 127) #u64(18446744073709551615) \
+                                     #s64(-9223372036854775808) #f32(0.1 1 1/2) \
+                                     #f64(1.5 +inf.0 -2)")?;
+    assert_eq!(numeric_vector(&vals[0]), &NumericVector::S8(vec![-128, 127]));
+    assert_eq!(numeric_vector(&vals[1]), &NumericVector::U64(vec![u64::MAX]));
+    assert_eq!(numeric_vector(&vals[2]), &NumericVector::S64(vec![i64::MIN]));
+    assert_eq!(numeric_vector(&vals[3]), &NumericVector::F32(vec![0.1, 1.0, 0.5]));
+    assert_eq!(numeric_vector(&vals[4]).kind(), NumericVectorKind::F64);
+    assert_eq!(rewrite(&GAMBIT_FORMAT, "#f32(0.1 1) #f64(1.5 +inf.0 -2) #u16(65535)")?,
+               "#f32(0.1 1.0) #f64(1.5 +inf.0 -2.0) #u16(65535)");
+    assert_eq!(rewrite(&GUILE_FORMAT, "(#s32(-1 2) #u32())")?, "(#s32(-1 2) #u32())");
+    Ok(())
+}
+
+#[test]
+fn errors() {
+    let err = |format: &AnysexprFormat, s: &str| -> String {
+        match read(format, s) {
+            Ok(v) => panic!("no error for {:?}: {:?}", s, v),
+            Err(e) => {
+                let e = e.downcast::<ReadErrorWithPos>().expect("read error");
+                assert!(matches!(e.err, ReadError::PE(_)));
+                e.to_string()
+            }
+        }
+    };
+    assert_eq!(err(&R7RS_FORMAT, "(#u8(1 256 -1))"),
+               "number out of range for #u8(..) @1.7");
+    assert_eq!(err(&GAMBIT_FORMAT, "#s16(1 32768)"),
+               "number out of range for #s16(..) @1.7");
+    assert_eq!(err(&GAMBIT_FORMAT, "#f32(1e39)"),
+               "number out of range for #f32(..) @1.5");
+    assert_eq!(err(&GAMBIT_FORMAT, "#u8(1.5)"),
+               "#u8(..) can only contain numbers @1.4");
+    assert_eq!(err(&GAMBIT_FORMAT, "#u8(1 a)"),
+               "#u8(..) can only contain numbers @1.6");
+    assert_eq!(err(&GAMBIT_FORMAT, "#u8(1 (2))"),
+               "#u8(..) can only contain numbers @1.6");
+    assert_eq!(err(&GAMBIT_FORMAT, "#u8(1 2"),
+               "unexpected EOF reading numeric vector starting @1.0");
+    assert_eq!(err(&GAMBIT_FORMAT, "#u16 (1)"), "invalid '#' token @1.0");
+
+    // Recovering from an element out of range: the vector is skipped
+    let recovering = Modes { recover_from_errors: true, ..MODES };
+    let settings = Settings { format: &GAMBIT_FORMAT, modes: &recovering };
+    let tokens: Vec<_> = parse(buffered_chars("#u8(300) x".as_bytes()), &settings).collect();
+    assert!(matches!(&tokens[0], Err(e)
+                     if matches!(e.err, ParseError::NumericVectorElementOutOfRange(_))));
+    assert_eq!(tokens[1].as_ref().expect("token").0.to_string(), "x");
+}
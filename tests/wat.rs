@@ -0,0 +1,111 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{buffered_chars::buffered_chars,
+               parse::{parse, CommentStyle, Token},
+               settings::{Modes, Settings, WAT_FORMAT},
+               value::{Atom, VValue, VValueWithPos}};
+
+mod common;
+use common::{MODES, items, read};
+
+const MODULE: &str = r#"(module ;; a module
+  (; block (; nested ;) comment ;)
+  (func $add (@name "add") (param $a i32) (param $b i32) (result i32)
+    local.get $a local.get $b i32.add)
+  (export "add" (func $add)))
+"#;
+
+fn atom(v: &VValueWithPos) -> String {
+    match &v.0 {
+        VValue::Atom(Atom::Symbol(s)) => format!("sym {}", s),
+        VValue::Atom(Atom::String(s)) => format!("str {:?}", s.as_str()),
+        VValue::Atom(Atom::Number(n)) => format!("num {}", n),
+        v => panic!("not a symbol, string or number: {}", v)
+    }
+}
+
+fn atoms(v: &VValueWithPos) -> Vec<String> {
+    items(v).iter().map(atom).collect()
+}
+
+#[test]
+fn comments() -> Result<()> {
+    let modes = Modes { retain_comments: true, ..MODES };
+    let settings = Settings { format: &WAT_FORMAT, modes: &modes };
+    let comments: Vec<String> = parse(buffered_chars(MODULE.as_bytes()), &settings)
+        .filter_map(|t| match t {
+            Ok(t) => match t.0 {
+                Token::Comment(CommentStyle::Singleline(_), _)
+                    | Token::Comment(CommentStyle::ParenSemicolon, _) =>
+                    Some(format!("{} {}", t.0, t.1)),
+                _ => None
+            }
+            Err(e) => panic!("{}", e)
+        }).collect();
+    assert_eq!(comments, vec![
+        ";; a module @1.8",
+        "(; block (; nested ;) comment ;) @2.2",
+    ]);
+    Ok(())
+}
+
+#[test]
+fn module_tree() -> Result<()> {
+    let vals = read(&WAT_FORMAT, MODULE)?;
+    assert_eq!(vals.len(), 1);
+    let module = items(&vals[0]);
+    let func = &module[1];
+    assert_eq!(atoms(&items(func)[2]), vec!["sym @name", "str \"add\""]);
+    assert_eq!(atoms(&items(func)[3]), vec!["sym param", "sym $a", "sym i32"]);
+    assert_eq!(items(func)[6..].iter().map(atom).collect::<Vec<_>>(), ["sym local.get", "sym $a", "sym local.get", "sym $b",
+                                   "sym i32.add"]);
+    assert_eq!(items(func)[10].1.to_string(), "@4.30");
+    Ok(())
+}
+
+#[test]
+fn idchars_and_numbers() -> Result<()> {
+    let vals = read(&WAT_FORMAT,
+                    "(a'b x|y $\\ #z 1_000 0xFF_FF -0x1 +7 1.5e3 1. inf -nan \
+                     0x1p3 nan:0x7f 1__0 _1)")?;
+    assert_eq!(atoms(&vals[0]), vec![
+        "sym a'b", "sym x|y", "sym $\\", "sym #z", "num 1000", "num 65535", "num -1",
+        "num 7", "num 1500.0", "num 1.0", "num +inf.0", "num +nan.0",
+        "sym 0x1p3", "sym nan:0x7f", "sym 1__0", "sym _1"]);
+    Ok(())
+}
+
+#[test]
+fn strings() -> Result<()> {
+    let vals = read(&WAT_FORMAT,
+                    r#"("a\tb\n\"\'\\" "\e4\bd\a0\n\41" "\00\ff\e4" "\u{1F600}\u{41}")"#)?;
+    assert_eq!(atoms(&vals[0]), vec![
+        r#"str "a\tb\n\"'\\""#,
+        r#"str "你\nA""#,
+        r#"str "\0ÿä""#,
+        r#"str "😀A""#,
+    ]);
+    Ok(())
+}
+
+#[test]
+fn errors() -> Result<()> {
+    let msgs: Vec<String> = ["(; (; ;)", r#""\q""#, r#""\4""#, r#""\u41""#].iter().map(|s| {
+        read(&WAT_FORMAT, s).unwrap_err().to_string()
+    }).collect();
+    assert_eq!(msgs, vec![
+        "unexpected EOF reading comment starting @1.0",
+        "invalid escaped character 'q' @1.2",
+        "not a hex digit: '\"' @1.3",
+        "missing delimiter '{' after code sequence @1.3",
+    ]);
+    Ok(())
+}
@@ -0,0 +1,179 @@
+// Copyright 2023 Christian Jaeger <ch@christianjaeger.ch>. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use anyhow::Result;
+use anysexpr::{pretty::{Pretty, PrettyOptions},
+               settings::{AnysexprFormat, CLOJURE_FORMAT, COMMON_LISP_FORMAT, EDN_FORMAT,
+                          ELISP_FORMAT, GAMBIT_FORMAT, GUILE_FORMAT, R7RS_FORMAT,
+                          SEXPLIB_FORMAT, SMTLIB_FORMAT, WAT_FORMAT},
+               value::{Atom, VValue},
+               write::WriteError};
+use std::io::{self, Write};
+
+mod common;
+use common::read_values;
+
+const ALL_FORMATS: [&AnysexprFormat; 10] = [
+    &GAMBIT_FORMAT, &R7RS_FORMAT, &GUILE_FORMAT, &CLOJURE_FORMAT, &EDN_FORMAT,
+    &COMMON_LISP_FORMAT, &ELISP_FORMAT, &SEXPLIB_FORMAT, &SMTLIB_FORMAT, &WAT_FORMAT,
+];
+
+/// `s` read in Gambit syntax and written in `format`.
+fn convert(format: &AnysexprFormat, s: &str) -> Result<String, WriteError> {
+    let vals = read_values(&GAMBIT_FORMAT, s).expect("valid Gambit syntax");
+    let strs = vals.iter()
+        .map(|v| format.to_string(v))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(strs.join(" "))
+}
+
+#[test]
+fn booleans_and_chars() -> Result<()> {
+    let s = r"#t #f #\a #\newline #\space #\x1 #\(";
+    assert_eq!(convert(&GAMBIT_FORMAT, s)?, r"#t #f #\a #\newline #\space #\x1 #\(");
+    assert_eq!(convert(&CLOJURE_FORMAT, s)?, r"true false \a \newline \space \u0001 \(");
+    assert_eq!(convert(&COMMON_LISP_FORMAT, s)?,
+               "T NIL #\\a #\\Newline #\\Space #\\\u{1} #\\(");
+    assert_eq!(convert(&ELISP_FORMAT, s)?, r"t nil ?a ?\n ?\s ?\x1 ?\(");
+    assert_eq!(convert(&SMTLIB_FORMAT, "#t #f")?, "true false");
+    assert_eq!(convert(&SEXPLIB_FORMAT, "#t").unwrap_err().to_string(),
+               "not representable in Sexplib: #t");
+    assert_eq!(convert(&WAT_FORMAT, r"#\a").unwrap_err().to_string(),
+               r"not representable in WAT: #\a");
+    Ok(())
+}
+
+#[test]
+fn string_escapes() -> Result<()> {
+    let s = r#""a\"b\\c\nd\001e""#;
+    assert_eq!(convert(&GAMBIT_FORMAT, s)?, r#""a\"b\\c\nd\001e""#);
+    assert_eq!(convert(&R7RS_FORMAT, s)?, r#""a\"b\\c\nd\x1;e""#);
+    assert_eq!(convert(&EDN_FORMAT, s)?, r#""a\"b\\c\nd\u0001e""#);
+    assert_eq!(convert(&WAT_FORMAT, s)?, r#""a\"b\\c\nd\u{1}e""#);
+    assert_eq!(convert(&COMMON_LISP_FORMAT, s)?, "\"a\\\"b\\\\c\nd\u{1}e\"");
+    assert_eq!(convert(&SMTLIB_FORMAT, s)?, "\"a\"\"b\\c\nd\u{1}e\"");
+    assert_eq!(convert(&SEXPLIB_FORMAT, s)?, r#""a\"b\\c\nd\001e""#);
+    Ok(())
+}
+
+#[test]
+fn symbols_and_keywords() -> Result<()> {
+    let s = "foo |a b| |1| foo: #!eof";
+    assert_eq!(convert(&GAMBIT_FORMAT, s)?, "foo |a b| |1| foo: #!eof");
+    assert_eq!(convert(&GUILE_FORMAT, s)?, "foo |a b| |1| #:foo #!eof");
+    assert_eq!(convert(&COMMON_LISP_FORMAT, "foo FOO FOO:")?, "|foo| FOO :FOO");
+    assert_eq!(convert(&ELISP_FORMAT, "|a b| |1| foo:")?, r"a\ b \1 :foo");
+    assert_eq!(convert(&SMTLIB_FORMAT, "|a b| foo:")?, "|a b| :foo");
+    assert_eq!(convert(&CLOJURE_FORMAT, "foo foo:")?, "foo :foo");
+    // Text that looks like a number is checked with the number syntax
+    let s = "|1+| |+| |-| |...| |1/2| |-1a| |1e5| |inf| |nan|";
+    assert_eq!(convert(&GAMBIT_FORMAT, s)?, "1+ + - ... |1/2| -1a |1e5| inf nan");
+    assert_eq!(convert(&WAT_FORMAT, "|1+| |-a| |0x| |$1|")?, "1+ -a 0x $1");
+    assert_eq!(convert(&ELISP_FORMAT, s)?, r"1+ + - ... 1/2 -1a \1e5 inf nan");
+    assert_eq!(convert(&CLOJURE_FORMAT, "|a b|").unwrap_err().to_string(),
+               "not representable in Clojure: symbol \"a b\"");
+    assert_eq!(convert(&CLOJURE_FORMAT, "#!eof").unwrap_err().to_string(),
+               "not representable in Clojure: #!eof");
+    Ok(())
+}
+
+#[test]
+fn not_representable() -> Result<()> {
+    assert_eq!(convert(&CLOJURE_FORMAT, "(1 . 2)").unwrap_err().to_string(),
+               "not representable in Clojure: improper list");
+    assert_eq!(convert(&SEXPLIB_FORMAT, "(a [b])").unwrap_err().to_string(),
+               "not representable in Sexplib: [..] list");
+    assert_eq!(convert(&SMTLIB_FORMAT, "-1").unwrap_err().to_string(),
+               "not representable in SMT-LIB: -1");
+    assert_eq!(convert(&R7RS_FORMAT, "#u8(1 2) #s8(1)").unwrap_err().to_string(),
+               "not representable in R7RS: #s8(1)");
+    assert_eq!(convert(&GAMBIT_FORMAT, "#u8(1 2) #s8(1)")?, "#u8(1 2) #s8(1)");
+    let nil: VValue = VValue::Atom(Atom::Nil);
+    assert_eq!(EDN_FORMAT.to_string(&nil)?, "nil");
+    assert_eq!(GAMBIT_FORMAT.to_string(&nil).unwrap_err().to_string(),
+               "not representable in Gambit: nil");
+    // The output is written in chunks, nothing of small values on
+    // errors
+    let mut out = Vec::new();
+    assert!(CLOJURE_FORMAT.write(&mut out, &read_values(&GAMBIT_FORMAT, "(a b . c)")?[0]).is_err());
+    assert!(out.is_empty());
+    Ok(())
+}
+
+#[test]
+fn reads_back() -> Result<()> {
+    // Written values read back the same in every format that can
+    // write them
+    let s = "a |b c| \"d\\te\\x7f;\" #\\x7 1 -2.5 (f . g) foo: #:bar #!rest #(1 2) |.| |;x|";
+    for format in ALL_FORMATS {
+        for v in read_values(&GAMBIT_FORMAT, s)? {
+            let Ok(text) = format.to_string(&v) else { continue };
+            let back = read_values(format, &text)?;
+            assert_eq!(back.len(), 1, "{}: {}", format.name, text);
+            assert_eq!(format.to_string(&back[0])?, text, "{}", format.name);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn pretty_in_format() -> Result<()> {
+    let vals = read_values(&GAMBIT_FORMAT, "(defun f (x) (if (null? x) #t (g |a b| x)))")?;
+    let opts = PrettyOptions { width: 20, ..PrettyOptions::new(&ELISP_FORMAT) };
+    assert_eq!(ELISP_FORMAT.to_string(&Pretty(&vals[0], &opts))?,
+               "(defun f (x)\n  (if (null? x) t\n    (g a\\ b x)))");
+    let opts = PrettyOptions::new(&CLOJURE_FORMAT);
+    assert_eq!(CLOJURE_FORMAT.to_string(&Pretty(&vals[0], &opts)).unwrap_err().to_string(),
+               "not representable in Clojure: symbol \"a b\"");
+    Ok(())
+}
+
+/// Records the sizes of the writes, failing after `fail_after` bytes.
+struct Chunks {
+    sizes: Vec<usize>,
+    fail_after: usize,
+}
+
+impl Write for Chunks {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.sizes.iter().sum::<usize>() + buf.len() > self.fail_after {
+            return Err(io::Error::other("disk full"))
+        }
+        self.sizes.push(buf.len());
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn write_in_chunks() -> Result<()> {
+    let n = 100_000;
+    let s = format!("({}|a b|)", "abc ".repeat(n));
+    let v = &read_values(&GAMBIT_FORMAT, &s)?[0];
+    let mut out = Chunks { sizes: Vec::new(), fail_after: usize::MAX };
+    GAMBIT_FORMAT.writeln(&mut out, v)?;
+    assert_eq!(out.sizes.iter().sum::<usize>(), s.len() + 1);
+    assert!(out.sizes.len() > 1);
+    assert!(out.sizes.iter().all(|size| *size < 10_000));
+
+    // Not representable at the end: the chunks before have been
+    // written
+    let mut out = Chunks { sizes: Vec::new(), fail_after: usize::MAX };
+    let e = CLOJURE_FORMAT.write(&mut out, v).unwrap_err();
+    assert_eq!(e.to_string(), "not representable in Clojure: symbol \"a b\"");
+    assert!(!out.sizes.is_empty());
+
+    let mut out = Chunks { sizes: Vec::new(), fail_after: 100_000 };
+    let e = GAMBIT_FORMAT.write(&mut out, v).unwrap_err();
+    assert!(matches!(e, WriteError::IO(_)));
+    assert_eq!(e.to_string(), "disk full");
+    Ok(())
+}